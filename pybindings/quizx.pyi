@@ -191,4 +191,5 @@ def dummy(a: int) -> str: ...
 def interior_clifford_simp(g: VecGraph): ...
 def clifford_simp(g: VecGraph): ...
 def full_simp(g: VecGraph): ...
+def clean_frontier(g: VecGraph): ...
 def extract_circuit(g: VecGraph) -> Circuit: ...
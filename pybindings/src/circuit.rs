@@ -62,6 +62,11 @@ pub fn to_pyzx_circuit(py: Python<'_>, c: Circuit) -> PyResult<PyObject> {
             CCZ => {
                 c1.call_method("add_gate", ("CCZ", g.qs[0], g.qs[1], g.qs[2]), None)?;
             }
+            Fanout => {
+                for &t in &g.qs[1..] {
+                    c1.call_method("add_gate", ("CNOT", g.qs[0], t), None)?;
+                }
+            }
             InitAncilla => {
                 c1.call_method("add_gate", ("InitAncilla", g.qs[0]), None)?;
             }
@@ -76,7 +81,11 @@ pub fn to_pyzx_circuit(py: Python<'_>, c: Circuit) -> PyResult<PyObject> {
                     "PyZX doesn't support gate: MeasureReset",
                 ));
             }
-            UnknownGate => {}
+            MeasureZ => {
+                return Err(PyValueError::new_err("PyZX doesn't support gate: MeasureZ"));
+            }
+            // PyZX has no barriers, and they act as the identity
+            Barrier | UnknownGate => {}
         }
     }
 
@@ -5,7 +5,7 @@ use crate::vec_graph::VecGraph;
 use crate::Scalar;
 // use num::integer;
 use pyo3::prelude::*;
-use quizx::decompose::Driver;
+use quizx::decompose::{Driver, SpiderSelection};
 
 #[pyclass]
 pub struct Decomposer {
@@ -59,12 +59,20 @@ impl Decomposer {
     }
 
     fn with_driver(&mut self, driver_type: &str, random_t: bool) {
+        let selection = if random_t {
+            SpiderSelection::Random(None)
+        } else {
+            SpiderSelection::First
+        };
         match driver_type {
             "BssTOnly" => {
-                self.d.with_driver(Driver::BssTOnly(random_t));
+                self.d.with_driver(Driver::BssTOnly(selection));
             }
             "BssWithCats" => {
-                self.d.with_driver(Driver::BssWithCats(random_t));
+                self.d.with_driver(Driver::BssWithCats(selection));
+            }
+            "GhzAware" => {
+                self.d.with_driver(Driver::GhzAware(selection));
             }
             _ => {
                 println!("Driver Not Supported!");
@@ -25,6 +25,7 @@ fn quizx(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(clifford_simp, m)?)?;
     m.add_function(wrap_pyfunction!(fuse_gadgets, m)?)?;
     m.add_function(wrap_pyfunction!(full_simp, m)?)?;
+    m.add_function(wrap_pyfunction!(clean_frontier, m)?)?;
     m.add_function(wrap_pyfunction!(extract_circuit, m)?)?;
     m.add_function(wrap_pyfunction!(qasm, m)?)?;
     m.add_class::<VecGraph>()?;
@@ -35,7 +36,8 @@ fn quizx(m: &Bound<'_, PyModule>) -> PyResult<()> {
 
 #[pyfunction]
 fn qasm(source: &str) -> PyResult<VecGraph> {
-    let c = ::quizx::circuit::Circuit::from_qasm(source).map_err(PyValueError::new_err)?;
+    let c = ::quizx::circuit::Circuit::from_qasm(source)
+        .map_err(|e| PyValueError::new_err(e.to_string()))?;
     Ok(VecGraph { g: c.to_graph() })
 }
 
@@ -59,6 +61,11 @@ fn full_simp(g: &mut VecGraph) {
     ::quizx::simplify::full_simp(&mut g.g);
 }
 
+#[pyfunction]
+fn clean_frontier(g: &mut VecGraph) {
+    ::quizx::simplify::clean_frontier(&mut g.g);
+}
+
 #[pyfunction]
 fn extract_circuit(py: Python<'_>, g: &mut VecGraph) -> PyResult<PyObject> {
     match g.g.to_circuit() {
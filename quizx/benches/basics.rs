@@ -1,4 +1,5 @@
 use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use num::Rational64;
 use quizx::circuit::Circuit;
 use quizx::graph::GraphLike;
 use quizx::vec_graph::Graph;
@@ -48,5 +49,58 @@ fn benchmark_loading_saving_cloning(c: &mut Criterion) {
     }
 }
 
-criterion_group!(benches, benchmark_loading_saving_cloning);
+/// A circuit of X rotations, NOTs and CNOTs, i.e. made almost entirely of X-spiders
+fn x_heavy_circuit(qubits: usize, layers: usize) -> Circuit {
+    let mut c = Circuit::new(qubits);
+    for l in 0..layers {
+        for q in 0..qubits {
+            c.add_gate_with_phase("rx", vec![q], Rational64::new(((q + l) % 7) as i64, 8));
+            if (q + l) % 3 == 0 {
+                c.add_gate("x", vec![q]);
+            }
+        }
+        for q in (l % 2..qubits - 1).step_by(2) {
+            c.add_gate("cx", vec![q + 1, q]);
+        }
+    }
+    c
+}
+
+/// Compares building the graph of an X-heavy circuit with X-spiders kept as they are,
+/// against building it in graph-like form, where they are turned into Z-spiders as
+/// the circuit is read
+fn benchmark_x_heavy_graph(c: &mut Criterion) {
+    let circuit = x_heavy_circuit(20, 200);
+    let native: Graph = circuit.to_graph();
+    let graph_like: Graph = circuit.to_graph_with_options(true, false);
+    println!(
+        "x_heavy: {} gates, {} vertices with X-spiders, {} in graph-like form",
+        circuit.num_gates(),
+        native.num_vertices(),
+        graph_like.num_vertices()
+    );
+
+    c.bench_function("x_heavy_to_graph", |b| {
+        b.iter(|| std::hint::black_box(circuit.to_graph::<Graph>().num_vertices()))
+    });
+    c.bench_function("x_heavy_to_graph_like", |b| {
+        b.iter(|| {
+            let g: Graph = circuit.to_graph_with_options(true, false);
+            std::hint::black_box(g.num_vertices())
+        })
+    });
+    c.bench_function("x_heavy_to_graph_and_x_to_z", |b| {
+        b.iter(|| {
+            let mut g: Graph = circuit.to_graph();
+            g.x_to_z();
+            std::hint::black_box(g.num_vertices())
+        })
+    });
+}
+
+criterion_group!(
+    benches,
+    benchmark_loading_saving_cloning,
+    benchmark_x_heavy_graph
+);
 criterion_main!(benches);
@@ -1,9 +1,70 @@
-use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
 use quizx::circuit::Circuit;
-use quizx::decompose::Decomposer;
+use quizx::decompose::{
+    fit_alpha, Decomposer, DecomposerPool, Driver, ScalarMode, SimpFunc, SpiderSelection,
+};
+use quizx::gate::{GType, Gate};
+use quizx::graph::{BasisElem, GraphLike};
 use quizx::hash_graph::Graph as HashGraph;
 use quizx::vec_graph::Graph as VecGraph;
 
+/// A global allocator that tracks the current and peak heap usage
+///
+/// This is only enabled with the `bench-memory` feature, so that the bookkeeping does not
+/// affect the timings of the other benchmarks.
+#[cfg(feature = "bench-memory")]
+mod memory {
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static CURRENT: AtomicUsize = AtomicUsize::new(0);
+    static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+    struct Counting;
+
+    unsafe impl GlobalAlloc for Counting {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let p = System.alloc(layout);
+            if !p.is_null() {
+                let current = CURRENT.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+                PEAK.fetch_max(current, Ordering::Relaxed);
+            }
+            p
+        }
+
+        unsafe fn dealloc(&self, p: *mut u8, layout: Layout) {
+            System.dealloc(p, layout);
+            CURRENT.fetch_sub(layout.size(), Ordering::Relaxed);
+        }
+
+        unsafe fn realloc(&self, p: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            let p1 = System.realloc(p, layout, new_size);
+            if !p1.is_null() {
+                if new_size > layout.size() {
+                    let grow = new_size - layout.size();
+                    let current = CURRENT.fetch_add(grow, Ordering::Relaxed) + grow;
+                    PEAK.fetch_max(current, Ordering::Relaxed);
+                } else {
+                    CURRENT.fetch_sub(layout.size() - new_size, Ordering::Relaxed);
+                }
+            }
+            p1
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: Counting = Counting;
+
+    /// Runs `f` and returns its result, along with the peak number of bytes allocated
+    /// on top of what was allocated before
+    pub fn peak_heap<T>(f: impl FnOnce() -> T) -> (T, usize) {
+        let start = CURRENT.load(Ordering::Relaxed);
+        PEAK.store(start, Ordering::Relaxed);
+        let result = f();
+        (result, PEAK.load(Ordering::Relaxed) - start)
+    }
+}
+
 fn get_test_files() -> Vec<String> {
     vec!["../circuits/small/barenco_tof_3.qasm".to_string()]
 }
@@ -49,5 +110,447 @@ fn benchmark_graph_scalar(c: &mut Criterion) {
     }
 }
 
-criterion_group!(benches, benchmark_graph_scalar);
+/// A circuit consisting of `blocks` independent random Clifford+T circuits, each acting on
+/// its own `qubits`-qubit register
+fn block_circuit(blocks: usize, qubits: usize, depth: usize, seed: u64) -> Circuit {
+    let mut c = Circuit::new(blocks * qubits);
+    for b in 0..blocks {
+        let block = Circuit::random()
+            .seed(seed + b as u64)
+            .qubits(qubits)
+            .depth(depth)
+            .clifford_t(0.3)
+            .build();
+        for g in block.gates {
+            let qs = g.qs.iter().map(|q| q + b * qubits).collect();
+            c.push(Gate { qs, ..g });
+        }
+    }
+    c
+}
+
+/// The scalar <0...0|C|0...0> as a closed graph
+fn amplitude_graph(c: &Circuit) -> VecGraph {
+    let mut g: VecGraph = c.to_graph();
+    g.plug_inputs(&vec![BasisElem::Z0; c.num_qubits()]);
+    g.plug_outputs(&vec![BasisElem::Z0; c.num_qubits()]);
+    g
+}
+
+fn decompose_amplitude(g: &VecGraph, driver: Driver, split: bool) -> Decomposer<VecGraph> {
+    let mut d = Decomposer::new(g);
+    d.with_full_simp()
+        .with_driver(driver)
+        .with_split_graphs_components(split)
+        .decompose();
+    d
+}
+
+/// Compares decomposition with and without splitting graphs into connected components
+///
+/// Splitting requires a connectivity analysis on every branch, so it only pays off if the
+/// graphs actually fall apart.
+fn benchmark_split_components(c: &mut Criterion) {
+    let circuits = vec![
+        ("blocks_4x4", block_circuit(4, 4, 100, 1)),
+        ("blocks_2x6", block_circuit(2, 6, 130, 1)),
+        ("dense_10", block_circuit(1, 10, 300, 1)),
+    ];
+    let drivers = [
+        ("bss", Driver::BssTOnly(SpiderSelection::First)),
+        ("cats", Driver::BssWithCats(SpiderSelection::First)),
+    ];
+
+    let mut group = c.benchmark_group("split_components");
+    group.sample_size(10);
+
+    for (name, circ) in &circuits {
+        let g = amplitude_graph(circ);
+        for (dname, driver) in &drivers {
+            for split in [false, true] {
+                let id = format!(
+                    "{}_{}_{}",
+                    name,
+                    dname,
+                    if split { "split" } else { "nosplit" }
+                );
+                group.bench_with_input(BenchmarkId::from_parameter(id), &g, |b, g| {
+                    b.iter(|| {
+                        std::hint::black_box(decompose_amplitude(g, driver.clone(), split).scalar())
+                    })
+                });
+            }
+        }
+    }
+
+    group.finish();
+}
+
+/// Compares floating point and exact accumulation of scalars in the decomposer
+fn benchmark_scalar_mode(c: &mut Criterion) {
+    let circuits = vec![
+        ("dense_10", block_circuit(1, 10, 300, 1)),
+        ("dense_12", block_circuit(1, 12, 300, 2)),
+    ];
+
+    let mut group = c.benchmark_group("scalar_mode");
+    group.sample_size(10);
+
+    for (name, circ) in &circuits {
+        let g = amplitude_graph(circ);
+        for mode in [ScalarMode::Float, ScalarMode::ExactPhase] {
+            let decompose = |g: &VecGraph| {
+                let mut d = Decomposer::new(g);
+                d.with_full_simp().with_scalar_mode(mode).decompose();
+                d
+            };
+            let id = format!("{}_{:?}", name, mode);
+            group.bench_with_input(BenchmarkId::from_parameter(id), &g, |b, g| {
+                b.iter(|| std::hint::black_box(decompose(g).scalar()))
+            });
+        }
+    }
+
+    group.finish();
+}
+
+/// Compares the simplification applied to each branch of the decomposition
+///
+/// Simplifying costs time on every branch, but it can lower the T-count of a branch
+/// and so prune the tree.
+fn benchmark_simp_func(c: &mut Criterion) {
+    use SimpFunc::*;
+    // without simplification, the number of terms grows with the T-count of the
+    // original circuit, so NoSimp is only run on a small circuit
+    let circuits = vec![
+        (
+            "dense_5",
+            block_circuit(1, 5, 40, 1),
+            vec![NoSimp, CliffordSimp, FullSimp],
+        ),
+        (
+            "dense_10",
+            block_circuit(1, 10, 300, 1),
+            vec![CliffordSimp, FullSimp],
+        ),
+    ];
+
+    let mut group = c.benchmark_group("simp_func");
+    group.sample_size(10);
+
+    for (name, circ, simp_funcs) in &circuits {
+        let g = amplitude_graph(circ);
+        let decompose = |g: &VecGraph, f: SimpFunc| {
+            let mut d = Decomposer::new(g);
+            d.with_simp(f).decompose();
+            d
+        };
+        for &f in simp_funcs {
+            let id = format!("{}_{:?}", name, f);
+            group.bench_with_input(BenchmarkId::from_parameter(id), &g, |b, g| {
+                b.iter(|| std::hint::black_box(decompose(g, f).scalar()))
+            });
+        }
+    }
+
+    group.finish();
+}
+
+/// A surface code memory experiment with coherent T errors
+///
+/// Before each round of syndrome extraction, every data qubit picks up a T gate. The
+/// syndrome measurements are postselected onto the trivial outcome and the final
+/// measurements are dropped, so that the outputs can be plugged.
+fn surface_code_t(distance: usize, rounds: usize) -> Circuit {
+    let round = Circuit::surface_code().distance(distance).rounds(1).build();
+    let mut c = Circuit::new(round.num_qubits());
+    for q in 0..distance * distance {
+        c.add_gate("h", vec![q]);
+    }
+    for _ in 0..rounds {
+        for q in 0..distance * distance {
+            c.add_gate("t", vec![q]);
+        }
+        for g in &round.gates {
+            match g.t {
+                GType::InitAncilla | GType::Measure => {}
+                GType::MeasureReset => c.push(Gate::measure_z(g.qs[0], false)),
+                _ => c.push(g.clone()),
+            }
+        }
+    }
+    c
+}
+
+/// Compares [`Driver::GhzAware`] with [`Driver::BssWithCats`] on circuits derived from
+/// surface code syndrome extraction, whose stabiliser checks give rise to GHZ-like parity
+/// structures
+fn benchmark_ghz_aware(c: &mut Criterion) {
+    let circuits = vec![
+        ("surface_d3_r2", surface_code_t(3, 2)),
+        ("surface_d3_r4", surface_code_t(3, 4)),
+        ("surface_d5_r1", surface_code_t(5, 1)),
+        ("surface_d5_r2", surface_code_t(5, 2)),
+        ("surface_d5_r3", surface_code_t(5, 3)),
+    ];
+    let drivers = [
+        ("cats", Driver::BssWithCats(SpiderSelection::First)),
+        ("ghz", Driver::GhzAware(SpiderSelection::First)),
+    ];
+
+    let mut group = c.benchmark_group("ghz_aware");
+    group.sample_size(10);
+
+    for (name, circ) in &circuits {
+        for (oname, data_out) in [("z", BasisElem::Z0), ("x", BasisElem::X0)] {
+            let n = circ.num_qubits();
+            // the first d^2 of the 2d^2 - 1 qubits are data qubits
+            let d2 = n.div_ceil(2);
+            let mut g: VecGraph = circ.to_graph();
+            g.plug_inputs(&vec![BasisElem::Z0; n]);
+            let mut outputs = vec![data_out; d2];
+            outputs.resize(n, BasisElem::Z0);
+            g.plug_outputs(&outputs);
+
+            for (dname, driver) in &drivers {
+                let id = format!("{}_{}_{}", name, oname, dname);
+                group.bench_with_input(BenchmarkId::from_parameter(id), &g, |b, g| {
+                    b.iter(|| {
+                        std::hint::black_box(decompose_amplitude(g, driver.clone(), false).scalar())
+                    })
+                });
+            }
+        }
+    }
+
+    group.finish();
+}
+
+/// Compares picking T-like spiders by degree, with [`Driver::DegreeOrderedT`], against
+/// picking the first ones, with [`Driver::BssTOnly`], when graphs are split into
+/// connected components
+fn benchmark_degree_order(c: &mut Criterion) {
+    let circuits = vec![
+        ("blocks_4x4", amplitude_graph(&block_circuit(4, 4, 100, 1))),
+        ("blocks_2x6", amplitude_graph(&block_circuit(2, 6, 130, 1))),
+        ("dense_10", amplitude_graph(&block_circuit(1, 10, 300, 1))),
+        ("surface_d3_r2", amplitude_graph(&surface_code_t(3, 2))),
+    ];
+    let drivers = [
+        ("first", Driver::BssTOnly(SpiderSelection::First)),
+        ("desc", Driver::DegreeOrderedT { descending: true }),
+        ("asc", Driver::DegreeOrderedT { descending: false }),
+    ];
+
+    let mut group = c.benchmark_group("degree_order");
+    group.sample_size(10);
+
+    for (name, g) in &circuits {
+        for (dname, driver) in &drivers {
+            let id = format!("{}_{}", name, dname);
+            group.bench_with_input(BenchmarkId::from_parameter(id), g, |b, g| {
+                b.iter(|| {
+                    std::hint::black_box(decompose_amplitude(g, driver.clone(), true).scalar())
+                })
+            });
+        }
+    }
+
+    group.finish();
+}
+
+/// Fits the decomposition exponent α of each driver, see [`fit_alpha`]
+///
+/// Random 8-qubit circuits of increasing depth are decomposed, and the fit is over the
+/// T-counts after simplification, which is what the decomposer starts from. The number of
+/// terms is deterministic, so instead of criterion timings a summary line with α and the
+/// range of T-counts is printed for each driver.
+fn benchmark_alpha(_c: &mut Criterion) {
+    let mut graphs = vec![];
+    for seed in 0..3 {
+        for depth in [100, 150, 200, 250, 300] {
+            graphs.push(amplitude_graph(&block_circuit(1, 8, depth, seed)));
+        }
+    }
+    let drivers = [
+        ("bss", Driver::BssTOnly(SpiderSelection::First)),
+        ("cats", Driver::BssWithCats(SpiderSelection::First)),
+        ("ghz", Driver::GhzAware(SpiderSelection::First)),
+    ];
+
+    for (dname, driver) in &drivers {
+        let results: Vec<(usize, usize)> = graphs
+            .iter()
+            .filter_map(|g| {
+                let mut h = g.clone();
+                quizx::simplify::full_simp(&mut h);
+                let nterms = decompose_amplitude(g, driver.clone(), false).nterms;
+                // Clifford graphs and zero amplitudes don't say anything about α
+                (h.tcount() > 0 && nterms > 0).then_some((h.tcount(), nterms))
+            })
+            .collect();
+        let tmin = results.iter().map(|r| r.0).min().unwrap_or(0);
+        let tmax = results.iter().map(|r| r.0).max().unwrap_or(0);
+        println!(
+            "alpha {}: {:.3} over T-counts {}..={}",
+            dname,
+            fit_alpha(&results),
+            tmin,
+            tmax,
+        );
+    }
+}
+
+/// Reports the peak heap usage of a decomposition for each driver, run sequentially
+/// (depth first) and in parallel
+///
+/// This needs the `bench-memory` feature, e.g.
+/// `cargo bench --bench decomposer --features bench-memory -- memory`. Memory use doesn't
+/// vary between runs, so instead of criterion timings a summary line with the number of
+/// terms and the peak heap usage is printed for each circuit, driver and mode.
+fn benchmark_memory(_c: &mut Criterion) {
+    #[cfg(not(feature = "bench-memory"))]
+    println!("memory: skipped, needs the bench-memory feature");
+
+    #[cfg(feature = "bench-memory")]
+    {
+        let circuits = vec![
+            ("blocks_4x4", amplitude_graph(&block_circuit(4, 4, 100, 1))),
+            ("dense_10", amplitude_graph(&block_circuit(1, 10, 300, 1))),
+            ("dense_12", amplitude_graph(&block_circuit(1, 12, 300, 2))),
+        ];
+        let drivers = [
+            ("bss", Driver::BssTOnly(SpiderSelection::First)),
+            ("cats", Driver::BssWithCats(SpiderSelection::First)),
+            ("ghz", Driver::GhzAware(SpiderSelection::First)),
+        ];
+
+        for (name, g) in &circuits {
+            for (dname, driver) in &drivers {
+                for parallel in [false, true] {
+                    let (nterms, peak) = memory::peak_heap(|| {
+                        let mut d = Decomposer::new(g);
+                        d.with_full_simp().with_driver(driver.clone());
+                        if parallel {
+                            d.decompose_parallel();
+                        } else {
+                            d.decompose();
+                        }
+                        // nterms is not updated by parallel branches
+                        d.partial_scalar_handle().terms()
+                    });
+                    println!(
+                        "memory {} {} {}: nterms {}, peak heap {:.1} KiB",
+                        name,
+                        dname,
+                        if parallel { "parallel" } else { "sequential" },
+                        nterms,
+                        peak as f64 / 1024.0,
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Compares the throughput of many small amplitude queries answered by a
+/// [`DecomposerPool`] with building a fresh [`Decomposer`] for each query
+fn benchmark_pool(c: &mut Criterion) {
+    let circ = Circuit::random()
+        .seed(1)
+        .qubits(6)
+        .depth(60)
+        .clifford_t(0.3)
+        .build();
+    let g: VecGraph = circ.to_graph();
+    let inputs = vec![BasisElem::Z0; 6];
+    let outputs: Vec<Vec<BasisElem>> = (0..64)
+        .map(|x| {
+            (0..6)
+                .map(|q| {
+                    if x >> q & 1 == 1 {
+                        BasisElem::Z1
+                    } else {
+                        BasisElem::Z0
+                    }
+                })
+                .collect()
+        })
+        .collect();
+    let driver = Driver::BssWithCats(SpiderSelection::First);
+
+    let mut group = c.benchmark_group("pool");
+    group.throughput(Throughput::Elements(outputs.len() as u64));
+
+    group.bench_function("fresh_decomposer", |b| {
+        b.iter(|| {
+            for out in &outputs {
+                let mut h = g.clone();
+                h.plug_inputs(&inputs);
+                h.plug_outputs(out);
+                let mut d = Decomposer::new(&h);
+                d.with_full_simp()
+                    .with_driver(driver.clone())
+                    .decompose_parallel();
+                std::hint::black_box(d.scalar());
+            }
+        })
+    });
+
+    let pool = DecomposerPool::new(0);
+    group.bench_function("pool", |b| {
+        b.iter(|| {
+            for out in &outputs {
+                std::hint::black_box(pool.amplitude(&g, &inputs, out, driver.clone()));
+            }
+        })
+    });
+    group.finish();
+}
+
+/// Compares the bitset versions of local complementation and pivoting from
+/// [`quizx::dense`] with the ones from [`quizx::basic_rules`] in the decomposer
+///
+/// Both give the same decomposition.
+fn benchmark_dense_rules(c: &mut Criterion) {
+    let circuits = vec![
+        ("dense_10", block_circuit(1, 10, 300, 1)),
+        ("dense_12", block_circuit(1, 12, 300, 2)),
+        ("surface_d3_r4", surface_code_t(3, 4)),
+    ];
+
+    let mut group = c.benchmark_group("dense_rules");
+    group.sample_size(10);
+
+    for (name, circ) in &circuits {
+        let g = amplitude_graph(circ);
+        let decompose = |g: &VecGraph, dense: bool| {
+            let mut d = Decomposer::new(g);
+            d.with_full_simp().with_dense_rules(dense).decompose();
+            d
+        };
+        for dense in [false, true] {
+            let id = format!("{}_{}", name, if dense { "dense" } else { "sparse" });
+            group.bench_with_input(BenchmarkId::from_parameter(id), &g, |b, g| {
+                b.iter(|| std::hint::black_box(decompose(g, dense).scalar()))
+            });
+        }
+    }
+
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    benchmark_graph_scalar,
+    benchmark_split_components,
+    benchmark_scalar_mode,
+    benchmark_simp_func,
+    benchmark_ghz_aware,
+    benchmark_degree_order,
+    benchmark_alpha,
+    benchmark_memory,
+    benchmark_pool,
+    benchmark_dense_rules
+);
 criterion_main!(benches);
@@ -1,6 +1,9 @@
 use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
 use quizx::circuit::Circuit;
-use quizx::simplify::{clifford_simp, flow_simp, full_simp, interior_clifford_simp};
+use quizx::generate::trotter_ising;
+use quizx::simplify::{
+    clifford_simp, flow_simp, full_simp, full_simp_extractable, interior_clifford_simp,
+};
 use quizx::vec_graph::*;
 
 fn simp_surface_code(c: &mut Criterion) {
@@ -66,5 +69,111 @@ fn simp_surface_code(c: &mut Criterion) {
     });
 }
 
-criterion_group!(benches, simp_surface_code);
+fn simp_neighbor_index(c: &mut Criterion) {
+    // initial setup
+    let circuit = Circuit::random()
+        .seed(1337)
+        .qubits(50)
+        .depth(4000)
+        .clifford_t(0.1)
+        .build();
+    let g: Graph = circuit.to_graph();
+    let mut g_index = g.clone();
+    g_index.set_neighbor_index(true);
+
+    // benchmarking code
+    let mut group = c.benchmark_group("neighbor_index");
+    group.sample_size(10); // 10 is the minimum, 100 is default
+
+    group.bench_function("random_full_simp", |b| {
+        b.iter_batched_ref(
+            || g.clone(), // clone the graph before timing
+            |g1| {
+                // timed application of the simplifier
+                full_simp(g1);
+            },
+            BatchSize::LargeInput,
+        )
+    });
+
+    group.bench_function("random_full_simp_with_neighbor_index", |b| {
+        b.iter_batched_ref(
+            || g_index.clone(), // clone the graph before timing
+            |g1| {
+                // timed application of the simplifier
+                full_simp(g1);
+            },
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+fn simp_extractable(c: &mut Criterion) {
+    // initial setup
+    let circuits: Vec<Circuit> = (0..5)
+        .map(|seed| {
+            Circuit::random()
+                .seed(seed)
+                .qubits(20)
+                .depth(1000)
+                .clifford_t(0.2)
+                .build()
+        })
+        .collect();
+    for (seed, circuit) in circuits.iter().enumerate() {
+        let mut g: Graph = circuit.to_graph();
+        let mut g1 = g.clone();
+        full_simp(&mut g);
+        full_simp_extractable(&mut g1).unwrap();
+        println!(
+            "extractable: seed {seed}, tcount {} -> {} (full_simp {})",
+            circuit.stats().non_cliff,
+            g1.tcount(),
+            g.tcount()
+        );
+    }
+    let g: Graph = circuits[0].to_graph();
+
+    // benchmarking code
+    let mut group = c.benchmark_group("extractable");
+    group.sample_size(10); // 10 is the minimum, 100 is default
+
+    group.bench_function("random_full_simp", |b| {
+        b.iter_batched_ref(|| g.clone(), full_simp, BatchSize::LargeInput)
+    });
+
+    group.bench_function("random_full_simp_extractable", |b| {
+        b.iter_batched_ref(
+            || g.clone(),
+            |g1| full_simp_extractable(g1).unwrap(),
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+fn simp_trotter_ising(c: &mut Criterion) {
+    // initial setup: the rzz gates give one phase gadget per pair and step
+    let circuit = trotter_ising(30, 20, 0.7, 0.3);
+    let g: Graph = circuit.to_graph();
+
+    // benchmarking code
+    let mut group = c.benchmark_group("trotter_ising");
+    group.sample_size(10); // 10 is the minimum, 100 is default
+
+    group.bench_function("ising_clifford_simp", |b| {
+        b.iter_batched_ref(|| g.clone(), clifford_simp, BatchSize::LargeInput)
+    });
+
+    group.bench_function("ising_full_simp", |b| {
+        b.iter_batched_ref(|| g.clone(), full_simp, BatchSize::LargeInput)
+    });
+}
+
+criterion_group!(
+    benches,
+    simp_surface_code,
+    simp_neighbor_index,
+    simp_extractable,
+    simp_trotter_ising
+);
 criterion_main!(benches);
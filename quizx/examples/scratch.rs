@@ -42,14 +42,18 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let time = Instant::now();
     let mut d = Decomposer::new(&g);
     d.with_full_simp()
-        .with_driver(quizx::decompose::Driver::BssTOnly(true));
+        .with_driver(quizx::decompose::Driver::BssTOnly(
+            quizx::decompose::SpiderSelection::Random(None),
+        ));
     let mut max = d.max_terms();
     let mut best_d = d.clone();
 
     for _ in 0..100 {
         let mut d1 = d.clone();
         d1.decomp_until_depth(1)
-            .with_driver(quizx::decompose::Driver::BssTOnly(false))
+            .with_driver(quizx::decompose::Driver::BssTOnly(
+                quizx::decompose::SpiderSelection::First,
+            ))
             .decomp_until_depth(3);
         if d1.max_terms() < max {
             max = d1.max_terms();
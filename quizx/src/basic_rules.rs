@@ -30,6 +30,7 @@ use crate::fscalar::*;
 use crate::graph::*;
 use crate::params::Expr;
 use crate::phase::Phase;
+use crate::symbolic::SymPhase;
 use num::traits::Zero;
 use rustc_hash::FxHashSet;
 use std::iter::FromIterator;
@@ -131,6 +132,7 @@ pub fn spider_fusion_unchecked(g: &mut impl GraphLike, v0: V, v1: V) {
 
     g.add_to_phase(v0, g.phase(v1));
     g.add_to_vars(v0, &g.vars(v1));
+    g.add_to_sym_phase(v0, &g.sym_phase(v1));
     g.remove_vertex(v1);
 }
 
@@ -183,8 +185,9 @@ pub fn check_pi_copy(g: &impl GraphLike, v: V) -> bool {
             _ => return false,
         };
 
-        // No pi-copy on empty spiders.
-        if g.degree(v) == 0 {
+        // No pi-copy on empty spiders, or on symbolic ones, whose phase would go into
+        // the scalar.
+        if g.degree(v) == 0 || g.is_symbolic(v) {
             return false;
         }
 
@@ -239,6 +242,7 @@ pub fn check_remove_id(g: &impl GraphLike, v: V) -> bool {
             && vd.phase.is_zero()
             && g.degree(v) == 2
             && vd.vars.is_empty()
+            && vd.sym.is_empty()
     } else {
         false
     }
@@ -288,6 +292,32 @@ pub fn color_change_unchecked(g: &mut impl GraphLike, v: V) {
 
 checked_rule1!(check_color_change, color_change_unchecked, color_change);
 
+/// Check [h_color_change_unchecked] applies
+///
+/// The vertex must be a Z or X spider with at least one edge, and all of its
+/// edges must be Hadamard edges.
+#[inline]
+pub fn check_h_color_change(g: &impl GraphLike, v: V) -> bool {
+    check_color_change(g, v) && g.degree(v) > 0 && g.incident_edges(v).all(|(_, et)| et == EType::H)
+}
+
+/// Change the color of a spider surrounded by Hadamard edges
+///
+/// This is [color_change_unchecked] restricted to spiders whose edges are all
+/// Hadamard edges, so it turns them into normal edges and never adds Hadamards
+/// to the graph. E.g. an H-Z-H chain becomes an X spider, which can then be fused with
+/// X neighbours.
+#[inline]
+pub fn h_color_change_unchecked(g: &mut impl GraphLike, v: V) {
+    color_change_unchecked(g, v);
+}
+
+checked_rule1!(
+    check_h_color_change,
+    h_color_change_unchecked,
+    h_color_change
+);
+
 /// Check [local_comp_unchecked] applies
 ///
 /// The vertex must be Z, have a phase pi/2 or -pi/2, and be
@@ -297,6 +327,7 @@ pub fn check_local_comp(g: &impl GraphLike, v: V) -> bool {
     if let Some(vd) = g.vertex_data_opt(v) {
         vd.ty == VType::Z
             && vd.phase.is_proper_clifford()
+            && vd.sym.is_empty()
             && g.incident_edges(v)
                 .all(|(v0, et)| g.vertex_type(v0) == VType::Z && et == EType::H)
     } else {
@@ -349,6 +380,7 @@ pub fn check_pivot1(g: &impl GraphLike, v0: V) -> bool {
     if let Some(vd0) = g.vertex_data_opt(v0) {
         vd0.ty == VType::Z
             && vd0.phase.is_pauli()
+            && vd0.sym.is_empty()
             && g.incident_edges(v0)
                 .all(|(w, et)| g.vertex_type(w) == VType::Z && et == EType::H)
     } else {
@@ -362,6 +394,7 @@ pub fn check_pivot2(g: &impl GraphLike, v0: V, v1: V) -> bool {
     if let Some(vd1) = g.vertex_data_opt(v1) {
         vd1.ty == VType::Z
             && vd1.phase.is_pauli()
+            && vd1.sym.is_empty()
             && g.edge_type_opt(v0, v1) == Some(EType::H)
             && g.incident_edges(v1)
                 .all(|(w, et)| g.vertex_type(w) == VType::Z && et == EType::H)
@@ -449,12 +482,12 @@ fn unfuse_boundary(g: &mut impl GraphLike, v: V, b: V) {
     g.remove_edge(v, b);
 }
 
-/// Unfuse a non-Pauli phase as a degree-1 phase gadget
+/// Unfuse a non-Pauli or symbolic phase as a degree-1 phase gadget
 ///
 /// If the vertex already has a Pauli phase, this is a noop.
 #[inline]
 fn unfuse_gadget(g: &mut impl GraphLike, v: V) {
-    if g.phase(v).is_pauli() {
+    if g.phase(v).is_pauli() && !g.is_symbolic(v) {
         return;
     }
     let vd1 = VData {
@@ -467,6 +500,7 @@ fn unfuse_gadget(g: &mut impl GraphLike, v: V) {
     let vd2 = VData {
         ty: VType::Z,
         phase: g.phase(v),
+        sym: g.sym_phase(v),
         row: g.row(v),
         qubit: -2.0,
         ..Default::default()
@@ -474,6 +508,7 @@ fn unfuse_gadget(g: &mut impl GraphLike, v: V) {
     let v1 = g.add_vertex_with_data(vd1);
     let v2 = g.add_vertex_with_data(vd2);
     g.set_phase(v, Phase::zero());
+    g.set_sym_phase(v, SymPhase::default());
     // note if v has any boolean vars, we just leave them there, rather than moving
     // them on to v1. This should be fine, since Paulis don't interfere with any
     // of the Clifford simplifications.
@@ -516,6 +551,7 @@ pub fn check_gen_pivot(g: &impl GraphLike, v0: V, v1: V) -> bool {
 #[inline]
 fn is_interior_pauli(g: &impl GraphLike, v: V) -> bool {
     g.phase(v).is_pauli()
+        && !g.is_symbolic(v)
         && g.neighbors(v)
             .all(|n| g.vertex_type(n) == VType::Z && g.degree(n) > 1)
 }
@@ -524,7 +560,9 @@ fn is_interior_pauli(g: &impl GraphLike, v: V) -> bool {
 // a phase gadget
 #[inline]
 fn is_boundary_pauli(g: &impl GraphLike, v: V) -> bool {
-    g.phase(v).is_pauli() && g.neighbors(v).any(|n| g.vertex_type(n) == VType::B)
+    g.phase(v).is_pauli()
+        && !g.is_symbolic(v)
+        && g.neighbors(v).any(|n| g.vertex_type(n) == VType::B)
 }
 
 // check that a vertex is on a boundary, has phase 0 or pi, and is not
@@ -532,6 +570,7 @@ fn is_boundary_pauli(g: &impl GraphLike, v: V) -> bool {
 #[inline]
 fn is_boundary_pauli_with_h(g: &impl GraphLike, v: V) -> bool {
     g.phase(v).is_pauli()
+        && !g.is_symbolic(v)
         && g.incident_edges(v)
             .any(|(n, et)| et == EType::H && g.vertex_type(n) == VType::B)
 }
@@ -540,7 +579,9 @@ fn is_boundary_pauli_with_h(g: &impl GraphLike, v: V) -> bool {
 // a phase gadget
 #[inline]
 fn is_boundary_proper_clifford(g: &impl GraphLike, v: V) -> bool {
-    g.phase(v).is_proper_clifford() && g.neighbors(v).any(|n| g.vertex_type(n) == VType::B)
+    g.phase(v).is_proper_clifford()
+        && !g.is_symbolic(v)
+        && g.neighbors(v).any(|n| g.vertex_type(n) == VType::B)
 }
 
 /// Check gen_pivot applies and at least one vertex is interior Pauli
@@ -563,6 +604,30 @@ pub fn check_h_boundary_pivot(g: &impl GraphLike, v0: V, v1: V) -> bool {
     check_gen_pivot(g, v0, v1) && is_boundary_pauli_with_h(g, v0)
 }
 
+/// Check gen_pivot applies and removes a Hadamard edge to an output
+///
+/// `v0` must be a Pauli spider whose only boundary neighbour is an output, connected
+/// by a Hadamard edge, and `v1` must be an interior Pauli spider. The pivot replaces `v0` by an
+/// identity spider connected to the output by a plain edge, so the number of Hadamard
+/// edges to outputs strictly decreases.
+#[inline]
+pub fn check_frontier_pivot(g: &impl GraphLike, v0: V, v1: V) -> bool {
+    let pauli = |v: V| g.phase(v).is_pauli() && !g.is_symbolic(v);
+    if !check_gen_pivot(g, v0, v1) || !pauli(v0) {
+        return false;
+    }
+    if !pauli(v1) || g.neighbors(v1).any(|n| g.vertex_type(n) == VType::B) {
+        return false;
+    }
+    let mut bs = g
+        .incident_edges(v0)
+        .filter(|&(n, _)| g.vertex_type(n) == VType::B);
+    match (bs.next(), bs.next()) {
+        (Some((b, EType::H)), None) => g.outputs().contains(&b),
+        _ => false,
+    }
+}
+
 /// Generic version of the pivot rule
 ///
 /// This version of the pivoting rule allows either of the vertices
@@ -600,15 +665,35 @@ checked_rule2!(
     gen_pivot_unchecked,
     h_boundary_pivot
 );
+checked_rule2!(check_frontier_pivot, gen_pivot_unchecked, frontier_pivot);
 
+/// Check boundary_local_comp applies
+///
+/// `v0` must be a boundary spider with phase pi/2 or -pi/2, and `v1` an interior
+/// Pauli spider connected to it by a Hadamard edge. Apart from boundaries, both
+/// must only have Hadamard edges to Z spiders.
 #[inline]
 pub fn check_boundary_local_comp(g: &impl GraphLike, v0: V, v1: V) -> bool {
+    let h_to_z = |v: V| {
+        g.incident_edges(v).all(|(n, et)| {
+            let t = g.vertex_type(n);
+            t == VType::B || (t == VType::Z && et == EType::H)
+        })
+    };
     v0 != v1
-        && g.edge_type_opt(v0, v1) != Some(EType::H)
+        && g.vertex_type_opt(v0) == Some(VType::Z)
+        && g.vertex_type_opt(v1) == Some(VType::Z)
+        && g.edge_type_opt(v0, v1) == Some(EType::H)
         && is_boundary_proper_clifford(g, v0)
         && is_interior_pauli(g, v1)
+        && h_to_z(v0)
+        && h_to_z(v1)
 }
 
+/// Local complementation at a boundary spider and an interior neighbour
+///
+/// After unfusing the boundaries of `v0`, complementing at `v0` turns the Pauli phase
+/// of `v1` into pi/2 or -pi/2, so both can be removed.
 #[inline]
 pub fn boundary_local_comp_unchecked(g: &mut impl GraphLike, v0: V, v1: V) {
     for b in g.neighbor_vec(v0) {
@@ -627,7 +712,8 @@ checked_rule2!(
 
 #[inline]
 pub fn check_gadget_fusion(g: &impl GraphLike, v0: V, v1: V) -> bool {
-    if v0 == v1 {
+    // two connected hubs would be each other's phase
+    if v0 == v1 || g.connected(v0, v1) {
         return false;
     }
 
@@ -635,10 +721,14 @@ pub fn check_gadget_fusion(g: &impl GraphLike, v0: V, v1: V) -> bool {
         // let (p0, vars0) = g.phase_and_vars(v0);
         // let (p1, vars1) = g.phase_and_vars(v1);
 
-        if !vd0.phase.is_zero()
+        if vd0.ty != VType::Z
+            || vd1.ty != VType::Z
+            || !vd0.phase.is_zero()
             || !vd1.phase.is_zero()
             || !vd0.vars.is_empty()
             || !vd1.vars.is_empty()
+            || !vd0.sym.is_empty()
+            || !vd1.sym.is_empty()
         {
             return false;
         }
@@ -689,6 +779,7 @@ pub fn gadget_fusion_unchecked(g: &mut impl GraphLike, v0: V, v1: V) {
         .expect("v1 isn't a gadget");
     g.add_to_phase(gphase0, g.phase(gphase1));
     g.add_to_vars(gphase0, &g.vars(gphase1));
+    g.add_to_sym_phase(gphase0, &g.sym_phase(gphase1));
     g.remove_vertex(v1);
     g.remove_vertex(gphase1);
 
@@ -701,7 +792,7 @@ checked_rule2!(check_gadget_fusion, gadget_fusion_unchecked, gadget_fusion);
 #[inline]
 pub fn check_remove_single(g: &impl GraphLike, v: V) -> bool {
     if let Some(t) = g.vertex_type_opt(v) {
-        g.degree(v) == 0 && (t == VType::Z || t == VType::X)
+        g.degree(v) == 0 && (t == VType::Z || t == VType::X) && !g.is_symbolic(v)
     } else {
         false
     }
@@ -732,6 +823,8 @@ pub fn check_remove_pair(g: &impl GraphLike, v0: V, v1: V) -> bool {
             && g.degree(v1) == 1
             && (t0 == VType::Z || t0 == VType::X)
             && (t1 == VType::Z || t1 == VType::X)
+            && !g.is_symbolic(v0)
+            && !g.is_symbolic(v1)
             && g.connected(v0, v1)
     } else {
         false
@@ -806,10 +899,101 @@ pub fn remove_pair_unchecked(g: &mut impl GraphLike, v0: V, v1: V) {
 
 checked_rule2!(check_remove_pair, remove_pair_unchecked, remove_pair);
 
+/// Returns true if a degree-1 spider of type `vt` connected to a spider of type `wt`
+/// by an edge of type `et` can fuse with it
+fn same_colour(vt: VType, wt: VType, et: EType) -> bool {
+    (vt == wt && et == EType::N) || (vt != wt && et == EType::H)
+}
+
+/// Check [remove_pendant_unchecked] applies
+///
+/// The vertex must be a Z or X spider of degree 1, whose neighbour is a Z or X spider.
+/// Either it can fuse with its neighbour, or its phase is Pauli and the neighbour has
+/// no variables, symbolic phase or self-loop.
+#[inline]
+pub fn check_remove_pendant(g: &impl GraphLike, v: V) -> bool {
+    let Some(vd) = g.vertex_data_opt(v) else {
+        return false;
+    };
+    if !(vd.ty == VType::Z || vd.ty == VType::X) || g.degree(v) != 1 {
+        return false;
+    }
+    let (w, et) = g.incident_edges(v).next().unwrap();
+    let wt = g.vertex_type(w);
+    if w == v || !(wt == VType::Z || wt == VType::X) || et == EType::Wio {
+        return false;
+    }
+    same_colour(vd.ty, wt, et)
+        || (vd.phase.is_pauli()
+            && vd.vars.is_empty()
+            && vd.sym.is_empty()
+            && g.vars(w).is_empty()
+            && !g.is_symbolic(w)
+            && !g.connected(w, w))
+}
+
+/// Remove a spider of degree 1
+///
+/// If the spider can fuse with its neighbour, its phase is added to the neighbour.
+/// Otherwise it is a basis state of the neighbour's colour, up to a scalar, which the
+/// neighbour copies onto its other legs. The neighbour is then removed too, and each of
+/// its other neighbours gets the copied state, which is fused into it if possible.
+#[inline]
+pub fn remove_pendant_unchecked(g: &mut impl GraphLike, v: V) {
+    let (w, et) = g.incident_edges(v).next().unwrap();
+    let vt = g.vertex_type(v);
+    let wt = g.vertex_type(w);
+    let (a, vars) = g.phase_and_vars(v);
+    if same_colour(vt, wt, et) {
+        g.add_to_phase(w, a);
+        g.add_to_vars(w, &vars);
+        g.add_to_sym_phase(w, &g.sym_phase(v));
+        g.remove_vertex(v);
+        return;
+    }
+
+    // v is sqrt(2) times the basis state |a> in the colour of w, and each copy on the
+    // legs of w is 1/sqrt(2) times a spider of the other colour
+    let ot = if wt == VType::Z { VType::X } else { VType::Z };
+    let nhd: Vec<(V, EType)> = g.incident_edges(w).filter(|&(u, _)| u != v).collect();
+    if a.is_one() {
+        let p = g.phase(w);
+        g.scalar_mut().mul_phase(p);
+    }
+    g.scalar_mut().mul_sqrt2_pow(1 - nhd.len() as i32);
+    g.remove_vertex(v);
+    g.remove_vertex(w);
+
+    for (u, e) in nhd {
+        let ut = g.vertex_type(u);
+        if (e == EType::N && ut == ot) || (e == EType::H && ut == wt) {
+            g.add_to_phase(u, a);
+        } else {
+            let x = g.add_vertex_with_data(VData {
+                ty: ot,
+                phase: a,
+                qubit: g.qubit(u),
+                row: g.row(u),
+                ..Default::default()
+            });
+            g.add_edge_with_type(x, u, e);
+        }
+    }
+}
+
+checked_rule1!(
+    check_remove_pendant,
+    remove_pendant_unchecked,
+    remove_pendant
+);
+
 #[inline]
 pub fn check_remove_duplicate(g: &impl GraphLike, v0: V, v1: V) -> bool {
+    if v0 == v1 {
+        return false;
+    }
     if let (Some(VType::Z), Some(VType::Z)) = (g.vertex_type_opt(v0), g.vertex_type_opt(v1)) {
-        if !g.phase(v1).is_pauli() {
+        if !g.phase(v1).is_pauli() || g.is_symbolic(v0) || g.is_symbolic(v1) {
             return false;
         }
         let mut inc0 = g.incident_edge_vec(v0);
@@ -846,6 +1030,34 @@ checked_rule2!(
     remove_duplicate
 );
 
+#[inline]
+pub fn check_remove_self_loop(g: &impl GraphLike, v: V) -> bool {
+    if let Some(t) = g.vertex_type_opt(v) {
+        (t == VType::Z || t == VType::X) && g.connected(v, v)
+    } else {
+        false
+    }
+}
+
+/// Remove a self-loop from a Z or X vertex
+///
+/// A normal self-loop is the identity, whereas a Hadamard self-loop adds a pi
+/// phase and a scalar of 1/sqrt(2).
+#[inline]
+pub fn remove_self_loop_unchecked(g: &mut impl GraphLike, v: V) {
+    if g.edge_type(v, v) == EType::H {
+        g.add_to_phase(v, Phase::one());
+        g.scalar_mut().mul_sqrt2_pow(-1);
+    }
+    g.remove_edge(v, v);
+}
+
+checked_rule1!(
+    check_remove_self_loop,
+    remove_self_loop_unchecked,
+    remove_self_loop
+);
+
 // Tests {{{
 
 #[cfg(test)]
@@ -950,6 +1162,37 @@ mod tests {
         assert_eq!(g.phase(vs[2]), Rational64::new(3, 4).into());
     }
 
+    #[test]
+    fn h_color_change_chain() {
+        // B - X(1/4) -H- Z(1/2) -H- X(1/4) - B
+        let mut g = Graph::new();
+        let b0 = g.add_vertex(VType::B);
+        let x0 = g.add_vertex_with_phase(VType::X, Rational64::new(1, 4));
+        let z = g.add_vertex_with_phase(VType::Z, Rational64::new(1, 2));
+        let x1 = g.add_vertex_with_phase(VType::X, Rational64::new(1, 4));
+        let b1 = g.add_vertex(VType::B);
+        g.add_edge(b0, x0);
+        g.add_edge_with_type(x0, z, EType::H);
+        g.add_edge_with_type(z, x1, EType::H);
+        g.add_edge(x1, b1);
+        g.set_inputs(vec![b0]);
+        g.set_outputs(vec![b1]);
+        let h = g.clone();
+
+        assert!(!check_h_color_change(&g, x0));
+        assert!(h_color_change(&mut g, z));
+        assert_eq!(g.vertex_type(z), VType::X);
+        assert_eq!(g.edge_type(x0, z), EType::N);
+        assert_eq!(g.edge_type(z, x1), EType::N);
+
+        assert!(spider_fusion(&mut g, x0, z));
+        assert!(spider_fusion(&mut g, x0, x1));
+        assert_eq!(g.num_vertices(), 3);
+        assert_eq!(g.vertex_type(x0), VType::X);
+        assert_eq!(g.phase(x0), Rational64::new(1, 1).into());
+        assert_eq!(g.to_tensorf(), h.to_tensorf());
+    }
+
     #[test]
     fn local_comp_1() {
         let mut g = Graph::new();
@@ -1161,6 +1404,39 @@ mod tests {
         assert_eq!(g.to_tensorf(), h.to_tensorf());
     }
 
+    /// A boundary spider with phase pi/2 and an interior Pauli spider, joined by
+    /// an edge of the given type
+    fn boundary_local_comp_graph(et: EType) -> (Graph, V, V) {
+        let mut g = Graph::new();
+        let bs: Vec<_> = (0..3).map(|_| g.add_vertex(VType::B)).collect();
+        let v0 = g.add_vertex_with_phase(VType::Z, Rational64::new(1, 2));
+        let v1 = g.add_vertex_with_phase(VType::Z, 1);
+        let ns: Vec<_> = (0..2).map(|_| g.add_vertex(VType::Z)).collect();
+        g.add_edge(bs[0], v0);
+        g.add_edge_with_type(v0, v1, et);
+        g.add_edge_with_type(v0, ns[0], EType::H);
+        for (&n, &b) in ns.iter().zip(&bs[1..]) {
+            g.add_edge_with_type(v1, n, EType::H);
+            g.add_edge(n, b);
+        }
+        g.set_inputs(vec![bs[0]]);
+        g.set_outputs(bs[1..].to_vec());
+        (g, v0, v1)
+    }
+
+    #[test]
+    fn boundary_local_comp_1() {
+        let (mut g, v0, v1) = boundary_local_comp_graph(EType::H);
+        let h = g.clone();
+        assert!(boundary_local_comp(&mut g, v0, v1));
+        assert!(!g.contains_vertex(v0));
+        assert!(!g.contains_vertex(v1));
+        assert_eq!(g.to_tensorf(), h.to_tensorf());
+
+        let (g, v0, v1) = boundary_local_comp_graph(EType::N);
+        assert!(!check_boundary_local_comp(&g, v0, v1));
+    }
+
     #[test]
     fn gadget_fusion_1() {
         // fuse gadgets of various sizes
@@ -1205,6 +1481,33 @@ mod tests {
         }
     }
 
+    #[test]
+    fn gadget_fusion_non_gadgets() {
+        // X-spiders with the shape of two gadgets
+        let mut g = Graph::new();
+        let b = g.add_vertex(VType::B);
+        let v = g.add_vertex(VType::Z);
+        g.add_edge(b, v);
+        g.set_inputs(vec![b]);
+        let hubs: Vec<_> = (0..2).map(|_| g.add_vertex(VType::X)).collect();
+        for (&hub, p) in hubs
+            .iter()
+            .zip([Rational64::new(1, 4), Rational64::new(1, 2)])
+        {
+            let pv = g.add_vertex_with_phase(VType::Z, p);
+            g.add_edge_with_type(hub, pv, EType::H);
+            g.add_edge_with_type(hub, v, EType::H);
+        }
+        assert!(!check_gadget_fusion(&g, hubs[0], hubs[1]));
+
+        // two phase-free spiders joined by a Hadamard edge, each the other's phase
+        let mut g = Graph::new();
+        let v0 = g.add_vertex(VType::Z);
+        let v1 = g.add_vertex(VType::Z);
+        g.add_edge_with_type(v0, v1, EType::H);
+        assert!(!check_gadget_fusion(&g, v0, v1));
+    }
+
     #[test]
     fn scalar_rules() {
         for &t in &[VType::Z, VType::X] {
@@ -1309,6 +1612,290 @@ mod tests {
         assert!(remove_duplicate(&mut g, v0, v1));
         assert_eq!(g.to_tensorf(), h.to_tensorf());
     }
+
+    #[test]
+    fn remove_duplicate_same_vertex() {
+        // a Pauli spider is not a duplicate of itself
+        let mut g = Graph::new();
+        let bs: Vec<_> = (0..2).map(|_| g.add_vertex(VType::B)).collect();
+        let ns: Vec<_> = (0..2).map(|_| g.add_vertex(VType::Z)).collect();
+        let v = g.add_vertex_with_phase(VType::Z, 1);
+        for (&b, &n) in bs.iter().zip(ns.iter()) {
+            g.add_edge(b, n);
+            g.add_edge_with_type(n, v, EType::H);
+        }
+        g.set_outputs(bs);
+
+        assert!(!check_remove_duplicate(&g, v, v));
+        assert!(!remove_duplicate(&mut g, v, v));
+        assert!(g.contains_vertex(v));
+    }
+
+    /// A Z-spider with a self-loop of the given type, and the same diagram where the
+    /// loop goes through two extra phase-free spiders
+    fn self_loop_graphs(et: EType) -> (Graph, Graph, V) {
+        let mut g = Graph::new();
+        let b0 = g.add_vertex(VType::B);
+        let b1 = g.add_vertex(VType::B);
+        let v = g.add_vertex_with_phase(VType::Z, Rational64::new(1, 4));
+        g.add_edge(b0, v);
+        g.add_edge(v, b1);
+        g.set_inputs(vec![b0]);
+        g.set_outputs(vec![b1]);
+
+        let mut h = g.clone();
+        let w0 = h.add_vertex(VType::Z);
+        let w1 = h.add_vertex(VType::Z);
+        h.add_edge(v, w0);
+        h.add_edge_with_type(w0, w1, et);
+        h.add_edge(w1, v);
+
+        g.add_edge_with_type(v, v, et);
+        (g, h, v)
+    }
+
+    #[test]
+    fn remove_self_loops() {
+        for et in [EType::N, EType::H] {
+            let (mut g, h, v) = self_loop_graphs(et);
+            assert!(check_remove_self_loop(&g, v));
+            assert_eq!(g.num_edges(), 3);
+
+            assert!(remove_self_loop(&mut g, v));
+            assert!(!g.connected(v, v));
+            assert_eq!(g.num_edges(), 2);
+            assert_eq!(g.degree(v), 2);
+            assert_eq!(g.to_tensorf(), h.to_tensorf());
+            assert!(!remove_self_loop(&mut g, v));
+        }
+
+        let (mut g, _, v) = self_loop_graphs(EType::H);
+        remove_self_loop(&mut g, v);
+        assert_eq!(g.phase(v), Rational64::new(-3, 4).into());
+        assert_eq!(*g.scalar(), FScalar::sqrt2_pow(-1));
+    }
+
+    /// A Z-spider with phase 1/4 connected to an input, an output by a Hadamard edge and
+    /// an X-spider with phase 1/2 connected to a second output, plus a pendant of the
+    /// given type and phase
+    fn pendant_graph(vt: VType, phase: Rational64, et: EType) -> (Graph, V) {
+        let mut g = Graph::new();
+        let bs: Vec<V> = (0..3).map(|_| g.add_vertex(VType::B)).collect();
+        let w = g.add_vertex_with_phase(VType::Z, Rational64::new(1, 4));
+        let u = g.add_vertex_with_phase(VType::X, Rational64::new(1, 2));
+        g.add_edge(bs[0], w);
+        g.add_edge_with_type(w, bs[1], EType::H);
+        g.add_edge(w, u);
+        g.add_edge(u, bs[2]);
+        g.set_inputs(vec![bs[0]]);
+        g.set_outputs(vec![bs[1], bs[2]]);
+        let v = g.add_vertex_with_phase(vt, phase);
+        g.add_edge_with_type(v, w, et);
+        (g, v)
+    }
+
+    #[test]
+    fn remove_pendant_phaseless() {
+        // fuses into the neighbour
+        let (mut g, v) = pendant_graph(VType::Z, Rational64::zero(), EType::N);
+        let h = g.clone();
+        assert!(remove_pendant(&mut g, v));
+        assert_eq!(g.num_vertices(), 5);
+        assert_eq!(g.to_tensorf(), h.to_tensorf());
+
+        // copied as |0> to the other legs, which removes the neighbour
+        let (mut g, v) = pendant_graph(VType::Z, Rational64::zero(), EType::H);
+        let h = g.clone();
+        assert!(remove_pendant(&mut g, v));
+        assert_eq!(g.num_vertices(), 6);
+        assert_eq!(g.to_tensorf(), h.to_tensorf());
+    }
+
+    #[test]
+    fn remove_pendant_pi() {
+        for (vt, et) in [(VType::X, EType::N), (VType::Z, EType::H)] {
+            let (mut g, v) = pendant_graph(vt, Rational64::new(1, 1), et);
+            let h = g.clone();
+            assert!(remove_pendant(&mut g, v));
+            assert_eq!(g.to_tensorf(), h.to_tensorf(), "{vt:?} {et:?}");
+
+            // the neighbour is gone, and the X-spider behind it picked up the copy
+            assert_eq!(g.num_vertices(), 6);
+            assert!(g.vertices().any(
+                |u| g.vertex_type(u) == VType::X && g.phase(u) == Rational64::new(3, 2).into()
+            ));
+        }
+    }
+
+    #[test]
+    fn remove_pendant_t() {
+        let t = Rational64::new(1, 4);
+        for (vt, et) in [(VType::Z, EType::N), (VType::X, EType::H)] {
+            let (mut g, v) = pendant_graph(vt, t, et);
+            let h = g.clone();
+            assert!(remove_pendant(&mut g, v));
+            assert_eq!(g.num_vertices(), 5);
+            assert!(g.vertices().any(
+                |u| g.phase(u) == Rational64::new(1, 2).into() && g.vertex_type(u) == VType::Z
+            ));
+            assert_eq!(g.to_tensorf(), h.to_tensorf());
+        }
+
+        // a T state of the other colour can't be removed
+        for (vt, et) in [(VType::X, EType::N), (VType::Z, EType::H)] {
+            let (g, v) = pendant_graph(vt, t, et);
+            assert!(!check_remove_pendant(&g, v));
+        }
+
+        // nor can a spider next to a boundary
+        let mut g = Graph::new();
+        let b = g.add_vertex(VType::B);
+        let v = g.add_vertex(VType::Z);
+        g.add_edge(b, v);
+        g.set_outputs(vec![b]);
+        assert!(!check_remove_pendant(&g, v));
+    }
+
+    #[test]
+    fn full_simp_self_loops() {
+        for et in [EType::N, EType::H] {
+            let (mut g, h, _) = self_loop_graphs(et);
+            crate::simplify::full_simp(&mut g);
+            assert_eq!(g.num_vertices(), 3);
+            assert_eq!(g.to_tensorf(), h.to_tensorf());
+        }
+    }
+}
+
+/// Randomised tests of the rules, checking that applying a rule never changes the
+/// tensor of a graph, including its scalar
+#[cfg(test)]
+mod proptests {
+    use super::*;
+    use crate::tensor::*;
+    use crate::vec_graph::Graph;
+    use num::Rational64;
+    use proptest::prelude::*;
+
+    type Rule = fn(&mut Graph, V, V) -> bool;
+
+    /// The checked rules, with the rules on one vertex ignoring the second vertex
+    const RULES: &[(&str, Rule)] = &[
+        ("spider_fusion", |g, v0, v1| spider_fusion(g, v0, v1)),
+        ("pi_copy", |g, v, _| pi_copy(g, v)),
+        ("remove_id", |g, v, _| remove_id(g, v)),
+        ("color_change", |g, v, _| color_change(g, v)),
+        ("h_color_change", |g, v, _| h_color_change(g, v)),
+        ("local_comp", |g, v, _| local_comp(g, v)),
+        ("pivot", |g, v0, v1| pivot(g, v0, v1)),
+        ("gen_pivot", |g, v0, v1| gen_pivot(g, v0, v1)),
+        ("boundary_pivot", |g, v0, v1| boundary_pivot(g, v0, v1)),
+        ("h_boundary_pivot", |g, v0, v1| h_boundary_pivot(g, v0, v1)),
+        ("frontier_pivot", |g, v0, v1| frontier_pivot(g, v0, v1)),
+        ("boundary_local_comp", |g, v0, v1| {
+            boundary_local_comp(g, v0, v1)
+        }),
+        ("gadget_fusion", |g, v0, v1| gadget_fusion(g, v0, v1)),
+        ("remove_single", |g, v, _| remove_single(g, v)),
+        ("remove_pair", |g, v0, v1| remove_pair(g, v0, v1)),
+        ("remove_duplicate", |g, v0, v1| remove_duplicate(g, v0, v1)),
+        ("remove_self_loop", |g, v, _| remove_self_loop(g, v)),
+        ("remove_pendant", |g, v, _| remove_pendant(g, v)),
+    ];
+
+    /// A small graph with mostly Z-spiders, Hadamard edges and Clifford phases, so that
+    /// rules often match, and at most 3 boundary vertices
+    fn graph() -> impl Strategy<Value = Graph> {
+        let spider = (
+            prop::bool::weighted(0.8),
+            prop::sample::select(vec![0, 0, 1, 2, 2, 4, 6]),
+        );
+        let edge = (
+            any::<prop::sample::Index>(),
+            any::<prop::sample::Index>(),
+            prop::bool::weighted(0.75),
+        );
+        let boundary = (any::<prop::sample::Index>(), any::<bool>(), any::<bool>());
+        (
+            prop::collection::vec(spider, 1..7),
+            prop::collection::vec(edge, 0..12),
+            prop::collection::vec(boundary, 0..4),
+        )
+            .prop_map(|(spiders, edges, boundaries)| {
+                let mut g = Graph::new();
+                let vs: Vec<V> = spiders
+                    .iter()
+                    .map(|&(z, p)| {
+                        let ty = if z { VType::Z } else { VType::X };
+                        g.add_vertex_with_phase(ty, Rational64::new(p, 4))
+                    })
+                    .collect();
+                for (s, t, h) in edges {
+                    let (s, t) = (*s.get(&vs), *t.get(&vs));
+                    if s != t && !g.connected(s, t) {
+                        g.add_edge_with_type(s, t, if h { EType::H } else { EType::N });
+                    }
+                }
+                let (mut inputs, mut outputs) = (vec![], vec![]);
+                for (v, h, input) in boundaries {
+                    let b = g.add_vertex(VType::B);
+                    g.add_edge_with_type(*v.get(&vs), b, if h { EType::H } else { EType::N });
+                    if input {
+                        inputs.push(b);
+                    } else {
+                        outputs.push(b);
+                    }
+                }
+                g.set_inputs(inputs);
+                g.set_outputs(outputs);
+                g
+            })
+    }
+
+    /// Applies the rule at the first pair of vertices where it matches, starting from
+    /// the pair with the given index, and returns the pair
+    fn apply_somewhere(g: &mut Graph, f: Rule, start: prop::sample::Index) -> Option<(V, V)> {
+        let vs = g.vertex_vec();
+        let pairs: Vec<(V, V)> = vs
+            .iter()
+            .flat_map(|&v0| vs.iter().map(move |&v1| (v0, v1)))
+            .collect();
+        if pairs.is_empty() {
+            return None;
+        }
+        let i = start.index(pairs.len());
+        pairs[i..]
+            .iter()
+            .chain(&pairs[..i])
+            .copied()
+            .find(|&(v0, v1)| f(g, v0, v1))
+    }
+
+    fn assert_tensor_eq(t0: &TensorF, t1: &TensorF) -> Result<(), TestCaseError> {
+        prop_assert_eq!(t0.shape(), t1.shape());
+        for (x0, x1) in t0.iter().zip(t1.iter()) {
+            prop_assert!(x0.approx_eq(x1, 1e-8), "{} != {}", x0, x1);
+        }
+        Ok(())
+    }
+
+    proptest! {
+        #[test]
+        fn rules_preserve_tensor(
+            mut g in graph(),
+            steps in prop::collection::vec((0..RULES.len(), any::<prop::sample::Index>()), 1..5),
+        ) {
+            let t0 = g.to_tensorf();
+            for (rule, start) in steps {
+                let (name, f) = RULES[rule];
+                if let Some((v0, v1)) = apply_somewhere(&mut g, f, start) {
+                    let t1 = g.to_tensorf();
+                    assert_tensor_eq(&t0, &t1)
+                        .map_err(|e| TestCaseError::fail(format!("{name}({v0}, {v1}): {e}")))?;
+                }
+            }
+        }
+    }
 }
 
 // }}}
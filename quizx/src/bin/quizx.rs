@@ -1,11 +1,14 @@
 use clap::Parser;
 use quizx::cli::Cli;
+use std::process::ExitCode;
 
-fn main() {
+fn main() -> ExitCode {
     let cli = Cli::parse();
-    let result = cli.run();
-    if let Err(e) = result {
-        eprintln!("{e}");
-        std::process::exit(1);
+    match cli.run() {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
     }
 }
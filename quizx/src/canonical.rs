@@ -0,0 +1,307 @@
+// QuiZX - Rust library for quantum circuit rewriting and optimisation
+//         using the ZX-calculus
+// Copyright (C) 2021 - Aleks Kissinger
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Canonical forms of graphs, for comparing graphs up to renaming of vertices
+//!
+//! The canonical labelling is computed by individualisation-refinement: the vertices
+//! are coloured by their data and role as a boundary, the colouring is refined by the
+//! colours of the neighbours until it is stable, and if some colour is still shared by
+//! several vertices, each of them is singled out in turn. The labelling giving the
+//! smallest edge list is kept.
+
+use crate::fscalar::FScalar;
+use crate::graph::*;
+use crate::params::{Expr, Parity};
+use crate::symbolic::SymPhase;
+use num::Rational64;
+use rustc_hash::FxHashMap;
+
+/// The data of a vertex kept in a [`CanonicalForm`]
+pub type VKey = (VType, Rational64, Parity, SymPhase);
+
+/// A graph with vertices labelled `0..n` in a way that does not depend on the original
+/// vertex names
+///
+/// The positions (qubit and row) of vertices are not part of the canonical form.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CanonicalForm {
+    /// vertex type, phase, variables and symbolic phase of each vertex
+    pub vertices: Vec<VKey>,
+    /// sorted edges `(s, t, ety)` with `s <= t`
+    pub edges: Vec<(usize, usize, EType)>,
+    pub inputs: Vec<usize>,
+    pub outputs: Vec<usize>,
+    pub scalar: FScalar,
+    /// scalar factors, sorted by expression
+    pub scalar_factors: Vec<(Expr, FScalar)>,
+}
+
+/// Assigns to each key its position among the sorted distinct keys
+fn rank<T: Ord>(keys: &[T]) -> Vec<usize> {
+    let mut sorted: Vec<&T> = keys.iter().collect();
+    sorted.sort();
+    sorted.dedup();
+    keys.iter()
+        .map(|k| sorted.binary_search(&k).unwrap())
+        .collect()
+}
+
+fn num_colours(colour: &[usize]) -> usize {
+    colour.iter().copied().max().map_or(0, |c| c + 1)
+}
+
+/// Refines the colouring by the multisets of colours and edge types of neighbours,
+/// until it is stable
+fn refine(nhd: &[Vec<(usize, EType)>], colour: &mut Vec<usize>) {
+    loop {
+        let n = num_colours(colour);
+        let keys: Vec<(usize, Vec<(usize, EType)>)> = nhd
+            .iter()
+            .enumerate()
+            .map(|(i, ns)| {
+                let mut cs: Vec<_> = ns.iter().map(|&(j, et)| (colour[j], et)).collect();
+                cs.sort();
+                (colour[i], cs)
+            })
+            .collect();
+        *colour = rank(&keys);
+        if num_colours(colour) == n {
+            break;
+        }
+    }
+}
+
+/// Returns true if swapping `u` and `w` is an automorphism, given that they have the
+/// same colour
+fn twins(nhd: &[Vec<(usize, EType)>], u: usize, w: usize) -> bool {
+    let nu = nhd[u].iter().filter(|&&(j, _)| j != w);
+    let nw = nhd[w].iter().filter(|&&(j, _)| j != u);
+    nu.eq(nw)
+}
+
+type Edges = Vec<(usize, usize, EType)>;
+
+fn search(nhd: &[Vec<(usize, EType)>], colour: Vec<usize>, best: &mut Option<(Edges, Vec<usize>)>) {
+    let n = num_colours(&colour);
+    if n == colour.len() {
+        let mut edges: Edges = nhd
+            .iter()
+            .enumerate()
+            .flat_map(|(i, ns)| {
+                let colour = &colour;
+                ns.iter()
+                    .filter(move |&&(j, _)| i <= j)
+                    .map(move |&(j, et)| {
+                        let (s, t) = (colour[i], colour[j]);
+                        (s.min(t), s.max(t), et)
+                    })
+            })
+            .collect();
+        edges.sort();
+        if !best.as_ref().is_some_and(|(e, _)| *e <= edges) {
+            *best = Some((edges, colour));
+        }
+        return;
+    }
+
+    // single out the vertices of the first colour that is not a singleton, skipping
+    // vertices that are interchangeable with one that was already tried
+    let mut size = vec![0; n];
+    for &c in &colour {
+        size[c] += 1;
+    }
+    let c = size.iter().position(|&s| s > 1).unwrap();
+    let mut tried: Vec<usize> = vec![];
+    for u in (0..colour.len()).filter(|&u| colour[u] == c) {
+        if tried.iter().any(|&w| twins(nhd, u, w)) {
+            continue;
+        }
+        tried.push(u);
+        let keys: Vec<(usize, bool)> = colour
+            .iter()
+            .enumerate()
+            .map(|(i, &ci)| (ci, i != u))
+            .collect();
+        let mut colour1 = rank(&keys);
+        refine(nhd, &mut colour1);
+        search(nhd, colour1, best);
+    }
+}
+
+/// Computes the canonical form of a graph
+///
+/// Two graphs have the same canonical form if and only if they are equal up to
+/// renaming vertices, including the order of inputs and outputs and the scalar.
+///
+/// Each refinement takes O((V + E) log V) per round, for at most V rounds. For most
+/// graphs only a few vertices need to be singled out, but the search is exponential
+/// in the worst case, e.g. for large graphs with many symmetries. Vertices with the
+/// same data and the same neighbours are only tried once, so e.g. the legs of a
+/// spider do not cause blowup.
+pub fn canonical_form(g: &impl GraphLike) -> CanonicalForm {
+    let verts: Vec<V> = g.vertices().collect();
+    let index: FxHashMap<V, usize> = verts.iter().enumerate().map(|(i, &v)| (v, i)).collect();
+    let nhd: Vec<Vec<(usize, EType)>> = verts
+        .iter()
+        .map(|&v| {
+            let mut ns: Vec<_> = g.incident_edges(v).map(|(w, et)| (index[&w], et)).collect();
+            ns.sort();
+            ns
+        })
+        .collect();
+    let keys: Vec<VKey> = verts
+        .iter()
+        .map(|&v| {
+            let d = g.vertex_data(v);
+            (d.ty, d.phase.to_rational(), d.vars.clone(), d.sym.clone())
+        })
+        .collect();
+
+    let position = |bs: &[V], v: V| bs.iter().position(|&b| b == v);
+    let initial: Vec<_> = verts
+        .iter()
+        .zip(&keys)
+        .map(|(&v, k)| (position(g.inputs(), v), position(g.outputs(), v), k))
+        .collect();
+    let mut colour = rank(&initial);
+    refine(&nhd, &mut colour);
+
+    let mut best = None;
+    search(&nhd, colour, &mut best);
+    let (edges, label) = best.unwrap();
+
+    let mut vertices = vec![Default::default(); verts.len()];
+    for (i, k) in keys.into_iter().enumerate() {
+        vertices[label[i]] = k;
+    }
+    let relabel = |bs: &[V]| bs.iter().map(|v| label[index[v]]).collect();
+    let mut scalar_factors: Vec<(Expr, FScalar)> =
+        g.scalar_factors().map(|(e, s)| (e.clone(), *s)).collect();
+    scalar_factors.sort_by(|(e1, _), (e2, _)| e1.cmp(e2));
+
+    CanonicalForm {
+        vertices,
+        edges,
+        inputs: relabel(g.inputs()),
+        outputs: relabel(g.outputs()),
+        scalar: *g.scalar(),
+        scalar_factors,
+    }
+}
+
+/// Returns true if the graphs are equal up to renaming vertices, see [`canonical_form`]
+pub fn graphs_equal(g: &impl GraphLike, h: &impl GraphLike) -> bool {
+    g.num_vertices() == h.num_vertices()
+        && g.num_edges() == h.num_edges()
+        && g.inputs().len() == h.inputs().len()
+        && g.outputs().len() == h.outputs().len()
+        && g.scalar() == h.scalar()
+        && canonical_form(g) == canonical_form(h)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash_graph::Graph as HashGraph;
+    use crate::vec_graph::Graph;
+
+    /// A circuit-like graph, with its vertices added in the given order
+    fn graph(order: &[usize]) -> Graph {
+        let ty = [VType::B, VType::B, VType::Z, VType::X, VType::B, VType::B];
+        let mut g = Graph::new();
+        let mut vs = [0; 6];
+        for &i in order {
+            vs[i] = g.add_vertex(ty[i]);
+        }
+        g.set_phase(vs[2], Rational64::new(1, 4));
+        g.add_edge(vs[0], vs[2]);
+        g.add_edge(vs[1], vs[3]);
+        g.add_edge_with_type(vs[2], vs[3], EType::H);
+        g.add_edge(vs[2], vs[4]);
+        g.add_edge(vs[3], vs[5]);
+        g.set_inputs(vec![vs[0], vs[1]]);
+        g.set_outputs(vec![vs[4], vs[5]]);
+        g
+    }
+
+    #[test]
+    fn equal_up_to_renaming() {
+        let g = graph(&[0, 1, 2, 3, 4, 5]);
+        let h = graph(&[5, 3, 1, 0, 2, 4]);
+        assert_ne!(g.inputs(), h.inputs());
+        assert_eq!(canonical_form(&g), canonical_form(&h));
+        assert_eq!(g, h);
+
+        let mut h1 = h.clone();
+        let z = h1
+            .vertices()
+            .find(|&v| h1.vertex_type(v) == VType::Z)
+            .unwrap();
+        h1.set_phase(z, Rational64::new(3, 4));
+        assert_ne!(g, h1);
+
+        let mut h1 = h.clone();
+        let x = h1
+            .vertices()
+            .find(|&v| h1.vertex_type(v) == VType::X)
+            .unwrap();
+        h1.set_edge_type(z, x, EType::N);
+        assert_ne!(g, h1);
+
+        let mut h1 = h.clone();
+        h1.inputs_mut().reverse();
+        assert_ne!(g, h1);
+
+        let mut h1 = h.clone();
+        h1.scalar_mut().mul_sqrt2_pow(1);
+        assert_ne!(g, h1);
+    }
+
+    #[test]
+    fn equal_symmetric_graphs() {
+        // a spider with many identical legs and a cycle of identical spiders
+        let mut g = HashGraph::new();
+        let z = g.add_vertex(VType::Z);
+        let mut cycle = vec![];
+        for i in 0..20 {
+            let t = g.add_vertex_with_phase(VType::Z, Rational64::new(1, 4));
+            g.add_edge_with_type(z, t, EType::H);
+            let c = g.add_vertex_with_phase(VType::X, Rational64::new(1, 2));
+            if i > 0 {
+                g.add_edge(cycle[i - 1], c);
+            }
+            cycle.push(c);
+        }
+        g.add_edge(cycle[19], cycle[0]);
+
+        // the same graph with its vertices renamed
+        let mut h = HashGraph::new();
+        let offset = 100;
+        let rename = |v: V| (v * 7 + 3) % 41 + offset;
+        for v in g.vertices() {
+            h.add_named_vertex_with_data(rename(v), g.vertex_data(v).clone())
+                .unwrap();
+        }
+        for (s, t, et) in g.edges() {
+            h.add_edge_with_type(rename(s), rename(t), et);
+        }
+        assert_eq!(g, h);
+
+        h.add_edge(rename(cycle[0]), rename(cycle[10]));
+        g.add_edge(cycle[0], cycle[9]);
+        assert_ne!(g, h);
+    }
+}
@@ -16,15 +16,17 @@
 
 use crate::gate::*;
 use crate::graph::*;
+use crate::json::JsonCircuit;
 use crate::linalg::RowOps;
 use crate::params::Parity;
 use crate::params::Var;
 use crate::phase::Phase;
 use crate::simplify::local_ap_simp;
 use crate::util::pmax;
-use num::{Rational64, Zero};
+use num::{One, Rational64, Zero};
 use openqasm::{ast::Symbol, translate::Value, GenericError, ProgramVisitor};
 use rustc_hash::FxHashMap;
+use serde::de::Error as _;
 use std::collections::VecDeque;
 use std::fmt;
 use std::str;
@@ -36,6 +38,22 @@ pub struct Circuit {
     pub gates: VecDeque<Gate>,
 }
 
+impl serde::Serialize for Circuit {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        JsonCircuit::from_circuit(self).serialize(serializer)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Circuit {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        JsonCircuit::deserialize(deserializer)?
+            .to_circuit()
+            .map_err(|e| {
+                D::Error::custom(format!("Failed to convert JSON circuit to circuit: {e}"))
+            })
+    }
+}
+
 #[derive(PartialEq, Eq, Clone, Copy, Debug)]
 pub struct CircuitStats {
     pub qubits: usize,
@@ -51,14 +69,15 @@ impl CircuitStats {
     pub fn make(c: &Circuit) -> Self {
         let mut s = CircuitStats {
             qubits: c.num_qubits(),
-            total: c.num_gates(),
+            total: c.num_gates() - c.num_gates_of_type(Barrier),
             oneq: 0,
             twoq: 0,
             moreq: 0,
             cliff: 0,
             non_cliff: 0,
         };
-        for g in &c.gates {
+        // barriers are not counted as gates
+        for g in c.gates.iter().filter(|g| g.t != Barrier) {
             match g.qs.len() {
                 1 => {
                     s.oneq += 1;
@@ -71,20 +90,10 @@ impl CircuitStats {
                 }
             }
 
-            match g.t {
-                NOT | Z | S | Sdg | CNOT | CZ | SWAP | HAD => {
-                    s.cliff += 1;
-                }
-                ZPhase | XPhase => {
-                    if g.phase.is_clifford() {
-                        s.cliff += 1;
-                    } else {
-                        s.non_cliff += 1;
-                    }
-                }
-                _ => {
-                    s.non_cliff += 1;
-                }
+            if g.is_clifford() {
+                s.cliff += 1;
+            } else {
+                s.non_cliff += 1;
             }
         }
         s
@@ -117,10 +126,47 @@ impl Circuit {
         }
     }
 
+    /// Builds a circuit from a list of gates, on as many qubits as the gates act on
+    ///
+    /// The number of qubits is one more than the largest qubit index of any gate.
+    pub fn from_gates(gates: impl IntoIterator<Item = Gate>) -> Circuit {
+        let gates: VecDeque<Gate> = gates.into_iter().collect();
+        let nqubits = gates
+            .iter()
+            .flat_map(|g| g.qs.iter())
+            .max()
+            .map_or(0, |&q| q + 1);
+        Circuit { nqubits, gates }
+    }
+
     pub fn num_qubits(&self) -> usize {
         self.nqubits
     }
 
+    /// Checks that every gate acts on distinct qubits within the number of qubits
+    ///
+    /// Gates are not checked when they are added, so this can be used to catch bad
+    /// qubit indices before they cause a panic further on, e.g. in
+    /// [`Circuit::to_graph`].
+    pub fn validate(&self) -> Result<(), String> {
+        for (i, g) in self.gates.iter().enumerate() {
+            if let Some(q) = g.qs.iter().find(|&&q| q >= self.nqubits) {
+                return Err(format!(
+                    "Gate {i} ({}) acts on qubit {q}, but the circuit has {} qubits",
+                    g.to_qasm(),
+                    self.nqubits
+                ));
+            }
+            if g.qs.iter().enumerate().any(|(j, q)| g.qs[..j].contains(q)) {
+                return Err(format!(
+                    "Gate {i} ({}) acts on the same qubit more than once",
+                    g.to_qasm()
+                ));
+            }
+        }
+        Ok(())
+    }
+
     pub fn num_gates(&self) -> usize {
         self.gates.len()
     }
@@ -135,6 +181,54 @@ impl Circuit {
         n
     }
 
+    /// Returns the T-depth of the circuit
+    ///
+    /// This is the number of layers containing a T-like gate, where gates acting on
+    /// disjoint qubits are put in the same layer. T-like gates are T, T^dagger, and Z or
+    /// X phase gates with a non-Clifford multiple of π/4. Toffoli, CCZ and parity phase
+    /// gates are counted by their decomposition in [`Circuit::to_basic_gates`].
+    pub fn t_depth(&self) -> usize {
+        let mut depth = vec![0; self.nqubits];
+        for g in &self.to_basic_gates().gates {
+            let d = g.qs.iter().map(|&q| depth[q]).max().unwrap_or(0) + usize::from(g.is_t());
+            for &q in &g.qs {
+                depth[q] = d;
+            }
+        }
+        depth.into_iter().max().unwrap_or(0)
+    }
+
+    /// Returns the number of gates acting on each qubit
+    ///
+    /// Multi-qubit gates count once for every qubit they touch. Barriers are not gates,
+    /// so they are not counted.
+    pub fn depth_by_qubit(&self) -> Vec<usize> {
+        let mut depth = vec![0; self.nqubits];
+        for g in self.gates.iter().filter(|g| g.t != Barrier) {
+            for q in g.qubits() {
+                depth[q] += 1;
+            }
+        }
+        depth
+    }
+
+    /// Returns the length of the longest chain of gates that depend on each other
+    ///
+    /// This is the circuit depth, where gates acting on disjoint qubits are put in the
+    /// same layer. Barriers add no depth of their own, but gates after a barrier wait
+    /// for all gates before it on the qubits it spans.
+    pub fn critical_path(&self) -> usize {
+        let mut depth = vec![0; self.nqubits];
+        for g in &self.gates {
+            let qs = g.qubits();
+            let d = qs.iter().map(|&q| depth[q]).max().unwrap_or(0) + usize::from(g.t != Barrier);
+            for q in qs {
+                depth[q] = d;
+            }
+        }
+        depth.into_iter().max().unwrap_or(0)
+    }
+
     pub fn push(&mut self, g: Gate) {
         self.gates.push_back(g);
     }
@@ -187,11 +281,102 @@ impl Circuit {
         c
     }
 
+    /// returns the gates in the range [start, end) as a new circuit on the
+    /// same number of qubits
+    ///
+    /// Panics if the range is decreasing or runs past the end of the circuit.
+    pub fn slice(&self, start: usize, end: usize) -> Circuit {
+        if start > end || end > self.gates.len() {
+            panic!(
+                "Invalid gate range {}..{} for circuit with {} gates",
+                start,
+                end,
+                self.gates.len()
+            );
+        }
+
+        Circuit {
+            gates: self.gates.range(start..end).cloned().collect(),
+            nqubits: self.nqubits,
+        }
+    }
+
+    /// Returns the gates in the backward light-cone of the output of `output_qubit`, as
+    /// a new circuit on the same number of qubits
+    ///
+    /// A gate is kept if it acts on a qubit that is in the light-cone at that point, and
+    /// then all of its qubits join the light-cone. No later gate connects a dropped gate
+    /// to `output_qubit`, so for unitary circuits the reduced state of `output_qubit`,
+    /// and hence its marginals, are the same as for the whole circuit. Barriers are
+    /// dropped. This doesn't hold with post-selection, which can change the state on
+    /// other qubits.
+    ///
+    /// Panics if `output_qubit` is out of range.
+    pub fn light_cone(&self, output_qubit: usize) -> Circuit {
+        if output_qubit >= self.nqubits {
+            panic!(
+                "Invalid qubit {} for circuit with {} qubits",
+                output_qubit, self.nqubits
+            );
+        }
+
+        let mut in_cone = vec![false; self.nqubits];
+        in_cone[output_qubit] = true;
+        let mut gates = VecDeque::new();
+        for g in self.gates.iter().rev() {
+            if g.t != Barrier && g.qs.iter().any(|&q| in_cone[q]) {
+                for &q in &g.qs {
+                    in_cone[q] = true;
+                }
+                gates.push_front(g.clone());
+            }
+        }
+
+        Circuit {
+            gates,
+            nqubits: self.nqubits,
+        }
+    }
+
     pub fn to_qasm(&self) -> String {
         String::from("OPENQASM 2.0;\ninclude \"qelib1.inc\";\n") + &self.to_string()
     }
 
-    fn from_qasm_parser(read: impl FnOnce(&mut openqasm::Parser)) -> Result<Circuit, String> {
+    /// Writes the circuit, its graph and the simplified graph to a directory
+    ///
+    /// The directory is created if needed, and will contain `circuit.qasm`,
+    /// `graph.qgraph`, `simplified.qgraph` (after [`crate::simplify::full_simp`]) and
+    /// `metadata.json`, which records the number of qubits and gates and the T-counts
+    /// of both graphs. Existing files are overwritten.
+    pub fn save_artifacts(&self, dir: &str) -> Result<(), String> {
+        let dir = std::path::Path::new(dir);
+        let write = |name: &str, contents: &str| {
+            std::fs::write(dir.join(name), contents).map_err(|e| format!("{name}: {e}"))
+        };
+        let encode =
+            |g: &crate::vec_graph::Graph| crate::json::encode_graph(g).map_err(|e| e.to_string());
+
+        std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        let mut g: crate::vec_graph::Graph = self.to_graph();
+        let tcount = g.tcount();
+        write("circuit.qasm", &self.to_qasm())?;
+        write("graph.qgraph", &encode(&g)?)?;
+        crate::simplify::full_simp(&mut g);
+        write("simplified.qgraph", &encode(&g)?)?;
+
+        let metadata = serde_json::json!({
+            "qubits": self.num_qubits(),
+            "gates": self.num_gates(),
+            "tcount": tcount,
+            "reduced_tcount": g.tcount(),
+        });
+        write(
+            "metadata.json",
+            &serde_json::to_string_pretty(&metadata).map_err(|e| e.to_string())?,
+        )
+    }
+
+    fn from_qasm_parser(read: impl FnOnce(&mut openqasm::Parser)) -> Result<Circuit, CircuitError> {
         let mut cache = openqasm::SourceCache::new();
         let mut parser = openqasm::Parser::new(&mut cache)
             .with_file_policy(openqasm::parser::FilePolicy::Ignore);
@@ -216,37 +401,114 @@ impl Circuit {
             opaque init_anc a;
             opaque post_sel a;
             opaque measure_d q;
+            opaque u3(theta, phi, lambda) q;
+            opaque u2(phi, lambda) q;
+            opaque u1(lambda) q;
+            opaque p(lambda) q;
+            opaque id q;
+            opaque y q;
+            opaque ry(phase) q;
+            opaque sx q;
+            opaque sxdg q;
+            opaque rzz(phase) a, b;
+            opaque rxx(phase) a, b;
+            opaque cy a, b;
+            opaque ch a, b;
+            opaque crz(lambda) a, b;
+            opaque cu1(lambda) a, b;
+            opaque cp(lambda) a, b;
+            opaque cswap a, b, c;
+            opaque cu3(theta, phi, lambda) a, b;
+            opaque csx a, b;
+            opaque ryy(phase) a, b;
+            opaque rzx(phase) a, b;
+            opaque ecr a, b;
+            opaque rccx a, b, c;
+            opaque c3x a, b, c, d;
         "
             .to_string(),
             None,
         );
 
-        let program = parser.done().to_errors().map_err(|e| e.to_string())?;
-        program
-            .type_check()
-            .to_errors()
-            .map_err(|e| e.to_string())?;
+        let parse_error = |e: openqasm::Errors| CircuitError::Parse(e.to_string());
+        let program = parser.done().to_errors().map_err(parse_error)?;
+        program.type_check().to_errors().map_err(parse_error)?;
 
         let mut writer = CircuitWriter {
             circuit: Circuit::new(0),
         };
         let mut linearize = openqasm::Linearize::new(&mut writer, usize::MAX);
-        linearize
-            .visit_program(&program)
-            .to_errors()
-            .map_err(|e| e.to_string())?;
+        let result = linearize.visit_program(&program);
+        if let Err(openqasm::translate::LinearizeError {
+            kind: openqasm::translate::LinearizeErrorKind::WriterError { error },
+            ..
+        }) = &result
+        {
+            if let Some(e) = error.downcast_ref::<CircuitError>() {
+                return Err(e.clone());
+            }
+        }
+        result.to_errors().map_err(parse_error)?;
 
         Ok(writer.circuit)
     }
 
-    pub fn from_qasm(source: &str) -> Result<Circuit, String> {
+    /// Reads a circuit from OpenQASM 2.0
+    ///
+    /// Besides the built-in `U` and `CX`, this supports the gates of `qelib1.inc` and
+    /// the common Qiskit gates `x`, `y`, `z`, `h`, `s`, `sdg`, `t`, `tdg`, `sx`, `sxdg`,
+    /// `id`, `rx`, `ry`, `rz`, `p`, `u1`, `u2`, `u3`, `cx`, `cy`, `cz`, `ch`, `crz`,
+    /// `cu1`, `cp`, `cu3`, `csx`, `swap`, `ccx`, `ccz`, `cswap`, `rccx`, `c3x`, `rzz`,
+    /// `rxx`, `ryy`, `rzx` and `ecr`, up to global phase. E.g. `rzz` becomes a phase
+    /// gadget and `sx` an X-rotation by π/2. Any other opaque gate gives a
+    /// [`CircuitError::UnsupportedGate`].
+    pub fn from_qasm(source: &str) -> Result<Circuit, CircuitError> {
         Circuit::from_qasm_parser(|parser| parser.parse_source::<String>(source.to_string(), None))
     }
 
-    pub fn from_file(name: &str) -> Result<Circuit, String> {
+    pub fn from_file(name: &str) -> Result<Circuit, CircuitError> {
         Circuit::from_qasm_parser(|parser| parser.parse_file(name))
     }
 
+    /// Reads a circuit from pytket's json format
+    ///
+    /// Supports the ops `H`, `X`, `Z`, `S`, `Sdg`, `T`, `Tdg`, `CX`, `CZ`, `CCX`, `SWAP`,
+    /// `Rz`, `Rx`, `TK1`, and `noop`, and returns an error for any other op. Global phases
+    /// are ignored.
+    pub fn from_tket_json(source: &str) -> Result<Circuit, String> {
+        crate::json::circuit_from_tket_json(source)
+    }
+
+    /// Reads a circuit from Quipper's ASCII format
+    ///
+    /// Supports `QGate` lines for `not`, `X`, `Y`, `Z`, `H`, `S`, `T`, `V`, `iX`,
+    /// `omega`, `swap` and `multinot`, and `QRot` lines for `exp(-i%Z)` and `R(2pi/%)`,
+    /// along with their inverses and any positive or negative controls. Ancillas from
+    /// `QInit` and `QTerm` become [`GType::InitAncilla`] and [`GType::PostSelect`]
+    /// gates on their own qubits. Any other gate, classical wires, measurements and
+    /// subroutines give an error. Global phases of uncontrolled gates are ignored.
+    pub fn from_quipper(source: &str) -> Result<Circuit, String> {
+        crate::quipper::circuit_from_quipper(source)
+    }
+
+    /// Removes gates that are the identity, i.e. phase gates with angle 0
+    ///
+    /// This drops `rz`, `rx` and parity phase gates without variables or symbols whose
+    /// phase is within `tolerance` of a multiple of 2π. The tolerance is
+    /// in half turns, like phases, so `0.0` only drops gates whose phase is exactly
+    /// zero. Other gates, including barriers, are kept. Explicit `id` gates are already
+    /// dropped by [`Circuit::from_qasm`].
+    pub fn strip_identities(&mut self, tolerance: f64) {
+        self.gates.retain(|g| {
+            let is_phase = matches!(g.t, ZPhase | XPhase | ParityPhase);
+            if !is_phase || !g.vars.is_zero() || !g.sym.is_empty() {
+                return true;
+            }
+            let r = g.phase.to_f64().rem_euclid(2.0);
+            r.min(2.0 - r) > tolerance
+        });
+    }
+
     /// returns a copy of the circuit, decomposed into 1- and 2-qubit Clifford +
     /// phase gates.
     pub fn to_basic_gates(&self) -> Circuit {
@@ -263,6 +525,185 @@ impl Circuit {
         c
     }
 
+    /// Returns a Clifford circuit `d` such that this circuit is `other` followed by `d`,
+    /// up to global phase, if the ZX-calculus can show that one exists
+    ///
+    /// The circuit `other^dagger` followed by `self` is simplified with
+    /// [`crate::simplify::full_simp`]. If no non-Clifford phases are left, the result
+    /// is a Clifford diagram and is extracted as `d`. Otherwise, this returns `None`,
+    /// e.g. if the non-Clifford gates of the two circuits differ. As `full_simp` does
+    /// not always remove non-Clifford phases that cancel, a Clifford difference can
+    /// occasionally be missed.
+    ///
+    /// Returns `None` if the circuits have different numbers of qubits, or if either
+    /// has gates that are not unitary, variables, or symbolic phases.
+    pub fn clifford_difference(&self, other: &Circuit) -> Option<Circuit> {
+        use crate::extract::ToCircuit;
+        let unitary = |c: &Circuit| {
+            c.gates.iter().all(|g| {
+                g.vars.is_zero()
+                    && g.sym.is_empty()
+                    && !matches!(
+                        g.t,
+                        InitAncilla | PostSelect | Measure | MeasureReset | MeasureZ | UnknownGate
+                    )
+            })
+        };
+        if self.num_qubits() != other.num_qubits() || !unitary(self) || !unitary(other) {
+            return None;
+        }
+
+        let mut g: crate::vec_graph::Graph = (other.to_adjoint() + self).to_graph();
+        crate::simplify::full_simp(&mut g);
+        if g.vertices().any(|v| !g.phase(v).is_clifford()) {
+            return None;
+        }
+        g.to_circuit().ok()
+    }
+
+    /// Returns the circuit controlled on `n_controls` qubits
+    ///
+    /// The control qubits are `0..n_controls`, and each qubit `q` of this circuit
+    /// becomes qubit `q + n_controls`. The whole circuit, including its global phase,
+    /// is applied when all of the controls are |1>, and the identity otherwise.
+    ///
+    /// Controlled NOTs are Toffolis where possible. Any other phase is controlled as
+    /// a phase polynomial, with one phase gadget per subset of the control and target
+    /// qubits, so this uses O(2^n_controls) gates per gate. E.g. controlling T on one
+    /// qubit gives three phases of ±π/8. Hadamards are conjugated to NOTs by Clifford+T
+    /// gates, and phase gadgets are controlled on their target qubit only.
+    ///
+    /// Panics on measurements, postselections, and gates with variables, which have no
+    /// controlled version.
+    pub fn controlled(&self, n_controls: usize) -> Circuit {
+        let controls: Vec<usize> = (0..n_controls).collect();
+        let with_controls = |qs: &[usize]| -> Vec<usize> {
+            controls
+                .iter()
+                .copied()
+                .chain(qs.iter().map(|&q| q + n_controls))
+                .collect()
+        };
+        let mut c = Circuit::new(self.nqubits + n_controls);
+
+        for g in &self.gates {
+            if !g.vars.is_zero() || !g.sym.is_empty() {
+                panic!("Cannot control gate with variables: {}", g.to_qasm());
+            }
+            let qs = with_controls(&g.qs);
+            let t = qs.last().copied().unwrap_or_default();
+            let phase = match g.t {
+                ZPhase | XPhase | XCX => g.phase,
+                Z | CZ | CCZ => Phase::one(),
+                S => Rational64::new(1, 2).into(),
+                T => Rational64::new(1, 4).into(),
+                Sdg => Rational64::new(-1, 2).into(),
+                Tdg => Rational64::new(-1, 4).into(),
+                _ => Phase::zero(),
+            };
+
+            match g.t {
+                ZPhase | Z | S | T | Sdg | Tdg | CZ | CCZ => c.push_controlled_phase(&qs, phase),
+                NOT | CNOT | TOFF => c.push_controlled_not(&qs[..qs.len() - 1], t),
+                Fanout => {
+                    for &q in &qs[n_controls + 1..] {
+                        c.push_controlled_not(&qs[..n_controls + 1], q);
+                    }
+                }
+                XPhase => {
+                    c.push(Gate::new(HAD, vec![t]));
+                    c.push_controlled_phase(&qs, phase);
+                    c.push(Gate::new(HAD, vec![t]));
+                }
+                XCX => {
+                    let ts = &qs[n_controls..];
+                    c.gates.extend(ts.iter().map(|&q| Gate::new(HAD, vec![q])));
+                    c.push_controlled_phase(&qs, phase);
+                    c.gates.extend(ts.iter().map(|&q| Gate::new(HAD, vec![q])));
+                }
+                HAD => {
+                    c.push(Gate::new(S, vec![t]));
+                    c.push(Gate::new(HAD, vec![t]));
+                    c.push(Gate::new(T, vec![t]));
+                    c.push_controlled_not(&controls, t);
+                    c.push(Gate::new(Tdg, vec![t]));
+                    c.push(Gate::new(HAD, vec![t]));
+                    c.push(Gate::new(Sdg, vec![t]));
+                }
+                SWAP => {
+                    let (q0, q1) = (qs[n_controls], qs[n_controls + 1]);
+                    c.push(Gate::new(CNOT, vec![q1, q0]));
+                    c.push_controlled_not(&qs[..n_controls + 1], q1);
+                    c.push(Gate::new(CNOT, vec![q1, q0]));
+                }
+                ParityPhase => {
+                    let ts = &qs[n_controls..];
+                    if let Some((&t, rest)) = ts.split_last() {
+                        for &q in rest {
+                            c.push(Gate::new(CNOT, vec![q, t]));
+                        }
+                        let mut cqs = controls.clone();
+                        cqs.push(t);
+                        c.push_controlled_phase(&cqs, g.phase);
+                        for &q in rest.iter().rev() {
+                            c.push(Gate::new(CNOT, vec![q, t]));
+                        }
+                    }
+                }
+                Barrier => c.push(Gate::barrier(qs)),
+                _ => panic!("Cannot control gate: {}", g.to_qasm()),
+            }
+        }
+
+        c
+    }
+
+    /// Pushes a NOT on `t`, controlled on all of `controls`
+    fn push_controlled_not(&mut self, controls: &[usize], t: usize) {
+        let mut qs = controls.to_vec();
+        qs.push(t);
+        match controls.len() {
+            0 => self.push(Gate::new(NOT, qs)),
+            1 => self.push(Gate::new(CNOT, qs)),
+            2 => self.push(Gate::new(TOFF, qs)),
+            _ => {
+                self.push(Gate::new(HAD, vec![t]));
+                self.push_controlled_phase(&qs, Phase::one());
+                self.push(Gate::new(HAD, vec![t]));
+            }
+        }
+    }
+
+    /// Pushes the phase `phase` on the state where all of `qs` are |1>
+    ///
+    /// Uses the phase polynomial x1...xm = sum_S (-1)^(|S|-1) (XOR_{i in S} x_i) / 2^(m-1),
+    /// with a phase gadget for every non-empty subset S of the qubits.
+    fn push_controlled_phase(&mut self, qs: &[usize], phase: Phase) {
+        let m = qs.len();
+        if phase.is_zero() || m == 0 {
+            return;
+        }
+        if phase == Phase::one() && m <= 3 {
+            let t = [Z, CZ, CCZ][m - 1];
+            self.push(Gate::new(t, qs.to_vec()));
+            return;
+        }
+
+        let p = phase / (1 << (m - 1));
+        for subset in 1..(1usize << m) {
+            let sqs: Vec<usize> = (0..m)
+                .filter(|&i| subset & (1 << i) != 0)
+                .map(|i| qs[i])
+                .collect();
+            let p = if sqs.len() % 2 == 1 { p } else { -p };
+            if sqs.len() == 1 {
+                self.push(Gate::new_with_phase(ZPhase, sqs, p));
+            } else {
+                Gate::new_with_phase(ParityPhase, sqs, p).push_basic_gates(self);
+            }
+        }
+    }
+
     pub fn to_graph_with_options<G: GraphLike>(&self, simplify: bool, postselect: bool) -> G {
         let mut graph = G::new();
         let mut qs = FxHashMap::default();
@@ -409,34 +850,197 @@ struct CircuitWriter {
     circuit: Circuit,
 }
 
-#[derive(Debug)]
-#[allow(clippy::enum_variant_names)]
-enum CircuitWriterError {
-    UnitaryNotSupported,
-    BarrierNotSupported,
+/// An error reading a circuit from QASM, see [`Circuit::from_qasm`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum CircuitError {
+    /// The source could not be parsed or type checked, with the parser's errors.
+    Parse(String),
+    /// The circuit has a reset.
     ResetNotSupported,
+    /// The circuit has a classically controlled gate.
     ConditionalNotSupported,
+    /// The circuit has a gate quizx doesn't support, with the name of the gate.
+    UnsupportedGate(String),
 }
 
-impl std::fmt::Display for CircuitWriterError {
+impl std::fmt::Display for CircuitError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
-            CircuitWriterError::UnitaryNotSupported => {
-                write!(f, "arbitrary unitaries are not supported")
-            }
-            CircuitWriterError::BarrierNotSupported => write!(f, "barriers are not supported"),
-            CircuitWriterError::ResetNotSupported => write!(f, "resets are not supported"),
-            CircuitWriterError::ConditionalNotSupported => {
+            CircuitError::Parse(errors) => write!(f, "{errors}"),
+            CircuitError::ResetNotSupported => write!(f, "resets are not supported"),
+            CircuitError::ConditionalNotSupported => {
                 write!(f, "conditionals are not supported")
             }
+            CircuitError::UnsupportedGate(name) => write!(f, "unsupported gate: {name}"),
         }
     }
 }
 
-impl std::error::Error for CircuitWriterError {}
+impl std::error::Error for CircuitError {}
+
+/// Converts a QASM parameter, which is `a + b*pi`, to a phase in half turns
+fn param_to_phase(value: Value) -> Phase {
+    Phase::new(param_to_half_turns(value))
+}
+
+/// Converts a QASM parameter to half turns, without reducing it mod 2
+///
+/// Gates such as `crz` use half of the angle, which depends on more than the angle
+/// mod 2π.
+fn param_to_half_turns(value: Value) -> Rational64 {
+    let b = Rational64::new(*value.b.numer(), *value.b.denom());
+    if value.a.is_zero() {
+        return b;
+    }
+    // angles in radians that are multiples of π/4 up to rounding are read exactly
+    let quarters = 4.0 * (*value.a.numer() as f64 / *value.a.denom() as f64) / std::f64::consts::PI;
+    if (quarters - quarters.round()).abs() < 1e-9 {
+        return Rational64::new(quarters.round() as i64, 4) + b;
+    }
+    let a = *value.a.numer() as f32 / *value.a.denom() as f32;
+    Rational64::approximate_float(a / std::f32::consts::PI).unwrap_or(0.into()) + b
+}
+
+/// Returns the gates for standard QASM gates that are not a single quizx gate
+fn standard_gate(name: &str, params: &[Value], regs: &[usize]) -> Option<Vec<Gate>> {
+    let phase = || param_to_phase(params[0]);
+    let half = |i: usize| param_to_half_turns(params[i]) / 2;
+    let gate = |t: GType, q: usize| Gate::new(t, vec![q]);
+    let z = |q: usize, p: Rational64| Gate::new_with_phase(ZPhase, vec![q], p);
+    let cx = |c: usize, t: usize| Gate::new(CNOT, vec![c, t]);
+    let cu1 = |l: Rational64, c: usize, t: usize| {
+        vec![z(c, l / 2), z(t, l / 2), cx(c, t), z(t, -l / 2), cx(c, t)]
+    };
+    let rzx = |p: Rational64| {
+        vec![
+            gate(HAD, regs[1]),
+            Gate::new_with_phase(ParityPhase, regs.to_vec(), p),
+            gate(HAD, regs[1]),
+        ]
+    };
+    let gs = match name {
+        "id" => vec![],
+        "y" => vec![gate(Z, regs[0]), gate(NOT, regs[0])],
+        "ry" => vec![
+            gate(Sdg, regs[0]),
+            Gate::new_with_phase(XPhase, vec![regs[0]], phase()),
+            gate(S, regs[0]),
+        ],
+        "sx" => vec![Gate::new_with_phase(
+            XPhase,
+            vec![regs[0]],
+            Rational64::new(1, 2),
+        )],
+        "sxdg" => vec![Gate::new_with_phase(
+            XPhase,
+            vec![regs[0]],
+            Rational64::new(-1, 2),
+        )],
+        "rzz" => vec![Gate::new_with_phase(ParityPhase, regs.to_vec(), phase())],
+        "rxx" => vec![
+            gate(HAD, regs[0]),
+            gate(HAD, regs[1]),
+            Gate::new_with_phase(ParityPhase, regs.to_vec(), phase()),
+            gate(HAD, regs[0]),
+            gate(HAD, regs[1]),
+        ],
+        "ryy" => vec![
+            gate(Sdg, regs[0]),
+            gate(Sdg, regs[1]),
+            gate(HAD, regs[0]),
+            gate(HAD, regs[1]),
+            Gate::new_with_phase(ParityPhase, regs.to_vec(), phase()),
+            gate(HAD, regs[0]),
+            gate(HAD, regs[1]),
+            gate(S, regs[0]),
+            gate(S, regs[1]),
+        ],
+        "rzx" => rzx(phase().to_rational()),
+        "ecr" => {
+            let mut gs = rzx(Rational64::new(1, 4));
+            gs.push(gate(NOT, regs[0]));
+            gs.extend(rzx(Rational64::new(-1, 4)));
+            gs
+        }
+        "cy" => vec![
+            gate(Sdg, regs[1]),
+            Gate::new(CNOT, regs.to_vec()),
+            gate(S, regs[1]),
+        ],
+        "ch" => vec![
+            gate(S, regs[1]),
+            gate(HAD, regs[1]),
+            gate(T, regs[1]),
+            Gate::new(CNOT, regs.to_vec()),
+            gate(Tdg, regs[1]),
+            gate(HAD, regs[1]),
+            gate(Sdg, regs[1]),
+        ],
+        "crz" => vec![
+            z(regs[1], half(0)),
+            cx(regs[0], regs[1]),
+            z(regs[1], -half(0)),
+            cx(regs[0], regs[1]),
+        ],
+        "cu1" | "cp" => cu1(param_to_half_turns(params[0]), regs[0], regs[1]),
+        "csx" => {
+            let mut gs = vec![gate(HAD, regs[1])];
+            gs.extend(cu1(Rational64::new(1, 2), regs[0], regs[1]));
+            gs.push(gate(HAD, regs[1]));
+            gs
+        }
+        "cu3" => {
+            // the decomposition of cu3 in qelib1.inc
+            let (theta, phi, lambda) = (half(0), half(1), half(2));
+            let mut gs = vec![
+                z(regs[0], lambda + phi),
+                z(regs[1], lambda - phi),
+                cx(regs[0], regs[1]),
+            ];
+            gs.extend(Gate::u3(regs[1], -theta, 0, -phi - lambda));
+            gs.push(cx(regs[0], regs[1]));
+            gs.extend(Gate::u3(regs[1], theta, phi * 2, 0));
+            gs
+        }
+        "cswap" => vec![
+            Gate::new(CNOT, vec![regs[2], regs[1]]),
+            Gate::new(TOFF, regs.to_vec()),
+            Gate::new(CNOT, vec![regs[2], regs[1]]),
+        ],
+        "rccx" => vec![
+            gate(HAD, regs[2]),
+            gate(T, regs[2]),
+            cx(regs[1], regs[2]),
+            gate(Tdg, regs[2]),
+            cx(regs[0], regs[2]),
+            gate(T, regs[2]),
+            cx(regs[1], regs[2]),
+            gate(Tdg, regs[2]),
+            gate(HAD, regs[2]),
+        ],
+        "c3x" => {
+            // a CCCZ is a phase of ±π/8 on the parity of each non-empty set of qubits
+            let mut gs = vec![gate(HAD, regs[3])];
+            for set in 1..16usize {
+                let qs: Vec<usize> = (0..4)
+                    .filter(|i| set >> i & 1 == 1)
+                    .map(|i| regs[i])
+                    .collect();
+                let sign = if qs.len() % 2 == 1 { 1 } else { -1 };
+                let t = if qs.len() == 1 { ZPhase } else { ParityPhase };
+                gs.push(Gate::new_with_phase(t, qs, Rational64::new(sign, 8)));
+            }
+            gs.push(gate(HAD, regs[3]));
+            gs
+        }
+        _ => return None,
+    };
+    Some(gs)
+}
 
 impl openqasm::GateWriter for &mut CircuitWriter {
-    type Error = CircuitWriterError;
+    type Error = CircuitError;
 
     fn initialize(&mut self, qubits: &[Symbol], _: &[Symbol]) -> Result<(), Self::Error> {
         self.circuit = Circuit::new(qubits.len());
@@ -454,19 +1058,40 @@ impl openqasm::GateWriter for &mut CircuitWriter {
         params: &[Value],
         regs: &[usize],
     ) -> Result<(), Self::Error> {
-        fn param_to_phase(value: Value) -> Phase {
-            if value.a.is_zero() {
-                Rational64::new(*value.b.numer(), *value.b.denom()).into()
-            } else {
-                let a = *value.a.numer() as f32 / *value.a.denom() as f32;
-                let mut r =
-                    Rational64::approximate_float(a / std::f32::consts::PI).unwrap_or(0.into());
-                r += Rational64::new(*value.b.numer(), *value.b.denom());
-                Phase::new(r)
-            }
+        let half = Rational64::new(1, 2);
+        let u3 = match (name.as_str(), params) {
+            ("u3", &[theta, phi, lambda]) => Some(Gate::u3(
+                regs[0],
+                param_to_phase(theta),
+                param_to_phase(phi),
+                param_to_phase(lambda),
+            )),
+            ("u2", &[phi, lambda]) => Some(Gate::u3(
+                regs[0],
+                half,
+                param_to_phase(phi),
+                param_to_phase(lambda),
+            )),
+            _ => None,
+        };
+        if let Some(gs) = u3 {
+            self.circuit.gates.extend(gs);
+            return Ok(());
+        }
+
+        if let Some(gs) = standard_gate(name.as_str(), params, regs) {
+            self.circuit.gates.extend(gs);
+            return Ok(());
         }
 
-        let mut g = Gate::from_qasm_name(name.as_str());
+        let mut g = if let "u1" | "p" = name.as_str() {
+            Gate::new(GType::ZPhase, vec![])
+        } else {
+            Gate::from_qasm_name(name.as_str())
+        };
+        if g.t == UnknownGate {
+            return Err(CircuitError::UnsupportedGate(name.to_string()));
+        }
         g.qs.extend_from_slice(regs);
         if !params.is_empty() {
             g.phase = param_to_phase(params[0]);
@@ -477,16 +1102,32 @@ impl openqasm::GateWriter for &mut CircuitWriter {
         Ok(())
     }
 
-    fn write_u(&mut self, _: Value, _: Value, _: Value, _: usize) -> Result<(), Self::Error> {
-        Err(CircuitWriterError::UnitaryNotSupported)
+    fn write_u(
+        &mut self,
+        theta: Value,
+        phi: Value,
+        lambda: Value,
+        reg: usize,
+    ) -> Result<(), Self::Error> {
+        self.circuit.gates.extend(Gate::u3(
+            reg,
+            param_to_phase(theta),
+            param_to_phase(phi),
+            param_to_phase(lambda),
+        ));
+        Ok(())
     }
 
-    fn write_barrier(&mut self, _: &[usize]) -> Result<(), Self::Error> {
-        Err(CircuitWriterError::BarrierNotSupported)
+    fn write_barrier(&mut self, regs: &[usize]) -> Result<(), Self::Error> {
+        // the parser does not keep the order of the qubits, which doesn't matter here
+        let mut qs = regs.to_vec();
+        qs.sort_unstable();
+        self.circuit.push(Gate::barrier(qs));
+        Ok(())
     }
 
     fn write_reset(&mut self, _: usize) -> Result<(), Self::Error> {
-        Err(CircuitWriterError::ResetNotSupported)
+        Err(CircuitError::ResetNotSupported)
     }
 
     fn write_measure(&mut self, from: usize, to: usize) -> Result<(), Self::Error> {
@@ -500,11 +1141,11 @@ impl openqasm::GateWriter for &mut CircuitWriter {
     }
 
     fn start_conditional(&mut self, _: usize, _: usize, _: u64) -> Result<(), Self::Error> {
-        Err(CircuitWriterError::ConditionalNotSupported)
+        Err(CircuitError::ConditionalNotSupported)
     }
 
     fn end_conditional(&mut self) -> Result<(), Self::Error> {
-        Err(CircuitWriterError::ConditionalNotSupported)
+        Err(CircuitError::ConditionalNotSupported)
     }
 }
 
@@ -514,6 +1155,107 @@ mod tests {
     use crate::tensor::*;
     use crate::vec_graph::Graph;
 
+    #[test]
+    fn validate() {
+        let mut c = Circuit::new(2);
+        c.add_gate("cx", vec![0, 1]);
+        assert_eq!(c.validate(), Ok(()));
+        c.add_gate("h", vec![2]);
+        let e = c.validate().unwrap_err();
+        assert!(e.contains("Gate 1 (h q[2]) acts on qubit 2"), "{e}");
+
+        let mut c = Circuit::new(2);
+        c.add_gate("cz", vec![1, 1]);
+        assert!(c.validate().is_err());
+
+        let c = Circuit::from_gates([Gate::new(HAD, vec![0]), Gate::new(CNOT, vec![0, 3])]);
+        assert_eq!(c.num_qubits(), 4);
+        assert_eq!(c.num_gates(), 2);
+        assert_eq!(c.validate(), Ok(()));
+        assert_eq!(Circuit::from_gates([]).num_qubits(), 0);
+    }
+
+    fn assert_close(t0: &Tensor64, t1: &Tensor64) {
+        assert_eq!(t0.dim(), t1.dim());
+        for (a, b) in t0.iter().zip(t1.iter()) {
+            assert!((a - b).norm() < 1e-8);
+        }
+    }
+
+    /// The tensor of `c` controlled on one qubit, from the tensor of `c`
+    fn controlled_tensor(c: &Circuit) -> Tensor64 {
+        let n = c.num_qubits();
+        let t = c.to_tensor64();
+        Tensor64::from_shape_fn(vec![2; 2 * n + 2], |ix| {
+            let (i, o) = (ix[0], ix[n + 1]);
+            let ix1: Vec<usize> = (1..=n).chain(n + 2..2 * n + 2).map(|j| ix[j]).collect();
+            if i != o {
+                num::Complex::new(0.0, 0.0)
+            } else if i == 1 {
+                t[&ix1[..]]
+            } else if (0..n).all(|j| ix1[j] == ix1[n + j]) {
+                num::Complex::new(1.0, 0.0)
+            } else {
+                num::Complex::new(0.0, 0.0)
+            }
+        })
+    }
+
+    #[test]
+    fn controlled() {
+        let mut c = Circuit::new(3);
+        for (name, qs) in [
+            ("h", vec![0]),
+            ("t", vec![1]),
+            ("cx", vec![0, 2]),
+            ("s", vec![2]),
+            ("x", vec![1]),
+            ("cz", vec![1, 2]),
+            ("swap", vec![0, 1]),
+            ("ccx", vec![2, 1, 0]),
+            ("sdg", vec![0]),
+            ("ccz", vec![0, 1, 2]),
+            ("z", vec![1]),
+            ("tdg", vec![2]),
+        ] {
+            c.add_gate(name, qs);
+        }
+        c.add_gate_with_phase("rz", vec![0], Rational64::new(1, 3));
+        c.add_gate_with_phase("rx", vec![2], Rational64::new(-2, 5));
+        c.push(Gate::barrier(vec![0, 1, 2]));
+        c.push(Gate::new_with_phase(
+            ParityPhase,
+            vec![0, 1, 2],
+            Rational64::new(1, 4),
+        ));
+
+        let cc = c.controlled(1);
+        assert_eq!(cc.num_qubits(), 4);
+        assert_eq!(cc.validate(), Ok(()));
+        assert_close(&cc.to_tensor64(), &controlled_tensor(&c.to_basic_gates()));
+
+        // controlling twice is the same as controlling on two qubits
+        let mut c1 = Circuit::new(1);
+        c1.add_gate("h", vec![0]);
+        c1.add_gate("t", vec![0]);
+        let cc = c1.controlled(2);
+        assert_eq!(cc.num_qubits(), 3);
+        assert_close(&cc.to_tensor64(), &controlled_tensor(&c1.controlled(1)));
+
+        assert_close(
+            &c.controlled(0).to_tensor64(),
+            &c.to_basic_gates().to_tensor64(),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot control gate")]
+    fn controlled_measurement() {
+        let mut c = Circuit::new(1);
+        c.push(Gate::measure_z(0, true));
+        c.controlled(1);
+    }
+
     #[test]
     fn mk_circuit() {
         let mut c = Circuit::new(3);
@@ -649,6 +1391,31 @@ mod tests {
         assert_eq!(c.to_tensorf(), g.to_tensorf());
     }
 
+    #[test]
+    fn tograph_fanout() {
+        let mut c = Circuit::new(4);
+        c.push(Gate::new(HAD, vec![1]));
+        c.push(Gate::new(T, vec![3]));
+        c.push(Gate::fanout(1, &[3, 0, 2]));
+        c.push(Gate::new(S, vec![0]));
+        let g: Graph = c.to_graph();
+
+        // one Z-spider and three X-spiders, instead of three of each
+        assert_eq!(g.num_vertices(), 8 + 2 + 4 + 1);
+        assert_eq!(c.to_basic_gates().num_gates_of_type(CNOT), 3);
+        assert_eq!(g.to_tensorf(), c.to_basic_gates().to_tensorf());
+        assert_eq!(c.to_tensorf(), c.to_basic_gates().to_tensorf());
+
+        let s = serde_json::to_string(&c).unwrap();
+        assert!(s.contains(r#"{"gate":"fanout","controls":[1],"targets":[3,0,2]}"#));
+        let c1: Circuit = serde_json::from_str(&s).unwrap();
+        assert_eq!(c, c1);
+        assert_close(
+            &c.controlled(1).to_tensor64(),
+            &controlled_tensor(&c.to_basic_gates()),
+        );
+    }
+
     #[test]
     fn tograph_more() {
         let c = Circuit::from_qasm(
@@ -701,6 +1468,441 @@ mod tests {
         assert_eq!(c.to_tensorf(), g.to_tensorf());
     }
 
+    #[test]
+    fn t_depth() {
+        let depth = |qasm: &str| {
+            Circuit::from_qasm(&format!("qreg q[3];\n{qasm}"))
+                .unwrap()
+                .t_depth()
+        };
+        assert_eq!(depth(""), 0);
+        assert_eq!(depth("h q[0]; s q[1]; cx q[0], q[2]; rz(pi/2) q[1];"), 0);
+        // T gates on disjoint qubits are parallel
+        assert_eq!(depth("t q[0]; tdg q[1]; t q[2];"), 1);
+        assert_eq!(depth("t q[0]; h q[0]; t q[0]; t q[1];"), 2);
+        // a CNOT makes its target wait for T gates on its control
+        assert_eq!(depth("t q[0]; cx q[0], q[1]; t q[1]; t q[2];"), 2);
+        assert_eq!(depth("t q[0]; cx q[1], q[2]; t q[1]; t q[2];"), 1);
+        assert_eq!(depth("rz(3*pi/4) q[0]; rx(pi/4) q[0]; rz(pi/8) q[0];"), 2);
+
+        let mut c = Circuit::new(3);
+        c.add_gate("ccz", vec![0, 1, 2]);
+        assert_eq!(c.t_depth(), c.to_basic_gates().t_depth());
+        assert!(c.t_depth() > 0);
+    }
+
+    #[test]
+    fn depth_by_qubit() {
+        let c = Circuit::from_qasm(
+            r#"
+            qreg q[4];
+            h q[0];
+            t q[0];
+            cx q[0], q[1];
+            h q[0];
+            s q[0];
+            barrier q[0], q[2];
+            ccz q[0], q[1], q[2];
+            x q[1];
+        "#,
+        )
+        .unwrap();
+        assert_eq!(c.depth_by_qubit(), vec![6, 3, 1, 0]);
+        // h, t, cx, h, s, ccz, x on q[1]
+        assert_eq!(c.critical_path(), 7);
+
+        // gates on disjoint qubits don't add up
+        let c = Circuit::from_qasm("qreg q[3]; h q[0]; h q[1]; h q[2]; cx q[1], q[2];").unwrap();
+        assert_eq!(c.depth_by_qubit(), vec![1, 2, 2]);
+        assert_eq!(c.critical_path(), 2);
+
+        // a barrier synchronizes the qubits it spans
+        let c =
+            Circuit::from_qasm("qreg q[2]; h q[0]; h q[0]; barrier q[0], q[1]; x q[1];").unwrap();
+        assert_eq!(c.critical_path(), 3);
+        assert_eq!(Circuit::new(2).critical_path(), 0);
+    }
+
+    #[test]
+    fn save_artifacts() {
+        let c = Circuit::random()
+            .seed(1337)
+            .qubits(4)
+            .depth(40)
+            .clifford_t(0.3)
+            .build();
+        let dir = std::env::temp_dir().join(format!("quizx-artifacts-{}", std::process::id()));
+        c.save_artifacts(dir.to_str().unwrap()).unwrap();
+
+        let c1 = Circuit::from_file(dir.join("circuit.qasm").to_str().unwrap()).unwrap();
+        assert_eq!(c1.gates, c.gates);
+        let g: Graph = crate::json::read_graph(&dir.join("graph.qgraph")).unwrap();
+        let h: Graph = crate::json::read_graph(&dir.join("simplified.qgraph")).unwrap();
+        assert_eq!(g.tcount(), c.to_graph::<Graph>().tcount());
+        assert!(h.tcount() <= g.tcount());
+
+        let metadata: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(dir.join("metadata.json")).unwrap())
+                .unwrap();
+        assert_eq!(metadata["qubits"], 4);
+        assert_eq!(metadata["gates"], 40);
+        assert_eq!(metadata["tcount"], g.tcount());
+        assert_eq!(metadata["reduced_tcount"], h.tcount());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// Checks the 1-qubit circuit or graph `c` is the textbook U3(θ, φ, λ) up to a global phase,
+    /// with angles in half turns
+    fn assert_u3(c: &impl ToTensor, theta: f64, phi: f64, lambda: f64, eps: f64) {
+        use num::complex::Complex64;
+        use std::f64::consts::PI;
+        let (theta, phi, lambda) = (theta * PI, phi * PI, lambda * PI);
+        let (cos, sin) = ((theta / 2.0).cos(), (theta / 2.0).sin());
+        let u = [
+            [Complex64::from(cos), -Complex64::cis(lambda) * sin],
+            [
+                Complex64::cis(phi) * sin,
+                Complex64::cis(phi + lambda) * cos,
+            ],
+        ];
+
+        // the tensor is indexed by (input, output)
+        let t = c.to_tensor64();
+        let global = t[[0, 0]] / u[0][0];
+        assert!((global.norm() - 1.0).abs() < eps);
+        for (i, j) in [(0, 0), (0, 1), (1, 0), (1, 1)] {
+            assert!((t[[i, j]] - global * u[j][i]).norm() < eps);
+        }
+    }
+
+    #[test]
+    fn u3() {
+        let (theta, phi, lambda) = (
+            Rational64::new(1, 3),
+            Rational64::new(2, 5),
+            Rational64::new(-3, 7),
+        );
+        let mut c = Circuit::new(1);
+        c.gates.extend(Gate::u3(0, theta, phi, lambda));
+        assert_eq!(c.num_gates(), 3);
+        let f = |r: Rational64| *r.numer() as f64 / *r.denom() as f64;
+        assert_u3(&c, f(theta), f(phi), f(lambda), 1e-10);
+
+        // the graph has three spiders
+        let g: Graph = c.to_graph();
+        assert_eq!(g.num_vertices(), 5);
+        assert_u3(&g, f(theta), f(phi), f(lambda), 1e-10);
+
+        let c = Circuit::from_qasm(
+            r#"
+            OPENQASM 2.0;
+            include "qelib1.inc";
+            qreg q[1];
+            u3(0.3, 0.2*pi, -0.1) q[0];
+        "#,
+        )
+        .unwrap();
+        use std::f64::consts::PI;
+        assert_u3(&c, 0.3 / PI, 0.2, -0.1 / PI, 1e-6);
+
+        for (gate, theta, phi, lambda) in [
+            ("U(0.7*pi, 0.5, pi/3)", 0.7, 0.5 / PI, 1.0 / 3.0),
+            ("u2(pi/4, 0.25*pi)", 0.5, 0.25, 0.25),
+            ("u1(pi/8)", 0.0, 0.0, 0.125),
+        ] {
+            let qasm =
+                format!("OPENQASM 2.0;\ninclude \"qelib1.inc\";\nqreg q[1];\n{gate} q[0];\n");
+            let c = Circuit::from_qasm(&qasm).unwrap();
+            assert_u3(&c, theta, phi, lambda, 1e-6);
+        }
+    }
+
+    #[test]
+    fn qasm_standard_gates() {
+        let c = Circuit::from_qasm(
+            r#"
+            OPENQASM 2.0;
+            include "qelib1.inc";
+            qreg q[3];
+            sx q[0];
+            sxdg q[1];
+            rzz(0.25*pi) q[0], q[1];
+            id q[2];
+        "#,
+        )
+        .unwrap();
+        assert_eq!(
+            Vec::from(c.gates),
+            vec![
+                Gate::new_with_phase(XPhase, vec![0], Rational64::new(1, 2)),
+                Gate::new_with_phase(XPhase, vec![1], Rational64::new(-1, 2)),
+                Gate::new_with_phase(ParityPhase, vec![0, 1], Rational64::new(1, 4)),
+            ]
+        );
+
+        // each gate against its definition in qelib1.inc, up to global phase
+        for (gate, qubits, expected) in [
+            ("y q[0];", 1, "u3(pi, pi/2, pi/2) q[0];"),
+            ("ry(0.3) q[0];", 1, "u3(0.3, 0, 0) q[0];"),
+            ("p(0.3) q[0];", 1, "u1(0.3) q[0];"),
+            ("sx q[0];", 1, "sdg q[0]; h q[0]; sdg q[0];"),
+            ("sxdg q[0];", 1, "s q[0]; h q[0]; s q[0];"),
+            (
+                "rzz(0.3) q[0], q[1];",
+                2,
+                "cx q[0], q[1]; u1(0.3) q[1]; cx q[0], q[1];",
+            ),
+            (
+                "rxx(0.3) q[0], q[1];",
+                2,
+                "h q[0]; h q[1]; cx q[0], q[1]; rz(0.3) q[1]; cx q[0], q[1]; h q[0]; h q[1];",
+            ),
+            ("cy q[0], q[1];", 2, "sdg q[1]; cx q[0], q[1]; s q[1];"),
+            (
+                "ch q[0], q[1];",
+                2,
+                "h q[1]; sdg q[1]; cx q[0], q[1]; h q[1]; t q[1]; cx q[0], q[1]; \
+                 t q[1]; h q[1]; s q[1]; x q[1]; s q[0];",
+            ),
+            (
+                "crz(0.3) q[0], q[1];",
+                2,
+                "u1(0.15) q[1]; cx q[0], q[1]; u1(-0.15) q[1]; cx q[0], q[1];",
+            ),
+            (
+                "cu1(0.3) q[0], q[1];",
+                2,
+                "u1(0.15) q[0]; cx q[0], q[1]; u1(-0.15) q[1]; cx q[0], q[1]; u1(0.15) q[1];",
+            ),
+            (
+                "cp(0.3) q[0], q[1];",
+                2,
+                "u1(0.15) q[0]; cx q[0], q[1]; u1(-0.15) q[1]; cx q[0], q[1]; u1(0.15) q[1];",
+            ),
+            (
+                "cswap q[0], q[1], q[2];",
+                3,
+                "cx q[2], q[1]; ccx q[0], q[1], q[2]; cx q[2], q[1];",
+            ),
+            // half of the angle depends on more than the angle mod 2π
+            (
+                "crz(5) q[0], q[1];",
+                2,
+                "u1(2.5) q[1]; cx q[0], q[1]; u1(-2.5) q[1]; cx q[0], q[1];",
+            ),
+            (
+                "csx q[0], q[1];",
+                2,
+                "h q[1]; cu1(pi/2) q[0], q[1]; h q[1];",
+            ),
+            (
+                "cu3(5, 0.2, 0.3) q[0], q[1];",
+                2,
+                "u1(0.25) q[0]; u1(0.05) q[1]; cx q[0], q[1]; u3(-2.5, 0, -0.25) q[1]; \
+                 cx q[0], q[1]; u3(2.5, 0.2, 0) q[1];",
+            ),
+            (
+                "ryy(0.3) q[0], q[1];",
+                2,
+                "rx(pi/2) q[0]; rx(pi/2) q[1]; cx q[0], q[1]; rz(0.3) q[1]; cx q[0], q[1]; \
+                 rx(-pi/2) q[0]; rx(-pi/2) q[1];",
+            ),
+            (
+                "rzx(0.3) q[0], q[1];",
+                2,
+                "h q[1]; cx q[0], q[1]; u1(0.3) q[1]; cx q[0], q[1]; h q[1];",
+            ),
+            (
+                "ecr q[0], q[1];",
+                2,
+                "h q[1]; cx q[0], q[1]; u1(pi/4) q[1]; cx q[0], q[1]; h q[1]; x q[0]; \
+                 h q[1]; cx q[0], q[1]; u1(-pi/4) q[1]; cx q[0], q[1]; h q[1];",
+            ),
+            (
+                "rccx q[0], q[1], q[2];",
+                3,
+                "u2(0, pi) q[2]; u1(pi/4) q[2]; cx q[1], q[2]; u1(-pi/4) q[2]; cx q[0], q[2]; \
+                 u1(pi/4) q[2]; cx q[1], q[2]; u1(-pi/4) q[2]; u2(0, pi) q[2];",
+            ),
+            (
+                "c3x q[0], q[1], q[2], q[3];",
+                4,
+                "h q[3]; p(pi/8) q[0]; p(pi/8) q[1]; p(pi/8) q[2]; p(pi/8) q[3]; \
+                 cx q[0], q[1]; p(-pi/8) q[1]; cx q[0], q[1]; cx q[1], q[2]; \
+                 p(-pi/8) q[2]; cx q[0], q[2]; p(pi/8) q[2]; cx q[1], q[2]; \
+                 p(-pi/8) q[2]; cx q[0], q[2]; cx q[2], q[3]; p(-pi/8) q[3]; \
+                 cx q[1], q[3]; p(pi/8) q[3]; cx q[2], q[3]; p(-pi/8) q[3]; \
+                 cx q[0], q[3]; p(pi/8) q[3]; cx q[2], q[3]; p(-pi/8) q[3]; \
+                 cx q[1], q[3]; p(pi/8) q[3]; cx q[2], q[3]; p(-pi/8) q[3]; \
+                 cx q[0], q[3]; h q[3];",
+            ),
+        ] {
+            let qasm = |body: &str| {
+                format!("OPENQASM 2.0;\ninclude \"qelib1.inc\";\nqreg q[{qubits}];\n{body}\n")
+            };
+            let c = Circuit::from_qasm(&qasm(gate)).unwrap();
+            let c1 = Circuit::from_qasm(&qasm(expected)).unwrap();
+            let (t, t1) = (c.to_basic_gates().to_tensor64(), c1.to_tensor64());
+            let k = t.iter().position(|x| x.norm() > 1e-6).unwrap();
+            let phase = t1.iter().nth(k).unwrap() / t.iter().nth(k).unwrap();
+            assert!((phase.norm() - 1.0).abs() < 1e-6, "{gate}");
+            for (a, b) in t.iter().zip(t1.iter()) {
+                assert!((a * phase - b).norm() < 1e-6, "{gate}");
+            }
+        }
+    }
+
+    #[test]
+    fn qasm_unsupported_gate() {
+        let e = Circuit::from_qasm(
+            r#"
+            OPENQASM 2.0;
+            qreg q[1];
+            opaque magic a;
+            magic q[0];
+        "#,
+        )
+        .unwrap_err();
+        assert_eq!(e, CircuitError::UnsupportedGate("magic".to_string()));
+        assert_eq!(e.to_string(), "unsupported gate: magic");
+
+        let e = Circuit::from_qasm("qreg q[1]; magic q[0];").unwrap_err();
+        assert!(matches!(e, CircuitError::Parse(_)), "{e}");
+    }
+
+    #[test]
+    fn gate_predicates() {
+        let g = Gate::new(TOFF, vec![2, 0, 1]);
+        assert_eq!(g.qubits(), vec![2, 0, 1]);
+        assert!(!g.is_two_qubit() && !g.is_clifford() && !g.is_t());
+
+        let g = Gate::new(CZ, vec![1, 0]);
+        assert!(g.is_two_qubit() && g.is_clifford() && !g.is_t());
+        assert!(!Gate::barrier(vec![0, 1]).is_two_qubit());
+        assert!(!Gate::barrier(vec![0, 1]).is_clifford());
+
+        for (t, phase, clifford, t_like) in [
+            (T, 0, false, true),
+            (Tdg, 0, false, true),
+            (S, 0, true, false),
+            (ZPhase, 4, true, false),
+            (XPhase, 6, false, true),
+            (ZPhase, 1, false, false),
+            (ParityPhase, 12, true, false),
+            (ParityPhase, 2, false, true),
+            (XCX, 0, true, false),
+            (XCX, 4, true, false),
+        ] {
+            let g = Gate::new_with_phase(t, vec![0, 1], Rational64::new(phase, 8));
+            assert_eq!(g.is_clifford(), clifford, "{t:?}({phase}/8)");
+            assert_eq!(g.is_t(), t_like, "{t:?}({phase}/8)");
+        }
+    }
+
+    #[test]
+    fn strip_identities() {
+        let mut c = Circuit::from_qasm(
+            r#"
+            OPENQASM 2.0;
+            include "qelib1.inc";
+            qreg q[2];
+            rz(0) q[0];
+            h q[0];
+            id q[1];
+            rx(0) q[1];
+            cx q[0], q[1];
+            rz(2*pi) q[1];
+            rzz(0) q[0], q[1];
+            rz(0.000000001) q[0];
+            rx(0.25*pi) q[1];
+            rz(-0.000000001) q[1];
+            barrier q[0], q[1];
+            t q[0];
+        "#,
+        )
+        .unwrap();
+        let c0 = c.clone();
+
+        c.strip_identities(0.0);
+        assert_eq!(c.num_gates(), 7);
+        assert_close(
+            &c.to_basic_gates().to_tensor64(),
+            &c0.to_basic_gates().to_tensor64(),
+        );
+        c.strip_identities(1e-6);
+        let names: Vec<&str> = c.gates.iter().map(|g| g.qasm_name()).collect();
+        assert_eq!(names, vec!["h", "cx", "rx", "barrier", "t"]);
+
+        // xcx has no phase of its own, so it is never the identity
+        let mut c = Circuit::new(2);
+        c.add_gate("xcx", vec![0, 1]);
+        c.strip_identities(0.0);
+        assert_eq!(c.num_gates(), 1);
+    }
+
+    #[test]
+    fn barrier() {
+        let c = Circuit::from_qasm(
+            r#"
+            OPENQASM 2.0;
+            include "qelib1.inc";
+            qreg q[2];
+            rz(0.25*pi) q[0];
+            barrier q[0], q[1];
+            rz(0.5*pi) q[0];
+            cx q[0], q[1];
+        "#,
+        )
+        .unwrap();
+        assert_eq!(c.num_gates(), 4);
+        assert_eq!(c.gates[1], Gate::barrier(vec![0, 1]));
+        assert_eq!(c.stats().total, 3);
+
+        let qasm = c.to_qasm();
+        assert!(qasm.contains("barrier q[0], q[1];"));
+        assert_eq!(Circuit::from_qasm(&qasm).unwrap().gates, c.gates);
+
+        // a barrier is the identity
+        let mut c1 = c.clone();
+        c1.gates.remove(1);
+        assert_eq!(c.to_tensorf(), c1.to_tensorf());
+        let g: Graph = c.to_graph();
+        let g1: Graph = c1.to_graph();
+        assert_eq!(g.to_tensorf(), g1.to_tensorf());
+    }
+
+    #[test]
+    fn tograph_measure_z() {
+        use crate::decompose::Decomposer;
+
+        let mut total = 0.0;
+        for outcome in [false, true] {
+            let mut c = Circuit::new(2);
+            c.add_gate("h", vec![0]);
+            c.add_gate("cx", vec![0, 1]);
+            c.push(Gate::measure_z(0, outcome));
+
+            let g: Graph = c.to_graph();
+            assert_eq!(c.to_tensorf(), g.to_tensorf());
+
+            // amplitude of the recorded outcome on qubit 0 and the correlated one on qubit 1
+            let b = if outcome {
+                BasisElem::Z1
+            } else {
+                BasisElem::Z0
+            };
+            let mut h = g.clone();
+            h.plug_inputs(&[BasisElem::Z0, BasisElem::Z0]);
+            h.plug_outputs(&[b, b]);
+            let mut d = Decomposer::new(&h);
+            d.with_full_simp().decompose();
+            let p = d.scalar().complex_value().norm_sqr();
+            assert!((p - 0.5).abs() < 1e-8);
+            total += p;
+        }
+        assert!((total - 1.0).abs() < 1e-8);
+    }
+
     #[test]
     fn tograph_simplify() {
         let c = Circuit::random()
@@ -712,4 +1914,194 @@ mod tests {
         let g2 = c.to_graph_with_options::<Graph>(true, false);
         assert_eq!(g1.to_tensorf(), g2.to_tensorf());
     }
+
+    #[test]
+    fn json_roundtrip() {
+        let mut c = Circuit::random()
+            .seed(1337)
+            .qubits(5)
+            .depth(40)
+            .clifford_t(0.2)
+            .build();
+        c.add_gate_with_phase("rz", vec![2], Rational64::new(3, 7));
+        c.add_gate("ccz", vec![0, 3, 4]);
+        c.push(Gate::new_with_phase_and_vars(
+            Measure,
+            vec![1],
+            0,
+            vec![2, 5],
+        ));
+        c.push(Gate::measure_z(4, true));
+
+        let s = serde_json::to_string(&c).unwrap();
+        let c1: Circuit = serde_json::from_str(&s).unwrap();
+        assert_eq!(c, c1);
+    }
+
+    #[test]
+    fn json_gate_format() {
+        let g: Gate =
+            serde_json::from_str(r#"{"gate": "cx", "controls": [0], "targets": [1]}"#).unwrap();
+        assert_eq!(g, Gate::new(CNOT, vec![0, 1]));
+
+        let g = Gate::new_with_phase(ZPhase, vec![3], Rational64::new(1, 4));
+        assert_eq!(
+            serde_json::to_string(&g).unwrap(),
+            r#"{"gate":"rz","targets":[3],"phase":"pi/4"}"#
+        );
+
+        // angles that are not exact fractions are read as floats
+        let g: Gate =
+            serde_json::from_str(r#"{"gate": "rx", "targets": [0], "phase": "0.5"}"#).unwrap();
+        assert_eq!(g.phase, Rational64::new(1, 2).into());
+
+        assert!(
+            serde_json::from_str::<Gate>(r#"{"gate": "cx", "targets": [0, 1]}"#).is_err(),
+            "controls of a cx should be given explicitly"
+        );
+        assert!(serde_json::from_str::<Gate>(r#"{"gate": "foo", "targets": [0]}"#).is_err());
+        assert!(serde_json::from_str::<Circuit>(
+            r#"{"qubits": 1, "gates": [{"gate": "h", "targets": [1]}]}"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn slice_circuit() {
+        let c = Circuit::random()
+            .qubits(3)
+            .depth(15)
+            .with_cliffords()
+            .build();
+        let n = c.num_gates();
+
+        let s = c.slice(3, 8);
+        assert_eq!(s.num_qubits(), 3);
+        assert_eq!(s.num_gates(), 5);
+        assert!(s.gates.iter().eq(c.gates.iter().skip(3).take(5)));
+
+        assert_eq!(c.slice(0, 0).num_gates(), 0);
+        assert_eq!(c.slice(n, n).num_gates(), 0);
+        assert_eq!(c.slice(0, n), c);
+        assert_eq!(c.slice(0, 7) + c.slice(7, n), c);
+        assert_eq!((c.slice(0, 7) + c.slice(7, n)).to_tensorf(), c.to_tensorf());
+    }
+
+    #[test]
+    fn light_cone() {
+        let c = Circuit::from_qasm(
+            r#"
+            qreg q[4];
+            h q[0];
+            h q[2];
+            cx q[2], q[3];
+            cx q[0], q[1];
+            t q[3];
+            cx q[1], q[2];
+            h q[0];
+        "#,
+        )
+        .unwrap();
+        let l = c.light_cone(1);
+        assert_eq!(l.num_qubits(), 4);
+        let qasm = "qreg q[4]; h q[0]; h q[2]; cx q[2], q[3]; cx q[0], q[1]; cx q[1], q[2];";
+        assert_eq!(l, Circuit::from_qasm(qasm).unwrap());
+        assert_eq!(c.light_cone(2), l);
+        // t q[3] and the last h q[0] are dropped
+        let qasm = "qreg q[4]; h q[0]; cx q[0], q[1]; h q[0];";
+        assert_eq!(c.light_cone(0), Circuit::from_qasm(qasm).unwrap());
+        let qasm = "qreg q[4]; h q[2]; cx q[2], q[3]; t q[3];";
+        assert_eq!(c.light_cone(3), Circuit::from_qasm(qasm).unwrap());
+
+        // the probability of measuring 0 on qubit q
+        let marginal = |c: &Circuit, q: usize| {
+            let mut g: Graph = c.to_graph();
+            g.plug_inputs(&vec![BasisElem::Z0; c.num_qubits()]);
+            g.plug_output(q, BasisElem::Z0);
+            g.to_tensor64().iter().map(|a| a.norm_sqr()).sum::<f64>()
+        };
+        for seed in 0..5 {
+            let c = Circuit::random()
+                .seed(seed)
+                .qubits(6)
+                .depth(12)
+                .clifford_t(0.3)
+                .build();
+            for q in 0..6 {
+                let l = c.light_cone(q);
+                assert!(l.num_gates() <= c.num_gates());
+                assert!((marginal(&c, q) - marginal(&l, q)).abs() < 1e-10);
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn slice_out_of_range() {
+        let mut c = Circuit::new(2);
+        c.add_gate("cx", vec![0, 1]);
+        c.slice(0, 2);
+    }
+
+    #[test]
+    fn clifford_difference() {
+        for seed in 0..5 {
+            let c = Circuit::random()
+                .seed(seed)
+                .qubits(4)
+                .depth(30)
+                .clifford_t(0.3)
+                .build();
+            let d = Circuit::random()
+                .seed(seed + 100)
+                .qubits(4)
+                .depth(20)
+                .clifford_t(0.0)
+                .build();
+            let c1 = &c + &d;
+            let d1 = c1.clifford_difference(&c).unwrap();
+            assert!(d1.gates.iter().all(|g| g.is_clifford()));
+            assert!(TensorF::scalar_compare(&c1, &(&c + &d1)));
+
+            let mut c2 = c1.clone();
+            c2.push(Gate::new(T, vec![seed as usize % 4]));
+            assert!(c2.clifford_difference(&c).is_none());
+        }
+
+        let c = Circuit::from_qasm("qreg q[2]; t q[0]; cx q[0], q[1];").unwrap();
+        assert!(c.clifford_difference(&Circuit::new(3)).is_none());
+        let c1 = Circuit::from_qasm("qreg q[2]; h q[1]; t q[0]; cz q[0], q[1]; h q[1];").unwrap();
+        let d = c1.clifford_difference(&c).unwrap();
+        assert!(TensorF::scalar_compare(&c1, &(&c + &d)));
+    }
+
+    #[test]
+    fn rz_pi_4_multiples() {
+        let c = Circuit::from_qasm("qreg q[1]; rz(3*pi/4) q[0];").unwrap();
+        let g: Graph = c.to_graph();
+        let spiders: Vec<_> = g
+            .vertices()
+            .filter(|&v| g.vertex_type(v) == VType::Z)
+            .collect();
+        assert_eq!(spiders.len(), 1);
+        assert_eq!(g.phase(spiders[0]), Rational64::new(3, 4).into());
+        assert_eq!(g.tcount(), 1);
+
+        // phases are taken mod 2π, and other angles keep their approximate value
+        let c = Circuit::from_qasm("qreg q[1]; rz(-5*pi/4) q[0]; rz(0.3) q[0];").unwrap();
+        let g: Graph = c.to_graph();
+        let phases: Vec<_> = g
+            .vertices()
+            .filter(|&v| g.vertex_type(v) == VType::Z)
+            .map(|v| g.phase(v))
+            .collect();
+        assert_eq!(phases.len(), 2);
+        assert_eq!(phases[0], Rational64::new(3, 4).into());
+        assert!((phases[1].to_f64() - 0.3 / std::f64::consts::PI).abs() < 1e-6);
+        assert_eq!(g.tcount(), 2);
+
+        // as do angles given in radians
+        let c = Circuit::from_qasm("qreg q[1]; rz(2.356194490192345) q[0];").unwrap();
+        assert_eq!(c.gates[0].phase, Rational64::new(3, 4).into());
+    }
 }
@@ -1,9 +1,11 @@
 //! The QuiZX command line interface.
 
 use clap::{crate_version, Parser};
+use std::process::ExitCode;
 
 pub mod opt;
 pub mod sim;
+pub mod verify;
 
 /// CLI arguments.
 #[derive(Parser, Debug)]
@@ -14,6 +16,8 @@ pub enum Cli {
     Opt(opt::OptArgs),
     /// Run the circuit simulator.
     Sim(sim::SimArgs),
+    /// Check whether two circuits are equal up to global phase.
+    Verify(verify::VerifyArgs),
 }
 
 /// Error type for the CLI.
@@ -24,17 +28,19 @@ pub enum CliError {
     IO(std::io::Error),
     /// Error parsing a QASM file.
     #[display("Error parsing input circuit: {_0}")]
-    CircuitParse(String),
+    CircuitParse(crate::circuit::CircuitError),
     /// Provided bit/Pauli string has the wrong length
     #[display("Circuit has {_0} qubits, but the provided {_2} string has length {_1}")]
     StringWrongLen(usize, usize, String),
 }
 
 impl Cli {
-    pub fn run(self) -> Result<(), CliError> {
+    /// Runs the subcommand, returning the exit code on success.
+    pub fn run(self) -> Result<ExitCode, CliError> {
         match self {
-            Cli::Opt(args) => args.run(),
-            Cli::Sim(args) => args.run(),
+            Cli::Opt(args) => args.run().map(|()| ExitCode::SUCCESS),
+            Cli::Sim(args) => args.run().map(|()| ExitCode::SUCCESS),
+            Cli::Verify(args) => args.run(),
         }
     }
 }
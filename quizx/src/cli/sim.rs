@@ -10,9 +10,10 @@ use std::fs;
 use std::path::PathBuf;
 
 use crate::circuit::Circuit;
-use crate::decompose::{Decomposer, Driver};
+use crate::decompose::{Decomposer, Driver, SpiderSelection};
 use crate::fscalar::FScalar;
 use crate::graph::{BasisElem, GraphLike, VType};
+use crate::scalar_traits::Sqrt2;
 use crate::simplify;
 use crate::vec_graph::Graph;
 
@@ -36,6 +37,10 @@ pub struct SimArgs {
     #[command(flatten)]
     task: Option<SimTask>,
 
+    /// Switch to select how amplitudes and expectation values are printed.
+    #[command(flatten)]
+    format: OutputFormat,
+
     /// Distribute computation across available CPU cores up to the given depth.
     #[arg(long, short)]
     parallel: Option<usize>,
@@ -46,10 +51,10 @@ impl SimArgs {
     pub fn run(self) -> Result<(), CliError> {
         let circ = Circuit::from_file(self.input.to_str().unwrap())?;
         let mut d = self.method.unwrap_or_default().build_decomposer();
-        let result = self
-            .task
-            .unwrap_or_default()
-            .run(&circ, &mut d, self.parallel)?;
+        let result =
+            self.task
+                .unwrap_or_default()
+                .run(&circ, &mut d, self.parallel, &self.format)?;
 
         if let Some(out_path) = self.out {
             fs::write(out_path, result)?;
@@ -88,9 +93,9 @@ impl Default for SimMethod {
 impl SimMethod {
     fn build_decomposer(&self) -> Decomposer<Graph> {
         let driver: Driver = if self.cats {
-            Driver::BssWithCats(true)
+            Driver::BssWithCats(SpiderSelection::Random(None))
         } else {
-            Driver::BssTOnly(true)
+            Driver::BssTOnly(SpiderSelection::Random(None))
         };
         let mut decomposer = Decomposer::empty();
         decomposer.with_full_simp().with_driver(driver);
@@ -134,6 +139,7 @@ impl SimTask {
         circ: &Circuit,
         decomposer: &mut Decomposer<Graph>,
         parallel: Option<usize>,
+        format: &OutputFormat,
     ) -> Result<String, CliError> {
         if let Some(shots) = self.shots {
             Ok((0..shots)
@@ -141,19 +147,76 @@ impl SimTask {
                 .join("\n")
                 .to_string())
         } else if let Some(ref bit_str) = self.bit_string {
-            Ok(format!("{}", amplitude(circ, decomposer, bit_str, parallel)?).to_string())
+            Ok(format.format(amplitude(circ, decomposer, bit_str, parallel)?))
         } else if let Some(ref pauli_str) = self.pauli_string {
-            Ok(format!(
-                "{}",
-                expectation_value(circ, decomposer, pauli_str, parallel)?
-            )
-            .to_string())
+            Ok(format.format(expectation_value(circ, decomposer, pauli_str, parallel)?))
         } else {
             unreachable!()
         }
     }
 }
 
+/// Output formats for real-valued results.
+#[derive(Args, Debug, Default)]
+#[group(multiple = false)]
+pub struct OutputFormat {
+    /// Round results to the given number of significant figures.
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    digits: Option<u32>,
+
+    /// Print results exactly, as a power of sqrt(2) times a phase where possible.
+    #[arg(long)]
+    exact: bool,
+}
+
+impl OutputFormat {
+    /// By default, results of the form sqrt(2)^p exp(i k pi/4) are printed exactly,
+    /// and all others are rounded to 6 significant figures.
+    fn format(&self, s: FScalar) -> String {
+        if let Some(digits) = self.digits {
+            return round_sig(s.complex_value().re, digits);
+        }
+        match exact_form(s) {
+            Some(e) => e.to_exact_string(),
+            None if self.exact => s.to_exact_string(),
+            None => round_sig(s.complex_value().re, 6),
+        }
+    }
+}
+
+/// Returns `s` as an exact power of sqrt(2) times a phase, if it is one up to
+/// floating point error.
+fn exact_form(s: FScalar) -> Option<FScalar> {
+    if s.exact_phase_and_sqrt2_pow().is_some() {
+        return Some(s);
+    }
+    let z = s.complex_value();
+    let x = z.re;
+    if x == 0.0 || z.im.abs() > 1e-9 * x.abs() {
+        return None;
+    }
+    let mut e = FScalar::sqrt2_pow((2.0 * x.abs().log2()).round() as i32);
+    if x < 0.0 {
+        e.mul_phase(1);
+    }
+    if (e.complex_value().re - x).abs() <= 1e-9 * x.abs() {
+        Some(e)
+    } else {
+        None
+    }
+}
+
+/// Rounds to the given number of significant figures, printing "0" rather than
+/// "-0" for values that round to zero.
+fn round_sig(x: f64, digits: u32) -> String {
+    let r: f64 = format!("{:.*e}", digits as usize - 1, x).parse().unwrap();
+    if r == 0.0 {
+        "0".to_string()
+    } else {
+        r.to_string()
+    }
+}
+
 // Need to wrap the vector into a type alias, otherwise clap tries to do some
 // varag parsing magic that breaks our custom parser below.
 type BitString = Vec<bool>;
@@ -231,7 +294,7 @@ fn amplitude(
     decomposer: &mut Decomposer<Graph>,
     bit_str: &BitString,
     parallel: Option<usize>,
-) -> Result<f64, CliError> {
+) -> Result<FScalar, CliError> {
     let qs = circ.num_qubits();
     let bit_str = match bit_str.as_slice() {
         [b] => &vec![*b; qs],
@@ -255,8 +318,7 @@ fn amplitude(
     );
 
     let scalar = decomp_graph(g, decomposer, parallel);
-    let amp = scalar * scalar.conj();
-    Ok(amp.complex_value().re)
+    Ok(scalar * scalar.conj())
 }
 
 /// Computes an expectation value by doubling the diagram.
@@ -265,7 +327,7 @@ fn expectation_value(
     decomposer: &mut Decomposer<Graph>,
     pauli_str: &PauliString,
     parallel: Option<usize>,
-) -> Result<f64, CliError> {
+) -> Result<FScalar, CliError> {
     let qs = circ.num_qubits();
     let pauli_str = match pauli_str.as_slice() {
         [p] => &vec![*p; qs],
@@ -312,8 +374,7 @@ fn expectation_value(
     }
     g.plug(&g_adj);
 
-    let scalar = decomp_graph(g, decomposer, parallel);
-    Ok(scalar.complex_value().re)
+    Ok(decomp_graph(g, decomposer, parallel))
 }
 
 /// Run the provided decomposer on a graph.
@@ -464,6 +525,44 @@ mod test {
             .stdout(eq("-1\n"));
     }
 
+    #[rstest]
+    #[case(&[], "0.125")]
+    #[case(&["--digits", "3"], "0.125")]
+    #[case(&["--digits", "1"], "0.1")]
+    #[case(&["--digits", "10"], "0.1249995684")]
+    fn amplitude_digits(mut cmd: Command, #[case] args: &[&str], #[case] out: &str) {
+        // qft_4 has rotations given to finitely many decimal places, so this is close to,
+        // but not exactly, 1/8
+        cmd.arg("../circuits/small/qft_4.qasm")
+            .args(["--bss", "--amplitude", "0"])
+            .args(args)
+            .assert()
+            .success()
+            .stdout(eq(format!("{out}\n")));
+    }
+
+    #[rstest]
+    #[case(&[], "1/64")]
+    #[case(&["--exact"], "1/64")]
+    #[case(&["--digits", "2"], "0.016")]
+    fn amplitude_exact(mut cmd: Command, #[case] args: &[&str], #[case] out: &str) {
+        cmd.arg("../circuits/small/grover_5.qasm")
+            .args(["--bss", "--amplitude", "0"])
+            .args(args)
+            .assert()
+            .success()
+            .stdout(eq(format!("{out}\n")));
+    }
+
+    #[rstest]
+    fn expectation_exact(mut cmd: Command) {
+        cmd.arg(CIRC)
+            .args(["--expval", "Z", "--exact"])
+            .assert()
+            .success()
+            .stdout(eq("-1\n"));
+    }
+
     #[rstest]
     fn doesnt_exist(mut cmd: Command) {
         cmd.arg("blah")
@@ -498,6 +597,17 @@ mod test {
             ));
     }
 
+    #[rstest]
+    fn multiple_formats(mut cmd: Command) {
+        cmd.arg(CIRC)
+            .args(["--expval", "Z", "--exact", "--digits", "2"])
+            .assert()
+            .failure()
+            .stderr(contains(
+                "the argument '--exact' cannot be used with '--digits <DIGITS>'",
+            ));
+    }
+
     #[rstest]
     fn bad_bit(mut cmd: Command) {
         cmd.arg(CIRC)
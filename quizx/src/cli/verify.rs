@@ -0,0 +1,106 @@
+//! The `verify` CLI subcommand.
+
+use clap::Parser;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use crate::circuit::Circuit;
+use crate::equality;
+
+use super::CliError;
+
+/// Check whether two circuits are equal up to global phase.
+///
+/// Exits with 0 if the circuits are equal, 1 if they are not, and 2 if simplifying the
+/// first circuit composed with the adjoint of the second does not decide it. Errors
+/// reading or parsing the circuits exit with 3.
+#[derive(Parser, Debug)]
+pub struct VerifyArgs {
+    /// First QASM file.
+    a: PathBuf,
+
+    /// Second QASM file.
+    b: PathBuf,
+}
+
+impl VerifyArgs {
+    /// Run the `verify` command using the provided arguments.
+    pub fn run(self) -> Result<ExitCode, CliError> {
+        let read = |p: &PathBuf| Circuit::from_file(p.to_str().unwrap());
+        let (a, b) = match (read(&self.a), read(&self.b)) {
+            (Ok(a), Ok(b)) => (a, b),
+            // not an inequality, so these get their own exit code
+            (Err(e), _) | (_, Err(e)) => {
+                eprintln!("{}", CliError::from(e));
+                return Ok(ExitCode::from(3));
+            }
+        };
+        let code = match equality::equal_circuit(&a, &b) {
+            Some(true) => {
+                println!("equal");
+                0
+            }
+            Some(false) => {
+                println!("not equal");
+                1
+            }
+            None => {
+                println!("inconclusive");
+                2
+            }
+        };
+        Ok(ExitCode::from(code))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use assert_cmd::Command;
+    use predicates::{ord::eq, str::contains};
+    use rstest::{fixture, rstest};
+
+    const CIRC: &str = "../circuits/small/mod5_4.qasm";
+
+    #[fixture]
+    fn cmd() -> Command {
+        let mut cmd = Command::cargo_bin("quizx").unwrap();
+        cmd.arg("verify");
+        cmd
+    }
+
+    #[rstest]
+    fn equal(mut cmd: Command) {
+        cmd.arg(CIRC)
+            .arg(CIRC)
+            .assert()
+            .code(0)
+            .stdout(eq("equal\n"));
+    }
+
+    #[rstest]
+    fn not_equal(mut cmd: Command) {
+        cmd.arg(CIRC)
+            .arg("../circuits/small/tof_4.qasm")
+            .assert()
+            .code(1)
+            .stdout(eq("not equal\n"));
+    }
+
+    #[rstest]
+    fn inconclusive(mut cmd: Command) {
+        cmd.arg(CIRC)
+            .arg("../circuits/small/qft_4.qasm")
+            .assert()
+            .code(2)
+            .stdout(eq("inconclusive\n"));
+    }
+
+    #[rstest]
+    fn doesnt_exist(mut cmd: Command) {
+        cmd.arg(CIRC)
+            .arg("blah")
+            .assert()
+            .code(3)
+            .stderr(contains("Error parsing input circuit: can't read file"));
+    }
+}
@@ -15,16 +15,29 @@
 // limitations under the License.
 
 // use crate::decompose;
+use crate::basic_rules::remove_single_unchecked;
 use crate::fscalar::*;
 use crate::graph::*;
+use crate::json::JsonError;
+use crate::phase::Phase;
+use crate::stabilizer::{OperatorStabSum, StabSum};
 // use crate::hash_graph::Graph;
 // use crate::tensor::Tensor;
 // use itertools::Itertools;
 // use itertools::Itertools;
+use num::complex::Complex;
 use num::Rational64;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
 use rand::{thread_rng, Rng};
-// use rand::rngs::StdRng;
 use rayon::prelude::*;
+use rustc_hash::FxHashSet;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 /// Gives upper bound for number of terms needed for BSS decomposition
 ///
@@ -40,6 +53,56 @@ pub fn terms_for_tcount(tcount: usize) -> f64 {
     count
 }
 
+/// Fits the decomposition exponent α to the results of some decompositions
+///
+/// Each result is a pair `(t_count, nterms)`. This returns the slope of the
+/// least-squares fit of `log2(nterms)` against the T-count, so that the number of
+/// terms grows like `2^(α t_count)`. For example, the BSS decomposition of
+/// [`terms_for_tcount`] has α = log2(7) / 6 ≈ 0.468.
+///
+/// Panics if the results have fewer than two distinct T-counts, or if some `nterms`
+/// is 0.
+pub fn fit_alpha(results: &[(usize, usize)]) -> f64 {
+    if let Some(&(t, _)) = results.iter().find(|&&(_, nterms)| nterms == 0) {
+        panic!("Got 0 terms for T-count {t}");
+    }
+    let n = results.len() as f64;
+    let mean_t = results.iter().map(|&(t, _)| t as f64).sum::<f64>() / n;
+    let mean_log = results
+        .iter()
+        .map(|&(_, nterms)| (nterms as f64).log2())
+        .sum::<f64>()
+        / n;
+    let (mut cov, mut var) = (0.0, 0.0);
+    for &(t, nterms) in results {
+        let dt = t as f64 - mean_t;
+        cov += dt * ((nterms as f64).log2() - mean_log);
+        var += dt * dt;
+    }
+    if var == 0.0 {
+        panic!("Need at least two distinct T-counts to fit α");
+    }
+    cov / var
+}
+
+/// Estimates the stabilizer nullity of the state or map represented by a graph
+///
+/// The stabilizer nullity of an n-qubit state is n minus the dimension of the group
+/// of Pauli operators stabilising it (Beverland et al., arXiv:1904.01124). It is 0
+/// for stabilizer states and grows by at most 1 with each non-Clifford phase, so it
+/// is a lower bound on the number of T gates needed to prepare the state.
+///
+/// Here, it is computed as the number of non-Clifford phases left after running
+/// [`crate::simplify::full_simp`] on a copy of `g`. This removes T's that cancel or
+/// fuse into Clifford phases. The result is an upper bound on the true nullity,
+/// which is tight e.g. for products of magic states, and bounds the number of
+/// terms of a stabilizer decomposition, see [`terms_for_tcount`].
+pub fn stabilizer_nullity(g: &impl GraphLike) -> usize {
+    let mut g = g.clone();
+    crate::simplify::full_simp(&mut g);
+    g.tcount()
+}
+
 /// Pick the first <= 6 T gates from the given graph
 pub fn first_ts<G: GraphLike>(g: &G) -> Vec<V> {
     let mut t = vec![];
@@ -55,6 +118,20 @@ pub fn first_ts<G: GraphLike>(g: &G) -> Vec<V> {
     t
 }
 
+/// Pick <= 6 T gates from the given graph, in order of their degree
+///
+/// Ties are broken by the order of [`GraphLike::vertices`], as in [`first_ts`].
+pub fn degree_ordered_ts<G: GraphLike>(g: &G, descending: bool) -> Vec<V> {
+    let mut t: Vec<_> = g.vertices().filter(|&v| g.phase(v).is_t()).collect();
+    if descending {
+        t.sort_by_key(|&v| std::cmp::Reverse(g.degree(v)));
+    } else {
+        t.sort_by_key(|&v| g.degree(v));
+    }
+    t.truncate(6);
+    t
+}
+
 /// Pick <= 6 T gates from the given graph, chosen at random
 pub fn random_ts<G: GraphLike>(g: &G, rng: &mut impl Rng) -> Vec<V> {
     // the graph g is assumed to contain no X spiders
@@ -68,48 +145,202 @@ pub fn random_ts<G: GraphLike>(g: &G, rng: &mut impl Rng) -> Vec<V> {
     t
 }
 
+/// How drivers pick the T-like spiders to decompose, when there is a choice
+#[derive(Clone, Copy, Debug, derive_more::Display, Serialize, Deserialize)]
+pub enum SpiderSelection {
+    /// the first T-like spiders, see [`first_ts`]
+    First,
+    /// T-like spiders chosen at random, see [`random_ts`]. With a seed, the choice
+    /// only depends on the graph, so decompositions are reproducible, also in
+    /// parallel. Without one, [`thread_rng`] is used.
+    #[display("Random")]
+    Random(Option<u64>),
+    /// T-like spiders chosen one at a time by a function, which is given the graph and
+    /// the T-like spiders not chosen yet, in vertex order, and returns one of the latter
+    ///
+    /// The function sees a [`crate::vec_graph::Graph`] with the same vertex names as
+    /// the graph being decomposed, which is copied at every branch. This variant can't
+    /// be serialized, e.g. as part of a [`DecomposerConfig`].
+    #[display("Custom")]
+    #[serde(skip)]
+    Custom(fn(&crate::vec_graph::Graph, &[V]) -> V),
+}
+
+impl SpiderSelection {
+    /// Picks <= 6 T-like spiders from the given graph
+    pub fn select_ts<G: GraphLike>(&self, g: &G) -> Vec<V> {
+        match self {
+            SpiderSelection::First => first_ts(g),
+            SpiderSelection::Random(None) => random_ts(g, &mut thread_rng()),
+            SpiderSelection::Random(Some(seed)) => random_ts(g, &mut StdRng::seed_from_u64(*seed)),
+            SpiderSelection::Custom(f) => {
+                let h = copy_to_vec_graph(g);
+                let mut all_t: Vec<_> = g.vertices().filter(|&v| g.phase(v).is_t()).collect();
+                let mut t = vec![];
+                while t.len() < 6 && !all_t.is_empty() {
+                    let v = f(&h, &all_t);
+                    let i = all_t.iter().position(|&w| w == v).unwrap_or_else(|| {
+                        panic!("Spider selection picked {v}, which is not one of {all_t:?}")
+                    });
+                    t.push(all_t.remove(i));
+                }
+                t
+            }
+        }
+    }
+}
+
+/// Copies a graph into a [`crate::vec_graph::Graph`], keeping the vertex names
+fn copy_to_vec_graph(g: &impl GraphLike) -> crate::vec_graph::Graph {
+    let mut h = crate::vec_graph::Graph::new();
+    let n = g.vertices().max().map_or(0, |v| v + 1);
+    for v in 0..n {
+        let w = h.add_vertex_with_data(if g.contains_vertex(v) {
+            g.vertex_data(v).clone()
+        } else {
+            VData::default()
+        });
+        debug_assert_eq!(v, w);
+    }
+    for v in 0..n {
+        if !g.contains_vertex(v) {
+            h.remove_vertex(v);
+        }
+    }
+    for (s, t, ty) in g.edges() {
+        h.add_edge_with_type(s, t, ty);
+    }
+    h.set_inputs(g.inputs().clone());
+    h.set_outputs(g.outputs().clone());
+    h
+}
+
+/// A cat state found in a graph-like graph
+///
+/// This is a Pauli Z-spider connected by Hadamard edges to all and only T-like Z-spiders.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CatState {
+    /// the central Pauli spider
+    pub pauli: V,
+    /// the T-like spiders connected to the Pauli spider
+    pub legs: Vec<V>,
+    /// the phase of the Pauli spider, followed by the phases of the legs
+    pub phases: Vec<Phase>,
+    /// the efficiency of decomposing this cat state, i.e. log2 of the number of terms
+    /// divided by the number of T-like spiders the decomposition removes. Smaller is better.
+    pub alpha: f64,
+}
+
+impl CatState {
+    /// The vertices of the cat state, starting with the Pauli spider
+    pub fn vertices(&self) -> Vec<V> {
+        let mut verts = vec![self.pauli];
+        verts.extend_from_slice(&self.legs);
+        verts
+    }
+
+    /// The efficiency of the cat decomposition with the given number of legs, if there is one
+    ///
+    /// Cats with 3 or 5 legs are padded with an extra leg, which introduces a new T-like
+    /// spider, so they remove one less T than they have legs.
+    fn alpha_for_legs(legs: usize) -> Option<f64> {
+        match legs {
+            3 => Some(1.0 / 2.0),
+            4 => Some(1.0 / 4.0),
+            5 => Some(3f64.log2() / 4.0),
+            6 => Some(3f64.log2() / 6.0),
+            _ => None,
+        }
+    }
+}
+
+/// Returns all the cat states in a graph-like graph that can be used by the cats driver,
+/// i.e. those with 3 to 6 legs, in the order of their Pauli spiders
+pub fn find_cat_states(g: &impl GraphLike) -> Vec<CatState> {
+    let mut cats = vec![];
+    for v in g.vertices() {
+        if g.vertex_type(v) == VType::Z && g.phase(v).is_pauli() {
+            let legs: Vec<V> = g.h_neighbors(v).collect();
+            if legs.len() <= 6
+                && legs.len() == g.degree(v)
+                && legs
+                    .iter()
+                    .all(|&n| g.vertex_type(n) == VType::Z && g.phase(n).is_t())
+            {
+                if let Some(alpha) = CatState::alpha_for_legs(legs.len()) {
+                    let mut phases = vec![g.phase(v)];
+                    phases.extend(legs.iter().map(|&n| g.phase(n)));
+                    cats.push(CatState {
+                        pauli: v,
+                        legs,
+                        phases,
+                        alpha,
+                    });
+                }
+            }
+        }
+    }
+    cats
+}
+
 /// Returns a best occurrence of a cat state
 /// The fist vertex in the result is the Pauli spider
 pub fn cat_ts<G: GraphLike>(g: &G) -> Vec<V> {
     // the graph g is assumed to be graph-like
-    let preferred_order = [4, 6, 5, 3];
+    find_cat_states(g)
+        .into_iter()
+        .min_by(|c0, c1| c0.alpha.total_cmp(&c1.alpha))
+        .map(|c| c.vertices())
+        .unwrap_or_default()
+}
+
+/// Returns the hub of the largest GHZ-like parity structure, i.e. a phase-free or
+/// π-phase Z spider with at least 4 T-like neighbours that are connected to nothing else
+///
+/// The first vertex in the result is the hub, followed by its T-like leaves. Cutting
+/// the hub removes all of these leaves with 2 terms, see [`Driver::GhzAware`].
+pub fn ghz_ts<G: GraphLike>(g: &G) -> Vec<V> {
+    // the graph g is assumed to be graph-like
     let mut res = vec![];
-    let mut index = None;
     for v in g.vertices() {
-        if g.vertex_type(v) == VType::Z && g.phase(v).is_pauli() {
-            let mut neigh = g.neighbor_vec(v);
-            if neigh.len() <= 6
-                && neigh.iter().all(|&n| {
-                    g.vertex_type(n) == VType::Z
-                        && g.phase(n).is_t()
-                        && g.edge_type(v, n) == EType::H
-                })
-            {
-                if let Some(this_ind) = preferred_order.iter().position(|&r| r == neigh.len()) {
-                    match index {
-                        Some(ind) if this_ind < ind => {
-                            res = vec![v];
-                            res.append(&mut neigh);
-                            index = Some(this_ind);
-                        }
-                        None => {
-                            res = vec![v];
-                            res.append(&mut neigh);
-                            index = Some(this_ind);
-                        }
-                        _ => (),
-                    }
-                }
-                if index == Some(0) {
-                    break;
-                }
-            }
+        if g.vertex_type(v) != VType::Z
+            || !g.phase(v).is_pauli()
+            || !g.vars(v).is_empty()
+            || g.degree(v) < 4.max(res.len())
+        {
+            continue;
+        }
+        if !g
+            .incident_edges(v)
+            .all(|(n, et)| et == EType::H && g.vertex_type(n) == VType::Z)
+        {
+            continue;
+        }
+        let leaves: Vec<V> = g
+            .neighbors(v)
+            .filter(|&n| g.degree(n) == 1 && g.phase(n).is_t())
+            .collect();
+        if leaves.len() >= 4.max(res.len()) {
+            res = vec![v];
+            res.extend(leaves);
         }
     }
     res
 }
 
-#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+/// How the decomposer accumulates the scalars of the terms of a decomposition
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub enum ScalarMode {
+    /// floating point arithmetic on [`FScalar`]s
+    #[default]
+    Float,
+    /// exact arithmetic in the dyadic rationals extended by ω, see
+    /// [`FScalar::exact_sum`]. The result does not depend on the order the terms
+    /// are computed in, e.g. when decomposing in parallel.
+    ExactPhase,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
 pub enum SimpFunc {
     FullSimp,
     CliffordSimp,
@@ -117,6 +348,7 @@ pub enum SimpFunc {
 }
 use SimpFunc::*;
 
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Decomp {
     CatDecomp(Vec<usize>),
     Magic5FromCat(Vec<usize>),
@@ -124,38 +356,66 @@ pub enum Decomp {
     BssDecomp(Vec<usize>),
     SymDecomp(Vec<usize>),
     SingleDecomp(Vec<usize>),
+    GhzDecomp(Vec<usize>),
 }
 use Decomp::*;
 
-#[derive(Clone, Debug, derive_more::Display)]
+impl Decomp {
+    /// The terms of the decomposition of `g`, with their coefficients multiplied in
+    fn apply<G: GraphLike>(&self, g: &G) -> Vec<G> {
+        match self {
+            Magic5FromCat(vertices) => apply_magic5_from_cat_decomp(g, &vertices[0..5]),
+            TDecomp(vertices) => apply_ts_decomp(g, vertices),
+            CatDecomp(vertices) => apply_cat_decomp(g, vertices),
+            GhzDecomp(vertices) => apply_ghz_decomp(g, vertices),
+            BssDecomp(vertices) => apply_bss_decomp(g, vertices),
+            SymDecomp(vertices) => apply_sym_decomp(g, vertices),
+            SingleDecomp(vertices) => apply_single_decomp(g, vertices),
+        }
+    }
+
+    /// The name of the variant, without the vertices
+    fn name(&self) -> &'static str {
+        match self {
+            CatDecomp(_) => "CatDecomp",
+            Magic5FromCat(_) => "Magic5FromCat",
+            TDecomp(_) => "TDecomp",
+            BssDecomp(_) => "BssDecomp",
+            SymDecomp(_) => "SymDecomp",
+            SingleDecomp(_) => "SingleDecomp",
+            GhzDecomp(_) => "GhzDecomp",
+        }
+    }
+}
+
+#[derive(Clone, Debug, derive_more::Display, Serialize, Deserialize)]
 pub enum Driver {
-    BssTOnly(bool),
-    BssWithCats(bool),
+    BssTOnly(SpiderSelection),
+    BssWithCats(SpiderSelection),
+    /// like [`Driver::BssWithCats`], but first cuts the hubs of GHZ-like parity
+    /// structures found by [`ghz_ts`]
+    GhzAware(SpiderSelection),
+    /// like [`Driver::BssTOnly`], but picks the T-like spiders by degree, see
+    /// [`degree_ordered_ts`]. Cutting high-degree spiders first tends to make graphs
+    /// fall apart sooner, which helps with
+    /// [`Decomposer::with_split_graphs_components`].
+    DegreeOrderedT {
+        descending: bool,
+    },
 }
 use Driver::*;
 
 impl Driver {
     fn choose_decomp(&self, g: &impl GraphLike) -> Decomp {
         match self {
-            BssTOnly(random_t) => {
-                let ts = if *random_t {
-                    random_ts(g, &mut thread_rng())
-                } else {
-                    first_ts(g)
-                };
-                TDecomp(ts)
-            }
-            BssWithCats(random_t) => {
+            BssTOnly(selection) => TDecomp(selection.select_ts(g)),
+            BssWithCats(selection) => {
                 let cat_nodes = cat_ts(g);
                 if cat_nodes.len() > 3 {
                     // println!("using cat!");
                     CatDecomp(cat_nodes)
                 } else {
-                    let ts = if *random_t {
-                        random_ts(g, &mut thread_rng())
-                    } else {
-                        first_ts(g)
-                    };
+                    let ts = selection.select_ts(g);
                     if ts.len() >= 5 {
                         // println!("using M5!");
                         Magic5FromCat(ts[0..5].to_vec())
@@ -165,10 +425,357 @@ impl Driver {
                     }
                 }
             }
+            DegreeOrderedT { descending } => TDecomp(degree_ordered_ts(g, *descending)),
+            GhzAware(selection) => {
+                let ghz_nodes = ghz_ts(g);
+                if !ghz_nodes.is_empty() {
+                    GhzDecomp(ghz_nodes)
+                } else {
+                    BssWithCats(*selection).choose_decomp(g)
+                }
+            }
         }
     }
 }
 
+/// A decomposition driver given by a closure
+///
+/// At each branch, the closure is given the current graph and returns a list of
+/// pairs `(c, h)` of coefficients and child graphs. These must satisfy the
+/// invariant that the sum of `c * h` over all children is equal to the parent
+/// graph, including scalars. To guarantee termination, each child should also
+/// have a lower T-count than the parent.
+#[derive(Clone)]
+pub struct FnDriver<G: GraphLike> {
+    f: Arc<DecompFn<G>>,
+}
+
+type DecompFn<G> = dyn Fn(&G) -> Vec<(FScalar, G)> + Send + Sync;
+
+impl<G: GraphLike> FnDriver<G> {
+    pub fn new(f: impl Fn(&G) -> Vec<(FScalar, G)> + Send + Sync + 'static) -> Self {
+        FnDriver { f: Arc::new(f) }
+    }
+
+    /// Returns the children of the given graph, with their coefficients multiplied in
+    fn children(&self, g: &G) -> Vec<G> {
+        (self.f)(g)
+            .into_iter()
+            .map(|(c, mut h)| {
+                *h.scalar_mut() *= c;
+                h
+            })
+            .collect()
+    }
+}
+
+/// Whether a choice log is written or read, see [`Decomposer::with_choice_log`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ChoiceLogMode {
+    /// Write the decomposition chosen by the driver at each branch to the log
+    Record,
+    /// Use the decomposition in the log at each branch, in place of the driver
+    Replay,
+}
+
+/// The decompositions chosen at each branch of a run, keyed by the path to the
+/// branch from the root, see [`Decomposer::with_choice_log`]
+#[derive(Clone)]
+struct ChoiceLog {
+    file: PathBuf,
+    mode: ChoiceLogMode,
+    choices: Arc<Mutex<BTreeMap<Vec<usize>, Decomp>>>,
+    error: Arc<Mutex<Option<String>>>, // the first mismatch found while replaying
+}
+
+impl ChoiceLog {
+    fn fail(&self, message: String) {
+        self.error.lock().unwrap().get_or_insert(message);
+    }
+
+    fn failed(&self) -> bool {
+        self.error.lock().unwrap().is_some()
+    }
+
+    /// The decomposition for the branch at `path`, checked against the graph there
+    fn replay(&self, path: &[usize], g: &impl GraphLike) -> Result<Decomp, String> {
+        let branch = format_branch(path);
+        let d = self
+            .choices
+            .lock()
+            .unwrap()
+            .get(path)
+            .cloned()
+            .ok_or_else(|| format!("no choice for branch {branch}"))?;
+        let (name, vs) = decomp_parts(&d);
+        let mismatch = |reason: &str| format!("{name} at branch {branch} {reason}");
+        if let Some(&v) = vs.iter().find(|&&v| !g.contains_vertex(v)) {
+            return Err(mismatch(&format!("uses missing vertex {v}")));
+        }
+        if let Some((i, &v)) = vs.iter().enumerate().find(|&(i, v)| vs[..i].contains(v)) {
+            return Err(mismatch(&format!("lists vertex {v} twice, at {i}")));
+        }
+        let expected = match d {
+            Magic5FromCat(_) => 5..=5,
+            BssDecomp(_) => 6..=6,
+            SymDecomp(_) => 2..=2,
+            SingleDecomp(_) => 1..=1,
+            // a hub and 3 to 6 legs, see find_cat_states
+            CatDecomp(_) => 4..=7,
+            GhzDecomp(_) => 2..=usize::MAX,
+            _ => 1..=usize::MAX,
+        };
+        if !expected.contains(&vs.len()) {
+            return Err(mismatch(&format!("has {} vertices", vs.len())));
+        }
+        let ts = match d {
+            // a Pauli hub, then T-spiders adjacent to it
+            CatDecomp(_) | GhzDecomp(_) => {
+                let hub = vs[0];
+                if !g.phase(hub).is_pauli() {
+                    return Err(mismatch(&format!("expects a Pauli hub at {hub}")));
+                }
+                if let Some(&v) = vs[1..].iter().find(|&&v| !g.connected(hub, v)) {
+                    return Err(mismatch(&format!("has {v} not adjacent to the hub")));
+                }
+                let legs = &vs[1..];
+                match d {
+                    CatDecomp(_) => {
+                        let mut ns = g.neighbor_vec(hub);
+                        let mut ls = legs.to_vec();
+                        ns.sort_unstable();
+                        ls.sort_unstable();
+                        if ns != ls {
+                            return Err(mismatch("doesn't list exactly the hub's neighbours"));
+                        }
+                    }
+                    _ => {
+                        if let Some(&v) = legs.iter().find(|&&v| g.degree(v) != 1) {
+                            return Err(mismatch(&format!("has leaf {v} with other neighbours")));
+                        }
+                    }
+                }
+                legs
+            }
+            _ => vs,
+        };
+        if let Some(&v) = ts.iter().find(|&&v| !g.phase(v).is_t()) {
+            return Err(mismatch(&format!("expects a T-spider at {v}")));
+        }
+        Ok(d)
+    }
+
+    fn read(&self) -> Result<(), String> {
+        let text = std::fs::read_to_string(&self.file)
+            .map_err(|e| format!("can't read {}: {e}", self.file.display()))?;
+        let mut choices = BTreeMap::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (path, d) = parse_choice(line).map_err(|e| format!("line {}: {e}", i + 1))?;
+            choices.insert(path, d);
+        }
+        *self.choices.lock().unwrap() = choices;
+        Ok(())
+    }
+
+    fn write(&self) -> Result<(), String> {
+        let mut text = String::from("# branch decomposition vertices...\n");
+        for (path, d) in self.choices.lock().unwrap().iter() {
+            let (name, vs) = decomp_parts(d);
+            text += &format_branch(path);
+            text += " ";
+            text += name;
+            for v in vs {
+                text += &format!(" {v}");
+            }
+            text += "\n";
+        }
+        std::fs::write(&self.file, text)
+            .map_err(|e| format!("can't write {}: {e}", self.file.display()))
+    }
+}
+
+/// Writes a branch path as child indices separated by dots, or `-` for the root
+fn format_branch(path: &[usize]) -> String {
+    if path.is_empty() {
+        "-".to_string()
+    } else {
+        path.iter()
+            .map(|i| i.to_string())
+            .collect::<Vec<_>>()
+            .join(".")
+    }
+}
+
+fn decomp_parts(d: &Decomp) -> (&'static str, &[V]) {
+    match d {
+        CatDecomp(vs) => ("cat", vs),
+        Magic5FromCat(vs) => ("magic5", vs),
+        TDecomp(vs) => ("t", vs),
+        BssDecomp(vs) => ("bss", vs),
+        SymDecomp(vs) => ("sym", vs),
+        SingleDecomp(vs) => ("single", vs),
+        GhzDecomp(vs) => ("ghz", vs),
+    }
+}
+
+/// Parses a line of a choice log, e.g. `0.2 t 4 7 9`
+fn parse_choice(line: &str) -> Result<(Vec<usize>, Decomp), String> {
+    let mut words = line.split_whitespace();
+    let branch = words.next().unwrap_or_default();
+    let path = if branch == "-" {
+        vec![]
+    } else {
+        branch
+            .split('.')
+            .map(|i| i.parse::<usize>())
+            .collect::<Result<_, _>>()
+            .map_err(|_| format!("invalid branch {branch}"))?
+    };
+    let name = words
+        .next()
+        .ok_or_else(|| "missing decomposition".to_string())?;
+    let vs = words
+        .map(|v| v.parse::<V>().map_err(|_| format!("invalid vertex {v}")))
+        .collect::<Result<Vec<_>, _>>()?;
+    let d = match name {
+        "cat" => CatDecomp(vs),
+        "magic5" => Magic5FromCat(vs),
+        "t" => TDecomp(vs),
+        "bss" => BssDecomp(vs),
+        "sym" => SymDecomp(vs),
+        "single" => SingleDecomp(vs),
+        "ghz" => GhzDecomp(vs),
+        _ => return Err(format!("unknown decomposition {name}")),
+    };
+    Ok((path, d))
+}
+
+/// A single-qubit stabiliser state, as used by [`MagicBasis`]
+///
+/// States are not normalised, so that they are easy to attach to a spider.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StabState {
+    /// the state |0>
+    Zero,
+    /// the state |1>
+    One,
+    /// the state |0> + e^(iπβ) |1>, for β a multiple of 1/2
+    Equator(Rational64),
+}
+
+impl StabState {
+    /// The coefficients of |0> and |1>
+    fn vector(&self) -> [Complex<f64>; 2] {
+        match self {
+            StabState::Zero => [1.0.into(), 0.0.into()],
+            StabState::One => [0.0.into(), 1.0.into()],
+            StabState::Equator(beta) => [1.0.into(), FScalar::from_phase(*beta).into()],
+        }
+    }
+}
+
+/// A decomposition of a single-qubit magic state into stabiliser states
+///
+/// A Z-spider with phase α is a phase-free spider with the state |0> + e^(iπα) |1>
+/// plugged into an extra leg. Given a decomposition of this state as a sum of
+/// `c_k |s_k>`, the decomposer replaces each spider with phase α by the terms where
+/// `|s_k>` is plugged in instead, with coefficient `c_k`. This also holds for spiders
+/// with boolean variables, which are left in place.
+///
+/// Only single-qubit magic states are supported, so each term replaces one spider.
+/// Decompositions of several magic states at once, like the decomposition of 5
+/// T-states used by [`Driver::BssWithCats`], can't be given as a `MagicBasis`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MagicBasis {
+    phase: Phase,
+    terms: Vec<(FScalar, StabState)>,
+}
+
+impl MagicBasis {
+    /// A decomposition of the magic state for `phase` into the given terms
+    ///
+    /// Panics if `phase` is Clifford, if an equatorial state has a non-Clifford phase,
+    /// or if the terms do not add up to the magic state.
+    pub fn new(phase: impl Into<Phase>, terms: Vec<(FScalar, StabState)>) -> Self {
+        let phase = phase.into();
+        if phase.is_clifford() {
+            panic!("A magic basis needs a non-Clifford phase, got {phase}");
+        }
+        let mut sum = [Complex::<f64>::zero(); 2];
+        for (c, state) in &terms {
+            if let StabState::Equator(beta) = state {
+                if !Phase::new(*beta).is_clifford() {
+                    panic!("Not a stabiliser state: {state:?}");
+                }
+            }
+            let c: Complex<f64> = c.into();
+            for (s, x) in sum.iter_mut().zip(state.vector()) {
+                *s += c * x;
+            }
+        }
+        let magic = StabState::Equator(phase.to_rational()).vector();
+        if (0..2).any(|i| (sum[i] - magic[i]).norm() > 1e-10) {
+            panic!("The terms do not add up to the magic state for phase {phase}");
+        }
+        MagicBasis { phase, terms }
+    }
+
+    /// The T-state |0> + e^(iπ/4) |1> as a sum of |0> and |1>
+    pub fn t() -> Self {
+        MagicBasis::new(
+            Rational64::new(1, 4),
+            vec![
+                (FScalar::one(), StabState::Zero),
+                (FScalar::from_phase(Rational64::new(1, 4)), StabState::One),
+            ],
+        )
+    }
+
+    /// The phase of the spiders this decomposes
+    pub fn phase(&self) -> Phase {
+        self.phase
+    }
+
+    /// Returns a Z-spider the basis applies to, if there is one
+    fn matching_spider(&self, g: &impl GraphLike) -> Option<V> {
+        g.vertices()
+            .find(|&v| g.vertex_type(v) == VType::Z && g.phase(v) == self.phase)
+    }
+
+    /// Returns the terms given by plugging each stabiliser state into `v`
+    fn apply<G: GraphLike>(&self, g: &G, v: V) -> Vec<G> {
+        self.terms
+            .iter()
+            .map(|&(c, state)| {
+                let mut g = g.clone();
+                *g.scalar_mut() *= c;
+                match state {
+                    StabState::Equator(beta) => g.set_phase(v, beta),
+                    StabState::Zero | StabState::One => {
+                        // a Z-spider leaf on a Hadamard edge is sqrt(2) |0> or sqrt(2) |1>
+                        g.set_phase(v, Rational64::zero());
+                        let phase = if state == StabState::One { 1 } else { 0 };
+                        let w = g.add_vertex_with_phase(VType::Z, phase);
+                        g.add_edge_with_type(v, w, EType::H);
+                        g.scalar_mut().mul_sqrt2_pow(-1);
+                    }
+                }
+                g
+            })
+            .collect()
+    }
+}
+
+impl Default for MagicBasis {
+    fn default() -> Self {
+        MagicBasis::t()
+    }
+}
+
 fn replace_cat6_0<G: GraphLike>(g: &G, verts: &[V]) -> G {
     let mut g = g.clone();
     g.scalar_mut().mul_sqrt2_pow(-2);
@@ -494,6 +1101,31 @@ fn apply_cat_decomp<G: GraphLike>(g: &G, verts: &[V]) -> Vec<G> {
     }
 }
 
+/// Cut the hub verts[0] of a GHZ-like parity structure
+///
+/// The hub is replaced by a sum over the Z basis, which adds 0 or π to each of its
+/// neighbours. The leaves verts[1..] are then isolated and are removed as scalars.
+fn apply_ghz_decomp<G: GraphLike>(g: &G, verts: &[V]) -> Vec<G> {
+    let hub = verts[0];
+    let mut terms = vec![];
+    for x in 0..2 {
+        let mut g = g.clone();
+        let d = g.degree(hub) as i32;
+        g.scalar_mut().mul_sqrt2_pow(-d);
+        let p = g.phase(hub) * x;
+        g.scalar_mut().mul_phase(p);
+        for n in g.neighbor_vec(hub) {
+            g.add_to_phase(n, Rational64::new(x, 1));
+        }
+        g.remove_vertex(hub);
+        for &v in &verts[1..] {
+            remove_single_unchecked(&mut g, v);
+        }
+        terms.push(g);
+    }
+    terms
+}
+
 #[derive(Clone)]
 enum ComputationNode<G: GraphLike> {
     Graph(G),
@@ -513,6 +1145,137 @@ fn calc_max_terms(node: &ComputationNode<impl GraphLike>) -> f64 {
     }
 }
 
+fn calc_residual_tcount(node: &ComputationNode<impl GraphLike>) -> usize {
+    match node {
+        ComputationNode::None | ComputationNode::Scalar(_) => 0,
+        ComputationNode::Graph(g) => g.tcount(),
+        ComputationNode::Prod(terms) | ComputationNode::Sum(terms) => {
+            terms.iter().map(calc_residual_tcount).max().unwrap_or(0)
+        }
+    }
+}
+
+/// The sum of the terms a [`Decomposer`] has finished so far
+///
+/// This is a shared handle, so it can be read from another thread while the decomposer
+/// is running. Reading and updating it is lock-free. Terms are always added up in
+/// floating point, regardless of the [`ScalarMode`], and coefficients are updated one
+/// at a time, so a concurrent read may see a term partially added.
+///
+/// When splitting graphs into components, the terms of the components are only added
+/// once the product of all components is known.
+#[derive(Clone, Default)]
+pub struct PartialScalar(Arc<PartialScalarData>);
+
+#[derive(Default)]
+struct PartialScalarData {
+    coeffs: [AtomicU64; 4],
+    terms: AtomicUsize,
+}
+
+impl PartialScalar {
+    /// The sum of the finished terms
+    pub fn get(&self) -> FScalar {
+        self.0
+            .coeffs
+            .each_ref()
+            .map(|c| f64::from_bits(c.load(Ordering::Relaxed)))
+            .into()
+    }
+
+    /// The number of finished terms
+    pub fn terms(&self) -> usize {
+        self.0.terms.load(Ordering::Relaxed)
+    }
+
+    fn add(&self, s: FScalar) {
+        let s: [f64; 4] = s.into();
+        for (c, x) in self.0.coeffs.iter().zip(s) {
+            if x != 0.0 {
+                let _ = c.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+                    Some((f64::from_bits(bits) + x).to_bits())
+                });
+            }
+        }
+        self.0.terms.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Resets the sum and the number of terms, e.g. to undo a failed run
+    fn set(&self, s: FScalar, terms: usize) {
+        let s: [f64; 4] = s.into();
+        for (c, x) in self.0.coeffs.iter().zip(s) {
+            c.store(x.to_bits(), Ordering::Relaxed);
+        }
+        self.0.terms.store(terms, Ordering::Relaxed);
+    }
+}
+
+/// An error that can occur while decomposing, see [`Decomposer::try_decompose`]
+#[derive(Debug, Clone, PartialEq, Eq, derive_more::Display, derive_more::Error)]
+#[non_exhaustive]
+pub enum DecomposeError {
+    /// The decomposition needed more terms than allowed by [`Decomposer::with_max_terms`].
+    #[display("term limit exceeded after exploring {explored} terms")]
+    TermLimitExceeded { explored: usize },
+    /// The decomposition took longer than allowed by [`Decomposer::with_timeout`].
+    #[display("timed out after exploring {explored} terms")]
+    Timeout { explored: usize },
+    /// The log set by [`Decomposer::with_choice_log`] could not be read or written, or
+    /// doesn't match the graph being replayed.
+    #[display("choice log: {message}")]
+    ChoiceLog { message: String },
+    /// A spider given to [`Decomposer::decompose_subset`] is not T-like, or the graph
+    /// has a non-Clifford spider which is not T-like.
+    #[display("vertex {vertex} is not a T-like spider")]
+    NotTLike { vertex: V },
+}
+
+/// The settings of a [`Decomposer`], which can be saved to and loaded from a file
+///
+/// Each field corresponds to one of the `with_*` methods, see [`Decomposer::from_config`]
+/// and [`Decomposer::config`]. Closures, i.e. [`Decomposer::with_fn_driver`] and
+/// [`SpiderSelection::Custom`], and magic bases are not part of the config. Missing
+/// fields are set to the defaults of [`Decomposer::new`] when deserializing.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DecomposerConfig {
+    pub simp: SimpFunc,
+    pub driver: Driver,
+    pub scalar_mode: ScalarMode,
+    pub split_graph_components: bool,
+    pub save: bool,
+    pub max_terms: Option<u128>,
+    /// see [`Decomposer::with_timeout`], in seconds
+    pub timeout_secs: Option<f64>,
+    pub choice_log: Option<(PathBuf, ChoiceLogMode)>,
+    pub level_stats: bool,
+    pub merge_leaves: bool,
+    pub dense_rules: bool,
+    /// whether [`DecomposerConfig::run`] uses [`Decomposer::try_decompose_parallel`]
+    pub parallel: bool,
+}
+
+impl Default for DecomposerConfig {
+    /// The settings of [`Decomposer::empty`]
+    fn default() -> Self {
+        Decomposer::<crate::vec_graph::Graph>::empty().config()
+    }
+}
+
+impl DecomposerConfig {
+    /// Decomposes `g` with these settings, sequentially or in parallel
+    pub fn run<G: GraphLike>(&self, g: &G) -> Result<Decomposer<G>, DecomposeError> {
+        let mut d = Decomposer::from_config(self);
+        d.set_target(g.clone());
+        if self.parallel {
+            d.try_decompose_parallel()?;
+        } else {
+            d.try_decompose()?;
+        }
+        Ok(d)
+    }
+}
+
 /// Store the (partial) decomposition of a graph into stabilisers
 #[derive(Clone)]
 pub struct Decomposer<G: GraphLike> {
@@ -521,8 +1284,23 @@ pub struct Decomposer<G: GraphLike> {
     result: ComputationNode<G>,
     simp_func: SimpFunc,
     driver: Driver,
+    fn_driver: Option<FnDriver<G>>,  // if set, used in place of driver
+    magic_basis: Option<MagicBasis>, // if set, used in place of driver for matching spiders
+    scalar_mode: ScalarMode,
     split_graph_components: bool,
     save: bool, // save graphs on 'done' stack
+    partial: PartialScalar,
+    in_product: bool, // true while decomposing a component of a split graph
+    term_limit: Option<u128>,
+    timeout: Option<Duration>,
+    deadline: Option<Instant>, // end of the current run, if there is a timeout
+    explored: Arc<AtomicUsize>, // terms in the current run, shared with parallel clones
+    level_stats: Option<Arc<Mutex<Vec<usize>>>>, // terms per depth, if enabled
+    merge_leaves: bool,        // merge equal states in decompose_to_sum
+    dense_rules: bool,         // simplify with the rules of crate::dense
+    leaf_counts: (usize, usize), // leaves and terms of the last decompose_to_sum
+    choice_log: Option<ChoiceLog>,
+    branch: Vec<usize>, // path from the root to the graph being decomposed
 }
 
 impl<G: GraphLike> Decomposer<G> {
@@ -532,22 +1310,36 @@ impl<G: GraphLike> Decomposer<G> {
             done: vec![],
             nterms: 0,
             simp_func: NoSimp,
-            driver: BssTOnly(false),
+            driver: BssTOnly(SpiderSelection::First),
+            fn_driver: None,
+            magic_basis: None,
+            scalar_mode: ScalarMode::Float,
             split_graph_components: false,
             save: false,
+            partial: PartialScalar::default(),
+            in_product: false,
+            term_limit: None,
+            timeout: None,
+            deadline: None,
+            explored: Arc::default(),
+            level_stats: None,
+            merge_leaves: true,
+            dense_rules: false,
+            leaf_counts: (0, 0),
+            choice_log: None,
+            branch: vec![],
         }
     }
 
+    /// Panics if `g` has symbolic phases, which must be substituted first, see
+    /// [`GraphLike::substitute`].
     pub fn new(g: &G) -> Decomposer<G> {
-        Decomposer {
-            result: ComputationNode::Graph(g.clone()),
-            done: vec![],
-            nterms: 0,
-            simp_func: NoSimp,
-            driver: BssTOnly(false),
-            split_graph_components: false,
-            save: false,
+        if g.has_symbols() {
+            panic!("Symbolic phases must be substituted before decomposing");
         }
+        let mut d = Decomposer::empty();
+        d.result = ComputationNode::Graph(g.clone());
+        d
     }
 
     pub fn scalar(&self) -> FScalar {
@@ -560,6 +1352,20 @@ impl<G: GraphLike> Decomposer<G> {
         }
     }
 
+    /// Returns the sum of the terms finished so far, see [`PartialScalar`]
+    ///
+    /// Once decomposition is complete, this agrees with [`Decomposer::scalar`] up to
+    /// rounding.
+    pub fn partial_scalar(&self) -> FScalar {
+        self.partial.get()
+    }
+
+    /// Returns a handle to the partial scalar, which can be read while decomposing,
+    /// e.g. from another thread
+    pub fn partial_scalar_handle(&self) -> PartialScalar {
+        self.partial.clone()
+    }
+
     pub fn with_simp(&mut self, f: SimpFunc) -> &mut Self {
         self.simp_func = f;
         self
@@ -571,6 +1377,11 @@ impl<G: GraphLike> Decomposer<G> {
         self.with_simp(CliffordSimp)
     }
 
+    pub fn with_scalar_mode(&mut self, m: ScalarMode) -> &mut Self {
+        self.scalar_mode = m;
+        self
+    }
+
     pub fn with_split_graphs_components(&mut self, b: bool) -> &mut Self {
         self.split_graph_components = b;
         self
@@ -583,9 +1394,189 @@ impl<G: GraphLike> Decomposer<G> {
 
     pub fn with_driver(&mut self, d: Driver) -> &mut Self {
         self.driver = d;
+        self.fn_driver = None;
         self
     }
 
+    /// Use a closure to choose decompositions, in place of the [Driver]
+    pub fn with_fn_driver(&mut self, d: FnDriver<G>) -> &mut Self {
+        self.fn_driver = Some(d);
+        self
+    }
+
+    /// Stop decomposing once more than `n` terms are needed
+    ///
+    /// The limit applies to each run, i.e. each call to one of the `decompose` methods.
+    /// When it is hit, [`Decomposer::try_decompose`] returns
+    /// [`DecomposeError::TermLimitExceeded`] and leaves the target, `nterms`, `done`
+    /// and the partial scalar as they were before the run, while the other methods
+    /// panic. Unlike [`Decomposer::max_terms`], this counts the terms that are actually
+    /// reached, after simplification.
+    pub fn with_max_terms(&mut self, n: u128) -> &mut Self {
+        self.term_limit = Some(n);
+        self
+    }
+
+    /// Stop decomposing once a run has taken longer than `t`
+    ///
+    /// Like [`Decomposer::with_max_terms`], this applies to each run. When the time is
+    /// up, [`Decomposer::try_decompose`] returns [`DecomposeError::Timeout`] and
+    /// restores the same state as for the term limit, while the other methods panic. The clock
+    /// is checked before each graph is decomposed, so a run can take a bit longer
+    /// than `t` if simplifying a single graph is slow.
+    pub fn with_timeout(&mut self, t: Duration) -> &mut Self {
+        self.timeout = Some(t);
+        self
+    }
+
+    /// Record the decomposition chosen at each branch to a file, or replay the choices
+    /// in a file
+    ///
+    /// Each line of the log gives the path to a branch from the root of the
+    /// decomposition tree, as child indices separated by dots or `-` for the root,
+    /// followed by the name of the decomposition and the vertices it uses, e.g.
+    /// `0.2 t 4 7 9`. Lines starting with `#` are ignored. Only the choices of the
+    /// [Driver] are logged, so a replay should use the same settings otherwise, in
+    /// particular the same simplification. Paths don't depend on the order branches
+    /// are visited in, so the log also works for [`Decomposer::decompose_parallel`].
+    ///
+    /// In [`ChoiceLogMode::Record`] mode, the log is written at the end of each run.
+    /// In [`ChoiceLogMode::Replay`] mode, it is read at the start of each run, and
+    /// [`Decomposer::try_decompose`] returns [`DecomposeError::ChoiceLog`] if a branch
+    /// is missing from the log or its vertices are not in the graph, not T-spiders or
+    /// the wrong number, while the other methods panic. The hub of a cat or GHZ
+    /// decomposition must also be a Pauli spider, whose neighbours are exactly the
+    /// listed legs for a cat, and only connected to the hub for a GHZ structure.
+    pub fn with_choice_log(&mut self, file: impl AsRef<Path>, mode: ChoiceLogMode) -> &mut Self {
+        self.choice_log = Some(ChoiceLog {
+            file: file.as_ref().to_path_buf(),
+            mode,
+            choices: Arc::default(),
+            error: Arc::default(),
+        });
+        self
+    }
+
+    /// Count the terms at each depth of the decomposition tree, see [`Decomposer::level_stats`]
+    pub fn with_level_stats(&mut self, b: bool) -> &mut Self {
+        self.level_stats = b.then(Arc::default);
+        self
+    }
+
+    /// Returns the number of terms at each depth of the decomposition tree in the last
+    /// run, or an empty vec if [`Decomposer::with_level_stats`] is not set
+    ///
+    /// Index `d` counts the graphs at depth `d` which are still non-zero after
+    /// simplification, so `stats[d + 1] / stats[d]` is the branching factor of level
+    /// `d` after pruning. Splitting a graph into components also counts as a level.
+    pub fn level_stats(&self) -> Vec<usize> {
+        self.level_stats
+            .as_ref()
+            .map_or(vec![], |s| s.lock().unwrap().clone())
+    }
+
+    fn count_level(&self, depth: i64) {
+        if let Some(stats) = &self.level_stats {
+            let mut stats = stats.lock().unwrap();
+            let d = depth as usize;
+            if stats.len() <= d {
+                stats.resize(d + 1, 0);
+            }
+            stats[d] += 1;
+        }
+    }
+
+    fn simp(&self, g: &mut G, f: fn(&mut G) -> bool) {
+        if self.dense_rules {
+            crate::simplify::with_dense_rules(|| f(g));
+        } else {
+            f(g);
+        }
+    }
+
+    /// Merge the leaves of [`Decomposer::decompose_to_sum`] which give the same
+    /// stabiliser state, which is the default
+    ///
+    /// If this is false, the sum has one term for each leaf with a non-zero scalar. In
+    /// either case, graph components are not split, see [`Decomposer::decompose_to_sum`].
+    pub fn with_merge_leaves(&mut self, b: bool) -> &mut Self {
+        self.merge_leaves = b;
+        self
+    }
+
+    /// Simplify with the bitset versions of local complementation and pivoting from
+    /// [`crate::dense`]
+    ///
+    /// This gives the same results either way. It is faster when the graphs have
+    /// spiders with dozens of neighbours, but slightly slower on sparse graphs.
+    pub fn with_dense_rules(&mut self, b: bool) -> &mut Self {
+        self.dense_rules = b;
+        self
+    }
+
+    /// Returns the number of leaves reached by the last call to
+    /// [`Decomposer::decompose_to_sum`], and the number of terms in the sum it returned
+    ///
+    /// With [`Decomposer::with_merge_leaves`], the second number can be much smaller.
+    pub fn leaf_counts(&self) -> (usize, usize) {
+        self.leaf_counts
+    }
+
+    /// Decompose spiders with the phase of the given [`MagicBasis`] using that basis
+    ///
+    /// Spiders are decomposed one at a time, before the [Driver] is used for any
+    /// remaining non-Clifford spiders. A closure set with
+    /// [`Decomposer::with_fn_driver`] takes precedence over the basis.
+    pub fn with_magic_basis(&mut self, basis: MagicBasis) -> &mut Self {
+        self.magic_basis = Some(basis);
+        self
+    }
+
+    /// A decomposer with no target and the given settings, see [`Decomposer::set_target`]
+    ///
+    /// Panics if the timeout is negative or not finite.
+    pub fn from_config(config: &DecomposerConfig) -> Decomposer<G> {
+        let mut d = Decomposer::empty();
+        d.with_simp(config.simp)
+            .with_driver(config.driver.clone())
+            .with_scalar_mode(config.scalar_mode)
+            .with_split_graphs_components(config.split_graph_components)
+            .with_save(config.save)
+            .with_level_stats(config.level_stats)
+            .with_merge_leaves(config.merge_leaves)
+            .with_dense_rules(config.dense_rules);
+        d.term_limit = config.max_terms;
+        d.timeout = config.timeout_secs.map(Duration::from_secs_f64);
+        if let Some((file, mode)) = &config.choice_log {
+            d.with_choice_log(file, *mode);
+        }
+        d
+    }
+
+    /// The current settings, e.g. to record which settings gave a result
+    ///
+    /// The `parallel` field is always false, as this is not a setting of the
+    /// decomposer itself.
+    pub fn config(&self) -> DecomposerConfig {
+        DecomposerConfig {
+            simp: self.simp_func,
+            driver: self.driver.clone(),
+            scalar_mode: self.scalar_mode,
+            split_graph_components: self.split_graph_components,
+            save: self.save,
+            max_terms: self.term_limit,
+            timeout_secs: self.timeout.map(|t| t.as_secs_f64()),
+            choice_log: self
+                .choice_log
+                .as_ref()
+                .map(|log| (log.file.clone(), log.mode)),
+            level_stats: self.level_stats.is_some(),
+            merge_leaves: self.merge_leaves,
+            dense_rules: self.dense_rules,
+            parallel: false,
+        }
+    }
+
     /// Computes the maximum number of terms that this decomposer will produce
     pub fn max_terms(&self) -> f64 {
         calc_max_terms(&self.result)
@@ -593,22 +1584,27 @@ impl<G: GraphLike> Decomposer<G> {
 
     pub fn set_target(&mut self, g: G) -> &mut Self {
         self.result = ComputationNode::Graph(g.clone());
+        self.partial = PartialScalar::default();
         self
     }
 
     #[deprecated = "This function is outdated and may not work as expected, please use with_driver instead"]
     pub fn use_cats(&mut self, b: bool) -> &mut Self {
         if b {
-            self.driver = BssWithCats(false);
+            self.driver = BssWithCats(SpiderSelection::First);
         } else {
-            self.driver = BssTOnly(false);
+            self.driver = BssTOnly(SpiderSelection::First);
         }
         self
     }
 
     #[deprecated = "This function is outdated and may not work as expected, please use with_driver instead"]
     pub fn random_t(&mut self, b: bool) -> &mut Self {
-        self.driver = BssTOnly(b);
+        self.driver = BssTOnly(if b {
+            SpiderSelection::Random(None)
+        } else {
+            SpiderSelection::First
+        });
         self
     }
 
@@ -619,7 +1615,8 @@ impl<G: GraphLike> Decomposer<G> {
     }
 
     pub fn decomp_until_depth(&mut self, depth: i64) -> &mut Self {
-        self.result = self.decompose_graph(self.result.clone(), false, 0, depth, false);
+        self.run(false, depth, false)
+            .unwrap_or_else(|e| panic!("{e}"));
         self
     }
 
@@ -629,14 +1626,379 @@ impl<G: GraphLike> Decomposer<G> {
     }
 
     /// Decompose until there are no T gates left
+    ///
+    /// Panics if a limit set by [`Decomposer::with_max_terms`] or
+    /// [`Decomposer::with_timeout`] is exceeded.
     pub fn decompose(&mut self) -> &mut Self {
-        self.result = self.decompose_graph(self.result.clone(), false, 0, -1, true);
-        self
+        self.try_decompose().unwrap_or_else(|e| panic!("{e}"))
     }
 
     pub fn decompose_parallel(&mut self) -> &mut Self {
-        self.result = self.decompose_graph(self.result.clone(), true, 0, -1, true);
-        self
+        self.try_decompose_parallel()
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Decompose until there are no T gates left, or return an error if a limit set
+    /// by [`Decomposer::with_max_terms`] or [`Decomposer::with_timeout`] is exceeded
+    pub fn try_decompose(&mut self) -> Result<&mut Self, DecomposeError> {
+        self.run(false, -1, true)?;
+        Ok(self)
+    }
+
+    /// Parallel version of [`Decomposer::try_decompose`]
+    pub fn try_decompose_parallel(&mut self) -> Result<&mut Self, DecomposeError> {
+        self.run(true, -1, true)?;
+        Ok(self)
+    }
+
+    /// Decomposes the target, only replacing it if the term limit was not exceeded
+    fn run(
+        &mut self,
+        parallel: bool,
+        target_depth: i64,
+        reduce_computation: bool,
+    ) -> Result<(), DecomposeError> {
+        self.start_run();
+        if self.level_stats.is_some() {
+            self.level_stats = Some(Arc::default());
+        }
+        if let Some(log) = &self.choice_log {
+            *log.error.lock().unwrap() = None;
+            match log.mode {
+                ChoiceLogMode::Record => log.choices.lock().unwrap().clear(),
+                ChoiceLogMode::Replay => log
+                    .read()
+                    .map_err(|message| DecomposeError::ChoiceLog { message })?,
+            }
+        }
+        let (nterms, ndone) = (self.nterms, self.done.len());
+        let (partial, partial_terms) = (self.partial.get(), self.partial.terms());
+        let result = self.decompose_graph(
+            self.result.clone(),
+            parallel,
+            0,
+            target_depth,
+            reduce_computation,
+        );
+        let finished = self.check_limits().and_then(|()| match &self.choice_log {
+            Some(log) if log.mode == ChoiceLogMode::Record => log
+                .write()
+                .map_err(|message| DecomposeError::ChoiceLog { message }),
+            _ => Ok(()),
+        });
+        if let Err(e) = finished {
+            self.nterms = nterms;
+            self.done.truncate(ndone);
+            self.partial.set(partial, partial_terms);
+            return Err(e);
+        }
+        self.result = result;
+        Ok(())
+    }
+
+    fn start_run(&mut self) {
+        self.explored = Arc::default();
+        self.deadline = self.timeout.map(|t| Instant::now() + t);
+    }
+
+    fn term_limit_exceeded(&self) -> bool {
+        self.term_limit
+            .is_some_and(|n| self.explored.load(Ordering::Relaxed) as u128 > n)
+    }
+
+    fn timed_out(&self) -> bool {
+        self.deadline.is_some_and(|d| Instant::now() > d)
+    }
+
+    fn check_limits(&self) -> Result<(), DecomposeError> {
+        let explored = self.explored.load(Ordering::Relaxed);
+        let log_error = self
+            .choice_log
+            .as_ref()
+            .and_then(|log| log.error.lock().unwrap().clone());
+        if let Some(message) = log_error {
+            Err(DecomposeError::ChoiceLog { message })
+        } else if self.term_limit_exceeded() {
+            Err(DecomposeError::TermLimitExceeded { explored })
+        } else if self.timed_out() {
+            Err(DecomposeError::Timeout { explored })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Decompose until there are no T gates left, and return the result as a sum of
+    /// stabiliser states
+    ///
+    /// The target graph should be a state, i.e. have no inputs. Unlike
+    /// [`Decomposer::decompose`], the outputs of each term are kept, and terms which
+    /// give the same stabiliser state are merged, unless
+    /// [`Decomposer::with_merge_leaves`] is false. See [`Decomposer::leaf_counts`] for
+    /// the number of terms before and after merging.
+    ///
+    /// [`Decomposer::with_split_graphs_components`] is ignored during this run, as the
+    /// leaves of each component would be saved as separate terms of the sum.
+    pub fn decompose_to_sum(&mut self) -> StabSum {
+        let (save, split) = (self.save, self.split_graph_components);
+        let start = self.done.len();
+        self.with_save(true)
+            .with_split_graphs_components(false)
+            .decompose();
+        (self.save, self.split_graph_components) = (save, split);
+
+        let sum = if self.merge_leaves {
+            StabSum::from_graphs(&self.done[start..])
+        } else {
+            let mut sum = StabSum::new();
+            for g in &self.done[start..] {
+                sum.push_graph(g);
+            }
+            sum
+        };
+        self.leaf_counts = (self.done.len() - start, sum.len());
+        if !save {
+            self.done.truncate(start);
+        }
+        sum
+    }
+
+    /// Decomposes the operator given by `g` into a sum of stabiliser operators
+    ///
+    /// The inputs of `g` are bent round to be outputs, and the resulting state is
+    /// decomposed with full simplification and the given driver, as in
+    /// [`Decomposer::decompose_to_sum`]. See [`OperatorStabSum`] for how the terms are
+    /// stored. For a graph with no inputs, this is the same as decomposing the state.
+    pub fn decompose_operator(g: &G, driver: Driver) -> OperatorStabSum {
+        let mut choi = g.clone();
+        let mut outputs = choi.inputs().clone();
+        outputs.extend_from_slice(g.outputs());
+        choi.set_inputs(vec![]);
+        choi.set_outputs(outputs);
+
+        let mut d = Decomposer::new(&choi);
+        d.with_full_simp().with_driver(driver);
+        OperatorStabSum {
+            num_inputs: g.inputs().len(),
+            num_outputs: g.outputs().len(),
+            choi: d.decompose_to_sum(),
+        }
+    }
+
+    /// Computes the norm squared <psi|psi> of the state given by `g`
+    ///
+    /// The state is plugged into its adjoint and the resulting scalar diagram is
+    /// decomposed with full simplification and the given driver. For the state of a
+    /// circuit applied to a basis state, this should be 1.
+    ///
+    /// Panics if `g` has inputs.
+    pub fn state_norm_squared(g: &G, driver: Driver) -> f64 {
+        if !g.inputs().is_empty() {
+            panic!(
+                "Expected a state, but the graph has {} inputs",
+                g.inputs().len()
+            );
+        }
+
+        let mut h = g.clone();
+        h.plug(&g.to_adjoint());
+        let mut d = Decomposer::new(&h);
+        d.with_full_simp().with_driver(driver).decompose();
+        d.scalar().complex_value().re
+    }
+
+    /// Computes the probability of every outcome of measuring all outputs of the state
+    /// given by `g` in the Z basis
+    ///
+    /// The state is simplified once, then all amplitudes are decomposed with [`batch`]
+    /// and the given driver. Outcomes are sorted by probability, largest first, and
+    /// ties by bit string. Returns an error if `g` has more than `max_qubits` outputs,
+    /// or if the probabilities don't sum to 1 up to 1e-6, e.g. because the state is
+    /// not normalised.
+    ///
+    /// Panics if `g` has inputs.
+    pub fn output_distribution(
+        g: &G,
+        driver: Driver,
+        max_qubits: usize,
+    ) -> Result<Vec<(Vec<bool>, f64)>, String> {
+        if !g.inputs().is_empty() {
+            panic!(
+                "Expected a state, but the graph has {} inputs",
+                g.inputs().len()
+            );
+        }
+        let n = g.outputs().len();
+        if n > max_qubits {
+            return Err(format!(
+                "Graph has {n} outputs, more than the limit of {max_qubits}"
+            ));
+        }
+
+        let mut state = g.clone();
+        crate::simplify::full_simp(&mut state);
+        let outcomes: Vec<Vec<bool>> = (0..1usize << n)
+            .map(|x| (0..n).map(|i| x >> (n - 1 - i) & 1 == 1).collect())
+            .collect();
+        let graphs: Vec<G> = outcomes
+            .iter()
+            .map(|bits| {
+                let mut h = state.clone();
+                let plug: Vec<BasisElem> = bits
+                    .iter()
+                    .map(|&b| if b { BasisElem::Z1 } else { BasisElem::Z0 })
+                    .collect();
+                h.plug_outputs(&plug);
+                h
+            })
+            .collect();
+
+        let mut dist: Vec<(Vec<bool>, f64)> = outcomes
+            .into_iter()
+            .zip(batch(&graphs, driver, None, true))
+            .map(|(bits, r)| {
+                let r = r.unwrap_or_else(|e| panic!("{e}"));
+                (bits, r.scalar.complex_value().norm_sqr())
+            })
+            .collect();
+        let total: f64 = dist.iter().map(|(_, p)| p).sum();
+        if (total - 1.0).abs() > 1e-6 {
+            return Err(format!("Probabilities sum to {total}, expected 1"));
+        }
+        dist.sort_by(|(b0, p0), (b1, p1)| p1.total_cmp(p0).then_with(|| b0.cmp(b1)));
+        Ok(dist)
+    }
+
+    /// Splits the decomposition of the scalar graph `g` into at least `n_units`
+    /// independent parts, e.g. to solve them on different machines
+    ///
+    /// The decomposition tree is expanded breadth first, with full simplification and
+    /// the given driver, until there are `n_units` parts or no part can be expanded.
+    /// Each part can then be solved with [`Decomposer::decompose_unit`] and the same
+    /// driver, and the scalar of `g` is the sum of the results, see [`combine_units`].
+    /// Parts that reach a Clifford graph while expanding are solved right away and
+    /// given as empty graphs, and parts with a zero scalar are dropped, so there can
+    /// be fewer than `n_units` parts. They are sorted by their paths.
+    pub fn split_into_units(g: &G, driver: Driver, n_units: usize) -> Vec<WorkUnit> {
+        let mut frontier = VecDeque::from([(vec![], g.clone())]);
+        let mut units = vec![];
+        while !frontier.is_empty() && frontier.len() + units.len() < n_units {
+            let (path, h) = frontier.pop_front().unwrap();
+            let mut d = Decomposer::new(&h);
+            d.with_full_simp()
+                .with_driver(driver.clone())
+                .decomp_until_depth(1);
+            match d.result {
+                ComputationNode::Sum(children) => {
+                    for (i, child) in children.into_iter().enumerate() {
+                        if let ComputationNode::Graph(child) = child {
+                            if !child.scalar().is_zero() {
+                                let mut child_path = path.clone();
+                                child_path.push(i);
+                                frontier.push_back((child_path, child));
+                            }
+                        }
+                    }
+                }
+                ComputationNode::Scalar(s) if !s.is_zero() => {
+                    let mut leaf = G::new();
+                    *leaf.scalar_mut() = s;
+                    units.push(WorkUnit::new(path, leaf));
+                }
+                _ => {}
+            }
+        }
+        units.extend(frontier.into_iter().map(|(path, h)| WorkUnit::new(path, h)));
+        units.sort_by(|u0, u1| u0.path.cmp(&u1.path));
+        units
+    }
+
+    /// Decomposes one part given by [`Decomposer::split_into_units`], with full
+    /// simplification and the given driver
+    ///
+    /// Returns an error if the graph of the part can't be decoded.
+    pub fn decompose_unit(unit: &WorkUnit, driver: Driver) -> Result<FScalar, JsonError> {
+        let g: G = unit.graph()?;
+        let mut d = Decomposer::new(&g);
+        d.with_full_simp().with_driver(driver).decompose();
+        Ok(d.scalar())
+    }
+
+    /// Builds the decomposition tree of `g`, without computing any scalars
+    ///
+    /// This branches exactly like decomposing `g` with full simplification and the
+    /// given driver, so the leaves of the tree are the terms of
+    /// [`Decomposer::decompose`], but they are not evaluated or added up. See
+    /// [`DecompTree::to_dot`] for drawing the tree.
+    pub fn dry_run(g: &G, driver: Driver) -> DecompTree {
+        let mut d = Decomposer::new(g);
+        d.with_full_simp().with_driver(driver);
+        d.dry_run_graph(g.clone())
+    }
+
+    /// Decompose only the given T-like spiders with `driver`, leaving all other
+    /// T-like spiders in place
+    ///
+    /// At each branch, the driver is shown a copy of the graph in which the unlisted
+    /// T-like spiders have phase π/2, so it only cuts listed spiders, or Pauli hubs
+    /// with listed legs. T-like spiders introduced by a decomposition, e.g. when a cat
+    /// is padded, count as listed. Branches are not simplified while decomposing, so
+    /// that the vertex names in `spiders` stay valid in every term. Terms with no
+    /// listed spiders left are reduced to scalars, the rest are kept as graphs and can
+    /// be finished later by calling [`Decomposer::decompose`].
+    ///
+    /// Returns [`DecomposeError::NotTLike`] if a listed vertex is not a T-like spider
+    /// of the target graph, or if the graph has some other non-Clifford spider, which
+    /// the remaining terms could never be reduced to Cliffords with.
+    pub fn decompose_subset(
+        &mut self,
+        spiders: &[V],
+        driver: Driver,
+    ) -> Result<&mut Self, DecomposeError> {
+        let g = match &self.result {
+            ComputationNode::Graph(g) => g,
+            ComputationNode::None => panic!("Not yet initialised"),
+            _ => panic!("Can only decompose a subset of an undecomposed graph"),
+        };
+        let not_t = |v: V| !g.contains_vertex(v) || !g.phase(v).is_t();
+        let not_reducible = |v: V| !g.phase(v).is_clifford() && !g.phase(v).is_t();
+        if let Some(&vertex) = spiders.iter().find(|&&v| not_t(v)) {
+            return Err(DecomposeError::NotTLike { vertex });
+        }
+        if let Some(vertex) = g.vertices().find(|&v| not_reducible(v)) {
+            return Err(DecomposeError::NotTLike { vertex });
+        }
+
+        self.start_run();
+        let spiders: FxHashSet<V> = spiders.iter().copied().collect();
+        self.result = match std::mem::replace(&mut self.result, ComputationNode::None) {
+            ComputationNode::Graph(g) => self.decompose_subset_graph(g, spiders, &driver),
+            _ => unreachable!(),
+        };
+        self.check_limits()?;
+        Ok(self)
+    }
+
+    /// Decompose the first `k` T-like spiders of the target graph, as in
+    /// [`Decomposer::decompose_subset`]
+    pub fn decompose_first_ts(
+        &mut self,
+        k: usize,
+        driver: Driver,
+    ) -> Result<&mut Self, DecomposeError> {
+        let spiders: Vec<V> = match &self.result {
+            ComputationNode::Graph(g) => g
+                .vertices()
+                .filter(|&v| g.phase(v).is_t())
+                .take(k)
+                .collect(),
+            _ => vec![],
+        };
+        self.decompose_subset(&spiders, driver)
+    }
+
+    /// Returns the largest T-count of any term which has not yet been decomposed
+    pub fn residual_tcount(&self) -> usize {
+        calc_residual_tcount(&self.result)
     }
 
     #[deprecated = "This function is outdated, please use decompose instead"]
@@ -644,6 +2006,47 @@ impl<G: GraphLike> Decomposer<G> {
         self.decompose()
     }
 
+    fn decompose_subset_graph(
+        &mut self,
+        g: G,
+        spiders: FxHashSet<V>,
+        driver: &Driver,
+    ) -> ComputationNode<G> {
+        let listed = |g: &G, v: V| spiders.contains(&v) && g.phase(v).is_t();
+        if g.vertices().any(|v| listed(&g, v)) {
+            let mut masked = g.clone();
+            for v in g.vertices() {
+                if g.phase(v).is_t() && !listed(&g, v) {
+                    masked.set_phase(v, Rational64::new(1, 2));
+                }
+            }
+            let terms: Vec<_> = driver
+                .choose_decomp(&masked)
+                .apply(&g)
+                .into_iter()
+                .map(|term| {
+                    let mut spiders = spiders.clone();
+                    spiders.extend(term.vertices().filter(|&v| {
+                        term.phase(v).is_t() && !(g.contains_vertex(v) && g.phase(v).is_t())
+                    }));
+                    self.decompose_subset_graph(term, spiders, driver)
+                })
+                .collect();
+            if terms
+                .iter()
+                .all(|node| matches!(node, ComputationNode::Scalar(_)))
+            {
+                ComputationNode::Scalar(self.sum_nodes(terms))
+            } else {
+                ComputationNode::Sum(terms)
+            }
+        } else if g.tcount() == 0 {
+            self.decompose_graph(ComputationNode::Graph(g), false, 0, -1, true)
+        } else {
+            ComputationNode::Graph(g)
+        }
+    }
+
     fn node_to_scalar(&mut self, node: ComputationNode<G>) -> FScalar {
         if let ComputationNode::Scalar(scalar) = node {
             scalar
@@ -652,6 +2055,101 @@ impl<G: GraphLike> Decomposer<G> {
         }
     }
 
+    /// Adds up the scalars of the given nodes, according to the scalar mode
+    fn sum_nodes(&mut self, nodes: Vec<ComputationNode<G>>) -> FScalar {
+        let mode = self.scalar_mode;
+        let scalars = nodes.into_iter().map(|node| self.node_to_scalar(node));
+        match mode {
+            ScalarMode::Float => scalars.sum(),
+            ScalarMode::ExactPhase => FScalar::exact_sum(scalars),
+        }
+    }
+
+    /// Multiplies the scalars of the given nodes, according to the scalar mode
+    fn prod_nodes(&mut self, nodes: Vec<ComputationNode<G>>) -> FScalar {
+        let mode = self.scalar_mode;
+        let scalars = nodes.into_iter().map(|node| self.node_to_scalar(node));
+        match mode {
+            ScalarMode::Float => scalars.product(),
+            ScalarMode::ExactPhase => FScalar::exact_product(scalars),
+        }
+    }
+
+    fn dry_run_graph(&mut self, mut g: G) -> DecompTree {
+        if !g.scalar().is_zero() {
+            match self.simp_func {
+                FullSimp => self.simp(&mut g, crate::simplify::full_simp),
+                CliffordSimp => self.simp(&mut g, crate::simplify::clifford_simp),
+                NoSimp => {}
+            }
+        }
+        let tcount = g.tcount();
+        let leaf = |kind| DecompTree {
+            tcount,
+            kind,
+            children: vec![],
+        };
+        if g.scalar().is_zero() {
+            return leaf(DecompTreeKind::Zero);
+        }
+        if tcount == 0 {
+            return leaf(DecompTreeKind::Leaf);
+        }
+        let decomp = self.driver.choose_decomp(&g);
+        let children = decomp
+            .apply(&g)
+            .into_iter()
+            .map(|h| self.dry_run_graph(h))
+            .collect();
+        DecompTree {
+            tcount,
+            kind: DecompTreeKind::Sum(decomp),
+            children,
+        }
+    }
+
+    /// Decomposes the `i`-th child of the graph at the current branch
+    fn decompose_child(
+        &mut self,
+        i: usize,
+        node: ComputationNode<G>,
+        parallel: bool,
+        current_depth: i64,
+        target_depth: i64,
+        reduce_computation: bool,
+    ) -> ComputationNode<G> {
+        self.branch.push(i);
+        let result = self.decompose_graph(
+            node,
+            parallel,
+            current_depth,
+            target_depth,
+            reduce_computation,
+        );
+        self.branch.pop();
+        result
+    }
+
+    /// Asks the driver for a decomposition, or replays one from the choice log
+    ///
+    /// Returns `None` if the log doesn't match the graph.
+    fn choose_decomp(&self, g: &G) -> Option<Decomp> {
+        match &self.choice_log {
+            Some(log) if log.mode == ChoiceLogMode::Replay => {
+                log.replay(&self.branch, g).map_err(|e| log.fail(e)).ok()
+            }
+            Some(log) => {
+                let d = self.driver.choose_decomp(g);
+                log.choices
+                    .lock()
+                    .unwrap()
+                    .insert(self.branch.clone(), d.clone());
+                Some(d)
+            }
+            None => Some(self.driver.choose_decomp(g)),
+        }
+    }
+
     fn decompose_graph(
         &mut self,
         node: ComputationNode<G>,
@@ -669,17 +2167,20 @@ impl<G: GraphLike> Decomposer<G> {
             ComputationNode::Sum(terms) => {
                 let results: Vec<_> = terms
                     .into_iter()
-                    .map(|term| {
-                        self.decompose_graph(term, parallel, current_depth + 1, target_depth, true)
+                    .enumerate()
+                    .map(|(i, term)| {
+                        self.decompose_child(
+                            i,
+                            term,
+                            parallel,
+                            current_depth + 1,
+                            target_depth,
+                            true,
+                        )
                     })
                     .collect();
                 if reduce_computation {
-                    ComputationNode::Scalar(
-                        results
-                            .into_iter()
-                            .map(|node| self.node_to_scalar(node))
-                            .sum(),
-                    )
+                    ComputationNode::Scalar(self.sum_nodes(results))
                 } else {
                     ComputationNode::Sum(results)
                 }
@@ -688,8 +2189,10 @@ impl<G: GraphLike> Decomposer<G> {
                 if reduce_computation {
                     let results: Vec<_> = terms
                         .into_iter()
-                        .map(|term| {
-                            self.decompose_graph(
+                        .enumerate()
+                        .map(|(i, term)| {
+                            self.decompose_child(
+                                i,
                                 term,
                                 parallel,
                                 current_depth + 1,
@@ -699,12 +2202,7 @@ impl<G: GraphLike> Decomposer<G> {
                         })
                         .collect();
                     if reduce_computation {
-                        ComputationNode::Scalar(
-                            results
-                                .into_iter()
-                                .map(|node| self.node_to_scalar(node))
-                                .product(),
-                        )
+                        ComputationNode::Scalar(self.prod_nodes(results))
                     } else {
                         ComputationNode::Prod(results)
                     }
@@ -712,8 +2210,10 @@ impl<G: GraphLike> Decomposer<G> {
                     ComputationNode::Prod(
                         terms
                             .into_iter()
-                            .map(|term| {
-                                self.decompose_graph(
+                            .enumerate()
+                            .map(|(i, term)| {
+                                self.decompose_child(
+                                    i,
                                     term,
                                     parallel,
                                     current_depth + 1,
@@ -728,10 +2228,19 @@ impl<G: GraphLike> Decomposer<G> {
             ComputationNode::Graph(mut g) => {
                 if current_depth == target_depth {
                     ComputationNode::Graph(g)
+                } else if self.term_limit_exceeded()
+                    || self.timed_out()
+                    || self.choice_log.as_ref().is_some_and(|log| log.failed())
+                {
+                    // the result is discarded, so just unwind as quickly as possible
+                    ComputationNode::Scalar(FScalar::zero())
+                } else if g.scalar().is_zero() {
+                    ComputationNode::Scalar(FScalar::zero())
                 } else {
                     if self.split_graph_components {
                         let components = g.component_vertices();
                         if components.len() > 1 {
+                            self.count_level(current_depth);
                             // println!("Number of components {}", components.len());
                             let subgraphs: Vec<G> = components
                                 .into_iter()
@@ -739,12 +2248,16 @@ impl<G: GraphLike> Decomposer<G> {
                                     g.subgraph_from_vertices(component.into_iter().collect())
                                 })
                                 .collect();
+                            let in_product = self.in_product;
+                            self.in_product = true;
                             let terms_vec: Vec<ComputationNode<G>> = if parallel {
                                 subgraphs
                                     .into_par_iter()
-                                    .map(|term| {
+                                    .enumerate()
+                                    .map(|(i, term)| {
                                         let mut d = self.clone();
-                                        d.decompose_graph(
+                                        d.decompose_child(
+                                            i,
                                             ComputationNode::Graph(term),
                                             parallel,
                                             current_depth + 1,
@@ -756,8 +2269,10 @@ impl<G: GraphLike> Decomposer<G> {
                             } else {
                                 subgraphs
                                     .into_iter()
-                                    .map(|term| {
-                                        self.decompose_graph(
+                                    .enumerate()
+                                    .map(|(i, term)| {
+                                        self.decompose_child(
+                                            i,
                                             ComputationNode::Graph(term),
                                             parallel,
                                             current_depth + 1,
@@ -767,6 +2282,7 @@ impl<G: GraphLike> Decomposer<G> {
                                     })
                                     .collect()
                             };
+                            self.in_product = in_product;
                             if reduce_computation {
                                 // println!("Component Scalars {:?}", (terms_vec.clone().into_iter().map(|node| self.node_to_scalar(node))).collect_vec());
                                 // let prod: FScalar = terms_vec.clone()
@@ -774,12 +2290,11 @@ impl<G: GraphLike> Decomposer<G> {
                                 // .map(|node| self.node_to_scalar(node))
                                 // .product();
                                 // println!("Product; {}", prod);
-                                return ComputationNode::Scalar(
-                                    terms_vec
-                                        .into_iter()
-                                        .map(|node| self.node_to_scalar(node))
-                                        .product(),
-                                );
+                                let scalar = self.prod_nodes(terms_vec);
+                                if !in_product {
+                                    self.partial.add(scalar);
+                                }
+                                return ComputationNode::Scalar(scalar);
                             } else {
                                 return ComputationNode::Prod(terms_vec);
                             }
@@ -787,17 +2302,24 @@ impl<G: GraphLike> Decomposer<G> {
                     };
                     match self.simp_func {
                         FullSimp => {
-                            crate::simplify::full_simp(&mut g);
+                            self.simp(&mut g, crate::simplify::full_simp);
                         }
                         CliffordSimp => {
-                            crate::simplify::clifford_simp(&mut g);
+                            self.simp(&mut g, crate::simplify::clifford_simp);
                         }
                         _ => {}
                     }
+                    // e.g. the graph was plugged with orthogonal states, so there is no
+                    // need to decompose it further
+                    if g.scalar().is_zero() {
+                        return ComputationNode::Scalar(FScalar::zero());
+                    }
+                    self.count_level(current_depth);
                     //check if clifford
                     if g.tcount() == 0 {
-                        crate::simplify::full_simp(&mut g);
+                        self.simp(&mut g, crate::simplify::full_simp);
                         self.nterms += 1;
+                        self.explored.fetch_add(1, Ordering::Relaxed);
                         if g.inputs().is_empty() && g.outputs().is_empty() && g.num_vertices() != 0
                         {
                             println!("{}", g.to_dot());
@@ -806,25 +2328,33 @@ impl<G: GraphLike> Decomposer<G> {
                         if self.save {
                             self.done.push(g.clone());
                         }
+                        if !self.in_product {
+                            self.partial.add(*g.scalar());
+                        }
                         return ComputationNode::Scalar(*g.scalar());
                     }
-                    let decomp = self.driver.choose_decomp(&g);
-                    let terms = match decomp {
-                        Magic5FromCat(vertices) => {
-                            apply_magic5_from_cat_decomp(&g, &vertices[0..5])
-                        }
-                        TDecomp(vertices) => apply_ts_decomp(&g, &vertices),
-                        CatDecomp(vertices) => apply_cat_decomp(&g, &vertices),
-                        BssDecomp(vertices) => apply_bss_decomp(&g, &vertices),
-                        SymDecomp(vertices) => apply_sym_decomp(&g, &vertices),
-                        SingleDecomp(vertices) => apply_single_decomp(&g, &vertices),
+                    let magic = self
+                        .magic_basis
+                        .as_ref()
+                        .and_then(|b| Some((b, b.matching_spider(&g)?)));
+                    let terms = if let Some(fn_driver) = &self.fn_driver {
+                        fn_driver.children(&g)
+                    } else if let Some((basis, v)) = magic {
+                        basis.apply(&g, v)
+                    } else {
+                        let Some(decomp) = self.choose_decomp(&g) else {
+                            return ComputationNode::Scalar(FScalar::zero());
+                        };
+                        decomp.apply(&g)
                     };
                     let terms_vec: Vec<ComputationNode<G>> = if parallel {
                         terms
                             .into_par_iter()
-                            .map(|term| {
+                            .enumerate()
+                            .map(|(i, term)| {
                                 let mut d = self.clone();
-                                d.decompose_graph(
+                                d.decompose_child(
+                                    i,
                                     ComputationNode::Graph(term),
                                     parallel,
                                     current_depth + 1,
@@ -836,8 +2366,10 @@ impl<G: GraphLike> Decomposer<G> {
                     } else {
                         terms
                             .into_iter()
-                            .map(|term| {
-                                self.decompose_graph(
+                            .enumerate()
+                            .map(|(i, term)| {
+                                self.decompose_child(
+                                    i,
                                     ComputationNode::Graph(term),
                                     parallel,
                                     current_depth + 1,
@@ -848,12 +2380,7 @@ impl<G: GraphLike> Decomposer<G> {
                             .collect()
                     };
                     if reduce_computation {
-                        ComputationNode::Scalar(
-                            terms_vec
-                                .into_iter()
-                                .map(|node| self.node_to_scalar(node))
-                                .sum(),
-                        )
+                        ComputationNode::Scalar(self.sum_nodes(terms_vec))
                     } else {
                         ComputationNode::Sum(terms_vec)
                     }
@@ -862,6 +2389,258 @@ impl<G: GraphLike> Decomposer<G> {
         }
     }
 }
+
+/// An independent part of a decomposition, see [`Decomposer::split_into_units`]
+///
+/// This can be serialized, e.g. to send it to another machine.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WorkUnit {
+    /// the graph, in the json format of [`crate::json`], with its scalar set to one
+    pub graph: String,
+    /// the child chosen at each branch from the root to the graph, as in
+    /// [`Decomposer::with_choice_log`]
+    pub path: Vec<usize>,
+    /// the scalar of the graph
+    pub prefactor: FScalar,
+}
+
+impl WorkUnit {
+    fn new(path: Vec<usize>, mut g: impl GraphLike) -> Self {
+        let prefactor = *g.scalar();
+        *g.scalar_mut() = FScalar::one();
+        WorkUnit {
+            graph: crate::json::encode_graph(&g).expect("Graphs being decomposed can be encoded"),
+            path,
+            prefactor,
+        }
+    }
+
+    /// Decodes the graph, with the prefactor as its scalar
+    pub fn graph<G: GraphLike>(&self) -> Result<G, JsonError> {
+        let mut g: G = crate::json::decode_graph(&self.graph)?;
+        *g.scalar_mut() *= self.prefactor;
+        Ok(g)
+    }
+}
+
+/// Adds up the results of [`Decomposer::decompose_unit`] for all the parts of a
+/// decomposition
+///
+/// The sum is exact, see [`FScalar::exact_sum`], so it doesn't depend on the order
+/// the results arrive in.
+pub fn combine_units(results: impl IntoIterator<Item = FScalar>) -> FScalar {
+    FScalar::exact_sum(results)
+}
+
+/// The shape of a decomposition, see [`Decomposer::dry_run`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecompTree {
+    /// the T-count of the graph at this node, after simplification
+    pub tcount: usize,
+    pub kind: DecompTreeKind,
+    pub children: Vec<DecompTree>,
+}
+
+/// What happens to the graph at a node of a [`DecompTree`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecompTreeKind {
+    /// a Clifford graph, which is a term of the decomposition
+    Leaf,
+    /// a graph with a zero scalar, which is dropped
+    Zero,
+    /// a graph that is replaced by the sum of the children, by the given decomposition
+    Sum(Decomp),
+}
+
+impl DecompTree {
+    /// The number of nodes in the tree
+    pub fn num_nodes(&self) -> usize {
+        1 + self.children.iter().map(|c| c.num_nodes()).sum::<usize>()
+    }
+
+    /// The number of [`DecompTreeKind::Leaf`] nodes, i.e. the number of terms
+    pub fn num_leaves(&self) -> usize {
+        match self.kind {
+            DecompTreeKind::Leaf => 1,
+            _ => self.children.iter().map(|c| c.num_leaves()).sum(),
+        }
+    }
+
+    /// The length of the longest path from the root to a leaf
+    pub fn depth(&self) -> usize {
+        self.children
+            .iter()
+            .map(|c| c.depth() + 1)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Return a graphviz-friendly string representation of the tree
+    ///
+    /// Each node is labelled by its T-count, and by its decomposition if it has
+    /// children. Zero nodes are grey.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph {\n");
+        let mut next = 0;
+        self.write_dot(&mut dot, &mut next);
+        dot += "}\n";
+        dot
+    }
+
+    /// Writes the nodes and edges of the subtree, returning the index of its root
+    fn write_dot(&self, dot: &mut String, next: &mut usize) -> usize {
+        let i = *next;
+        *next += 1;
+        match &self.kind {
+            DecompTreeKind::Leaf => *dot += &format!("  {i} [label=\"t={}\"]\n", self.tcount),
+            DecompTreeKind::Zero => {
+                *dot += &format!("  {i} [label=\"t={}\", color=grey]\n", self.tcount)
+            }
+            DecompTreeKind::Sum(d) => {
+                *dot += &format!("  {i} [label=\"{} t={}\"]\n", d.name(), self.tcount)
+            }
+        }
+        for c in &self.children {
+            let j = c.write_dot(dot, next);
+            *dot += &format!("  {i} -> {j}\n");
+        }
+        i
+    }
+}
+
+/// The outcome of decomposing one graph with [`batch`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DecompositionResult {
+    pub scalar: FScalar,
+    /// the number of stabiliser terms reached, see [`Decomposer::nterms`]
+    pub terms: usize,
+    pub elapsed: Duration,
+}
+
+/// Decomposes each of the given scalar graphs, with full simplification and the given
+/// driver
+///
+/// Each graph gets its own [`Decomposer::with_timeout`], if `timeout` is set, so a
+/// graph that takes too long gives a [`DecomposeError::Timeout`] in its place in the
+/// result, and the others are still decomposed. If `parallel` is set, the graphs are
+/// decomposed in parallel on rayon's global thread pool, each of them sequentially.
+pub fn batch<G: GraphLike>(
+    graphs: &[G],
+    driver: Driver,
+    timeout: Option<Duration>,
+    parallel: bool,
+) -> Vec<Result<DecompositionResult, DecomposeError>> {
+    let run = |g: &G| {
+        let start = Instant::now();
+        let mut d = Decomposer::new(g);
+        d.with_full_simp().with_driver(driver.clone());
+        if let Some(t) = timeout {
+            d.with_timeout(t);
+        }
+        d.try_decompose()?;
+        Ok(DecompositionResult {
+            scalar: d.scalar(),
+            terms: d.nterms,
+            elapsed: start.elapsed(),
+        })
+    };
+    if parallel {
+        graphs.par_iter().map(run).collect()
+    } else {
+        graphs.iter().map(run).collect()
+    }
+}
+
+/// Answers many amplitude queries on a dedicated thread pool
+///
+/// [`Decomposer::decompose_parallel`] runs on rayon's global thread pool, which is
+/// shared with everything else in the process. A `DecomposerPool` builds its own
+/// thread pool once, in [`DecomposerPool::new`], and runs every query on it with the
+/// same settings, so a service can keep one pool around and bound the number of
+/// threads used for decomposing.
+///
+/// The pool is `Send` and `Sync`, and [`DecomposerPool::amplitude`] takes `&self`, so
+/// it can be shared between threads, e.g. in an `Arc`. Concurrent queries share the
+/// worker threads: each caller blocks until its own query is done, while rayon
+/// interleaves the branches of all running queries. Each query still copies the
+/// graph it is given, which it needs in order to plug in the basis states.
+pub struct DecomposerPool {
+    pool: rayon::ThreadPool,
+    simp_func: SimpFunc,
+    scalar_mode: ScalarMode,
+}
+
+impl DecomposerPool {
+    /// Builds a pool with the given number of worker threads, or rayon's default
+    /// number if it is 0
+    ///
+    /// Queries are decomposed with full simplification and [`ScalarMode::Float`].
+    /// Panics if the threads can't be spawned.
+    pub fn new(num_threads: usize) -> Self {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(num_threads)
+            .thread_name(|i| format!("quizx-decomposer-{i}"))
+            .build()
+            .unwrap_or_else(|e| panic!("Failed to build the decomposer thread pool: {e}"));
+        DecomposerPool {
+            pool,
+            simp_func: FullSimp,
+            scalar_mode: ScalarMode::Float,
+        }
+    }
+
+    pub fn with_simp(&mut self, f: SimpFunc) -> &mut Self {
+        self.simp_func = f;
+        self
+    }
+
+    pub fn with_scalar_mode(&mut self, m: ScalarMode) -> &mut Self {
+        self.scalar_mode = m;
+        self
+    }
+
+    /// The number of worker threads
+    pub fn num_threads(&self) -> usize {
+        self.pool.current_num_threads()
+    }
+
+    /// Computes the amplitude `<output|g|input>` for the given basis states
+    ///
+    /// `inputs` and `outputs` are plugged into the boundaries of `g` as in
+    /// [`GraphLike::plug_inputs`] and [`GraphLike::plug_outputs`]. Panics unless there
+    /// is one basis state for each boundary, none of which is [`BasisElem::SKIP`].
+    pub fn amplitude<G: GraphLike>(
+        &self,
+        g: &G,
+        inputs: &[BasisElem],
+        outputs: &[BasisElem],
+        driver: Driver,
+    ) -> FScalar {
+        if inputs.len() != g.inputs().len() || outputs.len() != g.outputs().len() {
+            panic!(
+                "Expected {} inputs and {} outputs, got {} and {}",
+                g.inputs().len(),
+                g.outputs().len(),
+                inputs.len(),
+                outputs.len()
+            );
+        }
+        if inputs.contains(&BasisElem::SKIP) || outputs.contains(&BasisElem::SKIP) {
+            panic!("Amplitudes need a basis state on every input and output");
+        }
+        let mut h = g.clone();
+        h.plug_inputs(inputs);
+        h.plug_outputs(outputs);
+        self.pool.install(|| {
+            let mut d = Decomposer::new(&h);
+            d.with_simp(self.simp_func)
+                .with_scalar_mode(self.scalar_mode)
+                .with_driver(driver)
+                .decompose_parallel();
+            d.scalar()
+        })
+    }
+}
 #[cfg(test)]
 mod tests {
     // use num::rational::Ratio;
@@ -872,6 +2651,7 @@ mod tests {
     use super::*;
     use crate::tensor::*;
     use crate::vec_graph::Graph;
+    use std::sync::atomic::AtomicBool;
     // use itertools::Itertools;
 
     // Helper function to create a simple graph with T gates (no outputs)
@@ -958,6 +2738,92 @@ mod tests {
         assert_eq!(original_scalar, sum);
     }
 
+    #[test]
+    fn test_find_cat_states() {
+        assert!(find_cat_states(&create_t_graph(6)).is_empty());
+
+        // a 4-leg cat and a 5-leg cat, sharing nothing
+        let mut g = create_cat_graph(5, Rational64::new(0, 1));
+        let z = g.add_vertex_with_phase(VType::Z, Rational64::new(1, 1));
+        for _ in 0..4 {
+            let t = g.add_vertex_with_phase(VType::Z, Rational64::new(-1, 4));
+            g.add_edge_with_type(z, t, EType::H);
+        }
+
+        let cats = find_cat_states(&g);
+        assert_eq!(cats.len(), 2);
+        assert_eq!(cats[0].legs.len(), 5);
+        assert_eq!(cats[0].phases[0], Phase::zero());
+        assert_eq!(cats[1].pauli, z);
+        assert_eq!(cats[1].legs.len(), 4);
+        assert_eq!(cats[1].phases[0], Phase::one());
+        assert!(cats[1].phases[1..]
+            .iter()
+            .all(|&p| p == Rational64::new(-1, 4).into()));
+        assert!(cats[1].alpha < cats[0].alpha);
+
+        // the cats driver picks the most efficient one
+        assert_eq!(cat_ts(&g), cats[1].vertices());
+    }
+
+    #[test]
+    fn test_ghz_decomp() {
+        for (n, phase) in [(5, 0), (9, 1), (16, 1)] {
+            let mut g = create_cat_graph(n, Rational64::new(phase, 1));
+            // a T-like spider that is not a leaf is kept
+            let v = g.neighbor_vec(ghz_ts(&g)[0])[0];
+            let w = g.add_vertex_with_phase(VType::Z, Rational64::new(3, 4));
+            g.add_edge_with_type(v, w, EType::H);
+            let verts = ghz_ts(&g);
+            assert_eq!(verts.len(), n);
+
+            let original_scalar = g.to_tensorf()[[]];
+            let decomp = apply_ghz_decomp(&g, &verts);
+            assert_eq!(decomp.len(), 2);
+            let sum: FScalar = decomp.iter().map(|g| g.to_tensorf()[[]]).sum();
+            assert!(original_scalar.approx_eq(&sum, 1e-10));
+
+            let mut nterms = vec![];
+            for driver in [
+                BssWithCats(SpiderSelection::First),
+                GhzAware(SpiderSelection::First),
+            ] {
+                let mut d = Decomposer::new(&g);
+                d.with_full_simp().with_driver(driver).decompose();
+                assert!(original_scalar.approx_eq(&d.scalar(), 1e-10));
+                nterms.push(d.nterms);
+            }
+            assert!(nterms[1] < nterms[0]);
+        }
+    }
+
+    #[test]
+    fn test_degree_ordered_ts() {
+        // T-like spiders with degrees 1, 3 and 2, and a non-T spider of degree 4
+        let mut g = Graph::new();
+        let ts: Vec<V> = (0..3)
+            .map(|_| g.add_vertex_with_phase(VType::Z, Rational64::new(1, 4)))
+            .collect();
+        let hub = g.add_vertex(VType::Z);
+        let z = g.add_vertex_with_phase(VType::Z, Rational64::new(1, 2));
+        for &t in &ts {
+            g.add_edge_with_type(hub, t, EType::H);
+        }
+        g.add_edge_with_type(ts[1], z, EType::H);
+        g.add_edge_with_type(ts[1], ts[2], EType::H);
+        g.add_edge_with_type(hub, z, EType::H);
+
+        assert_eq!(degree_ordered_ts(&g, true), vec![ts[1], ts[2], ts[0]]);
+        assert_eq!(degree_ordered_ts(&g, false), vec![ts[0], ts[2], ts[1]]);
+
+        let expected = g.to_tensorf()[[]];
+        let mut d = Decomposer::new(&g);
+        d.with_full_simp()
+            .with_driver(DegreeOrderedT { descending: true })
+            .decompose();
+        assert!(expected.approx_eq(&d.scalar(), 1e-10));
+    }
+
     #[test]
     fn test_cat3_decomp() {
         let g = create_cat_graph(3, Rational64::new(1, 1));
@@ -1059,10 +2925,14 @@ mod tests {
         // Test configurations
         let simp_funcs = vec![NoSimp, CliffordSimp, FullSimp];
         let drivers = vec![
-            BssTOnly(false),
-            BssTOnly(true),
-            BssWithCats(false),
-            BssWithCats(true),
+            BssTOnly(SpiderSelection::First),
+            BssTOnly(SpiderSelection::Random(None)),
+            BssWithCats(SpiderSelection::First),
+            BssWithCats(SpiderSelection::Random(None)),
+            BssTOnly(SpiderSelection::Random(Some(7))),
+            BssWithCats(SpiderSelection::Custom(max_degree_t)),
+            DegreeOrderedT { descending: true },
+            DegreeOrderedT { descending: false },
         ];
         let split_components = vec![false, true];
         let parallel_modes = vec![false, true];
@@ -1111,7 +2981,7 @@ mod tests {
 
             let mut d = Decomposer::new(&g);
             d.with_full_simp()
-                .with_driver(BssWithCats(false))
+                .with_driver(BssWithCats(SpiderSelection::First))
                 .decompose();
 
             assert_eq!(
@@ -1145,7 +3015,7 @@ mod tests {
         // Test with cat-aware driver
         let mut d = Decomposer::new(&g);
         d.with_full_simp()
-            .with_driver(BssWithCats(false))
+            .with_driver(BssWithCats(SpiderSelection::First))
             .decompose();
 
         assert_eq!(expected_scalar, d.scalar());
@@ -1160,6 +3030,30 @@ mod tests {
         assert_eq!(FScalar::one(), d.scalar());
     }
 
+    #[test]
+    fn test_zero_graph() {
+        // the state of qubit 1 is orthogonal to the effect plugged into it, and the
+        // other qubits have T gates
+        let mut c = crate::circuit::Circuit::random()
+            .seed(1)
+            .qubits(3)
+            .depth(40)
+            .clifford_t(0.3)
+            .build();
+        c.gates.retain(|gate| !gate.qs.contains(&1));
+        c.add_gate("x", vec![1]);
+        let mut g: Graph = c.to_graph();
+        g.plug_inputs(&[BasisElem::Z0; 3]);
+        g.plug_outputs(&[BasisElem::SKIP, BasisElem::Z0, BasisElem::SKIP]);
+        assert!(g.tcount() > 0);
+
+        let mut d = Decomposer::new(&g);
+        d.with_full_simp().decompose();
+        assert!(d.scalar().is_zero());
+        assert_eq!(d.nterms, 0);
+        assert!(g.to_tensorf().iter().all(|s| s.is_zero()));
+    }
+
     #[test]
     fn test_clifford_only_graph() {
         let mut g = Graph::new();
@@ -1232,7 +3126,8 @@ mod tests {
         let expected_scalar = g.to_tensorf()[[]];
 
         let mut d = Decomposer::new(&g);
-        d.with_driver(BssWithCats(false)).decompose();
+        d.with_driver(BssWithCats(SpiderSelection::First))
+            .decompose();
 
         assert_eq!(expected_scalar, d.scalar());
     }
@@ -1306,6 +3201,689 @@ mod tests {
         assert_eq!(expected_scalar, d.scalar());
     }
 
+    #[test]
+    fn test_decompose_subset() {
+        let g = create_graph(8);
+        let expected_scalar = g.to_tensorf()[[]];
+        let ts: Vec<_> = g.vertices().filter(|&v| g.phase(v).is_t()).collect();
+
+        let driver = BssTOnly(SpiderSelection::First);
+
+        let mut d = Decomposer::new(&g);
+        d.decompose_subset(&ts[0..3], driver.clone()).unwrap();
+        assert_eq!(d.residual_tcount(), 5);
+        d.with_full_simp().decompose();
+        assert_eq!(d.residual_tcount(), 0);
+        assert_eq!(expected_scalar, d.scalar());
+
+        let mut d = Decomposer::new(&g);
+        d.decompose_first_ts(8, driver.clone()).unwrap();
+        assert_eq!(d.residual_tcount(), 0);
+        assert_eq!(expected_scalar, d.scalar());
+
+        // the cat driver only sees a cat if all of its legs are listed
+        let g = create_cat_graph(4, Rational64::new(0, 1));
+        let expected_scalar = g.to_tensorf()[[]];
+        let legs: Vec<_> = g.neighbors(0).collect();
+        for k in [3, 4] {
+            let mut d = Decomposer::new(&g);
+            d.decompose_subset(&legs[..k], BssWithCats(SpiderSelection::First))
+                .unwrap();
+            assert_eq!(d.residual_tcount(), 4 - k);
+            assert_eq!(d.nterms, if k == 4 { 2 } else { 0 });
+            d.decompose();
+            assert_eq!(expected_scalar, d.scalar());
+        }
+
+        let mut d = Decomposer::new(&g);
+        assert_eq!(
+            d.decompose_subset(&[0, legs[0]], driver.clone()).err(),
+            Some(DecomposeError::NotTLike { vertex: 0 })
+        );
+        let mut g = g;
+        let v = g.add_vertex_with_phase(VType::Z, Rational64::new(1, 8));
+        let mut d = Decomposer::new(&g);
+        assert_eq!(
+            d.decompose_first_ts(2, driver).err(),
+            Some(DecomposeError::NotTLike { vertex: v })
+        );
+    }
+
+    #[test]
+    fn test_decompose_to_sum() {
+        let mut g = Graph::new();
+        let mut outs = vec![];
+        for i in 0..4 {
+            let v = g.add_vertex_with_phase(VType::Z, Rational64::new(1, 4));
+            let w = g.add_vertex(VType::B);
+            outs.push(w);
+            g.add_edge(v, w);
+            for j in 0..i {
+                g.add_edge_with_type(v, 2 * j, EType::H);
+            }
+        }
+        g.set_outputs(outs);
+        let expected = g.to_tensor64();
+
+        for driver in [
+            BssTOnly(SpiderSelection::First),
+            BssWithCats(SpiderSelection::First),
+        ] {
+            let mut d = Decomposer::new(&g);
+            let sum = d
+                .with_full_simp()
+                .with_driver(driver.clone())
+                .decompose_to_sum();
+            assert!(d.done.is_empty());
+            assert!(sum.len() <= d.nterms);
+            for (a, b) in sum.to_tensor64().iter().zip(expected.iter()) {
+                assert!((a - b).norm() < 1e-8);
+            }
+            assert_eq!(d.leaf_counts().1, sum.len());
+        }
+    }
+
+    #[test]
+    fn test_decompose_to_sum_split_components() {
+        // two copies of a state on two qubits, with no edges between them
+        let mut g = Graph::new();
+        let mut outs = vec![];
+        for _ in 0..2 {
+            let vs: Vec<_> = (0..3)
+                .map(|_| g.add_vertex_with_phase(VType::Z, Rational64::new(1, 4)))
+                .collect();
+            g.add_edge_with_type(vs[0], vs[1], EType::H);
+            g.add_edge_with_type(vs[1], vs[2], EType::H);
+            g.add_edge_with_type(vs[0], vs[2], EType::H);
+            for &v in &vs[..2] {
+                let w = g.add_vertex(VType::B);
+                g.add_edge(v, w);
+                outs.push(w);
+            }
+        }
+        g.set_outputs(outs);
+        let mut scalar = g.clone();
+        scalar.plug_outputs(&[BasisElem::Z0, BasisElem::X1, BasisElem::Z1, BasisElem::X0]);
+
+        for g in [g, scalar] {
+            let expected = g.to_tensor64();
+            for merge in [true, false] {
+                let mut d = Decomposer::new(&g);
+                let sum = d
+                    .with_full_simp()
+                    .with_split_graphs_components(true)
+                    .with_merge_leaves(merge)
+                    .decompose_to_sum();
+                assert!(d.split_graph_components);
+                assert_eq!(d.leaf_counts().1, sum.len());
+                for (a, b) in sum.to_tensor64().iter().zip(expected.iter()) {
+                    assert!((a - b).norm() < 1e-8, "{a} != {b}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_decompose_operator() {
+        // T = |0><0| + e^(iπ/4) |1><1|
+        let mut c = crate::circuit::Circuit::new(1);
+        c.add_gate("t", vec![0]);
+        let g: Graph = c.to_graph();
+        let op = Decomposer::decompose_operator(&g, BssTOnly(SpiderSelection::First));
+        assert_eq!((op.num_inputs, op.num_outputs), (1, 1));
+        assert_eq!(op.len(), 2);
+        let t = op.to_tensor64();
+        assert_eq!(t.shape(), &[2, 2]);
+        for (a, b) in t.iter().zip(g.to_tensor64().iter()) {
+            assert!((a - b).norm() < 1e-8);
+        }
+
+        let c = crate::circuit::Circuit::random()
+            .seed(4)
+            .qubits(3)
+            .depth(20)
+            .clifford_t(0.3)
+            .build();
+        let g: Graph = c.to_graph();
+        let op = Decomposer::decompose_operator(&g, BssWithCats(SpiderSelection::First));
+        for (a, b) in op.to_tensor64().iter().zip(g.to_tensor64().iter()) {
+            assert!((a - b).norm() < 1e-8);
+        }
+    }
+
+    #[test]
+    fn test_merge_leaves() {
+        let c = crate::circuit::Circuit::random()
+            .seed(9)
+            .qubits(3)
+            .depth(20)
+            .clifford_t(0.4)
+            .build();
+        let mut g: Graph = c.to_graph();
+        g.plug_inputs(&[BasisElem::Z0; 3]);
+        let expected = g.to_tensor64();
+
+        let mut d = Decomposer::new(&g);
+        let sum = d.with_full_simp().decompose_to_sum();
+        assert_eq!(d.leaf_counts(), (3, 2));
+        let mut d = Decomposer::new(&g);
+        let sum1 = d
+            .with_full_simp()
+            .with_merge_leaves(false)
+            .decompose_to_sum();
+        assert_eq!(d.leaf_counts(), (3, 3));
+        assert_eq!(sum1.len(), 3);
+        for s in [sum, sum1] {
+            for (a, b) in s.to_tensor64().iter().zip(expected.iter()) {
+                assert!((a - b).norm() < 1e-8);
+            }
+        }
+    }
+
+    #[test]
+    fn test_state_norm_squared() {
+        for seed in 0..5 {
+            let c = crate::circuit::Circuit::random()
+                .seed(seed)
+                .qubits(4)
+                .depth(30)
+                .clifford_t(0.3)
+                .build();
+            let mut g: Graph = c.to_graph();
+            g.plug_inputs(&[BasisElem::Z0; 4]);
+            let n = Decomposer::state_norm_squared(&g, BssTOnly(SpiderSelection::First));
+            assert!((n - 1.0).abs() < 1e-8);
+
+            g.scalar_mut().mul_sqrt2_pow(1);
+            let n = Decomposer::state_norm_squared(&g, BssWithCats(SpiderSelection::First));
+            assert!((n - 2.0).abs() < 1e-8);
+        }
+    }
+
+    #[test]
+    fn test_output_distribution() {
+        let c = crate::circuit::Circuit::random()
+            .seed(3)
+            .qubits(3)
+            .depth(20)
+            .clifford_t(0.3)
+            .build();
+        let mut g: Graph = c.to_graph();
+        g.plug_inputs(&[BasisElem::Z0; 3]);
+        let dist =
+            Decomposer::output_distribution(&g, BssWithCats(SpiderSelection::First), 3).unwrap();
+        assert_eq!(dist.len(), 8);
+        assert!(dist.windows(2).all(|w| w[0].1 >= w[1].1));
+        for (bits, p) in &dist {
+            let mut h = g.clone();
+            let plug: Vec<BasisElem> = bits
+                .iter()
+                .map(|&b| if b { BasisElem::Z1 } else { BasisElem::Z0 })
+                .collect();
+            h.plug_outputs(&plug);
+            let expected = h.to_tensorf()[[]].complex_value().norm_sqr();
+            assert!((p - expected).abs() < 1e-8, "{bits:?}");
+        }
+
+        let err = Decomposer::output_distribution(&g, BssWithCats(SpiderSelection::First), 2)
+            .unwrap_err();
+        assert!(err.contains("limit"), "{err}");
+        g.scalar_mut().mul_sqrt2_pow(1);
+        let err = Decomposer::output_distribution(&g, BssWithCats(SpiderSelection::First), 3)
+            .unwrap_err();
+        assert!(err.contains("sum to 2"), "{err}");
+    }
+
+    #[test]
+    #[should_panic(expected = "Expected a state")]
+    fn test_state_norm_squared_not_state() {
+        let g: Graph = crate::circuit::Circuit::new(1).to_graph();
+        Decomposer::state_norm_squared(&g, BssTOnly(SpiderSelection::First));
+    }
+
+    #[test]
+    fn test_scalar_mode() {
+        for (g, split) in [(create_t_graph(8), false), (create_graph(10), true)] {
+            let expected_scalar = g.to_tensorf()[[]];
+            let mut results = vec![];
+            for parallel in [false, true] {
+                let mut d = Decomposer::new(&g);
+                d.with_full_simp()
+                    .with_split_graphs_components(split)
+                    .with_scalar_mode(ScalarMode::ExactPhase);
+                if parallel {
+                    d.decompose_parallel();
+                } else {
+                    d.decompose();
+                }
+                results.push(d.scalar());
+            }
+            assert!(expected_scalar.approx_eq(&results[0], 1e-10));
+            assert_eq!(results[0], results[1]);
+
+            let mut d = Decomposer::new(&g);
+            d.with_full_simp().decompose();
+            assert!(d.scalar().approx_eq(&results[0], 1e-10));
+        }
+    }
+
+    #[test]
+    fn test_partial_scalar() {
+        for (g, split) in [(create_t_graph(8), false), (create_graph(10), true)] {
+            for parallel in [false, true] {
+                let mut d = Decomposer::new(&g);
+                d.with_full_simp().with_split_graphs_components(split);
+                let handle = d.partial_scalar_handle();
+                assert_eq!(d.partial_scalar(), FScalar::zero());
+
+                // read the partial scalar while decomposing, until it is done
+                let done = Arc::new(AtomicBool::new(false));
+                let reader = std::thread::spawn({
+                    let done = done.clone();
+                    move || {
+                        let mut reads = 0;
+                        while !done.load(Ordering::Acquire) || reads < 10 {
+                            std::hint::black_box(handle.get());
+                            reads += 1;
+                        }
+                    }
+                });
+                if parallel {
+                    d.decompose_parallel();
+                } else {
+                    d.decompose();
+                }
+                done.store(true, Ordering::Release);
+                reader.join().unwrap();
+                assert!(d.partial_scalar().approx_eq(&d.scalar(), 1e-10));
+            }
+        }
+
+        // terms finished by decompose_subset are kept when finishing the decomposition
+        let g = create_t_graph(10);
+        let mut d = Decomposer::new(&g);
+        d.decompose_first_ts(8, BssTOnly(SpiderSelection::First))
+            .unwrap();
+        let terms = d.partial_scalar_handle().terms();
+        d.with_full_simp().decompose();
+        assert!(d.partial_scalar_handle().terms() >= terms);
+        assert!(d.partial_scalar().approx_eq(&d.scalar(), 1e-10));
+    }
+
+    #[test]
+    fn test_max_terms() {
+        let c = crate::circuit::Circuit::random()
+            .seed(1337)
+            .qubits(8)
+            .depth(300)
+            .p_t(0.1)
+            .with_cliffords()
+            .build();
+        let mut g: Graph = c.to_graph();
+        g.plug_inputs(&[BasisElem::Z0; 8]);
+        g.plug_outputs(&[BasisElem::Z0; 8]);
+
+        let mut d = Decomposer::new(&g);
+        d.with_full_simp().decompose();
+        let (scalar, nterms) = (d.scalar(), d.nterms);
+        assert!(nterms > 10);
+
+        for parallel in [false, true] {
+            let mut d = Decomposer::new(&g);
+            d.with_full_simp().with_save(true).with_max_terms(10);
+            let result = if parallel {
+                d.try_decompose_parallel().map(|_| ())
+            } else {
+                d.try_decompose().map(|_| ())
+            };
+            match result {
+                Err(DecomposeError::TermLimitExceeded { explored }) => assert!(explored > 10),
+                r => panic!("expected the term limit to be exceeded, got {r:?}"),
+            }
+            assert_eq!(d.nterms, 0);
+            assert!(d.done.is_empty());
+            assert_eq!(d.partial_scalar_handle().terms(), 0);
+            assert_eq!(d.partial_scalar(), FScalar::zero());
+
+            // the target is left in place, so we can try again with a higher limit
+            d.with_max_terms(nterms as u128);
+            assert!(d.try_decompose().is_ok());
+            assert!(d.scalar().approx_eq(&scalar, 1e-10));
+        }
+    }
+
+    fn max_degree_t(g: &Graph, ts: &[V]) -> V {
+        ts.iter().copied().max_by_key(|&v| g.degree(v)).unwrap()
+    }
+
+    #[test]
+    fn test_spider_selection() {
+        let g = create_graph(10);
+        assert_eq!(SpiderSelection::First.select_ts(&g), first_ts(&g));
+        let seeded = SpiderSelection::Random(Some(3));
+        assert_eq!(seeded.select_ts(&g), seeded.select_ts(&g));
+
+        // the custom function sees the same vertex names, also for other graph types
+        let mut h = crate::hash_graph::Graph::new();
+        let vs: Vec<_> = (0..8)
+            .map(|_| h.add_vertex_with_phase(VType::Z, Rational64::new(1, 4)))
+            .collect();
+        h.remove_vertex(vs[0]);
+        for &v in &vs[3..] {
+            h.add_edge_with_type(vs[2], v, EType::H);
+        }
+        let ts = SpiderSelection::Custom(max_degree_t).select_ts(&h);
+        assert_eq!(ts.len(), 6);
+        assert_eq!(ts[0], vs[2]);
+        assert!(!ts.contains(&vs[0]));
+
+        let expected = g.to_tensorf()[[]];
+        for driver in [
+            BssTOnly(SpiderSelection::Custom(max_degree_t)),
+            GhzAware(SpiderSelection::Custom(max_degree_t)),
+            GhzAware(seeded),
+        ] {
+            let mut d = Decomposer::new(&g);
+            d.with_full_simp().with_driver(driver).decompose();
+            assert_eq!(d.scalar(), expected);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Spider selection picked")]
+    fn test_spider_selection_invalid() {
+        let g = create_t_graph(3);
+        SpiderSelection::Custom(|_, _| 7).select_ts(&g);
+    }
+
+    #[test]
+    fn test_fit_alpha() {
+        let bss: Vec<_> = (1..=4)
+            .map(|k| (6 * k, terms_for_tcount(6 * k) as usize))
+            .collect();
+        assert!((fit_alpha(&bss) - 7f64.log2() / 6.0).abs() < 1e-12);
+
+        // noise around 2^(t / 2) averages out
+        let results = [(2, 1), (2, 4), (4, 2), (4, 8), (6, 4), (6, 16)];
+        assert!((fit_alpha(&results) - 0.5).abs() < 1e-12);
+    }
+
+    #[test]
+    #[should_panic(expected = "two distinct T-counts")]
+    fn test_fit_alpha_single_tcount() {
+        fit_alpha(&[(4, 3), (4, 5)]);
+    }
+
+    #[test]
+    fn test_config() {
+        let mut d = Decomposer::new(&create_graph(10));
+        d.with_full_simp()
+            .with_driver(BssWithCats(SpiderSelection::Random(Some(5))))
+            .with_split_graphs_components(true)
+            .with_max_terms(1000)
+            .with_timeout(Duration::from_millis(1500))
+            .with_merge_leaves(false);
+        let mut config = d.config();
+        config.parallel = true;
+        let json = serde_json::to_string(&config).unwrap();
+        let config1: DecomposerConfig = serde_json::from_str(&json).unwrap();
+        let mut d1: Decomposer<Graph> = Decomposer::from_config(&config1);
+        assert_eq!(
+            serde_json::to_string(&d1.config()).unwrap(),
+            json.replace("\"parallel\":true", "\"parallel\":false")
+        );
+
+        let g = create_graph(10);
+        let expected = g.to_tensorf()[[]];
+        assert_eq!(config1.run(&g).unwrap().scalar(), expected);
+        d1.set_target(g).decompose();
+        assert_eq!(d1.scalar(), expected);
+
+        // missing fields get their defaults
+        let config: DecomposerConfig = serde_json::from_str(r#"{"simp":"FullSimp"}"#).unwrap();
+        assert_eq!(config.simp, FullSimp);
+        assert!(config.merge_leaves && config.max_terms.is_none());
+        assert_eq!(
+            serde_json::to_string(&DecomposerConfig::default()).unwrap(),
+            serde_json::to_string(&Decomposer::new(&create_graph(2)).config()).unwrap()
+        );
+
+        let custom = DecomposerConfig {
+            driver: BssTOnly(SpiderSelection::Custom(max_degree_t)),
+            ..Default::default()
+        };
+        assert!(serde_json::to_string(&custom).is_err());
+    }
+
+    #[test]
+    fn test_choice_log() {
+        let file = std::env::temp_dir().join(format!("quizx-choices-{}.txt", std::process::id()));
+        let g = create_graph(14);
+        let mut d = Decomposer::new(&g);
+        d.with_full_simp()
+            .with_driver(BssTOnly(SpiderSelection::Random(None)))
+            .with_choice_log(&file, ChoiceLogMode::Record)
+            .decompose();
+        let (scalar, nterms) = (d.scalar(), d.nterms);
+        let log = std::fs::read_to_string(&file).unwrap();
+        assert!(log.lines().any(|l| l.starts_with("- t ")), "{log}");
+
+        // the random choices are replayed exactly, also in parallel
+        let mut d = Decomposer::new(&g);
+        d.with_full_simp()
+            .with_choice_log(&file, ChoiceLogMode::Replay)
+            .decompose();
+        assert_eq!(d.nterms, nterms);
+        assert!(d.scalar().approx_eq(&scalar, 1e-10));
+        d.set_target(g.clone()).decompose_parallel();
+        assert!(d.scalar().approx_eq(&scalar, 1e-10));
+
+        // a hand-edited log is checked against the graph at the root
+        let mut h = g.clone();
+        crate::simplify::full_simp(&mut h);
+        let t = h.vertices().find(|&v| h.degree(v) >= 3).unwrap();
+        let ns = h.neighbor_vec(t);
+        // a Pauli hub 0 with 5 T-legs, which full_simp leaves as it is
+        let cat = create_cat_graph(5, Rational64::new(0, 1));
+        for (g, edit, expected) in [
+            (&g, "- t 1000".to_string(), "missing vertex 1000"),
+            (&g, "- magic5 1 2".to_string(), "has 2 vertices"),
+            (&g, "- t x".to_string(), "line"),
+            (&g, String::new(), "no choice for branch -"),
+            (&g, format!("- t {t} {t}"), "twice"),
+            (&g, format!("- ghz {t}"), "has 1 vertices"),
+            (&g, format!("- cat {} {}", ns[0], ns[1]), "has 2 vertices"),
+            (
+                &g,
+                format!("- cat {t} {} {} {}", ns[0], ns[1], ns[2]),
+                "Pauli hub",
+            ),
+            (
+                &cat,
+                "- cat 0 1 2 3".to_string(),
+                "exactly the hub's neighbours",
+            ),
+        ] {
+            let mut lines: Vec<&str> = log.lines().filter(|l| !l.starts_with("- ")).collect();
+            lines.push(&edit);
+            std::fs::write(&file, lines.join("\n")).unwrap();
+            let mut d = Decomposer::new(g);
+            d.with_full_simp()
+                .with_choice_log(&file, ChoiceLogMode::Replay);
+            match d.try_decompose().map(|_| ()) {
+                Err(DecomposeError::ChoiceLog { message }) => {
+                    assert!(message.contains(expected), "{message}")
+                }
+                r => panic!("expected a choice log error, got {r:?}"),
+            }
+        }
+        std::fs::remove_file(&file).unwrap();
+    }
+
+    #[test]
+    fn test_timeout_and_batch() {
+        let small: Vec<Graph> = (1..6).map(create_t_graph).collect();
+        let big = create_graph(40);
+
+        let mut d = Decomposer::new(&big);
+        d.with_full_simp().with_timeout(Duration::from_millis(1));
+        match d.try_decompose().map(|_| ()) {
+            Err(DecomposeError::Timeout { .. }) => {}
+            r => panic!("expected a timeout, got {r:?}"),
+        }
+
+        // the small graphs should finish well within the timeout
+        let mut graphs = small.clone();
+        graphs.insert(2, big);
+        for parallel in [false, true] {
+            let results = batch(
+                &graphs,
+                BssTOnly(SpiderSelection::First),
+                Some(Duration::from_millis(50)),
+                parallel,
+            );
+            assert_eq!(results.len(), graphs.len());
+            assert!(matches!(results[2], Err(DecomposeError::Timeout { .. })));
+            for (g, r) in small.iter().zip(results[..2].iter().chain(&results[3..])) {
+                let r = r.as_ref().unwrap();
+                let mut d = Decomposer::new(g);
+                d.with_full_simp().decompose();
+                assert!(r.scalar.approx_eq(&d.scalar(), 1e-10));
+                assert_eq!(r.terms, d.nterms);
+            }
+        }
+
+        let results = batch(&small, BssTOnly(SpiderSelection::First), None, true);
+        assert!(results.iter().all(|r| r.is_ok()));
+    }
+
+    #[test]
+    fn test_level_stats() {
+        let g = create_t_graph(12);
+        let mut d = Decomposer::new(&g);
+        d.decompose();
+        assert!(d.level_stats().is_empty());
+
+        for parallel in [false, true] {
+            let mut d = Decomposer::new(&g);
+            d.with_level_stats(true);
+            if parallel {
+                d.decompose_parallel();
+            } else {
+                d.decompose();
+            }
+            assert_eq!(d.level_stats(), vec![1, 7, 49]);
+        }
+
+        // with simplification, some branches are pruned before the leaves
+        let mut d = Decomposer::new(&create_graph(14));
+        d.with_full_simp().with_level_stats(true).decompose();
+        let stats = d.level_stats();
+        assert_eq!(stats[0], 1);
+        assert!(stats[2] < 49);
+        assert!(stats.windows(2).all(|w| w[1] <= 7 * w[0]));
+        assert!(stats.iter().sum::<usize>() >= d.nterms);
+    }
+
+    #[test]
+    #[should_panic(expected = "term limit exceeded")]
+    fn test_max_terms_panic() {
+        let g = create_t_graph(8);
+        Decomposer::new(&g).with_max_terms(1).decompose();
+    }
+
+    #[test]
+    fn test_magic_basis() {
+        let g = create_graph(8);
+        let expected = g.to_tensorf()[[]];
+        let mut d = Decomposer::new(&g);
+        d.with_full_simp()
+            .with_magic_basis(MagicBasis::default())
+            .decompose();
+        assert!(d.scalar().approx_eq(&expected, 1e-10));
+
+        // the magic state for pi/8 in terms of |+> and |->
+        let phase = Rational64::new(1, 8);
+        let c = FScalar::from_phase(phase);
+        let basis = MagicBasis::new(
+            phase,
+            vec![
+                (
+                    (FScalar::one() + c) * FScalar::from(0.5),
+                    StabState::Equator(0.into()),
+                ),
+                (
+                    (FScalar::one() - c) * FScalar::from(0.5),
+                    StabState::Equator(1.into()),
+                ),
+            ],
+        );
+        let mut g = Graph::new();
+        let vs: Vec<_> = (0..5)
+            .map(|i| {
+                g.add_vertex_with_phase(
+                    VType::Z,
+                    if i % 2 == 0 {
+                        phase
+                    } else {
+                        Rational64::new(1, 4)
+                    },
+                )
+            })
+            .collect();
+        for i in 0..5 {
+            g.add_edge_with_type(vs[i], vs[(i + 1) % 5], EType::H);
+        }
+        let expected: Complex<f64> = g.to_tensor64()[[]];
+        let mut d = Decomposer::new(&g);
+        d.with_full_simp().with_magic_basis(basis).decompose();
+        assert!((d.scalar().complex_value() - expected).norm() < 1e-10);
+    }
+
+    #[test]
+    #[should_panic(expected = "do not add up")]
+    fn test_magic_basis_wrong_terms() {
+        MagicBasis::new(
+            Rational64::new(1, 4),
+            vec![
+                (FScalar::one(), StabState::Zero),
+                (FScalar::one(), StabState::One),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_fn_driver() {
+        // Z(a) = (1 + e^(i a))/2 Z(0) + (1 - e^(i a))/2 Z(pi)
+        let driver = FnDriver::new(|g: &Graph| {
+            let v = g.vertices().find(|&v| !g.phase(v).is_clifford()).unwrap();
+            let p = g.phase(v);
+            let mut half = FScalar::one();
+            half.mul_sqrt2_pow(-2);
+            let (mut g0, mut g1) = (g.clone(), g.clone());
+            g0.set_phase(v, 0);
+            g1.set_phase(v, 1);
+            vec![
+                (half * FScalar::one_plus_phase(p), g0),
+                (half * FScalar::one_plus_phase(p + Phase::one()), g1),
+            ]
+        });
+
+        for g in [create_t_graph(5), create_graph(7)] {
+            let expected_scalar = g.to_tensorf()[[]];
+            for parallel in [false, true] {
+                let mut d = Decomposer::new(&g);
+                d.with_full_simp().with_fn_driver(driver.clone());
+                if parallel {
+                    d.decompose_parallel();
+                } else {
+                    d.decompose();
+                }
+                assert!(expected_scalar.approx_eq(&d.scalar(), 1e-10));
+            }
+        }
+    }
+
     // Existing tests from the original code (kept for compatibility)
     #[test]
     fn bss_scalars() {
@@ -1380,7 +3958,7 @@ mod tests {
 
         let mut d = Decomposer::new(&g);
         d.with_full_simp()
-            .with_driver(Driver::BssTOnly(false))
+            .with_driver(Driver::BssTOnly(SpiderSelection::First))
             .with_save(true)
             .decompose();
         assert_eq!(d.done.len(), 7 * 2 * 2);
@@ -1404,7 +3982,7 @@ mod tests {
 
         let mut d = Decomposer::new(&g);
         d.with_full_simp()
-            .with_driver(Driver::BssWithCats(false))
+            .with_driver(Driver::BssWithCats(SpiderSelection::First))
             .with_save(true)
             .decompose();
         assert_eq!(d.done.len(), 2);
@@ -1428,10 +4006,173 @@ mod tests {
 
         let mut d = Decomposer::new(&g);
         d.with_full_simp()
-            .with_driver(Driver::BssWithCats(false))
+            .with_driver(Driver::BssWithCats(SpiderSelection::First))
             .with_save(true)
             .decompose();
 
         assert_eq!(d.done.len(), 3);
     }
+
+    #[test]
+    fn test_stabilizer_nullity() {
+        let state = |qasm: &str| {
+            let c = crate::circuit::Circuit::from_qasm(qasm).unwrap();
+            let mut g: Graph = c.to_graph();
+            g.plug_inputs(&vec![BasisElem::Z0; c.num_qubits()]);
+            g
+        };
+
+        let g = state("qreg q[3]; h q[0]; h q[1]; h q[2]; t q[0]; t q[1]; t q[2];");
+        assert_eq!(stabilizer_nullity(&g), 3);
+
+        let g = state("qreg q[2]; h q[0]; t q[0]; cx q[0], q[1]; tdg q[0]; h q[1];");
+        assert_eq!(g.tcount(), 2);
+        assert_eq!(stabilizer_nullity(&g), 0);
+
+        let g = state("qreg q[2]; h q[0]; t q[0]; cx q[0], q[1]; cx q[0], q[1]; t q[0];");
+        assert_eq!(stabilizer_nullity(&g), 0);
+    }
+
+    #[test]
+    fn dense_rules_same_decomposition() {
+        let c = crate::circuit::Circuit::random()
+            .seed(3)
+            .qubits(6)
+            .depth(80)
+            .clifford_t(0.3)
+            .build();
+        let mut g: Graph = c.to_graph();
+        g.plug_inputs(&[BasisElem::Z0; 6]);
+        g.plug_outputs(&[BasisElem::Z0; 6]);
+        let decompose = |dense: bool| {
+            let mut d = Decomposer::new(&g);
+            d.with_full_simp()
+                .with_save(true)
+                .with_dense_rules(dense)
+                .decompose();
+            d
+        };
+        let (d0, d1) = (decompose(false), decompose(true));
+        assert_eq!(d0.nterms, d1.nterms);
+        assert_eq!(d0.scalar(), d1.scalar());
+        assert_eq!(d0.done, d1.done);
+    }
+
+    #[test]
+    fn work_units() {
+        let g = create_graph(16);
+        let driver = BssWithCats(SpiderSelection::First);
+        let mut d = Decomposer::new(&g);
+        d.with_full_simp().with_driver(driver.clone()).decompose();
+        let expected = d.scalar().complex_value();
+
+        for n in [1, 4, 20] {
+            let units = Decomposer::split_into_units(&g, driver.clone(), n);
+            assert!(!units.is_empty());
+            let results = units.iter().map(|u| {
+                let json = serde_json::to_string(u).unwrap();
+                let u: WorkUnit = serde_json::from_str(&json).unwrap();
+                Decomposer::<Graph>::decompose_unit(&u, driver.clone()).unwrap()
+            });
+            let total = combine_units(results.collect::<Vec<_>>());
+            assert!((total.complex_value() - expected).norm() < 1e-8);
+        }
+
+        let units = Decomposer::split_into_units(&g, driver.clone(), 20);
+        assert!(units.len() >= 20);
+        assert!(units.windows(2).all(|w| w[0].path < w[1].path));
+    }
+
+    #[test]
+    fn dry_run() {
+        let g = create_graph(12);
+        for driver in [
+            BssTOnly(SpiderSelection::First),
+            BssWithCats(SpiderSelection::First),
+        ] {
+            let mut d = Decomposer::new(&g);
+            d.with_full_simp().with_driver(driver.clone()).decompose();
+            let tree = Decomposer::dry_run(&g, driver);
+            assert_eq!(tree.num_leaves(), d.nterms);
+            assert_eq!(tree.tcount, {
+                let mut h = g.clone();
+                crate::simplify::full_simp(&mut h);
+                h.tcount()
+            });
+            assert!(tree.depth() > 0);
+            assert!(tree.children.iter().all(|c| c.tcount < tree.tcount));
+
+            let dot = tree.to_dot();
+            assert!(dot.starts_with("digraph {"));
+            assert_eq!(dot.matches(" -> ").count(), tree.num_nodes() - 1);
+        }
+
+        let tree = Decomposer::dry_run(&create_t_graph(0), BssTOnly(SpiderSelection::First));
+        assert_eq!(tree.kind, DecompTreeKind::Leaf);
+        assert_eq!(tree.num_nodes(), 1);
+    }
+
+    #[test]
+    fn pool_amplitudes() {
+        let c = crate::circuit::Circuit::random()
+            .seed(7)
+            .qubits(4)
+            .depth(40)
+            .clifford_t(0.3)
+            .build();
+        let g: Graph = c.to_graph();
+        let inputs = [BasisElem::Z0, BasisElem::Z1, BasisElem::X0, BasisElem::Z0];
+        let outputs = [
+            [BasisElem::Z0; 4],
+            [BasisElem::Z1, BasisElem::Z0, BasisElem::Z0, BasisElem::Z0],
+            [BasisElem::Z1, BasisElem::Z1, BasisElem::X0, BasisElem::Z0],
+            [BasisElem::X1, BasisElem::Z1, BasisElem::Z0, BasisElem::Z1],
+        ];
+        let expected: Vec<FScalar> = outputs
+            .iter()
+            .map(|out| {
+                let mut h = g.clone();
+                h.plug_inputs(&inputs);
+                h.plug_outputs(out);
+                let mut d = Decomposer::new(&h);
+                d.with_full_simp().decompose();
+                d.scalar()
+            })
+            .collect();
+
+        let pool = DecomposerPool::new(2);
+        assert_eq!(pool.num_threads(), 2);
+        std::thread::scope(|s| {
+            for _ in 0..3 {
+                s.spawn(|| {
+                    for (out, e) in outputs.iter().zip(&expected) {
+                        let a = pool.amplitude(
+                            &g,
+                            &inputs,
+                            out,
+                            Driver::BssWithCats(SpiderSelection::First),
+                        );
+                        assert!((a.complex_value() - e.complex_value()).norm() < 1e-10);
+                    }
+                });
+            }
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Expected 1 inputs and 1 outputs, got 1 and 0")]
+    fn pool_missing_outputs() {
+        let mut g = Graph::new();
+        let i = g.add_vertex(VType::B);
+        let o = g.add_vertex(VType::B);
+        g.add_edge(i, o);
+        g.set_inputs(vec![i]);
+        g.set_outputs(vec![o]);
+        DecomposerPool::new(1).amplitude(
+            &g,
+            &[BasisElem::Z0],
+            &[],
+            Driver::BssTOnly(SpiderSelection::First),
+        );
+    }
 }
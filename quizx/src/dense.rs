@@ -0,0 +1,335 @@
+// QuiZX - Rust library for quantum circuit rewriting and optimisation
+//         using the ZX-calculus
+// Copyright (C) 2021 - Aleks Kissinger
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Bitset versions of local complementation and pivoting
+//!
+//! These rules add an edge between every pair of neighbours of the vertices they
+//! remove, which takes an edge lookup per pair in the graph. Here, the edges between
+//! the neighbours are read once into a [`DenseNhd`], so each lookup is a bit test.
+//! This makes no difference to the result: the functions below apply the same
+//! changes in the same order as their versions in [`crate::basic_rules`], so the
+//! graphs and scalars are identical. This pays off for spiders with dozens of
+//! neighbours, see [`crate::simplify::with_dense_rules`] and
+//! [`crate::decompose::Decomposer::with_dense_rules`].
+
+use crate::basic_rules;
+use crate::fscalar::*;
+use crate::graph::*;
+use crate::params::Expr;
+use num::traits::Zero;
+use num::Rational64;
+use rustc_hash::FxHashMap;
+
+/// Below this many vertices, reading the bitsets costs more than the edge lookups
+/// they save, so the rules below use the versions from [`crate::basic_rules`]
+pub const MIN_DENSE: usize = 8;
+
+/// The edges between a set of at most [`DenseNhd::MAX`] vertices, as bitsets
+///
+/// Vertices are referred to by their position in the list given to [`DenseNhd::new`].
+/// Edges to vertices outside the set are ignored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DenseNhd {
+    vs: Vec<V>,
+    h: Vec<u128>, // Hadamard edges of each vertex
+    n: Vec<u128>, // normal edges of each vertex
+}
+
+impl DenseNhd {
+    /// The largest number of vertices a [`DenseNhd`] can hold
+    pub const MAX: usize = 128;
+
+    /// Reads the edges between the (distinct) vertices `vs` of `g`
+    ///
+    /// Returns `None` if there are more than [`DenseNhd::MAX`] vertices.
+    pub fn new(g: &impl GraphLike, vs: Vec<V>) -> Option<Self> {
+        if vs.len() > Self::MAX {
+            return None;
+        }
+        let index: FxHashMap<V, usize> = vs.iter().enumerate().map(|(i, &v)| (v, i)).collect();
+        let mut h = vec![0; vs.len()];
+        let mut n = vec![0; vs.len()];
+        for (i, &v) in vs.iter().enumerate() {
+            for (w, et) in g.incident_edges(v) {
+                if let Some(&j) = index.get(&w) {
+                    match et {
+                        EType::H => h[i] |= 1 << j,
+                        _ => n[i] |= 1 << j,
+                    }
+                }
+            }
+        }
+        Some(DenseNhd { vs, h, n })
+    }
+
+    /// The vertices of the set, in the order given to [`DenseNhd::new`]
+    pub fn vertices(&self) -> &[V] {
+        &self.vs
+    }
+
+    pub fn len(&self) -> usize {
+        self.vs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vs.is_empty()
+    }
+
+    /// The type of the edge between the `i`-th and `j`-th vertices, if any
+    ///
+    /// Any edge that is not a Hadamard edge is reported as [`EType::N`].
+    pub fn edge_type_opt(&self, i: usize, j: usize) -> Option<EType> {
+        if self.h[i] & (1 << j) != 0 {
+            Some(EType::H)
+        } else if self.n[i] & (1 << j) != 0 {
+            Some(EType::N)
+        } else {
+            None
+        }
+    }
+
+    /// The positions of the Hadamard neighbours of the `i`-th vertex within the set
+    pub fn h_neighbors(&self, i: usize) -> u128 {
+        self.h[i]
+    }
+
+    /// Adds or removes the Hadamard edge between the `i`-th and `j`-th vertices
+    pub fn toggle_h(&mut self, i: usize, j: usize) {
+        self.h[i] ^= 1 << j;
+        self.h[j] ^= 1 << i;
+    }
+
+    /// Adds a Hadamard edge between the `i`-th and `j`-th vertices of `g` like
+    /// [`GraphLike::add_edge_smart`], keeping the bitsets up to date
+    ///
+    /// Both vertices must be Z spiders.
+    fn add_h_edge_smart(&mut self, g: &mut impl GraphLike, i: usize, j: usize) {
+        let (s, t) = (self.vs[i], self.vs[j]);
+        debug_assert!(g.vertex_type(s) == VType::Z && g.vertex_type(t) == VType::Z);
+        if i == j {
+            g.add_to_phase(s, Rational64::new(1, 1));
+            g.scalar_mut().mul_sqrt2_pow(-1);
+            return;
+        }
+        match self.edge_type_opt(i, j) {
+            None => {
+                g.add_edge_with_type(s, t, EType::H);
+                self.toggle_h(i, j);
+            }
+            Some(EType::H) => {
+                g.remove_edge(s, t);
+                g.scalar_mut().mul_sqrt2_pow(-2);
+                self.toggle_h(i, j);
+            }
+            Some(_) => {
+                g.add_to_phase(s, Rational64::new(1, 1));
+                g.scalar_mut().mul_sqrt2_pow(-1);
+            }
+        }
+    }
+}
+
+/// Same as [`basic_rules::local_comp_unchecked`], using a [`DenseNhd`] of the
+/// neighbours of `v`
+///
+/// Falls back to [`basic_rules::local_comp_unchecked`] if `v` has fewer than
+/// [`MIN_DENSE`] or more than [`DenseNhd::MAX`] neighbours.
+pub fn local_comp_unchecked(g: &mut impl GraphLike, v: V) {
+    let nhd = if g.degree(v) < MIN_DENSE {
+        None
+    } else {
+        DenseNhd::new(g, g.neighbor_vec(v))
+    };
+    let Some(mut nhd) = nhd else {
+        return basic_rules::local_comp_unchecked(g, v);
+    };
+    let p = g.phase(v);
+    let vars = g.vars(v);
+
+    let k = nhd.len();
+    for i in 0..k {
+        let n = nhd.vs[i];
+        g.add_to_phase(n, -p);
+
+        if !vars.is_empty() {
+            g.add_to_vars(n, &vars);
+        }
+
+        for j in (i + 1)..k {
+            nhd.add_h_edge_smart(g, i, j);
+        }
+    }
+    g.remove_vertex(v);
+
+    let x = k as i32;
+    g.scalar_mut().mul_sqrt2_pow(((x - 1) * (x - 2)) / 2);
+    g.scalar_mut().mul_phase(p / 2);
+
+    if !vars.is_empty() {
+        g.mul_scalar_factor(Expr::linear(vars), FScalar::from_phase(-p));
+    }
+}
+
+/// Same as [`basic_rules::pivot_unchecked`], using a [`DenseNhd`] of the neighbours
+/// of `v0` and `v1`
+///
+/// Falls back to [`basic_rules::pivot_unchecked`] if they have fewer than
+/// [`MIN_DENSE`] or more than [`DenseNhd::MAX`] neighbours between them.
+pub fn pivot_unchecked(g: &mut impl GraphLike, v0: V, v1: V) {
+    if g.degree(v0) + g.degree(v1) < MIN_DENSE {
+        return basic_rules::pivot_unchecked(g, v0, v1);
+    }
+    let ns0 = g.neighbor_vec(v0);
+    let ns1 = g.neighbor_vec(v1);
+
+    // the neighbours of v0, followed by those of v1 that aren't neighbours of v0
+    let mut vs = ns0.clone();
+    let mut pos1 = Vec::with_capacity(ns1.len());
+    for &n1 in &ns1 {
+        match ns0.iter().position(|&n0| n0 == n1) {
+            Some(i) => pos1.push(i),
+            None => {
+                pos1.push(vs.len());
+                vs.push(n1);
+            }
+        }
+    }
+    let Some(mut nhd) = DenseNhd::new(g, vs) else {
+        return basic_rules::pivot_unchecked(g, v0, v1);
+    };
+
+    let (p0, vars0) = g.phase_and_vars(v0);
+    let (p1, vars1) = g.phase_and_vars(v1);
+
+    for (i, &n0) in ns0.iter().enumerate() {
+        g.add_to_phase(n0, p1);
+        g.add_to_vars(n0, &vars1);
+        for (&j, &n1) in pos1.iter().zip(&ns1) {
+            if n0 != v1 && n1 != v0 {
+                nhd.add_h_edge_smart(g, i, j);
+            }
+        }
+    }
+
+    for &n1 in &ns1 {
+        g.add_to_phase(n1, p0);
+        g.add_to_vars(n1, &vars0);
+    }
+
+    g.remove_vertex(v0);
+    g.remove_vertex(v1);
+
+    let x = ns0.len() as i32;
+    let y = ns1.len() as i32;
+    g.scalar_mut().mul_sqrt2_pow((x - 2) * (y - 2));
+
+    if !p0.is_zero() && !p1.is_zero() {
+        *g.scalar_mut() *= FScalar::minus_one();
+    }
+
+    if !vars0.is_empty() && !vars1.is_empty() {
+        g.mul_scalar_factor(Expr::quadratic(vars0, vars1), FScalar::minus_one());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vec_graph::Graph;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    /// A random graph of Z spiders with Pauli and proper Clifford phases, mostly
+    /// connected by Hadamard edges
+    fn random_graph(n: usize, seed: u64) -> Graph {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut g = Graph::new();
+        for _ in 0..n {
+            let v = g.add_vertex(VType::Z);
+            g.set_phase(v, Rational64::new(rng.gen_range(0..4), 2));
+        }
+        for v in 0..n {
+            for w in (v + 1)..n {
+                if rng.gen_bool(0.5) {
+                    let et = if rng.gen_bool(0.97) {
+                        EType::H
+                    } else {
+                        EType::N
+                    };
+                    g.add_edge_with_type(v, w, et);
+                }
+            }
+        }
+        g
+    }
+
+    #[test]
+    fn local_comp_matches_sparse() {
+        let mut matches = 0;
+        for seed in 0..20 {
+            let g = random_graph(24, seed);
+            for v in g.vertex_vec() {
+                if basic_rules::check_local_comp(&g, v) {
+                    let mut h0 = g.clone();
+                    let mut h1 = g.clone();
+                    basic_rules::local_comp_unchecked(&mut h0, v);
+                    local_comp_unchecked(&mut h1, v);
+                    assert_eq!(h0, h1);
+                    assert_eq!(h0.scalar(), h1.scalar());
+                    matches += 1;
+                }
+            }
+        }
+        assert!(matches > 0);
+    }
+
+    #[test]
+    fn pivot_matches_sparse() {
+        let mut matches = 0;
+        for seed in 0..20 {
+            let g = random_graph(24, seed);
+            for (v0, v1, _) in g.edge_vec() {
+                if basic_rules::check_pivot(&g, v0, v1) {
+                    let mut h0 = g.clone();
+                    let mut h1 = g.clone();
+                    basic_rules::pivot_unchecked(&mut h0, v0, v1);
+                    pivot_unchecked(&mut h1, v0, v1);
+                    assert_eq!(h0, h1);
+                    assert_eq!(h0.scalar(), h1.scalar());
+                    matches += 1;
+                }
+            }
+        }
+        assert!(matches > 0);
+    }
+
+    #[test]
+    fn dense_nhd() {
+        let mut g = Graph::new();
+        let vs: Vec<V> = (0..4).map(|_| g.add_vertex(VType::Z)).collect();
+        g.add_edge_with_type(vs[0], vs[1], EType::H);
+        g.add_edge_with_type(vs[1], vs[2], EType::N);
+        g.add_edge_with_type(vs[2], vs[3], EType::H);
+        let mut nhd = DenseNhd::new(&g, vec![vs[0], vs[1], vs[2]]).unwrap();
+        assert_eq!(nhd.edge_type_opt(0, 1), Some(EType::H));
+        assert_eq!(nhd.edge_type_opt(2, 1), Some(EType::N));
+        assert_eq!(nhd.edge_type_opt(0, 2), None);
+        assert_eq!(nhd.h_neighbors(2), 0);
+        nhd.toggle_h(0, 2);
+        assert_eq!(nhd.edge_type_opt(2, 0), Some(EType::H));
+        assert!(DenseNhd::new(&g, (0..129).collect()).is_none());
+    }
+}
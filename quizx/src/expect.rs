@@ -0,0 +1,109 @@
+// QuiZX - Rust library for quantum circuit rewriting and optimisation
+//         using the ZX-calculus
+// Copyright (C) 2021 - Aleks Kissinger
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Overlaps and related quantities of circuits, computed by stabiliser decomposition
+
+use crate::circuit::Circuit;
+use crate::decompose::{Decomposer, Driver};
+use crate::fscalar::FScalar;
+use crate::graph::*;
+use crate::vec_graph::Graph;
+
+/// Returns the graph of the state obtained by applying `c` to |0...0>
+fn state_graph(c: &Circuit) -> Graph {
+    let mut g: Graph = c.to_graph();
+    g.plug_inputs(&vec![BasisElem::Z0; c.num_qubits()]);
+    g
+}
+
+/// Computes the overlap <0|C1^dag C2|0> of two circuits applied to |0...0>
+///
+/// Panics if the circuits have different numbers of qubits.
+pub fn state_overlap(c1: &Circuit, c2: &Circuit, driver: Driver) -> FScalar {
+    if c1.num_qubits() != c2.num_qubits() {
+        panic!("Cannot compare circuits with different numbers of qubits");
+    }
+
+    let mut g = state_graph(c2);
+    g.plug(&state_graph(c1).to_adjoint());
+
+    let mut d = Decomposer::new(&g);
+    d.with_full_simp().with_driver(driver).decompose();
+    d.scalar()
+}
+
+/// Computes the fidelity |<0|C1^dag C2|0>|^2 of the states obtained by applying two
+/// circuits to |0...0>
+///
+/// Panics if the circuits have different numbers of qubits.
+pub fn state_fidelity(c1: &Circuit, c2: &Circuit, driver: Driver) -> f64 {
+    state_overlap(c1, c2, driver).complex_value().norm_sqr()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decompose::SpiderSelection;
+    use crate::tensor::ToTensor;
+
+    fn statevector_fidelity(c1: &Circuit, c2: &Circuit) -> f64 {
+        let t1 = state_graph(c1).to_tensor64();
+        let t2 = state_graph(c2).to_tensor64();
+        t1.iter()
+            .zip(t2.iter())
+            .map(|(a, b)| a.conj() * b)
+            .sum::<num::Complex<f64>>()
+            .norm_sqr()
+    }
+
+    #[test]
+    fn fidelity_small_circuits() {
+        for seed in 0..10 {
+            let c1 = Circuit::random()
+                .seed(seed)
+                .qubits(4)
+                .depth(30)
+                .clifford_t(0.2)
+                .build();
+            let c2 = Circuit::random()
+                .seed(seed + 100)
+                .qubits(4)
+                .depth(30)
+                .clifford_t(0.2)
+                .build();
+            let mut c3 = c1.clone();
+            c3.add_gate("t", vec![0]);
+            c3.add_gate("h", vec![1]);
+
+            assert!(
+                (state_fidelity(&c1, &c1, Driver::BssWithCats(SpiderSelection::First)) - 1.0).abs()
+                    < 1e-8
+            );
+            for c in [&c2, &c3] {
+                let f = state_fidelity(&c1, c, Driver::BssTOnly(SpiderSelection::First));
+                assert!((f - statevector_fidelity(&c1, c)).abs() < 1e-8);
+            }
+        }
+    }
+
+    #[test]
+    fn fidelity_orthogonal() {
+        let c1 = Circuit::new(2);
+        let mut c2 = Circuit::new(2);
+        c2.add_gate("x", vec![1]);
+        assert!(state_fidelity(&c1, &c2, Driver::BssTOnly(SpiderSelection::First)).abs() < 1e-8);
+    }
+}
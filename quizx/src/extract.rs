@@ -49,7 +49,7 @@ pub trait ToCircuit: GraphLike {
         self.clone().to_circuit_mut()
     }
 
-    fn extractor(&mut self) -> Extractor<Self> {
+    fn extractor(&mut self) -> Extractor<'_, Self> {
         Extractor::new(self)
     }
 }
@@ -290,9 +290,11 @@ impl<'a, G: GraphLike> Extractor<'a, G> {
 
                 // replace a non-zero phase on the frontier with a phase gate
                 let p = self.g.phase(v);
-                if !p.is_zero() {
-                    c.push_front(Gate::new_with_phase(ZPhase, vec![q], p));
+                if !p.is_zero() || self.g.is_symbolic(v) {
+                    let sym = self.g.sym_phase(v);
+                    c.push_front(Gate::new_symbolic(ZPhase, vec![q], p, sym));
                     self.g.set_phase(v, Rational64::zero());
+                    self.g.set_sym_phase(v, Default::default());
                 }
 
                 // inspect neighbors of the frontier vertex
@@ -2,6 +2,7 @@ use approx::AbsDiffEq;
 use num::complex::Complex;
 pub use num::traits::identities::{One, Zero};
 use num::{Float, Rational64, ToPrimitive};
+use serde::{Deserialize, Serialize};
 use std::f64::consts::{PI, SQRT_2};
 use std::fmt;
 use std::iter::{Product, Sum};
@@ -29,7 +30,10 @@ pub use crate::scalar_traits::{FromPhase, Sqrt2};
 /// Note that ω² = i, so for all other complex numbers, `FScalar` gives an approximate
 /// representation of that number as a + c ω² = a + i c. This allows easy conversions
 /// to and from `Complex<f64>` provided in the standard library.
-#[derive(Debug, Clone, Copy, PartialEq)]
+///
+/// Scalars are serialized as the array `[a, b, c, d]` of their coefficients.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(from = "[f64; 4]", into = "[f64; 4]")]
 pub struct FScalar {
     c: [f64; 4],
 }
@@ -90,6 +94,23 @@ impl FScalar {
         self.into()
     }
 
+    /// Returns true if the complex values of the two scalars are within distance
+    /// `tol` of each other
+    ///
+    /// This compares the floating point values, not the representation as a sum of
+    /// powers of ω, so e.g. `sqrt2()` and `real(2f64.sqrt())` are approximately equal.
+    pub fn approx_eq(&self, other: &FScalar, tol: f64) -> bool {
+        (self.complex_value() - other.complex_value()).norm() <= tol
+    }
+
+    /// Returns true if the two scalars are approximately equal up to a global phase,
+    /// i.e. if their absolute values are within `tol` of each other
+    ///
+    /// As with [`FScalar::approx_eq`], this compares the floating point values.
+    pub fn approx_eq_phase(&self, other: &FScalar, tol: f64) -> bool {
+        (self.complex_value().norm() - other.complex_value().norm()).abs() <= tol
+    }
+
     /// Returns an array of 4 pairs giving each coefficient as a pair of integers
     /// `(a,p)`, meaning `a * 2^p`. This is convenient e.g. for the base-2 scientific
     /// notation used to output scalars.
@@ -149,6 +170,284 @@ impl FScalar {
 
         None
     }
+
+    /// Renders the scalar as a LaTeX expression
+    ///
+    /// Scalars of the form `sqrt(2)^p exp(i k π / 4)` are written as a power of 2 and a
+    /// phase, e.g. `2^{-3/2} e^{i\pi/4}`, with phases ±1 and ±i written as signs. Other
+    /// scalars are written as a sum of powers of ω = e^{iπ/4}, like the `Display` output.
+    pub fn to_latex(&self) -> String {
+        if self.c.iter().all(|&c| c == 0.0) {
+            return "0".to_string();
+        }
+
+        let Some((phase, p)) = self.exact_phase_and_sqrt2_pow() else {
+            return self.latex_sum();
+        };
+        let magnitude = match p {
+            0 => String::new(),
+            1 => "\\sqrt{2}".to_string(),
+            2 => "2".to_string(),
+            _ if p % 2 == 0 => format!("2^{{{}}}", p / 2),
+            _ => format!("2^{{{p}/2}}"),
+        };
+        let (sign, factor) = Self::sign_and_phase_factor(phase, |k| format!("e^{{{k}i\\pi/4}}"));
+        match (magnitude.is_empty(), factor.is_empty()) {
+            (true, true) => format!("{sign}1"),
+            (true, false) => format!("{sign}{factor}"),
+            (false, true) => format!("{sign}{magnitude}"),
+            (false, false) => format!("{sign}{magnitude} {factor}"),
+        }
+    }
+
+    /// Renders the scalar as plain text, e.g. `1/√2` or `1/(2√2) e^(iπ/4)`
+    ///
+    /// This uses the same forms as [`FScalar::to_latex`], and falls back to the
+    /// `Display` output for scalars that are not a power of sqrt(2) times a phase.
+    pub fn to_exact_string(&self) -> String {
+        if self.c.iter().all(|&c| c == 0.0) {
+            return "0".to_string();
+        }
+
+        let Some((phase, p)) = self.exact_phase_and_sqrt2_pow() else {
+            return self.to_string();
+        };
+        let k = p.unsigned_abs() / 2;
+        let int = if k < 20 {
+            (1u64 << k).to_string()
+        } else {
+            format!("2^{k}")
+        };
+        let magnitude = match (k, p % 2 != 0) {
+            (0, false) => "1".to_string(),
+            (0, true) => "√2".to_string(),
+            (_, false) => int,
+            (_, true) => format!("{int}√2"),
+        };
+        let magnitude = if p >= 0 || magnitude == "1" {
+            magnitude
+        } else if k > 0 && p % 2 != 0 {
+            format!("1/({magnitude})")
+        } else {
+            format!("1/{magnitude}")
+        };
+        let (sign, factor) = Self::sign_and_phase_factor(phase, |k| format!("e^({k}iπ/4)"));
+        match (magnitude == "1", factor.is_empty()) {
+            (true, false) => format!("{sign}{factor}"),
+            (_, true) => format!("{sign}{magnitude}"),
+            (false, false) => format!("{sign}{magnitude} {factor}"),
+        }
+    }
+
+    /// Splits a multiple of π/4 into a sign and a factor: nothing for ±1, `i` for ±i,
+    /// and `exp(k)` for e^(kiπ/4) with odd `k`, where `k` = 1 is passed as `""`
+    fn sign_and_phase_factor(phase: Phase, exp: impl Fn(&str) -> String) -> (&'static str, String) {
+        let r = phase.to_rational();
+        match (*r.numer(), *r.denom()) {
+            (0, _) => ("", String::new()),
+            (1, 1) => ("-", String::new()),
+            (1, 2) => ("", "i".to_string()),
+            (-1, 2) => ("-", "i".to_string()),
+            (1, 4) => ("", exp("")),
+            (3, 4) => ("", exp("3")),
+            (-1, 4) => ("", exp("-")),
+            _ => ("", exp("-3")),
+        }
+    }
+
+    fn latex_sum(&self) -> String {
+        let mut s = String::new();
+        for (i, (mut v, mut e)) in self.exact_dyadic_form().into_iter().enumerate() {
+            if v == 0 {
+                continue;
+            }
+            if v > -1024 && v < 1024 && e > 0 && e <= 10 {
+                v *= 2i64.pow(e as u32);
+                e = 0;
+            }
+
+            if s.is_empty() {
+                if v < 0 {
+                    s += "-";
+                }
+            } else if v > 0 {
+                s += " + ";
+            } else {
+                s += " - ";
+            }
+
+            let omega = match i {
+                0 => "",
+                1 => "\\omega",
+                2 => "\\omega^{2}",
+                _ => "\\omega^{3}",
+            };
+            let v = v.unsigned_abs();
+            let mut term = if v == 1 && (e != 0 || i != 0) {
+                String::new()
+            } else {
+                v.to_string()
+            };
+            if e != 0 {
+                if !term.is_empty() {
+                    term += " \\cdot ";
+                }
+                term += &format!("2^{{{e}}}");
+            }
+            if !term.is_empty() && !omega.is_empty() {
+                term += " ";
+            }
+            s += &term;
+            s += omega;
+        }
+        s
+    }
+}
+
+impl FScalar {
+    /// Sums scalars exactly, then rounds the result to the nearest `FScalar`
+    ///
+    /// Unlike summing with `+`, the result does not depend on the order of the terms. If a
+    /// term is not finite, or the exact sum does not fit in 128-bit coefficients, this
+    /// falls back to floating point addition.
+    pub fn exact_sum(terms: impl IntoIterator<Item = FScalar>) -> FScalar {
+        let terms: Vec<FScalar> = terms.into_iter().collect();
+        terms
+            .iter()
+            .try_fold(Dyadic::zero(), |acc, t| acc.add(&Dyadic::from_fscalar(t)?))
+            .map(|d| d.to_fscalar())
+            .unwrap_or_else(|| terms.into_iter().sum())
+    }
+
+    /// Multiplies scalars exactly, then rounds the result to the nearest `FScalar`
+    ///
+    /// As with [`FScalar::exact_sum`], this falls back to floating point arithmetic if
+    /// the exact product does not fit.
+    pub fn exact_product(factors: impl IntoIterator<Item = FScalar>) -> FScalar {
+        let factors: Vec<FScalar> = factors.into_iter().collect();
+        factors
+            .iter()
+            .try_fold(Dyadic::one(), |acc, f| acc.mul(&Dyadic::from_fscalar(f)?))
+            .map(|d| d.to_fscalar())
+            .unwrap_or_else(|| factors.into_iter().product())
+    }
+}
+
+/// An exact scalar `2^e (a + b ω + c ω² + d ω³)` with 128-bit integer coefficients, used
+/// for exact arithmetic on `FScalar`s
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Dyadic {
+    e: i32,
+    c: [i128; 4],
+}
+
+/// `x * 2^s`, or `None` if this overflows
+fn checked_shl(x: i128, s: u32) -> Option<i128> {
+    if x == 0 {
+        Some(0)
+    } else if s >= 127 || x.unsigned_abs().leading_zeros() <= s {
+        None
+    } else {
+        Some(x << s)
+    }
+}
+
+impl Dyadic {
+    fn zero() -> Self {
+        Dyadic { e: 0, c: [0; 4] }
+    }
+
+    fn one() -> Self {
+        Dyadic {
+            e: 0,
+            c: [1, 0, 0, 0],
+        }
+    }
+
+    fn from_fscalar(s: &FScalar) -> Option<Self> {
+        if !s.c.iter().all(|f| f.is_finite()) {
+            return None;
+        }
+        let edf = s.exact_dyadic_form();
+        let e = edf
+            .iter()
+            .filter(|(m, _)| *m != 0)
+            .map(|&(_, e)| e as i32)
+            .min()
+            .unwrap_or(0);
+        let mut c = [0; 4];
+        for (ci, &(m, me)) in c.iter_mut().zip(&edf) {
+            *ci = checked_shl(m as i128, (me as i32 - e) as u32)?;
+        }
+        Some(Dyadic { e, c })
+    }
+
+    fn to_fscalar(self) -> FScalar {
+        let f = 2.0_f64.powi(self.e);
+        FScalar {
+            c: self.c.map(|co| (co as f64) * f),
+        }
+    }
+
+    /// Returns the coefficients with respect to a smaller exponent `e`
+    fn coeffs_at(&self, e: i32) -> Option<[i128; 4]> {
+        let mut c = [0; 4];
+        for (ci, &co) in c.iter_mut().zip(&self.c) {
+            *ci = checked_shl(co, (self.e - e) as u32)?;
+        }
+        Some(c)
+    }
+
+    /// Divides out common factors of 2, to keep the coefficients small
+    fn normalize(mut self) -> Self {
+        if self.c.iter().all(|&co| co == 0) {
+            return Dyadic::zero();
+        }
+        let tz = self.c.iter().map(|co| co.trailing_zeros()).min().unwrap();
+        for co in self.c.iter_mut() {
+            *co >>= tz;
+        }
+        self.e += tz as i32;
+        self
+    }
+
+    fn add(&self, rhs: &Dyadic) -> Option<Dyadic> {
+        if rhs.c == [0; 4] {
+            return Some(*self);
+        }
+        if self.c == [0; 4] {
+            return Some(*rhs);
+        }
+        let e = self.e.min(rhs.e);
+        let (c0, c1) = (self.coeffs_at(e)?, rhs.coeffs_at(e)?);
+        let mut c = [0; 4];
+        for ((ci, x0), x1) in c.iter_mut().zip(c0).zip(c1) {
+            *ci = x0.checked_add(x1)?;
+        }
+        Some(Dyadic { e, c }.normalize())
+    }
+
+    fn mul(&self, rhs: &Dyadic) -> Option<Dyadic> {
+        let mut c = [0i128; 4];
+        for i in 0..4 {
+            for j in 0..4 {
+                let x = self.c[i].checked_mul(rhs.c[j])?;
+                // ω⁴ = -1
+                if i + j < 4 {
+                    c[i + j] = c[i + j].checked_add(x)?;
+                } else {
+                    c[i + j - 4] = c[i + j - 4].checked_sub(x)?;
+                }
+            }
+        }
+        Some(
+            Dyadic {
+                e: self.e.checked_add(rhs.e)?,
+                c,
+            }
+            .normalize(),
+        )
+    }
 }
 
 impl Default for FScalar {
@@ -485,6 +784,12 @@ impl From<[f64; 4]> for FScalar {
     }
 }
 
+impl From<FScalar> for [f64; 4] {
+    fn from(value: FScalar) -> Self {
+        value.c
+    }
+}
+
 impl From<&FScalar> for Complex<f64> {
     fn from(value: &FScalar) -> Self {
         Complex {
@@ -556,6 +861,61 @@ mod test {
         assert_eq!(format!("{s}"), "1e11 + 1e21 ω + 1e31 ω² + 1e41 ω³");
     }
 
+    #[test]
+    fn latex() {
+        let with = |p: i32, phase: Rational64| {
+            let mut s = FScalar::one();
+            s.mul_sqrt2_pow(p);
+            s.mul_phase(phase);
+            s.to_latex()
+        };
+        assert_eq!(FScalar::zero().to_latex(), "0");
+        assert_eq!(FScalar::one().to_latex(), "1");
+        assert_eq!(with(0, Rational64::one()), "-1");
+        assert_eq!(with(0, Rational64::new(1, 2)), "i");
+        assert_eq!(with(1, Rational64::zero()), "\\sqrt{2}");
+        assert_eq!(with(-2, Rational64::new(-1, 2)), "-2^{-1} i");
+        assert_eq!(with(-3, Rational64::new(1, 4)), "2^{-3/2} e^{i\\pi/4}");
+        assert_eq!(with(4, Rational64::new(-3, 4)), "2^{2} e^{-3i\\pi/4}");
+
+        let s: FScalar = [1, 1, 0, 0].into();
+        assert_eq!(s.to_latex(), "1 + \\omega");
+        let s: FScalar = [3, 0, -1, 0].into();
+        assert_eq!(s.to_latex(), "3 - \\omega^{2}");
+        let s: FScalar = [0.0, -0.25, 0.0, 0.75].into();
+        assert_eq!(
+            s.to_latex(),
+            "-2^{-2} \\omega + 3 \\cdot 2^{-2} \\omega^{3}"
+        );
+    }
+
+    #[test]
+    fn exact_string() {
+        let with = |p: i32, phase: Rational64| {
+            let mut s = FScalar::one();
+            s.mul_sqrt2_pow(p);
+            s.mul_phase(phase);
+            s.to_exact_string()
+        };
+        assert_eq!(FScalar::zero().to_exact_string(), "0");
+        assert_eq!(with(0, Rational64::zero()), "1");
+        assert_eq!(with(0, Rational64::one()), "-1");
+        assert_eq!(with(0, Rational64::new(-1, 2)), "-i");
+        assert_eq!(with(-1, Rational64::zero()), "1/√2");
+        assert_eq!(with(-2, Rational64::one()), "-1/2");
+        assert_eq!(with(3, Rational64::zero()), "2√2");
+        assert_eq!(with(-3, Rational64::new(1, 4)), "1/(2√2) e^(iπ/4)");
+        assert_eq!(with(4, Rational64::new(1, 2)), "4 i");
+        assert_eq!(with(0, Rational64::new(3, 4)), "e^(3iπ/4)");
+        assert_eq!(with(2, Rational64::new(-1, 4)), "2 e^(-iπ/4)");
+
+        // the sum 1/2 + 1/2 ω² is 1/√2 e^(iπ/4)
+        let s: FScalar = [0.5, 0.0, 0.5, 0.0].into();
+        assert_eq!(s.to_exact_string(), "1/√2 e^(iπ/4)");
+        let s: FScalar = [3, 0, -1, 0].into();
+        assert_eq!(s.to_exact_string(), "3 - 1 ω²");
+    }
+
     #[test]
     fn int_arith() {
         let s4: FScalar = 4.into();
@@ -765,4 +1125,41 @@ mod test {
         let s2: FScalar = c.into();
         assert_eq!(s1, s2);
     }
+
+    #[test]
+    fn exact_sum_and_product() {
+        let big = FScalar::dyadic(60, [1, 0, 0, 0]);
+        let small = FScalar::dyadic(-3, [1, 0, 0, 0]);
+        let terms = [big, small, FScalar::dyadic(60, [-1, 0, 0, 0])];
+        assert_eq!(terms.iter().copied().sum::<FScalar>(), FScalar::zero());
+        assert_eq!(FScalar::exact_sum(terms), small);
+        assert_eq!(FScalar::exact_sum([]), FScalar::zero());
+
+        let fs = [
+            FScalar::sqrt2(),
+            FScalar::from_phase(Rational64::new(1, 4)),
+            FScalar::dyadic(-5, [3, 1, 0, -7]),
+        ];
+        let p: FScalar = fs.iter().copied().product();
+        assert_eq!(FScalar::exact_product(fs), p);
+        assert_eq!(FScalar::exact_product([]), FScalar::one());
+
+        // non-finite values fall back to floats
+        let inf = FScalar::real(f64::INFINITY);
+        assert_eq!(FScalar::exact_sum([inf, FScalar::one()]), inf);
+    }
+
+    #[test]
+    fn approx_eq() {
+        let s = FScalar::sqrt2();
+        assert!(s.approx_eq(&FScalar::real(2f64.sqrt()), 1e-10));
+        assert!(s.approx_eq(&FScalar::real(1.4), 0.1));
+        assert!(!s.approx_eq(&FScalar::real(1.4), 1e-3));
+        assert!(!s.approx_eq(&(s * FScalar::from_phase(Rational64::new(1, 4))), 1e-6));
+
+        let t = s * FScalar::from_phase(Rational64::new(1, 4));
+        assert!(s.approx_eq_phase(&t, 1e-10));
+        assert!(s.approx_eq_phase(&FScalar::complex(0.0, -2f64.sqrt()), 1e-10));
+        assert!(!s.approx_eq_phase(&FScalar::one(), 1e-6));
+    }
 }
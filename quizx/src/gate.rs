@@ -17,9 +17,12 @@
 use crate::circuit::Circuit;
 use crate::fscalar::FScalar;
 use crate::graph::*;
+use crate::json::JsonGate;
 use crate::params::{Parity, Var};
 use crate::phase::Phase;
+use crate::symbolic::{fmt_phase, SymPhase};
 use num::{Rational64, Zero};
+use serde::de::Error as _;
 
 #[derive(PartialEq, Eq, Clone, Copy, Debug)]
 pub enum GType {
@@ -39,10 +42,13 @@ pub enum GType {
     HAD,
     TOFF,
     CCZ,
+    Fanout,
     InitAncilla,
     PostSelect,
     Measure,
     MeasureReset,
+    MeasureZ,
+    Barrier,
     UnknownGate,
 }
 
@@ -67,6 +73,8 @@ impl GType {
             "ccx" => TOFF,
             "ccz" => CCZ,
             "swap" => SWAP,
+            // n.b. this is a quizx-specific gate
+            "fanout" => Fanout,
             // n.b. these are pyzx-specific gates
             "pp" => ParityPhase,
             "xcx" => XCX,
@@ -74,6 +82,8 @@ impl GType {
             "post_sel" => PostSelect,
             "measure_d" => Measure,
             "measure_r" => MeasureReset,
+            "measure_z" => MeasureZ,
+            "barrier" => Barrier,
             _ => UnknownGate,
         }
     }
@@ -94,6 +104,8 @@ impl GType {
             TOFF => "ccx",
             CCZ => "ccz",
             SWAP => "swap",
+            // n.b. this is a quizx-specific gate
+            Fanout => "fanout",
             // n.b. these are pyzx-specific gates
             ParityPhase => "pp",
             XCX => "xcx",
@@ -101,6 +113,8 @@ impl GType {
             PostSelect => "post_sel",
             Measure => "measure_d",
             MeasureReset => "measure_r",
+            MeasureZ => "measure_z",
+            Barrier => "barrier",
             UnknownGate => "UNKNOWN",
         }
     }
@@ -113,18 +127,37 @@ impl GType {
         match self {
             CNOT | CZ | XCX | SWAP => Some(2),
             TOFF | CCZ => Some(3),
-            ParityPhase | UnknownGate => None,
+            ParityPhase | Fanout | Barrier | UnknownGate => None,
             _ => Some(1),
         }
     }
 }
 
+impl serde::Serialize for GType {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.qasm_name())
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for GType {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        let t = GType::from_qasm_name(&s);
+        if t == UnknownGate && s != UnknownGate.qasm_name() {
+            return Err(D::Error::custom(format!("Unknown gate type: {s}")));
+        }
+        Ok(t)
+    }
+}
+
 #[derive(PartialEq, Eq, Clone, Debug)]
 pub struct Gate {
     pub t: GType,
     pub qs: Vec<usize>,
     pub phase: Phase,
     pub vars: Parity,
+    /// symbolic part of the phase, see [`crate::symbolic`]
+    pub sym: SymPhase,
 }
 
 impl Default for Gate {
@@ -134,17 +167,32 @@ impl Default for Gate {
             qs: vec![],
             phase: Phase::zero(),
             vars: Parity::zero(),
+            sym: SymPhase::default(),
         }
     }
 }
 
+/// Gates are serialized as `{"gate": "cx", "controls": [0], "targets": [1]}`, with an
+/// optional `"phase"` in half turns.
+impl serde::Serialize for Gate {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        JsonGate::from_gate(self).serialize(serializer)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Gate {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        JsonGate::deserialize(deserializer)?
+            .to_gate()
+            .map_err(|e| D::Error::custom(format!("Failed to convert JSON gate to gate: {e}")))
+    }
+}
+
 impl Gate {
     pub fn from_qasm_name(s: &str) -> Gate {
         Gate {
             t: GType::from_qasm_name(s),
-            qs: vec![],
-            phase: Phase::zero(),
-            vars: Parity::zero(),
+            ..Default::default()
         }
     }
 
@@ -155,8 +203,8 @@ impl Gate {
     pub fn to_qasm(&self) -> String {
         let mut s = String::from(self.qasm_name());
 
-        if let ZPhase | XPhase = self.t {
-            s += &format!("({}*pi)", self.phase.to_f64());
+        if let ZPhase | XPhase | MeasureZ = self.t {
+            s += &format!("({})", fmt_phase(self.phase, &self.sym));
         }
 
         s += " ";
@@ -170,6 +218,7 @@ impl Gate {
         match self.t {
             ZPhase | XPhase | ParityPhase => {
                 self.phase *= -1;
+                self.sym = -std::mem::take(&mut self.sym);
             }
             S => self.t = Sdg,
             T => self.t = Tdg,
@@ -181,6 +230,42 @@ impl Gate {
 }
 
 impl Gate {
+    /// The qubits the gate acts on, i.e. its controls followed by its targets
+    pub fn qubits(&self) -> Vec<usize> {
+        self.qs.clone()
+    }
+
+    /// Returns true if the gate acts on exactly two qubits
+    ///
+    /// Barriers are not gates, so they are never two-qubit gates.
+    pub fn is_two_qubit(&self) -> bool {
+        self.t != Barrier && self.qs.len() == 2
+    }
+
+    /// Returns true if the gate is a Clifford unitary
+    ///
+    /// Phase gates and parity phases are Clifford if their phase is a multiple of π/2.
+    /// XCX gates are always Clifford, as [`Gate::add_to_graph`] ignores their phase.
+    /// Measurements, postselections, barriers, unknown gates and gates with symbolic
+    /// phases are not Clifford.
+    pub fn is_clifford(&self) -> bool {
+        match self.t {
+            NOT | Z | S | Sdg | CNOT | CZ | XCX | SWAP | HAD | Fanout => true,
+            ZPhase | XPhase | ParityPhase => self.phase.is_clifford() && self.sym.is_empty(),
+            _ => false,
+        }
+    }
+
+    /// Returns true if the gate is T-like, i.e. T, T^dagger, or a phase gate or parity
+    /// phase with an odd multiple of π/4
+    pub fn is_t(&self) -> bool {
+        match self.t {
+            T | Tdg => true,
+            ZPhase | XPhase | ParityPhase => self.phase.is_t() && self.sym.is_empty(),
+            _ => false,
+        }
+    }
+
     pub fn new(t: GType, qs: Vec<usize>) -> Gate {
         Gate {
             t,
@@ -198,6 +283,51 @@ impl Gate {
         }
     }
 
+    /// A fan-out CNOT, i.e. a CNOT from `control` onto each of the `targets`
+    ///
+    /// This is added to a graph as a single Z-spider on the control, connected to an
+    /// X-spider on each target.
+    pub fn fanout(control: usize, targets: &[usize]) -> Gate {
+        let mut qs = vec![control];
+        qs.extend_from_slice(targets);
+        Gate::new(Fanout, qs)
+    }
+
+    /// A barrier on the given qubits
+    ///
+    /// A barrier acts as the identity, but circuit-level optimisations should not move
+    /// gates on these qubits across it. Graph-level simplification ignores barriers.
+    pub fn barrier(qs: Vec<usize>) -> Gate {
+        Gate::new(Barrier, qs)
+    }
+
+    /// The single-qubit unitary U3(θ, φ, λ) = Rz(φ) Ry(θ) Rz(λ), as a Z-X-Z Euler
+    /// decomposition
+    ///
+    /// Angles are in half turns, as for other phase gates. The gates are returned in
+    /// circuit order, i.e. `rz(λ - 1/2)`, `rx(θ)`, `rz(φ + 1/2)`, which is U3 up to a
+    /// global phase.
+    pub fn u3(
+        qubit: usize,
+        theta: impl Into<Phase>,
+        phi: impl Into<Phase>,
+        lambda: impl Into<Phase>,
+    ) -> [Gate; 3] {
+        let half = Phase::new(Rational64::new(1, 2));
+        [
+            Gate::new_with_phase(ZPhase, vec![qubit], lambda.into() - half),
+            Gate::new_with_phase(XPhase, vec![qubit], theta),
+            Gate::new_with_phase(ZPhase, vec![qubit], phi.into() + half),
+        ]
+    }
+
+    /// A Z-basis measurement of `qubit` postselected onto the given outcome
+    ///
+    /// The outcome is stored in the phase of the gate, i.e. 0 for |0> and 1 for |1>.
+    pub fn measure_z(qubit: usize, outcome: bool) -> Gate {
+        Gate::new_with_phase(MeasureZ, vec![qubit], if outcome { 1 } else { 0 })
+    }
+
     pub fn new_with_phase_and_vars(
         t: GType,
         qs: Vec<usize>,
@@ -209,6 +339,24 @@ impl Gate {
             qs,
             phase: phase.into(),
             vars: vars.into(),
+            ..Default::default()
+        }
+    }
+
+    /// A phase gate or parity phase whose phase has a symbolic part, see
+    /// [`crate::symbolic`]
+    pub fn new_symbolic(
+        t: GType,
+        qs: Vec<usize>,
+        phase: impl Into<Phase>,
+        sym: impl Into<SymPhase>,
+    ) -> Gate {
+        Gate {
+            t,
+            qs,
+            phase: phase.into(),
+            sym: sym.into(),
+            ..Default::default()
         }
     }
 
@@ -240,6 +388,7 @@ impl Gate {
                     self.qs.len() * 2 - 1
                 }
             }
+            Fanout => self.qs.len().saturating_sub(1),
             _ => 1,
         }
     }
@@ -263,12 +412,22 @@ impl Gate {
                     for &c in self.qs[0..sz - 1].iter() {
                         circ.push(Gate::new(CNOT, vec![c, t]));
                     }
-                    circ.push(Gate::new_with_phase(ZPhase, vec![t], self.phase));
+                    circ.push(Gate::new_symbolic(
+                        ZPhase,
+                        vec![t],
+                        self.phase,
+                        self.sym.clone(),
+                    ));
                     for &c in self.qs[0..sz - 1].iter().rev() {
                         circ.push(Gate::new(CNOT, vec![c, t]));
                     }
                 }
             }
+            Fanout => {
+                for &t in &self.qs[1..] {
+                    circ.push(Gate::new(CNOT, vec![self.qs[0], t]));
+                }
+            }
             _ => circ.push(self.clone()),
         }
     }
@@ -391,9 +550,13 @@ impl Gate {
         postselect: bool,
     ) -> Vec<V> {
         match self.t {
-            ZPhase => Gate::add_spider(graph, qs, self.qs[0], VType::Z, EType::N, self.phase)
-                .into_iter()
-                .collect(),
+            ZPhase => {
+                let v = Gate::add_spider(graph, qs, self.qs[0], VType::Z, EType::N, self.phase);
+                if let Some(v) = v {
+                    graph.set_sym_phase(v, self.sym.clone());
+                }
+                v.into_iter().collect()
+            }
             Z => Gate::add_spider(
                 graph,
                 qs,
@@ -444,9 +607,13 @@ impl Gate {
             )
             .into_iter()
             .collect(),
-            XPhase => Gate::add_spider(graph, qs, self.qs[0], VType::X, EType::N, self.phase)
-                .into_iter()
-                .collect(),
+            XPhase => {
+                let v = Gate::add_spider(graph, qs, self.qs[0], VType::X, EType::N, self.phase);
+                if let Some(v) = v {
+                    graph.set_sym_phase(v, self.sym.clone());
+                }
+                v.into_iter().collect()
+            }
             NOT => Gate::add_spider(
                 graph,
                 qs,
@@ -526,6 +693,40 @@ impl Gate {
                     vec![]
                 }
             }
+            Fanout => {
+                if self.qs[1..].iter().any(|q| !qs.contains_key(q)) {
+                    return vec![];
+                }
+                let Some(v) =
+                    Gate::add_spider(graph, qs, self.qs[0], VType::Z, EType::N, Phase::zero())
+                else {
+                    return vec![];
+                };
+                let mut vs = vec![v];
+                for &q in &self.qs[1..] {
+                    let w =
+                        Gate::add_spider(graph, qs, q, VType::X, EType::N, Phase::zero()).unwrap();
+                    graph.add_edge(v, w);
+                    graph.scalar_mut().mul_sqrt2_pow(1);
+                    vs.push(w);
+                }
+
+                // line up the spiders, as for CNOT
+                let outs: Vec<V> = self
+                    .qs
+                    .iter()
+                    .map(|q| graph.outputs()[*qs.get(q).unwrap()])
+                    .collect();
+                let row = outs
+                    .iter()
+                    .map(|&o| graph.row(o))
+                    .fold(f64::NEG_INFINITY, f64::max);
+                for (&w, &o) in vs.iter().zip(&outs) {
+                    graph.set_row(w, row - 1.0);
+                    graph.set_row(o, row);
+                }
+                vs
+            }
             SWAP => {
                 if let (Some(&i0), Some(&i1)) = (qs.get(&self.qs[0]), qs.get(&self.qs[1])) {
                     qs.insert(self.qs[0], i1);
@@ -647,6 +848,42 @@ impl Gate {
                 }
                 vec![]
             }
+            MeasureZ => {
+                if let Some(&i) = qs.get(&self.qs[0]) {
+                    let v = graph.outputs()[i];
+                    if graph.vertex_type(v) == VType::B {
+                        // the projector |b><b| onto the recorded outcome, as an
+                        // X-effect followed by an X-state, both with phase b
+                        graph.set_vertex_type(v, VType::X);
+                        graph.set_phase(v, self.phase);
+
+                        let qubit = graph.qubit(v);
+                        let row = graph.row(v);
+
+                        let v1 = graph.add_vertex_with_data(VData {
+                            ty: VType::X,
+                            phase: self.phase,
+                            qubit,
+                            row: row + 1.0,
+                            ..Default::default()
+                        });
+
+                        let outp = graph.add_vertex_with_data(VData {
+                            ty: VType::B,
+                            qubit,
+                            row: row + 2.0,
+                            ..Default::default()
+                        });
+
+                        graph.add_edge(v1, outp);
+                        graph.outputs_mut()[i] = outp;
+
+                        graph.scalar_mut().mul_sqrt2_pow(-2);
+                        return vec![v, v1];
+                    }
+                }
+                vec![]
+            }
             CCZ => {
                 if postselect {
                     Gate::add_ccz_postselected(graph, qs, &self.qs);
@@ -682,7 +919,7 @@ impl Gate {
                 }
                 vec![]
             }
-            UnknownGate => {
+            Barrier | UnknownGate => {
                 vec![]
             }
         }
@@ -14,10 +14,13 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::f64::consts::PI;
 use std::mem;
+use std::ops::Range;
 
 use crate::circuit::*;
 use crate::gate::*;
+use crate::phase::Phase;
 use num::Rational64;
 use rand::rngs::StdRng;
 use rand::{Rng, SeedableRng};
@@ -31,6 +34,22 @@ pub struct RandomCircuitBuilder {
     pub p_h: f32,
     pub p_s: f32,
     pub p_t: f32,
+    /// make sure the interaction graph is connected, see [`RandomCircuitBuilder::connected`]
+    pub connected: bool,
+}
+
+/// Relative weights of each gate type produced by [`RandomCircuitBuilder`]
+///
+/// Weights need not sum to 1. They are divided by their total when passed to
+/// [`RandomCircuitBuilder::gate_weights`], so e.g. a CZ weight of 2.0 and H weight
+/// of 1.0 gives CZ gates with probability 2/3 and H gates with probability 1/3.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct GateWeights {
+    pub cnot: f32,
+    pub cz: f32,
+    pub h: f32,
+    pub s: f32,
+    pub t: f32,
 }
 
 pub struct RandomHiddenShiftCircuitBuilder {
@@ -83,6 +102,7 @@ impl Default for RandomCircuitBuilder {
             p_h: 0.0,
             p_s: 0.0,
             p_t: 0.0,
+            connected: false,
         }
     }
 }
@@ -154,6 +174,23 @@ impl RandomCircuitBuilder {
         self
     }
 
+    /// Set the probability of each gate type from relative weights
+    ///
+    /// The weights are normalized by dividing each by their total. Panics if the
+    /// total is not positive.
+    pub fn gate_weights(&mut self, weights: GateWeights) -> &mut Self {
+        let total = weights.cnot + weights.cz + weights.h + weights.s + weights.t;
+        if total <= 0.0 {
+            panic!("Gate weights must have a positive total");
+        }
+        self.p_cnot = weights.cnot / total;
+        self.p_cz = weights.cz / total;
+        self.p_h = weights.h / total;
+        self.p_s = weights.s / total;
+        self.p_t = weights.t / total;
+        self
+    }
+
     /// Distribute the remaining probability evenly among Clifford (CNOT, H, S) gates
     pub fn with_cliffords(&mut self) -> &mut Self {
         let p = (1.0 - self.p_t - self.p_cz) / 3.0;
@@ -171,6 +208,19 @@ impl RandomCircuitBuilder {
         self.p_t(p_t).with_cliffords()
     }
 
+    /// Make sure every qubit is linked to every other qubit by two-qubit gates
+    ///
+    /// If the interaction graph of the generated circuit is not connected, randomly
+    /// chosen single-qubit gates are replaced by CNOT or CZ gates between components,
+    /// so the number of gates is still `depth`. If two-qubit gates have probability 0,
+    /// the circuit is left as it is. If there are too few single-qubit gates, all of
+    /// them are replaced and the circuit is returned with fewer, but still more than
+    /// one, connected components.
+    pub fn connected(&mut self, connected: bool) -> &mut Self {
+        self.connected = connected;
+        self
+    }
+
     pub fn uniform(&mut self) -> &mut Self {
         self.p_cnot = 0.2;
         self.p_cz = 0.2;
@@ -181,6 +231,14 @@ impl RandomCircuitBuilder {
     }
 
     pub fn build(&mut self) -> Circuit {
+        let mut c = self.random_gates();
+        if self.connected {
+            self.connect(&mut c);
+        }
+        c
+    }
+
+    fn random_gates(&mut self) -> Circuit {
         let mut c = Circuit::new(self.qubits);
 
         for _ in 0..self.depth {
@@ -225,6 +283,70 @@ impl RandomCircuitBuilder {
 
         c
     }
+
+    /// Replace single-qubit gates by two-qubit gates until the interaction graph is connected
+    fn connect(&mut self, c: &mut Circuit) {
+        let components = interaction_components(c);
+        if components.len() <= 1 {
+            return;
+        }
+        let p2 = self.p_cnot + self.p_cz;
+        if p2 <= 0.0 {
+            return;
+        }
+
+        let mut oneq: Vec<usize> = (0..c.gates.len())
+            .filter(|&i| c.gates[i].qs.len() == 1)
+            .collect();
+        let mut linked = components[0].clone();
+        for component in &components[1..] {
+            if oneq.is_empty() {
+                return;
+            }
+            let i = oneq.swap_remove(self.rng.gen_range(0..oneq.len()));
+            let q0 = linked[self.rng.gen_range(0..linked.len())];
+            let q1 = component[self.rng.gen_range(0..component.len())];
+            let t = if self.rng.gen::<f32>() * p2 < self.p_cnot {
+                CNOT
+            } else {
+                CZ
+            };
+            c.gates[i] = Gate::new(t, vec![q0, q1]);
+            linked.extend_from_slice(component);
+        }
+    }
+}
+
+/// The connected components of the graph on qubits, with an edge for each gate acting
+/// on more than one qubit
+fn interaction_components(c: &Circuit) -> Vec<Vec<usize>> {
+    fn find(parent: &mut [usize], mut q: usize) -> usize {
+        while parent[q] != q {
+            parent[q] = parent[parent[q]];
+            q = parent[q];
+        }
+        q
+    }
+
+    let mut parent: Vec<usize> = (0..c.num_qubits()).collect();
+    for g in &c.gates {
+        for w in g.qs.windows(2) {
+            let (r0, r1) = (find(&mut parent, w[0]), find(&mut parent, w[1]));
+            parent[r0] = r1;
+        }
+    }
+
+    let mut components: Vec<Vec<usize>> = vec![];
+    let mut index = vec![usize::MAX; c.num_qubits()];
+    for q in 0..c.num_qubits() {
+        let r = find(&mut parent, q);
+        if index[r] == usize::MAX {
+            index[r] = components.len();
+            components.push(vec![]);
+        }
+        components[index[r]].push(q);
+    }
+    components
 }
 
 impl RandomHiddenShiftCircuitBuilder {
@@ -508,6 +630,73 @@ impl SurfaceCodeCircuitBuilder {
     }
 }
 
+/// Parameters of a testset of random Clifford+T circuits, see [`seeded_testset`]
+///
+/// Circuits are sorted into bins by their T-count, and generation stops once every
+/// bin holds `per_bin` circuits, or after `max_attempts` circuits.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TestsetConfig {
+    pub qubits: usize,
+    pub depth: usize,
+    /// ranges of T-counts, a circuit goes in the first bin containing its T-count
+    pub bins: Vec<Range<usize>>,
+    pub per_bin: usize,
+    pub max_attempts: usize,
+}
+
+/// Generates a testset of random Clifford+T circuits, one list of circuits per bin
+///
+/// Each circuit has a probability of T gates drawn uniformly from [0, 1), and is
+/// built by [`RandomCircuitBuilder`] with a seed drawn from `seed`. Circuits are
+/// generated and binned one at a time, so the same config and seed always give the
+/// same testset, on any machine. Bins may have fewer than `per_bin` circuits if
+/// `max_attempts` is reached first.
+pub fn seeded_testset(config: &TestsetConfig, seed: u64) -> Vec<Vec<Circuit>> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut bins: Vec<Vec<Circuit>> = vec![vec![]; config.bins.len()];
+    for _ in 0..config.max_attempts {
+        if bins.iter().all(|b| b.len() >= config.per_bin) {
+            break;
+        }
+        let p_t: f32 = rng.gen();
+        let c = Circuit::random()
+            .seed(rng.gen())
+            .qubits(config.qubits)
+            .depth(config.depth)
+            .clifford_t(p_t)
+            .build();
+        let tcount = c.num_gates_of_type(GType::T);
+        if let Some(i) = config.bins.iter().position(|r| r.contains(&tcount)) {
+            if bins[i].len() < config.per_bin {
+                bins[i].push(c);
+            }
+        }
+    }
+    bins
+}
+
+/// Returns a Trotterised evolution of an open transverse-field Ising chain on
+/// `n_qubits` qubits, with Hamiltonian `-jz Σ Z_i Z_(i+1) - hx Σ X_i`
+///
+/// Each of the `n_steps` steps evolves for unit time, as a layer of `rzz(-2 jz)` on
+/// each pair of neighbouring qubits, followed by a layer of `rx(-2 hx)` on every
+/// qubit. The `rzz` gates are phase gadgets, and for most couplings the phases are
+/// not multiples of π/4, so they are kept as the nearest rationals.
+pub fn trotter_ising(n_qubits: usize, n_steps: usize, jz: f64, hx: f64) -> Circuit {
+    let zz = Phase::from_f64(-2.0 * jz / PI);
+    let x = Phase::from_f64(-2.0 * hx / PI);
+    let mut c = Circuit::new(n_qubits);
+    for _ in 0..n_steps {
+        for q in 1..n_qubits {
+            c.push(Gate::new_with_phase(GType::ParityPhase, vec![q - 1, q], zz));
+        }
+        for q in 0..n_qubits {
+            c.push(Gate::new_with_phase(GType::XPhase, vec![q], x));
+        }
+    }
+    c
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -528,6 +717,83 @@ mod tests {
         assert_eq!(c.num_gates(), 20);
     }
 
+    #[test]
+    fn random_connected() {
+        for seed in 0..20 {
+            let mut b = Circuit::random();
+            b.seed(seed)
+                .qubits(12)
+                .depth(40)
+                .p_cnot(0.05)
+                .p_h(0.5)
+                .p_t(0.45);
+            let c = b.build();
+            assert_eq!(c.num_gates(), 40);
+            let c1 = b.seed(seed).connected(true).build();
+            assert_eq!(c1.num_gates(), 40);
+            assert_eq!(interaction_components(&c1).len(), 1);
+            if interaction_components(&c).len() == 1 {
+                assert_eq!(c.gates, c1.gates);
+            }
+        }
+
+        // without two-qubit gates, the circuit can't be connected
+        let c = Circuit::random()
+            .qubits(3)
+            .depth(10)
+            .p_h(1.0)
+            .connected(true)
+            .build();
+        assert_eq!(interaction_components(&c).len(), 3);
+
+        // with too few gates, all single-qubit gates are used up
+        let c = Circuit::random()
+            .qubits(8)
+            .depth(3)
+            .p_cnot(0.5)
+            .p_h(0.5)
+            .connected(true)
+            .build();
+        assert_eq!(c.num_gates(), 3);
+        assert!(c.gates.iter().all(|g| g.qs.len() == 2));
+        assert!(interaction_components(&c).len() > 1);
+    }
+
+    #[test]
+    fn random_gate_weights() {
+        let c = Circuit::random()
+            .seed(1337)
+            .qubits(5)
+            .depth(100)
+            .gate_weights(GateWeights {
+                cz: 3.0,
+                t: 1.0,
+                ..Default::default()
+            })
+            .build();
+
+        assert_eq!(c.num_gates(), 100);
+        assert_eq!(c.num_gates_of_type(CZ) + c.num_gates_of_type(T), 100);
+        assert!(c.num_gates_of_type(CZ) > c.num_gates_of_type(T));
+
+        let mut builder = Circuit::random();
+        builder.gate_weights(GateWeights {
+            cnot: 1.0,
+            cz: 1.0,
+            h: 1.0,
+            s: 1.0,
+            t: 4.0,
+        });
+        assert_eq!(builder.p_t, 0.5);
+        assert_eq!(builder.p_h, 0.125);
+    }
+
+    #[test]
+    #[should_panic]
+    fn random_gate_weights_zero() {
+        Circuit::random().gate_weights(GateWeights::default());
+    }
+
     #[test]
     fn random_seeds() {
         let mut builder = Circuit::random();
@@ -630,4 +896,46 @@ mod tests {
             assert_eq!(c.num_gates_of_type(ZPhase), depth);
         }
     }
+
+    #[test]
+    fn testset_reproducible() {
+        let config = TestsetConfig {
+            qubits: 4,
+            depth: 30,
+            bins: vec![0..5, 5..10, 10..20],
+            per_bin: 3,
+            max_attempts: 1000,
+        };
+        let set = seeded_testset(&config, 1337);
+        for (bin, r) in set.iter().zip(&config.bins) {
+            assert_eq!(bin.len(), 3);
+            assert!(bin.iter().all(|c| r.contains(&c.num_gates_of_type(T))));
+        }
+
+        let gates = |set: &[Vec<Circuit>]| -> Vec<String> {
+            set.iter().flatten().map(|c| c.to_string()).collect()
+        };
+        assert_eq!(gates(&set), gates(&seeded_testset(&config, 1337)));
+        assert_ne!(gates(&set), gates(&seeded_testset(&config, 1338)));
+    }
+
+    #[test]
+    fn trotter_ising_chain() {
+        use crate::tensor::ToTensor;
+
+        let c = trotter_ising(5, 3, 0.7, 0.3);
+        assert_eq!(c.num_qubits(), 5);
+        assert_eq!(c.num_gates_of_type(ParityPhase), 4 * 3);
+        assert_eq!(c.num_gates_of_type(XPhase), 5 * 3);
+        assert!((c.gates[0].phase.to_f64() + 1.4 / PI).abs() < 1e-12);
+        assert!((c.gates[4].phase.to_f64() + 0.6 / PI).abs() < 1e-12);
+
+        // one step maps |00> to e^(i jz) (cos(hx)|0> + i sin(hx)|1>)^⊗2
+        let (jz, hx) = (0.4_f64, 0.9_f64);
+        let t = trotter_ising(2, 1, jz, hx).to_basic_gates().to_tensorf();
+        let amp = |i: [usize; 4]| t[i].complex_value().norm();
+        assert!((amp([0, 0, 0, 0]) - hx.cos().powi(2)).abs() < 1e-9);
+        assert!((amp([1, 1, 0, 0]) - hx.sin().powi(2)).abs() < 1e-9);
+        assert!((amp([0, 1, 0, 0]) - (hx.cos() * hx.sin()).abs()).abs() < 1e-9);
+    }
 }
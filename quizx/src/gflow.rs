@@ -0,0 +1,243 @@
+// QuiZX - Rust library for quantum circuit rewriting and optimisation
+//         using the ZX-calculus
+// Copyright (C) 2021 - Aleks Kissinger
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Checking for generalised flow (gflow), the condition under which a graph can be
+//! extracted as a circuit
+//!
+//! The spiders of a graph-like graph form an open graph, whose inputs and outputs are
+//! the spiders connected to the boundary. A phase gadget is treated as a single vertex
+//! measured in the YZ plane, and all other spiders are measured in the XY plane. The
+//! check follows the layered algorithm of Backens et al. (arXiv:2003.01664): starting
+//! from the outputs, it repeatedly solves a linear system over GF(2) to find every
+//! vertex that can be corrected by the vertices found so far.
+
+use crate::graph::*;
+use rustc_hash::{FxHashMap, FxHashSet};
+
+/// A row of bits, used for the linear systems over GF(2)
+#[derive(Clone)]
+struct BitRow(Vec<u64>);
+
+impl BitRow {
+    fn new(len: usize) -> Self {
+        BitRow(vec![0; len.div_ceil(64)])
+    }
+
+    fn get(&self, i: usize) -> bool {
+        self.0[i / 64] >> (i % 64) & 1 == 1
+    }
+
+    fn flip(&mut self, i: usize) {
+        self.0[i / 64] ^= 1 << (i % 64);
+    }
+
+    fn add(&mut self, other: &BitRow) {
+        for (a, b) in self.0.iter_mut().zip(&other.0) {
+            *a ^= b;
+        }
+    }
+}
+
+/// Returns true if `g` has a gflow, so it can be extracted as a circuit
+///
+/// `g` should be graph-like, i.e. only have Z spiders connected by Hadamard edges, as
+/// after [`crate::simplify::full_simp`]. Otherwise, this returns false. Components
+/// that are not connected to any boundary are scalars and are ignored.
+pub fn has_gflow(g: &impl GraphLike) -> bool {
+    for v in g.vertices() {
+        match g.vertex_type(v) {
+            VType::B => {}
+            VType::Z => {
+                let plain = g
+                    .incident_edges(v)
+                    .any(|(w, et)| et != EType::H && g.vertex_type(w) != VType::B);
+                if plain {
+                    return false;
+                }
+            }
+            _ => return false,
+        }
+    }
+
+    // spiders in components with a boundary, and the ones attached to the boundary
+    let mut spiders: Vec<V> = vec![];
+    for comp in g.component_vertices() {
+        if comp.iter().any(|&v| g.vertex_type(v) == VType::B) {
+            spiders.extend(comp.into_iter().filter(|&v| g.vertex_type(v) != VType::B));
+        }
+    }
+    spiders.sort();
+    let mut inputs: FxHashSet<V> = FxHashSet::default();
+    let mut outputs: FxHashSet<V> = FxHashSet::default();
+    for (bs, set) in [(g.inputs(), &mut inputs), (g.outputs(), &mut outputs)] {
+        for &b in bs {
+            if let Some(v) = g.neighbors(b).find(|&v| g.vertex_type(v) != VType::B) {
+                // a spider can't be attached to two inputs, or two outputs
+                if !set.insert(v) {
+                    return false;
+                }
+            }
+        }
+    }
+
+    // phase gadgets are merged into their hub, which is measured in the YZ plane
+    let mut yz: FxHashSet<V> = FxHashSet::default();
+    let mut leaves: FxHashSet<V> = FxHashSet::default();
+    for &v in &spiders {
+        if g.degree(v) != 1 || inputs.contains(&v) || outputs.contains(&v) {
+            continue;
+        }
+        let h = g.neighbors(v).next().unwrap();
+        if g.vertex_type(h) == VType::Z
+            && g.degree(h) > 1
+            && !inputs.contains(&h)
+            && !outputs.contains(&h)
+            && yz.insert(h)
+        {
+            leaves.insert(v);
+        }
+    }
+    spiders.retain(|v| !leaves.contains(v));
+
+    let index: FxHashMap<V, usize> = spiders.iter().enumerate().map(|(i, &v)| (v, i)).collect();
+    let nhd: Vec<Vec<usize>> = spiders
+        .iter()
+        .map(|&v| {
+            g.neighbors(v)
+                .filter_map(|w| index.get(&w).copied())
+                .collect()
+        })
+        .collect();
+
+    let mut solved: Vec<bool> = spiders.iter().map(|v| outputs.contains(v)).collect();
+    loop {
+        let unsolved: Vec<usize> = (0..spiders.len()).filter(|&i| !solved[i]).collect();
+        if unsolved.is_empty() {
+            return true;
+        }
+        let cols: Vec<usize> = (0..spiders.len())
+            .filter(|&i| solved[i] && !inputs.contains(&spiders[i]))
+            .collect();
+
+        // The unsolved vertices index the rows. For a correction set K drawn from the
+        // columns, row u of the system is the parity of the neighbours of u in K. Each
+        // unsolved vertex v gets a right-hand side: {v} in the XY plane and the
+        // unsolved neighbours of v in the YZ plane.
+        let row_of: FxHashMap<usize, usize> =
+            unsolved.iter().enumerate().map(|(r, &u)| (u, r)).collect();
+        let width = cols.len() + unsolved.len();
+        let mut rows = vec![BitRow::new(width); unsolved.len()];
+        for (c, &w) in cols.iter().enumerate() {
+            for u in &nhd[w] {
+                if let Some(&r) = row_of.get(u) {
+                    rows[r].flip(c);
+                }
+            }
+        }
+        for (j, &v) in unsolved.iter().enumerate() {
+            if yz.contains(&spiders[v]) {
+                for u in &nhd[v] {
+                    if let Some(&r) = row_of.get(u) {
+                        rows[r].flip(cols.len() + j);
+                    }
+                }
+            } else {
+                rows[j].flip(cols.len() + j);
+            }
+        }
+
+        // reduce the columns of the system to echelon form, so a right-hand side can
+        // be solved iff it vanishes on the remaining zero rows
+        let mut rank = 0;
+        for c in 0..cols.len() {
+            if let Some(p) = (rank..rows.len()).find(|&r| rows[r].get(c)) {
+                rows.swap(rank, p);
+                let pivot = rows[rank].clone();
+                for (r, row) in rows.iter_mut().enumerate() {
+                    if r != rank && row.get(c) {
+                        row.add(&pivot);
+                    }
+                }
+                rank += 1;
+            }
+        }
+
+        let mut progress = false;
+        for (j, &v) in unsolved.iter().enumerate() {
+            if rows[rank..].iter().all(|row| !row.get(cols.len() + j)) {
+                solved[v] = true;
+                progress = true;
+            }
+        }
+        if !progress {
+            return false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::circuit::Circuit;
+    use crate::extract::ToCircuit;
+    use crate::simplify::*;
+    use crate::vec_graph::Graph;
+
+    #[test]
+    fn gflow_simplified_circuits() {
+        for seed in 0..20 {
+            let c = Circuit::random()
+                .seed(seed)
+                .qubits(5)
+                .depth(40)
+                .clifford_t(0.3)
+                .build();
+            let mut g: Graph = c.to_graph();
+            interior_clifford_simp(&mut g);
+            assert!(has_gflow(&g));
+            full_simp(&mut g);
+            assert!(has_gflow(&g));
+            assert!(g.to_circuit().is_ok());
+        }
+    }
+
+    #[test]
+    fn gflow_missing() {
+        // two inputs merged into a single output
+        let mut g = Graph::new();
+        let is: Vec<_> = (0..2).map(|_| g.add_vertex(VType::B)).collect();
+        let o = g.add_vertex(VType::B);
+        let vs: Vec<_> = (0..3).map(|_| g.add_vertex(VType::Z)).collect();
+        g.add_edge(is[0], vs[0]);
+        g.add_edge(is[1], vs[1]);
+        g.add_edge_with_type(vs[0], vs[2], EType::H);
+        g.add_edge_with_type(vs[1], vs[2], EType::H);
+        g.add_edge(vs[2], o);
+        g.set_inputs(is);
+        g.set_outputs(vec![o]);
+        assert!(!has_gflow(&g));
+
+        // adding a second output fixes it
+        let o1 = g.add_vertex(VType::B);
+        g.add_edge(vs[1], o1);
+        g.outputs_mut().push(o1);
+        assert!(has_gflow(&g));
+
+        // a plain edge between spiders is not graph-like
+        g.set_edge_type(vs[0], vs[2], EType::N);
+        assert!(!has_gflow(&g));
+    }
+}
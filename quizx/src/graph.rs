@@ -16,12 +16,17 @@
 
 use crate::params::Expr;
 use crate::phase::Phase;
+use crate::symbolic::SymPhase;
+pub use crate::unitary::from_unitary;
 use crate::util::*;
 use crate::{fscalar::*, params::Parity};
-use derive_more::{Display, From};
+use derive_more::{Display, Error, From};
 use num::rational::Rational64;
+use num::Complex;
 use rustc_hash::{FxHashMap, FxHashSet};
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::hash::BuildHasher;
 use std::iter::FromIterator;
 
 pub type V = usize;
@@ -50,6 +55,8 @@ pub struct VData {
     pub ty: VType,
     pub phase: Phase,
     pub vars: Parity,
+    /// symbolic part of the phase, see [`crate::symbolic`]
+    pub sym: SymPhase,
     pub qubit: f64,
     pub row: f64,
 }
@@ -60,6 +67,7 @@ impl Default for VData {
             ty: VType::B,
             phase: Phase::zero(),
             vars: Parity::zero(),
+            sym: SymPhase::default(),
             qubit: 0.0,
             row: 0.0,
         }
@@ -161,6 +169,33 @@ impl Coord {
     }
 }
 
+/// A structural problem with a graph, see [`GraphLike::validate`]
+#[derive(Debug, Clone, PartialEq, Eq, Display, Error)]
+#[non_exhaustive]
+pub enum GraphError {
+    /// A boundary vertex with other than one neighbour.
+    #[display("boundary {v} has degree {degree}, expected 1")]
+    BoundaryDegree { v: V, degree: usize },
+    /// A boundary vertex with a nonzero phase.
+    #[display("boundary {v} has a nonzero phase")]
+    BoundaryPhase { v: V },
+    /// An input or output that is not in the graph.
+    #[display("input/output {v} is not in the graph")]
+    MissingBoundary { v: V },
+    /// An input or output that is not a boundary vertex.
+    #[display("input/output {v} is not a boundary vertex")]
+    NotBoundary { v: V },
+    /// An edge from `v` to a vertex `w` that is not in the graph.
+    #[display("vertex {v} has an edge to missing vertex {w}")]
+    DanglingEdge { v: V, w: V },
+    /// More than one edge between `v` and `w`.
+    #[display("vertices {v} and {w} have {count} edges between them")]
+    ParallelEdges { v: V, w: V, count: usize },
+    /// An edge from `v` to itself.
+    #[display("vertex {v} has a self-loop")]
+    SelfLoop { v: V },
+}
+
 pub trait GraphLike: Clone + Sized + Send + Sync + std::fmt::Debug {
     /// Initialise a new empty graph
     fn new() -> Self;
@@ -255,8 +290,74 @@ pub trait GraphLike: Clone + Sized + Send + Sync + std::fmt::Debug {
     fn scalar(&self) -> &FScalar;
 
     /// Returns a mutable ref to the scalar associated with a ZX diagram
+    ///
+    /// The rewrite rules in this crate keep the scalar up to date, so the graph
+    /// stays equal to the linear map it started as. Changing the scalar by hand
+    /// breaks this, unless it compensates for a change to the graph, e.g. when
+    /// implementing a rewrite rule whose scalar factor is known.
     fn scalar_mut(&mut self) -> &mut FScalar;
 
+    /// Multiplies the scalar associated with a ZX diagram by `factor`
+    ///
+    /// See [`GraphLike::scalar_mut`] for when this is safe to use.
+    fn mul_scalar(&mut self, factor: FScalar) {
+        *self.scalar_mut() *= factor;
+    }
+
+    /// Checks the graph for structural problems, and returns all of them
+    ///
+    /// Boundaries should have degree 1 and no phase, and inputs and outputs should be
+    /// boundaries in the graph. Edges should join two distinct vertices in the graph, with
+    /// at most one edge between any two vertices. Errors are ordered by vertex.
+    fn validate(&self) -> Result<(), Vec<GraphError>> {
+        let mut errors = vec![];
+        for &v in self.inputs().iter().chain(self.outputs()) {
+            match self.vertex_type_opt(v) {
+                None => errors.push(GraphError::MissingBoundary { v }),
+                Some(VType::B) => {}
+                Some(_) => errors.push(GraphError::NotBoundary { v }),
+            }
+        }
+
+        let mut vs = self.vertex_vec();
+        vs.sort();
+        for v in vs {
+            if self.vertex_type(v) == VType::B {
+                if self.degree(v) != 1 {
+                    errors.push(GraphError::BoundaryDegree {
+                        v,
+                        degree: self.degree(v),
+                    });
+                }
+                if !self.phase(v).is_zero() {
+                    errors.push(GraphError::BoundaryPhase { v });
+                }
+            }
+
+            let mut nhd = self.neighbor_vec(v);
+            nhd.sort();
+            let mut i = 0;
+            while i < nhd.len() {
+                let w = nhd[i];
+                let count = nhd[i..].iter().take_while(|&&u| u == w).count();
+                i += count;
+                if w == v {
+                    errors.push(GraphError::SelfLoop { v });
+                } else if !self.contains_vertex(w) {
+                    errors.push(GraphError::DanglingEdge { v, w });
+                } else if count > 1 && v < w {
+                    errors.push(GraphError::ParallelEdges { v, w, count });
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
     /// Returns the first edge satisfying the given function, or None
     fn find_edge<F>(&self, f: F) -> Option<(V, V, EType)>
     where
@@ -289,6 +390,9 @@ pub trait GraphLike: Clone + Sized + Send + Sync + std::fmt::Debug {
     }
 
     /// Set the phase of a vertex
+    ///
+    /// Like every [`Phase`], it is stored normalized to (-1, 1], so phases that differ
+    /// by a multiple of 2 compare equal.
     fn set_phase(&mut self, v: V, phase: impl Into<Phase>) {
         self.vertex_data_mut(v).phase = phase.into();
     }
@@ -298,7 +402,7 @@ pub trait GraphLike: Clone + Sized + Send + Sync + std::fmt::Debug {
         self.vertex_data(v).phase
     }
 
-    /// Adds a value to the phase of a vertex
+    /// Adds a value to the phase of a vertex, modulo 2
     fn add_to_phase(&mut self, v: V, phase: impl Into<Phase>) {
         let vd = self.vertex_data_mut(v);
         vd.phase = (vd.phase + phase.into()).normalize();
@@ -383,6 +487,47 @@ pub trait GraphLike: Clone + Sized + Send + Sync + std::fmt::Debug {
         self.vertex_data_mut(v).vars = vars1;
     }
 
+    /// Sets the symbolic part of the phase of a vertex, see [`crate::symbolic`]
+    fn set_sym_phase(&mut self, v: V, sym: SymPhase) {
+        self.vertex_data_mut(v).sym = sym;
+    }
+
+    /// Returns the symbolic part of the phase of a vertex
+    fn sym_phase(&self, v: V) -> SymPhase {
+        self.vertex_data(v).sym.clone()
+    }
+
+    /// Adds to the symbolic part of the phase of a vertex
+    fn add_to_sym_phase(&mut self, v: V, sym: &SymPhase) {
+        if !sym.is_empty() {
+            self.vertex_data_mut(v).sym += sym;
+        }
+    }
+
+    /// Returns true if the phase of a vertex depends on symbolic parameters
+    fn is_symbolic(&self, v: V) -> bool {
+        !self.vertex_data(v).sym.is_empty()
+    }
+
+    /// Returns true if the phase of any vertex depends on symbolic parameters
+    fn has_symbols(&self) -> bool {
+        self.vertices().any(|v| self.is_symbolic(v))
+    }
+
+    /// Substitutes values, in half turns, for symbolic parameters
+    ///
+    /// The substituted terms are added to the numeric phase of each vertex, rounded
+    /// as for angles read from QASM. Parameters without a value are left symbolic.
+    fn substitute(&mut self, params: &HashMap<String, f64>) {
+        for v in self.vertex_vec() {
+            if self.is_symbolic(v) {
+                let (value, rest) = self.vertex_data(v).sym.substitute(params);
+                self.add_to_phase(v, approx_phase(value));
+                self.set_sym_phase(v, rest);
+            }
+        }
+    }
+
     /// Add an edge to the graph
     fn add_edge(&mut self, s: V, t: V) {
         self.add_edge_with_type(s, t, EType::N);
@@ -436,6 +581,20 @@ pub trait GraphLike: Clone + Sized + Send + Sync + std::fmt::Debug {
         self.incident_edges(v).collect()
     }
 
+    /// Returns an iterator over the neighbours of a vertex connected by a Hadamard edge
+    fn h_neighbors(&self, v: V) -> impl Iterator<Item = V> {
+        self.incident_edges(v)
+            .filter(|&(_, et)| et == EType::H)
+            .map(|(w, _)| w)
+    }
+
+    /// Returns an iterator over the neighbours of a vertex connected by a normal edge
+    fn normal_neighbors(&self, v: V) -> impl Iterator<Item = V> {
+        self.incident_edges(v)
+            .filter(|&(_, et)| et == EType::N)
+            .map(|(w, _)| w)
+    }
+
     /// Convert all X spiders to Z with the colour-change rule
     fn x_to_z(&mut self) {
         for v in Vec::from_iter(self.vertices()) {
@@ -455,6 +614,16 @@ pub trait GraphLike: Clone + Sized + Send + Sync + std::fmt::Debug {
         v
     }
 
+    /// Add a vertex to the graph with the given type and coordinates
+    ///
+    /// The coordinates are only used for drawing and are kept by
+    /// [`crate::json`], but the rewrite rules don't update them.
+    fn add_vertex_with_coord(&mut self, ty: VType, coord: impl Into<Coord>) -> V {
+        let v = self.add_vertex(ty);
+        self.set_coord(v, coord);
+        v
+    }
+
     /// Add an edge and simplify if necessary to remove parallel edges
     ///
     /// The behaviour of this function depends on the type of source/target
@@ -557,6 +726,48 @@ pub trait GraphLike: Clone + Sized + Send + Sync + std::fmt::Debug {
         self.scalar_mut().mul_sqrt2_pow(-1);
     }
 
+    /// Plug the state `alpha |0> + beta |1>` into the i-th input.
+    ///
+    /// The state is built as an X-spider followed by a Z-spider, where the phases
+    /// are snapped to the nearest multiple of 1/4 when they are within floating point
+    /// error of one. Hence Clifford+T states, such as magic states, are plugged in
+    /// exactly, whereas generic states introduce arbitrary phases. The norm and
+    /// global phase of the state are absorbed into the scalar.
+    ///
+    /// Panics if `alpha` and `beta` are both zero.
+    fn plug_state(&mut self, i: usize, alpha: Complex<f64>, beta: Complex<f64>) {
+        let norm = (alpha.norm_sqr() + beta.norm_sqr()).sqrt();
+        if norm == 0.0 {
+            panic!("Cannot plug in the zero vector");
+        }
+
+        // up to a scalar, X(x) = cos(x/2)|0> - i sin(x/2)|1>, so pick x to get the
+        // right magnitudes and correct the relative phase with a following Z(z)
+        let x = 2.0 * beta.norm().atan2(alpha.norm());
+        let z = if beta.norm() / norm < 1e-12 {
+            0.0
+        } else {
+            beta.arg() - alpha.arg() + std::f64::consts::FRAC_PI_2
+        };
+        let x_phase = approx_phase(x / std::f64::consts::PI);
+        let z_phase = approx_phase(z / std::f64::consts::PI);
+
+        let v = self.inputs()[i];
+        self.set_vertex_type(v, VType::Z);
+        self.set_phase(v, z_phase);
+        let w = self.add_vertex_with_phase(VType::Z, x_phase);
+        self.add_edge_with_type(v, w, EType::H);
+        self.inputs_mut().remove(i);
+
+        // X(x) = sqrt(2) e^(i x/2) (cos(x/2)|0> - i sin(x/2)|1>)
+        let s = Complex::from_polar(
+            norm,
+            alpha.arg() - x_phase.to_f64() * std::f64::consts::FRAC_PI_2,
+        );
+        *self.scalar_mut() *= FScalar::complex(s.re, s.im);
+        self.scalar_mut().mul_sqrt2_pow(-1);
+    }
+
     /// Plug the given list of normalised basis elements in as inputs, starting from the left
     ///
     /// The list `plug` should be of length <= the number of inputs.
@@ -657,18 +868,46 @@ pub trait GraphLike: Clone + Sized + Send + Sync + std::fmt::Debug {
             && (0..n).all(|i| self.connected(self.inputs()[i], self.outputs()[i]))
     }
 
-    /// Return number of Z or X spiders with non-Clifford phase
+    /// Return number of Z or X spiders with non-Clifford or symbolic phase
     fn tcount(&self) -> usize {
         let mut n = 0;
         for v in self.vertices() {
             let t = self.vertex_type(v);
-            if (t == VType::Z || t == VType::X) && !self.phase(v).is_clifford() {
+            if (t == VType::Z || t == VType::X)
+                && (!self.phase(v).is_clifford() || self.is_symbolic(v))
+            {
                 n += 1;
             }
         }
         n
     }
 
+    /// Return the number of spiders, i.e. vertices which are not boundaries
+    ///
+    /// Unlike [`GraphLike::num_vertices`], this doesn't count inputs and outputs, and
+    /// unlike [`GraphLike::tcount`], it counts every spider, including Clifford ones, as
+    /// well as H-boxes and W nodes. Like `tcount`, this iterates over the vertices, so
+    /// it takes linear time.
+    fn num_spiders(&self) -> usize {
+        self.num_vertices() - self.num_boundaries()
+    }
+
+    /// Return the number of boundary vertices
+    ///
+    /// This is usually the number of inputs and outputs, but also counts boundaries that
+    /// are in neither list. It takes linear time in the number of vertices.
+    fn num_boundaries(&self) -> usize {
+        self.vertices()
+            .filter(|&v| self.vertex_type(v) == VType::B)
+            .count()
+    }
+
+    /// Return the number of Hadamard edges, which takes linear time in the number of
+    /// edges
+    fn num_h_edges(&self) -> usize {
+        self.edges().filter(|&(_, _, et)| et == EType::H).count()
+    }
+
     /// Return a graphviz-friendly string representation of the graph
     fn to_dot(&self) -> String {
         let mut dot = String::from("graph {\n");
@@ -725,6 +964,10 @@ pub trait GraphLike: Clone + Sized + Send + Sync + std::fmt::Debug {
         for v in self.vertex_vec() {
             let p = self.phase(v);
             self.set_phase(v, -p);
+            if self.is_symbolic(v) {
+                let sym = -self.sym_phase(v);
+                self.set_sym_phase(v, sym);
+            }
         }
 
         let inp = self.inputs().clone();
@@ -792,6 +1035,67 @@ pub trait GraphLike: Clone + Sized + Send + Sync + std::fmt::Debug {
         g
     }
 
+    /// Returns the induced subgraph on the given vertices, with a boundary for each edge
+    /// that leaves the set
+    ///
+    /// The vertices keep their data and the edges between them keep their types. The
+    /// inputs and outputs of `self` in `vs` stay inputs and outputs, in the same order.
+    /// Each edge from `v` in `vs` to `w` outside it becomes an edge of the same type from
+    /// `v` to a new boundary vertex, with the qubit and row of `w`. These new boundaries
+    /// are added to the outputs, after the old ones, ordered by `(v, w)`. The scalar of
+    /// the subgraph is 1.
+    ///
+    /// Vertices of the subgraph are numbered in the order of the vertices of `vs`,
+    /// followed by the new boundaries.
+    fn subgraph<S: BuildHasher>(&self, vs: &HashSet<V, S>) -> Self {
+        let mut g = Self::new();
+        let mut verts: Vec<V> = vs.iter().copied().collect();
+        verts.sort();
+        let mut vert_map: FxHashMap<V, V> = FxHashMap::default();
+        for &v in &verts {
+            let w = g.add_vertex_with_data(self.vertex_data(v).clone());
+            vert_map.insert(v, w);
+        }
+
+        let mut cut = vec![];
+        for &v in &verts {
+            for (w, ety) in self.incident_edges(v) {
+                match vert_map.get(&w) {
+                    // add each internal edge once
+                    Some(&w1) if v < w => g.add_edge_with_type(vert_map[&v], w1, ety),
+                    Some(_) => {}
+                    None => cut.push((v, w, ety)),
+                }
+            }
+        }
+
+        let inputs: Vec<V> = self
+            .inputs()
+            .iter()
+            .filter_map(|v| vert_map.get(v).copied())
+            .collect();
+        let mut outputs: Vec<V> = self
+            .outputs()
+            .iter()
+            .filter_map(|v| vert_map.get(v).copied())
+            .collect();
+        cut.sort_by_key(|&(v, w, _)| (v, w));
+        for (v, w, ety) in cut {
+            let b = g.add_vertex_with_data(VData {
+                ty: VType::B,
+                qubit: self.qubit(w),
+                row: self.row(w),
+                ..Default::default()
+            });
+            g.add_edge_with_type(vert_map[&v], b, ety);
+            outputs.push(b);
+        }
+        g.set_inputs(inputs);
+        g.set_outputs(outputs);
+
+        g
+    }
+
     /// Returns max row of any vertex
     fn depth(&self) -> f64 {
         pmax(self.vertices().map(|v| self.row(v))).unwrap_or(-1.0)
@@ -826,11 +1130,145 @@ pub trait GraphLike: Clone + Sized + Send + Sync + std::fmt::Debug {
     }
 }
 
+/// Converts a number of half-turns into a phase, preferring exact multiples of 1/4
+pub(crate) fn approx_phase(half_turns: f64) -> Phase {
+    let quarters = (4.0 * half_turns).round();
+    if (4.0 * half_turns - quarters).abs() < 1e-9 {
+        Phase::new(Rational64::new(quarters as i64, 4))
+    } else {
+        Phase::from_f64(half_turns).limit_denominator(1 << 20)
+    }
+}
+
+/// The entanglement entropy, in bits, of the qubits `subsystem` of the state given by
+/// `g` with the rest of its qubits
+///
+/// Qubits are the positions of the outputs. If `g` is a graph state up to local
+/// unitaries, i.e. every output is connected to its own Z-spider and all other edges
+/// are Hadamard edges between these spiders, this is the rank over GF(2) of the
+/// adjacency matrix between the spiders in the subsystem and the rest. The phases of
+/// the spiders are local, so they can be arbitrary. Otherwise, `g` must be a
+/// Clifford diagram, and the entropy is computed from its stabiliser tableau, see
+/// [`crate::stabilizer::Tableau::from_graph`].
+///
+/// Returns an error if `g` has inputs, if a qubit is out of range, or if `g` is
+/// neither a graph state nor a Clifford diagram of a non-zero state.
+pub fn entanglement_entropy(g: &impl GraphLike, subsystem: &[usize]) -> Result<f64, String> {
+    if !g.inputs().is_empty() {
+        return Err(format!(
+            "Expected a state, but the graph has {} inputs",
+            g.inputs().len()
+        ));
+    }
+    let n = g.outputs().len();
+    if let Some(q) = subsystem.iter().find(|&&q| q >= n) {
+        return Err(format!("Qubit {q} out of range for {n} outputs"));
+    }
+    let mut in_subsystem = vec![false; n];
+    for &q in subsystem {
+        in_subsystem[q] = true;
+    }
+
+    if let Some(spiders) = graph_state_spiders(g) {
+        let (a, b): (Vec<_>, Vec<_>) = (0..n).partition(|&q| in_subsystem[q]);
+        let cut = crate::linalg::Mat2::build(a.len(), b.len(), |i, j| {
+            g.connected(spiders[a[i]], spiders[b[j]])
+        });
+        return Ok(cut.rank() as f64);
+    }
+
+    let clifford = g.vertices().all(|v| match g.vertex_type(v) {
+        VType::B => true,
+        VType::Z | VType::X => g.phase(v).is_clifford(),
+        _ => false,
+    });
+    if !clifford {
+        return Err("Expected a graph state or a Clifford diagram".to_string());
+    }
+    let tab = crate::stabilizer::Tableau::from_graph(g).ok_or("The state is zero")?;
+    // the stabilisers supported on the subsystem form a group of dimension
+    // |A| - S, so the generators restricted to it span a space of dimension |A| + S
+    let a: Vec<usize> = (0..n).filter(|&q| in_subsystem[q]).collect();
+    let restricted = crate::linalg::Mat2::build(n, 2 * a.len(), |i, j| {
+        let p = &tab.stabilizers()[i];
+        if j < a.len() {
+            p.x[a[j]]
+        } else {
+            p.z[a[j - a.len()]]
+        }
+    });
+    Ok((restricted.rank() - a.len()) as f64)
+}
+
+/// Reads a ZX diagram from TikZ code, e.g. as written by TikZiT or PyZX
+///
+/// Z and X spiders, H-boxes and boundaries are read from `\node` commands and edges,
+/// with or without a Hadamard, from `\draw` commands. The node coordinates become the
+/// rows and qubits of the vertices, and boundaries are inputs if they are to the left
+/// of their neighbour and outputs otherwise.
+///
+/// Node styles are the TikZiT ZX styles, like `Z dot`, `X phase dot`, `hadamard` and
+/// `none`, or the short forms `zx_z`, `zx_x` and `zx_h`. Edge styles are either empty
+/// or `hadamard edge`. Phases are node labels like `$\frac{3\pi}{4}$` or `$\pi/2$`.
+///
+/// Returns an error for unrecognized node or edge styles, phases that are not
+/// rational multiples of π, edges to undefined nodes, and self-loops or parallel
+/// edges on nodes other than spiders.
+pub fn from_tikz(s: &str) -> Result<crate::vec_graph::Graph, String> {
+    crate::tikz::graph_from_tikz(s)
+}
+
+/// The spider of each output, if `g` is a graph state up to local unitaries, see
+/// [`entanglement_entropy`]
+fn graph_state_spiders(g: &impl GraphLike) -> Option<Vec<V>> {
+    let mut spiders = Vec::with_capacity(g.outputs().len());
+    let mut seen = FxHashSet::default();
+    for &o in g.outputs() {
+        let mut ns = g.neighbors(o);
+        let w = ns.next()?;
+        if ns.next().is_some() || g.vertex_type(w) != VType::Z || !seen.insert(w) {
+            return None;
+        }
+        spiders.push(w);
+    }
+    if g.num_vertices() != 2 * spiders.len() {
+        return None;
+    }
+    for (s, t, et) in g.edges() {
+        let boundary = g.vertex_type(s) == VType::B || g.vertex_type(t) == VType::B;
+        if !boundary && et != EType::H {
+            return None;
+        }
+    }
+    Some(spiders)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::tensor::ToTensor;
     use crate::vec_graph::Graph;
+
+    #[test]
+    fn phases_mod_2() {
+        let mut g = Graph::new();
+        let v = g.add_vertex(VType::Z);
+        for _ in 0..4 {
+            g.add_to_phase(v, Phase::one());
+        }
+        assert_eq!(g.phase(v), Phase::zero());
+        assert!(g.phase(v).is_zero());
+
+        g.set_phase(v, Rational64::new(5, 2));
+        assert_eq!(g.phase(v), Rational64::new(1, 2).into());
+        g.add_to_phase(v, Rational64::new(1, 1));
+        assert_eq!(g.phase(v), Rational64::new(-1, 2).into());
+        g.set_phase(v, Rational64::new(-4, 1));
+        assert_eq!(g.phase(v), Phase::zero());
+        g.set_phase(v, Rational64::new(-1, 1));
+        assert_eq!(g.phase(v), Phase::one());
+    }
+
     #[test]
     fn smart_edges() {
         let mut g = Graph::new();
@@ -866,6 +1304,22 @@ mod tests {
         assert_eq!(tg, th);
     }
 
+    #[test]
+    fn typed_neighbors() {
+        let mut g = Graph::new();
+        let vs: Vec<V> = (0..5).map(|_| g.add_vertex(VType::Z)).collect();
+        g.add_edge_with_type(vs[0], vs[1], EType::H);
+        g.add_edge_with_type(vs[0], vs[2], EType::N);
+        g.add_edge_with_type(vs[3], vs[0], EType::H);
+        g.add_edge_with_type(vs[1], vs[4], EType::N);
+
+        let mut hs: Vec<V> = g.h_neighbors(vs[0]).collect();
+        hs.sort();
+        assert_eq!(hs, vec![vs[1], vs[3]]);
+        assert_eq!(g.normal_neighbors(vs[0]).collect::<Vec<_>>(), vec![vs[2]]);
+        assert_eq!(g.h_neighbors(vs[4]).count(), 0);
+    }
+
     #[test]
     fn plugs() {
         let mut g = Graph::new();
@@ -901,6 +1355,61 @@ mod tests {
         assert!(g.connected(zs[0], zs[1]));
     }
 
+    #[test]
+    fn plug_states() {
+        // a CNOT, with a spider on the control to make the inputs distinguishable
+        let mut g = Graph::new();
+        let (i0, i1, o0, o1) = (
+            g.add_vertex(VType::B),
+            g.add_vertex(VType::B),
+            g.add_vertex(VType::B),
+            g.add_vertex(VType::B),
+        );
+        let z = g.add_vertex_with_phase(VType::Z, Rational64::new(1, 2));
+        let x = g.add_vertex(VType::X);
+        g.add_edge(i0, z);
+        g.add_edge(z, o0);
+        g.add_edge_with_type(i1, x, EType::H);
+        g.add_edge(x, o1);
+        g.add_edge(z, x);
+        g.set_inputs(vec![i0, i1]);
+        g.set_outputs(vec![o0, o1]);
+
+        let states = [
+            (Complex::new(1.0, 0.0), Complex::new(0.0, 0.0)),
+            (Complex::new(0.0, 0.0), Complex::new(0.0, -2.0)),
+            (
+                Complex::new(0.5f64.sqrt(), 0.0),
+                Complex::from_polar(0.5f64.sqrt(), std::f64::consts::FRAC_PI_4),
+            ),
+            (Complex::new(0.3, -0.2), Complex::new(-0.7, 0.4)),
+        ];
+
+        for &(alpha, beta) in &states {
+            let mut g0 = g.clone();
+            g0.plug_input(1, BasisElem::Z0);
+            let mut g1 = g.clone();
+            g1.plug_input(1, BasisElem::Z1);
+            let expected = g0.to_tensor64() * alpha + g1.to_tensor64() * beta;
+
+            let mut h = g.clone();
+            h.plug_state(1, alpha, beta);
+            assert_eq!(h.inputs(), &vec![i0]);
+            let actual = h.to_tensor64();
+            for (a, b) in actual.iter().zip(expected.iter()) {
+                assert!((a - b).norm() < 1e-10, "{a} != {b}");
+            }
+        }
+
+        let mut h = g.clone();
+        h.plug_state(
+            0,
+            Complex::new(0.5f64.sqrt(), 0.0),
+            Complex::from_polar(0.5f64.sqrt(), std::f64::consts::FRAC_PI_4),
+        );
+        assert_eq!(h.tcount(), 1);
+    }
+
     #[test]
     fn dedupe() {
         let mut g: Graph = Graph::new();
@@ -990,4 +1499,218 @@ mod tests {
         assert_eq!(mixed_comps.len(), 1);
         assert_eq!(mixed_comps[0].len(), 3);
     }
+
+    #[test]
+    fn size_metrics() {
+        let mut c = crate::circuit::Circuit::new(3);
+        c.add_gate("h", vec![0]);
+        c.add_gate("cz", vec![0, 1]);
+        c.add_gate("t", vec![1]);
+        c.add_gate("cx", vec![1, 2]);
+        let g: Graph = c.to_graph();
+        assert_eq!(g.num_boundaries(), 6);
+        assert_eq!(g.num_spiders(), g.num_vertices() - 6);
+        let h_edges = g.edges().filter(|e| e.2 == EType::H).count();
+        assert_eq!(g.num_h_edges(), h_edges);
+        assert!(g.num_h_edges() > 0);
+
+        let mut h = g.clone();
+        crate::simplify::full_simp(&mut h);
+        assert_eq!(h.num_boundaries(), 6);
+        assert_eq!(h.tcount(), 1);
+        assert!(h.num_spiders() >= h.tcount());
+    }
+
+    #[test]
+    fn mul_scalar() {
+        let mut g = Graph::new();
+        let i = g.add_vertex(VType::B);
+        let z = g.add_vertex_with_phase(VType::Z, Rational64::new(1, 4));
+        let o = g.add_vertex(VType::B);
+        let lone = g.add_vertex(VType::Z);
+        g.add_edge(i, z);
+        g.add_edge(z, o);
+        g.set_inputs(vec![i]);
+        g.set_outputs(vec![o]);
+        let t = g.to_tensorf();
+
+        // a lone phase-free Z spider is the scalar 2
+        g.remove_vertex(lone);
+        g.mul_scalar(FScalar::sqrt2_pow(2));
+        assert_eq!(g.scalar(), &FScalar::sqrt2_pow(2));
+        assert_eq!(g.to_tensorf(), t);
+    }
+
+    #[test]
+    fn validate() {
+        let mut g = Graph::new();
+        let i = g.add_vertex(VType::B);
+        let z = g.add_vertex(VType::Z);
+        let x = g.add_vertex(VType::X);
+        let o = g.add_vertex(VType::B);
+        g.add_edge(i, z);
+        g.add_edge_with_type(z, x, EType::H);
+        g.add_edge(x, o);
+        g.set_inputs(vec![i]);
+        g.set_outputs(vec![o]);
+        assert_eq!(g.validate(), Ok(()));
+
+        g.set_phase(o, Rational64::new(1, 2));
+        g.add_edge(z, o);
+        g.add_edge_with_type(z, x, EType::H);
+        g.add_edge(x, x);
+        g.outputs_mut().push(z);
+        g.outputs_mut().push(10);
+        assert_eq!(
+            g.validate(),
+            Err(vec![
+                GraphError::NotBoundary { v: z },
+                GraphError::MissingBoundary { v: 10 },
+                GraphError::ParallelEdges {
+                    v: z,
+                    w: x,
+                    count: 2
+                },
+                GraphError::SelfLoop { v: x },
+                GraphError::BoundaryDegree { v: o, degree: 2 },
+                GraphError::BoundaryPhase { v: o },
+            ])
+        );
+    }
+
+    #[test]
+    fn subgraph() {
+        let mut g = Graph::new();
+        let i = g.add_vertex(VType::B);
+        let z1 = g.add_vertex(VType::Z);
+        let z2 = g.add_vertex_with_phase(VType::Z, Rational64::new(1, 4));
+        let o = g.add_vertex(VType::B);
+        let z4 = g.add_vertex_with_phase(VType::Z, Rational64::new(1, 2));
+        g.add_edge(i, z1);
+        g.add_edge_with_type(z1, z2, EType::H);
+        g.add_edge(z2, o);
+        g.add_edge_with_type(z2, z4, EType::H);
+        g.set_inputs(vec![i]);
+        g.set_outputs(vec![o]);
+        g.scalar_mut().mul_sqrt2_pow(2);
+
+        let all: HashSet<V> = g.vertices().collect();
+        let h = g.subgraph(&all);
+        assert_eq!(h.scalar(), &FScalar::one());
+        assert_eq!(h.to_tensorf(), g.to_tensorf() * FScalar::sqrt2_pow(-2));
+
+        let h = g.subgraph(&FxHashSet::from_iter([z2, z4]));
+        assert_eq!(h.num_vertices(), 4);
+        assert_eq!(h.num_edges(), 3);
+        assert_eq!(h.phase(0), Rational64::new(1, 4).into());
+        assert_eq!(h.edge_type(0, 1), EType::H);
+        assert!(h.inputs().is_empty());
+        assert_eq!(h.outputs(), &vec![2, 3]);
+        // the cut edges to z1 and o become a Hadamard edge and a plain edge
+        assert_eq!(h.edge_type(0, 2), EType::H);
+        assert_eq!(h.edge_type(0, 3), EType::N);
+        assert_eq!(h.vertex_type(3), VType::B);
+        assert_eq!(h.qubit(3), g.qubit(o));
+    }
+
+    /// The state of a qasm circuit applied to |0...0>
+    fn circuit_state(qasm: &str) -> Graph {
+        let c = crate::circuit::Circuit::from_qasm(qasm).unwrap();
+        let mut g: Graph = c.to_graph();
+        g.plug_inputs(&vec![BasisElem::Z0; c.num_qubits()]);
+        g
+    }
+
+    #[test]
+    fn entanglement_entropy_cluster() {
+        // a linear cluster state on 4 qubits
+        let mut g = Graph::new();
+        let zs: Vec<V> = (0..4).map(|_| g.add_vertex(VType::Z)).collect();
+        let bs: Vec<V> = (0..4).map(|_| g.add_vertex(VType::B)).collect();
+        for q in 0..4 {
+            g.add_edge(zs[q], bs[q]);
+        }
+        for q in 0..3 {
+            g.add_edge_with_type(zs[q], zs[q + 1], EType::H);
+        }
+        g.set_outputs(bs);
+        // a non-Clifford phase is a local unitary
+        g.set_phase(zs[1], Rational64::new(1, 4));
+
+        assert_eq!(entanglement_entropy(&g, &[]), Ok(0.0));
+        assert_eq!(entanglement_entropy(&g, &[0]), Ok(1.0));
+        assert_eq!(entanglement_entropy(&g, &[0, 1]), Ok(1.0));
+        assert_eq!(entanglement_entropy(&g, &[1, 2]), Ok(2.0));
+        assert_eq!(entanglement_entropy(&g, &[0, 2]), Ok(2.0));
+        assert_eq!(entanglement_entropy(&g, &[0, 1, 2, 3]), Ok(0.0));
+        assert!(entanglement_entropy(&g, &[4]).is_err());
+    }
+
+    #[test]
+    fn entanglement_entropy_clifford() {
+        let g = circuit_state("qreg q[4]; h q[0]; cx q[0], q[1]; cx q[1], q[2]; s q[3];");
+        assert_eq!(entanglement_entropy(&g, &[0]), Ok(1.0));
+        assert_eq!(entanglement_entropy(&g, &[0, 1]), Ok(1.0));
+        assert_eq!(entanglement_entropy(&g, &[3]), Ok(0.0));
+        assert_eq!(entanglement_entropy(&g, &[0, 1, 2]), Ok(0.0));
+
+        // simplifying doesn't change the state
+        let c = crate::circuit::Circuit::random()
+            .seed(1)
+            .qubits(6)
+            .depth(40)
+            .clifford_t(0.0)
+            .build();
+        let mut g: Graph = c.to_graph();
+        g.plug_inputs(&[BasisElem::Z0; 6]);
+        let mut h = g.clone();
+        crate::simplify::clifford_simp(&mut h);
+        for sub in [vec![0], vec![0, 1], vec![1, 3, 5], vec![2, 3, 4]] {
+            assert_eq!(
+                entanglement_entropy(&g, &sub),
+                entanglement_entropy(&h, &sub)
+            );
+        }
+    }
+
+    #[test]
+    fn entanglement_entropy_non_clifford() {
+        let g = circuit_state("qreg q[2]; h q[0]; t q[0]; cx q[0], q[1]; h q[0];");
+        assert!(entanglement_entropy(&g, &[0]).is_err());
+        let c = crate::circuit::Circuit::from_qasm("qreg q[2]; cx q[0], q[1];").unwrap();
+        let g: Graph = c.to_graph();
+        assert!(entanglement_entropy(&g, &[0]).is_err());
+    }
+
+    /// A Z-spider on each of `n` wires, connected pairwise by Hadamard edges, i.e. the
+    /// CZ gates of a complete graph state
+    fn complete_cz(n: usize) -> Graph {
+        let mut g = Graph::new();
+        let ins: Vec<V> = (0..n).map(|_| g.add_vertex(VType::B)).collect();
+        let zs: Vec<V> = (0..n).map(|_| g.add_vertex(VType::Z)).collect();
+        let outs: Vec<V> = (0..n).map(|_| g.add_vertex(VType::B)).collect();
+        for q in 0..n {
+            g.add_edge(ins[q], zs[q]);
+            g.add_edge(zs[q], outs[q]);
+            for r in 0..q {
+                g.add_edge_with_type(zs[r], zs[q], EType::H);
+            }
+        }
+        g.set_inputs(ins);
+        g.set_outputs(outs);
+        g
+    }
+
+    #[test]
+    fn composing_merges_parallel_edges() {
+        // the CZs of the two layers cancel when their spiders fuse, so no parallel
+        // edges are ever stored
+        let n = 6;
+        let mut g = complete_cz(n);
+        g.plug(&complete_cz(n));
+        crate::simplify::spider_simp(&mut g);
+        assert_eq!(g.validate(), Ok(()));
+        assert_eq!(g.num_edges(), 2 * n);
+        assert_eq!(*g.scalar(), FScalar::sqrt2_pow(-((n * (n - 1)) as i32)));
+    }
 }
@@ -25,7 +25,7 @@ use std::iter::FromIterator;
 
 pub type VTab<T> = FxHashMap<V, T>;
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone)]
 pub struct Graph {
     vdata: VTab<VData>,
     edata: VTab<VTab<EType>>,
@@ -38,6 +38,17 @@ pub struct Graph {
     scalar_factors: FxHashMap<Expr, FScalar>,
 }
 
+impl PartialEq for Graph {
+    /// Compares graphs up to renaming of vertices
+    ///
+    /// This computes the canonical forms of both graphs with
+    /// [`canonical_form`](crate::canonical::canonical_form), which is usually fast but
+    /// exponential in the worst case, see there. Vertex positions are ignored.
+    fn eq(&self, other: &Self) -> bool {
+        crate::canonical::graphs_equal(self, other)
+    }
+}
+
 impl Graph {
     /// Removes vertex 't' from the adjacency map of 's'. This private method
     /// is used by remove_edge and remove_vertex to make the latter slightly
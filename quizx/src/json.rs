@@ -45,18 +45,22 @@
 //! assert_eq!(g.to_tensorf(), g2.to_tensorf());
 //! ```
 
+mod circuit;
 mod graph;
 mod phase;
 mod scalar;
+mod tket;
 
 pub use phase::PhaseOptions;
+pub(crate) use tket::circuit_from_tket_json;
 
-use crate::graph::VType;
+use crate::graph::{GraphError, VType};
 use crate::hash_graph::{EType, GraphLike};
+use crate::params::Var;
 
 use derive_more::{Display, Error, From};
 use serde::{de, Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::path::Path;
 
 /// Returns the json-encoded representation of a graph.
@@ -155,6 +159,12 @@ struct VertexData {
     #[serde(deserialize_with = "deserialize_bool")]
     #[serde(serialize_with = "serialize_bool")]
     is_edge: bool,
+    /// The symbolic part of the vertex phase, see [`crate::symbolic`].
+    ///
+    /// This is a quizx extension, pyzx ignores it.
+    #[serde(skip_serializing_if = "is_default")]
+    #[serde(default)]
+    sym: JsonSymPhase,
 }
 
 /// The annotations of a vertex in the json-encoded graph.
@@ -204,6 +214,49 @@ struct EdgeAttrs {
     typ: EType,
 }
 
+/// The json-encoded format for quizx circuits.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct JsonCircuit {
+    /// Number of qubits of the circuit.
+    qubits: usize,
+    /// The gates of the circuit, in order.
+    #[serde(default)]
+    gates: Vec<JsonGate>,
+}
+
+/// A gate in the json-encoded circuit.
+///
+/// The qubits a gate acts on are split into controls and targets, where the controls
+/// come first. Only controlled gates such as `cx`, `cz`, `ccx`, and `ccz` have controls.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct JsonGate {
+    /// The gate type, by its qasm name.
+    gate: String,
+    /// Control qubits.
+    #[serde(skip_serializing_if = "is_default")]
+    #[serde(default)]
+    controls: Vec<usize>,
+    /// Target qubits.
+    #[serde(default)]
+    targets: Vec<usize>,
+    /// The phase of the gate, in half turns.
+    #[serde(skip_serializing_if = "is_default")]
+    #[serde(default)]
+    phase: JsonPhase,
+    /// Variables of the boolean parameter of the gate, e.g. for measurement outcomes.
+    #[serde(skip_serializing_if = "is_default")]
+    #[serde(default)]
+    vars: Vec<Var>,
+    /// Whether the boolean parameter of the gate is negated.
+    #[serde(skip_serializing_if = "is_default")]
+    #[serde(default)]
+    flip: bool,
+    /// The symbolic part of the phase of the gate, see [`crate::symbolic`].
+    #[serde(skip_serializing_if = "is_default")]
+    #[serde(default)]
+    sym: JsonSymPhase,
+}
+
 /// A phase, in half turns.
 ///
 /// Encoded as string-formatted rational or a floating point number.
@@ -212,6 +265,13 @@ struct EdgeAttrs {
 #[serde(transparent)]
 pub struct JsonPhase(String);
 
+/// The symbolic part of a phase, in half turns.
+///
+/// Encoded as a map from parameter names to string-formatted rational coefficients.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+struct JsonSymPhase(BTreeMap<String, String>);
+
 /// Global scalars in a graph
 #[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
 pub struct JsonScalar {
@@ -272,12 +332,13 @@ fn deserialize_bool<'de, D>(deserializer: D) -> Result<bool, D::Error>
 where
     D: de::Deserializer<'de>,
 {
-    let s: &str = de::Deserialize::deserialize(deserializer)?;
+    // an owned string, since readers can't hand out borrowed strings
+    let s: String = de::Deserialize::deserialize(deserializer)?;
 
-    match s {
+    match s.as_str() {
         "true" => Ok(true),
         "false" => Ok(false),
-        _ => Err(de::Error::unknown_variant(s, &["true", "false"])),
+        _ => Err(de::Error::unknown_variant(&s, &["true", "false"])),
     }
 }
 
@@ -341,6 +402,18 @@ pub enum JsonError {
     /// Found an invalid phase value in a node definition.
     #[display("Got an invalid phase value {phase} for node {name}")]
     InvalidNodePhase { name: String, phase: String },
+    /// Found an unknown gate name, or a gate on the wrong number of qubits.
+    #[display("Got an invalid gate {gate}")]
+    InvalidGate { gate: String },
+    /// Found an edge to an undefined vertex.
+    #[display("Got an edge to undefined vertex {name}")]
+    UnknownVertex { name: String },
+    /// The decoded graph has structural problems, see [`GraphLike::validate`].
+    #[display(
+        "Got an invalid graph: {}",
+        errors.iter().map(|e| e.to_string()).collect::<Vec<_>>().join("; ")
+    )]
+    InvalidGraph { errors: Vec<GraphError> },
     /// Some other serde error.
     #[from]
     SerdeError(serde_json::Error),
@@ -348,11 +421,12 @@ pub enum JsonError {
 
 #[cfg(test)]
 mod test {
-    use crate::graph::GraphLike;
+    use crate::graph::{Coord, GraphLike};
     use crate::vec_graph::{Graph, V};
 
     use super::*;
 
+    use num::Rational64;
     use rstest::{fixture, rstest};
 
     /// Makes a simple graph.
@@ -416,6 +490,131 @@ mod test {
         Ok(())
     }
 
+    /// The sorted types and coordinates of the vertices of a graph
+    fn coords(g: &Graph) -> Vec<(VType, f64, f64)> {
+        let mut cs: Vec<_> = g
+            .vertices()
+            .map(|v| (g.vertex_type(v), g.row(v), g.qubit(v)))
+            .collect();
+        cs.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        cs
+    }
+
+    #[rstest]
+    fn json_coord_roundtrip() {
+        let mut g = Graph::new();
+        let b0 = g.add_vertex_with_coord(VType::B, (0.0, 0.5));
+        let z = g.add_vertex_with_coord(VType::Z, (1.25, 0.5));
+        let x = g.add_vertex_with_coord(VType::X, (2.0, -1.75));
+        let b1 = g.add_vertex_with_coord(VType::B, (3.5, 0.5));
+        g.add_edge(b0, z);
+        g.add_edge_with_type(z, x, EType::H);
+        g.add_edge(x, b1);
+        g.set_inputs(vec![b0]);
+        g.set_outputs(vec![b1]);
+        assert_eq!(g.coord(x), Coord::new(2.0, -1.75));
+
+        let g1: Graph = decode_graph(&encode_graph(&g).unwrap()).unwrap();
+        assert_eq!(coords(&g1), coords(&g));
+
+        // a pyzx file that marks the boundaries with boolean flags
+        let g: Graph = decode_graph(TEST_JSON_4Q_UNITARY).unwrap();
+        assert_eq!(g.inputs().len(), 4);
+        assert_eq!(g.outputs().len(), 4);
+        assert_eq!(g.coord(g.inputs()[0]), Coord::new(0.0, -3.0));
+        assert_eq!(g.coord(g.outputs()[3]), Coord::new(7.0, 0.0));
+        let g1: Graph = decode_graph(&encode_graph(&g).unwrap()).unwrap();
+        assert_eq!(coords(&g1), coords(&g));
+    }
+
+    #[rstest]
+    fn json_sym_roundtrip() {
+        use crate::circuit::Circuit;
+        use crate::gate::{GType, Gate};
+        use crate::symbolic::SymPhase;
+
+        let mut g = Graph::new();
+        let b0 = g.add_vertex(VType::B);
+        let z = g.add_vertex_with_phase(VType::Z, Rational64::new(1, 4));
+        let x = g.add_vertex(VType::X);
+        let b1 = g.add_vertex(VType::B);
+        g.add_edge(b0, z);
+        g.add_edge_with_type(z, x, EType::H);
+        g.add_edge(x, b1);
+        g.set_inputs(vec![b0]);
+        g.set_outputs(vec![b1]);
+        let mut sym = SymPhase::param("a");
+        sym += &SymPhase::term("b", Rational64::new(-3, 2));
+        g.set_sym_phase(z, sym.clone());
+        g.set_sym_phase(x, SymPhase::param("a"));
+
+        let json = encode_graph(&g).unwrap();
+        assert!(json.contains(r#""sym":{"a":"1","b":"-3/2"}"#));
+        let g1: Graph = decode_graph(&json).unwrap();
+        let syms = |g: &Graph| {
+            let mut syms: Vec<_> = g
+                .vertices()
+                .map(|v| (g.vertex_type(v), g.phase(v), g.sym_phase(v)))
+                .collect();
+            syms.sort_by_key(|(_, _, sym)| sym.clone());
+            syms
+        };
+        assert_eq!(syms(&g1), syms(&g));
+
+        let mut c = Circuit::new(2);
+        c.push(Gate::new_symbolic(
+            GType::ZPhase,
+            vec![0],
+            Rational64::new(1, 4),
+            sym,
+        ));
+        c.push(Gate::new_symbolic(GType::ParityPhase, vec![0, 1], 0, "c"));
+        c.push(Gate::new(GType::CNOT, vec![0, 1]));
+        let s = serde_json::to_string(&c).unwrap();
+        assert!(s.contains(r#"{"gate":"pp","targets":[0,1],"sym":{"c":"1"}}"#));
+        let c1: Circuit = serde_json::from_str(&s).unwrap();
+        assert_eq!(c1, c);
+
+        let json = json.replace(r#""-3/2""#, r#""-3/x""#);
+        assert!(matches!(
+            decode_graph::<Graph>(&json),
+            Err(JsonError::InvalidNodePhase { phase, .. }) if phase == "-3/x*b"
+        ));
+    }
+
+    #[rstest]
+    fn json_decode_invalid() {
+        let json = r#"{
+            "wire_vertices": {
+                "b0": {"annotation": {"boundary": true, "input": 0}},
+                "b1": {"annotation": {"boundary": true, "output": 0}}
+            },
+            "node_vertices": {
+                "v0": {"data": {"type": "Z"}},
+                "v1": {"data": {"type": "X"}}
+            },
+            "undir_edges": {
+                "e0": {"src": "b0", "tgt": "v0"},
+                "e1": {"src": "b0", "tgt": "v1"}
+            }
+        }"#;
+        match decode_graph::<Graph>(json) {
+            Err(JsonError::InvalidGraph { errors }) => {
+                assert_eq!(errors.len(), 2);
+                assert!(errors
+                    .iter()
+                    .all(|e| matches!(e, GraphError::BoundaryDegree { .. })));
+            }
+            r => panic!("Expected an invalid graph, got {r:?}"),
+        }
+
+        let json = json.replace(r#""tgt": "v1""#, r#""tgt": "v2""#);
+        assert!(matches!(
+            decode_graph::<Graph>(&json),
+            Err(JsonError::UnknownVertex { name }) if name == "v2"
+        ));
+    }
+
     #[rstest]
     //#[case::simple(TEST_JSON_SIMPLE, 9, 9)]  TODO: Parameters are not yet supported
     #[case::unitary_4q(TEST_JSON_4Q_UNITARY, 26, 30)]
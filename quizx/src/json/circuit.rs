@@ -0,0 +1,110 @@
+// QuiZX - Rust library for quantum circuit rewriting and optimisation
+//         using the ZX-calculus
+// Copyright (C) 2021 - Aleks Kissinger
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Methods for converting between circuits and the json representation.
+
+use num::Zero;
+
+use super::phase::PhaseOptions;
+use super::{JsonCircuit, JsonError, JsonGate, JsonPhase, JsonSymPhase};
+use crate::circuit::Circuit;
+use crate::gate::{GType, Gate};
+use crate::params::Parity;
+
+/// Phases of gates are stored exactly.
+const GATE_PHASE_OPTIONS: PhaseOptions = PhaseOptions {
+    ignore_value: None,
+    ignore_approx: false,
+    ignore_pi: false,
+    limit_denom: None,
+};
+
+/// The number of qubits at the start of the gate's qubit list that are encoded as controls.
+fn num_controls(t: GType) -> usize {
+    match t {
+        GType::CNOT | GType::CZ | GType::Fanout => 1,
+        GType::TOFF | GType::CCZ => 2,
+        _ => 0,
+    }
+}
+
+impl JsonGate {
+    /// Encode a gate using the json representation.
+    pub fn from_gate(gate: &Gate) -> Self {
+        let nc = num_controls(gate.t).min(gate.qs.len());
+        JsonGate {
+            gate: gate.qasm_name().to_string(),
+            controls: gate.qs[..nc].to_vec(),
+            targets: gate.qs[nc..].to_vec(),
+            phase: if gate.phase.is_zero() {
+                JsonPhase::default()
+            } else {
+                JsonPhase::from_phase(gate.phase, GATE_PHASE_OPTIONS)
+            },
+            vars: gate.vars.iter().collect(),
+            flip: gate.vars.is_flipped(),
+            sym: JsonSymPhase::from_sym(&gate.sym),
+        }
+    }
+
+    /// Decode a gate from the json representation.
+    pub fn to_gate(&self) -> Result<Gate, JsonError> {
+        let t = GType::from_qasm_name(&self.gate);
+        let mut qs = self.controls.clone();
+        qs.extend_from_slice(&self.targets);
+
+        let invalid = (t == GType::UnknownGate && self.gate != GType::UnknownGate.qasm_name())
+            || self.controls.len() != num_controls(t)
+            || t.num_qubits().is_some_and(|n| n != qs.len());
+        if invalid {
+            return Err(JsonError::InvalidGate {
+                gate: self.gate.clone(),
+            });
+        }
+
+        let mut gate = Gate::new_with_phase_and_vars(
+            t,
+            qs,
+            self.phase.to_phase()?.unwrap_or_else(Zero::zero),
+            Parity::new(self.vars.clone(), self.flip),
+        );
+        gate.sym = self.sym.to_sym()?;
+        Ok(gate)
+    }
+}
+
+impl JsonCircuit {
+    /// Encode a circuit using the json representation.
+    pub fn from_circuit(circuit: &Circuit) -> Self {
+        JsonCircuit {
+            qubits: circuit.num_qubits(),
+            gates: circuit.gates.iter().map(JsonGate::from_gate).collect(),
+        }
+    }
+
+    /// Decode a circuit from the json representation.
+    pub fn to_circuit(&self) -> Result<Circuit, JsonError> {
+        let mut c = Circuit::new(self.qubits);
+        for g in &self.gates {
+            let g = g.to_gate()?;
+            if g.qs.iter().any(|&q| q >= self.qubits) {
+                return Err(JsonError::InvalidGate { gate: g.to_qasm() });
+            }
+            c.push(g);
+        }
+        Ok(c)
+    }
+}
@@ -20,14 +20,14 @@ use num::{One, Rational64, Zero};
 
 use super::phase::PhaseOptions;
 use super::{
-    EdgeAttrs, JsonError, JsonGraph, JsonPhase, JsonScalar, VertexAnnotations, VertexAttrs,
-    VertexData, VertexName,
+    EdgeAttrs, JsonError, JsonGraph, JsonPhase, JsonScalar, JsonSymPhase, VertexAnnotations,
+    VertexAttrs, VertexData, VertexName,
 };
 use crate::fscalar::FScalar;
 use crate::graph::{Coord, EType, GraphLike, VData, VType, V};
 use crate::phase::Phase;
 
-use std::collections::{BTreeMap, HashMap};
+use std::collections::HashMap;
 
 impl JsonGraph {
     /// Encode a graph using the json representation.
@@ -92,6 +92,7 @@ impl JsonGraph {
                     data: VertexData {
                         typ,
                         value,
+                        sym: JsonSymPhase::from_sym(&graph.sym_phase(v)),
                         ..Default::default()
                     },
                 };
@@ -205,19 +206,29 @@ impl JsonGraph {
                 // and zero for all others.
                 (None, _) => Rational64::zero().into(),
             };
+            let sym = attrs.data.sym.to_sym().map_err(|e| match e {
+                JsonError::InvalidPhase { phase } => JsonError::InvalidNodePhase {
+                    name: name.to_string(),
+                    phase,
+                },
+                e => e,
+            })?;
             let v = graph.add_vertex_with_data(VData {
                 ty: attrs.data.typ,
                 qubit: coord.qubit(),
                 row: coord.row(),
                 phase,
+                sym,
                 ..Default::default()
             });
             names.insert(name.to_string(), v);
         }
 
-        // Insert the boundary nodes, and collect the input and output vectors.
-        let mut inputs: BTreeMap<usize, &str> = BTreeMap::new();
-        let mut outputs: BTreeMap<usize, &str> = BTreeMap::new();
+        // Insert the boundary nodes, and collect the input and output vectors. Files
+        // that mark boundaries with a boolean flag give them all index 0, in which case
+        // they are ordered by qubit.
+        let mut inputs: Vec<(usize, f64, &str)> = Vec::new();
+        let mut outputs: Vec<(usize, f64, &str)> = Vec::new();
         for (name, attrs) in &self.wire_vertices {
             let coord = Coord {
                 x: attrs.annotation.coord.0,
@@ -231,17 +242,28 @@ impl JsonGraph {
             });
             names.insert(name.to_string(), v);
             if let Some(input) = attrs.annotation.input {
-                inputs.insert(input, name);
+                inputs.push((input, coord.qubit(), name));
             }
             if let Some(output) = attrs.annotation.output {
-                outputs.insert(output, name);
+                outputs.push((output, coord.qubit(), name));
             }
         }
-        graph.set_inputs(inputs.into_values().map(|name| names[name]).collect());
-        graph.set_outputs(outputs.into_values().map(|name| names[name]).collect());
+        let order = |mut bs: Vec<(usize, f64, &str)>| -> Vec<V> {
+            bs.sort_by(|a, b| (a.0, a.1).partial_cmp(&(b.0, b.1)).unwrap());
+            bs.into_iter().map(|(_, _, name)| names[name]).collect()
+        };
+        graph.set_inputs(order(inputs));
+        graph.set_outputs(order(outputs));
 
         // Insert the edges.
         for attrs in self.undir_edges.values() {
+            for name in [&attrs.src, &attrs.tgt] {
+                if !names.contains_key(name) && !hadamards.contains_key(name.as_str()) {
+                    return Err(JsonError::UnknownVertex {
+                        name: name.to_string(),
+                    });
+                }
+            }
             let src = || names[&attrs.src];
             let tgt = || names[&attrs.tgt];
 
@@ -294,6 +316,9 @@ impl JsonGraph {
             *graph.scalar_mut() = FScalar::try_from(scalar)?;
         }
 
+        graph
+            .validate()
+            .map_err(|errors| JsonError::InvalidGraph { errors })?;
         Ok(graph)
     }
 }
@@ -16,9 +16,10 @@
 
 //! Methods for converting phases.
 
-use super::{JsonError, JsonPhase};
+use super::{JsonError, JsonPhase, JsonSymPhase};
 use crate::phase::utils::limit_denominator;
 use crate::phase::Phase;
+use crate::symbolic::SymPhase;
 
 use num::{FromPrimitive, One, Rational64, Zero};
 
@@ -165,6 +166,29 @@ impl JsonPhase {
     }
 }
 
+impl JsonSymPhase {
+    /// Encode the symbolic part of a phase.
+    pub fn from_sym(sym: &SymPhase) -> Self {
+        Self(
+            sym.terms()
+                .map(|(name, c)| (name.to_string(), c.to_string()))
+                .collect(),
+        )
+    }
+
+    /// Decode the symbolic part of a phase.
+    pub fn to_sym(&self) -> Result<SymPhase, JsonError> {
+        let mut sym = SymPhase::default();
+        for (name, c) in &self.0 {
+            let c: Rational64 = c.parse().map_err(|_| JsonError::InvalidPhase {
+                phase: format!("{c}*{name}"),
+            })?;
+            sym += &SymPhase::term(name.as_str(), c);
+        }
+        Ok(sym)
+    }
+}
+
 #[cfg(test)]
 mod test {
     use rstest::rstest;
@@ -0,0 +1,215 @@
+// QuiZX - Rust library for quantum circuit rewriting and optimisation
+//         using the ZX-calculus
+// Copyright (C) 2021 - Aleks Kissinger
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Decoding of circuits in the pytket json format.
+//!
+//! Only the qubits, commands, and implicit permutation of a pytket circuit are read. The
+//! global phase is ignored, as are differences in global phase between pytket's gates and
+//! their quizx counterparts.
+
+use serde::Deserialize;
+
+use super::JsonPhase;
+use crate::circuit::Circuit;
+use crate::gate::{GType, Gate};
+use crate::phase::Phase;
+
+/// A qubit, given by its register name and index.
+type TketUnitId = (String, Vec<usize>);
+
+/// The parts of the pytket json format that are read by quizx.
+#[derive(Deserialize, Debug, Clone)]
+struct TketCircuit {
+    qubits: Vec<TketUnitId>,
+    #[serde(default)]
+    commands: Vec<TketCommand>,
+    /// Pairs of qubits (a, b) such that the output on wire a ends up at position b.
+    #[serde(default)]
+    implicit_permutation: Vec<(TketUnitId, TketUnitId)>,
+}
+
+/// A single operation applied to some qubits.
+#[derive(Deserialize, Debug, Clone)]
+struct TketCommand {
+    op: TketOp,
+    args: Vec<TketUnitId>,
+}
+
+/// An operation, with its parameters in half turns.
+#[derive(Deserialize, Debug, Clone)]
+struct TketOp {
+    #[serde(rename = "type")]
+    typ: String,
+    #[serde(default)]
+    params: Vec<String>,
+}
+
+impl TketOp {
+    /// Returns the gates implementing this operation on the given qubits.
+    fn to_gates(&self, qs: Vec<usize>) -> Result<Vec<Gate>, String> {
+        let (t, nparams) = match self.typ.as_str() {
+            "noop" => return Ok(vec![]),
+            "H" => (GType::HAD, 0),
+            "X" => (GType::NOT, 0),
+            "Z" => (GType::Z, 0),
+            "S" => (GType::S, 0),
+            "Sdg" => (GType::Sdg, 0),
+            "T" => (GType::T, 0),
+            "Tdg" => (GType::Tdg, 0),
+            "CX" => (GType::CNOT, 0),
+            "CZ" => (GType::CZ, 0),
+            "CCX" => (GType::TOFF, 0),
+            "SWAP" => (GType::SWAP, 0),
+            "Rz" => (GType::ZPhase, 1),
+            "Rx" => (GType::XPhase, 1),
+            // TK1(a, b, c) = Rz(a) Rx(b) Rz(c), so handled separately below
+            "TK1" => (GType::UnknownGate, 3),
+            _ => return Err(format!("Unsupported tket operation: {}", self.typ)),
+        };
+
+        if self.params.len() != nparams {
+            return Err(format!(
+                "Expected {} parameters for tket operation {}, got {}",
+                nparams,
+                self.typ,
+                self.params.len()
+            ));
+        }
+        let nqubits = t.num_qubits().unwrap_or(1);
+        if qs.len() != nqubits {
+            return Err(format!(
+                "Expected {} qubits for tket operation {}, got {}",
+                nqubits,
+                self.typ,
+                qs.len()
+            ));
+        }
+
+        let params = self
+            .params
+            .iter()
+            .map(|p| parse_param(p))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(match t {
+            GType::UnknownGate => vec![
+                Gate::new_with_phase(GType::ZPhase, qs.clone(), params[2]),
+                Gate::new_with_phase(GType::XPhase, qs.clone(), params[1]),
+                Gate::new_with_phase(GType::ZPhase, qs, params[0]),
+            ],
+            GType::ZPhase | GType::XPhase => vec![Gate::new_with_phase(t, qs, params[0])],
+            _ => vec![Gate::new(t, qs)],
+        })
+    }
+}
+
+/// Parses a numeric parameter, given in half turns.
+fn parse_param(p: &str) -> Result<Phase, String> {
+    JsonPhase(p.to_string())
+        .to_phase()
+        .ok()
+        .flatten()
+        .ok_or_else(|| format!("Unsupported tket parameter: {p}"))
+}
+
+/// Reads a circuit from the pytket json format.
+pub(crate) fn circuit_from_tket_json(s: &str) -> Result<Circuit, String> {
+    let tc: TketCircuit =
+        serde_json::from_str(s).map_err(|e| format!("Error parsing tket circuit: {e}"))?;
+
+    let qubit_index = |q: &TketUnitId| {
+        tc.qubits
+            .iter()
+            .position(|q1| q1 == q)
+            .ok_or_else(|| format!("Unknown qubit {}{:?}", q.0, q.1))
+    };
+
+    let mut c = Circuit::new(tc.qubits.len());
+    for cmd in &tc.commands {
+        let qs = cmd
+            .args
+            .iter()
+            .map(qubit_index)
+            .collect::<Result<Vec<_>, _>>()?;
+        for g in cmd.op.to_gates(qs)? {
+            c.push(g);
+        }
+    }
+
+    // realise the implicit permutation with swaps. at[i] is the wire whose output
+    // currently sits at position i.
+    let mut target: Vec<usize> = (0..c.num_qubits()).collect();
+    for (a, b) in &tc.implicit_permutation {
+        target[qubit_index(a)?] = qubit_index(b)?;
+    }
+    let mut at: Vec<usize> = (0..c.num_qubits()).collect();
+    for (a, &j) in target.iter().enumerate() {
+        let i = at.iter().position(|&w| w == a).unwrap();
+        if i != j {
+            c.push(Gate::new(GType::SWAP, vec![i, j]));
+            at.swap(i, j);
+        }
+    }
+
+    Ok(c)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tensor::*;
+    use num::Rational64;
+
+    const TEST_TKET_JSON: &str = include_str!("../../../test_files/tket-circuit.json");
+
+    #[test]
+    fn tket_decode() {
+        let c = circuit_from_tket_json(TEST_TKET_JSON).unwrap();
+        let c1 = Circuit::from_qasm(
+            r#"
+            qreg q[3];
+            h q[0];
+            cx q[0], q[1];
+            rz(0.25*pi) q[1];
+            rz(1.5*pi) q[2];
+            rx(pi) q[2];
+            rz(0.5*pi) q[2];
+            cz q[1], q[2];
+            t q[2];
+            cx q[0], q[2];
+            rx(-0.75*pi) q[2];
+            swap q[0], q[1];
+        "#,
+        )
+        .unwrap();
+        assert_eq!(c, c1);
+        assert!(Tensor64::scalar_compare(&c, &c1));
+        assert_eq!(c.gates[2].phase, Rational64::new(1, 4).into());
+    }
+
+    #[test]
+    fn tket_unsupported() {
+        let s = r#"{"qubits": [["q", [0]]], "commands": [
+            {"args": [["q", [0]]], "op": {"type": "Measure"}}
+        ]}"#;
+        assert!(circuit_from_tket_json(s).is_err());
+
+        let s = r#"{"qubits": [["q", [0]]], "commands": [
+            {"args": [["q", [1]]], "op": {"type": "H"}}
+        ]}"#;
+        assert!(circuit_from_tket_json(s).is_err());
+    }
+}
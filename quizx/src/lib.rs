@@ -58,25 +58,40 @@
 
 pub mod annealer;
 pub mod basic_rules;
+pub mod canonical;
 pub mod circuit;
 pub mod cli;
 pub mod decompose;
+pub mod dense;
 pub mod equality;
+pub mod expect;
 pub mod extract;
 pub mod fscalar;
 pub mod gate;
 pub mod generate;
+pub mod gflow;
 pub mod graph;
 pub mod hash_graph;
 pub mod json;
 pub mod linalg;
+pub mod lincomb;
 pub mod optimize_circuit;
 pub mod params;
+pub mod pattern;
+pub mod pauli_web;
 pub mod phase;
+mod quipper;
 pub mod random_graph;
 // pub mod scalar;
 pub mod scalar_traits;
 pub mod simplify;
+pub mod stabilizer;
+pub mod symbolic;
+pub mod symmetry;
+pub mod synth;
 pub mod tensor;
+mod tikz;
+pub mod unitary;
 pub mod util;
 pub mod vec_graph;
+pub mod wire_tracker;
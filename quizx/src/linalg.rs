@@ -260,6 +260,27 @@ impl Mat2 {
         }
     }
 
+    /// Returns a basis of the nullspace, i.e. the vectors v with M v = 0
+    ///
+    /// There is one vector for each column without a pivot, which has a 1 in that column
+    /// and 0 in the other free columns.
+    pub fn nullspace(&self) -> Vec<Vec<u8>> {
+        let mut m = self.clone();
+        let mut pivot_cols = vec![];
+        m.gauss_helper(true, 3, &mut (), &mut pivot_cols);
+        (0..self.num_cols())
+            .filter(|c| !pivot_cols.contains(c))
+            .map(|f| {
+                let mut v = vec![0; self.num_cols()];
+                v[f] = 1;
+                for (r, &p) in pivot_cols.iter().enumerate() {
+                    v[p] = m.d[r][f];
+                }
+                v
+            })
+            .collect()
+    }
+
     /// Return the hamming weight of the given row
     pub fn row_weight(&self, i: usize) -> u8 {
         self.d[i].iter().sum::<u8>()
@@ -503,4 +524,17 @@ mod tests {
         let vi_exp = Mat2::new(vec![vec![1, 1, 0], vec![0, 1, 1], vec![0, 0, 1]]);
         assert_eq!(vi_exp, vi);
     }
+
+    #[test]
+    fn nullspace() {
+        let v = Mat2::new(vec![vec![1, 0, 1, 0], vec![1, 1, 1, 1], vec![0, 1, 0, 1]]);
+        let ns = v.nullspace();
+        assert_eq!(ns.len(), 4 - v.rank());
+        for n in ns {
+            assert_eq!(&v * &Mat2::new(vec![n]).transpose(), Mat2::zeros(3, 1));
+        }
+
+        assert!(Mat2::id(3).nullspace().is_empty());
+        assert_eq!(Mat2::zeros(1, 2).nullspace(), vec![vec![1, 0], vec![0, 1]]);
+    }
 }
@@ -0,0 +1,375 @@
+// QuiZX - Rust library for quantum circuit rewriting and optimisation
+//         using the ZX-calculus
+// Copyright (C) 2021 - Aleks Kissinger
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Linear combinations of diagrams
+//!
+//! [linear_combination] builds a single diagram for a weighted sum of diagrams with
+//! the same numbers of inputs and outputs, e.g. as a building block for block
+//! encodings.
+//!
+//! The terms are chosen by a register of phase-free spiders, whose values are summed
+//! over. For each value `r` of the register, a selector spider `c_r` is constrained to
+//! be 1 if and only if the register holds `r`. Every phase and every Hadamard edge of
+//! term `r` is controlled by `c_r` using phase gadgets, so that the terms which are not
+//! selected are constant, and the boundaries of all terms are joined by Z-spiders. The
+//! weight of each term is a further factor controlled by its selector.
+
+use crate::fscalar::*;
+use crate::graph::*;
+use crate::phase::Phase;
+use num::complex::Complex;
+use num::{One, Zero};
+use rustc_hash::FxHashMap;
+use std::f64::consts::PI;
+
+/// Multiplies the diagram by `e^(i pi phase (v_1 + ... + v_k))`, where the sum is
+/// taken modulo 2 over the values of the Z-spiders `vs`
+fn add_parity_phase(g: &mut impl GraphLike, vs: &[V], phase: Phase) {
+    if phase.is_zero() {
+        return;
+    }
+    if let [v] = vs {
+        g.add_to_phase(*v, phase);
+        return;
+    }
+    let hub = g.add_vertex(VType::Z);
+    for &v in vs {
+        g.add_edge_with_type(v, hub, EType::H);
+    }
+    let leaf = g.add_vertex_with_phase(VType::Z, phase);
+    g.add_edge_with_type(hub, leaf, EType::H);
+    g.scalar_mut().mul_sqrt2_pow(vs.len() as i32 - 1);
+}
+
+/// Multiplies the diagram by `e^(i pi phase l_1 ... l_k)`, where the literal `(v, true)`
+/// is the value of the Z-spider `v` and `(v, false)` is its negation
+///
+/// This uses `l_1 ... l_k = 2^(1-k) sum_S (-1)^(|S|+1) (+_S l_i)`, where `S` ranges
+/// over the non-empty subsets of literals and `+_S` is their sum modulo 2.
+fn add_and_phase(g: &mut impl GraphLike, lits: &[(V, bool)], phase: Phase) {
+    let k = lits.len();
+    let base = phase / (1 << (k - 1));
+    for mask in 1usize..(1 << k) {
+        let subset: Vec<usize> = (0..k).filter(|&i| mask & (1 << i) != 0).collect();
+        let mut p = if subset.len() % 2 == 1 { base } else { -base };
+        // e^(i a (1 + x)) = e^(i a) e^(-i a x) for each negation
+        if subset.iter().filter(|&&i| !lits[i].1).count() % 2 == 1 {
+            g.scalar_mut().mul_phase(p);
+            p = -p;
+        }
+        let vs: Vec<V> = subset.iter().map(|&i| lits[i].0).collect();
+        add_parity_phase(g, &vs, p);
+    }
+}
+
+/// Adds a spider which is 1 if and only if the register `reg` holds `value`, with the
+/// first spider as the least significant bit
+fn add_selector(g: &mut impl GraphLike, reg: &[V], value: usize) -> V {
+    // sum_z (-1)^(z c) (-1)^(z f) / sqrt(2) = sqrt(2) delta(c = f)
+    let c = g.add_vertex(VType::Z);
+    let z = g.add_vertex(VType::Z);
+    g.add_edge_with_type(z, c, EType::H);
+    let mut lits = vec![(z, true)];
+    lits.extend(
+        reg.iter()
+            .enumerate()
+            .map(|(i, &r)| (r, value & (1 << i) != 0)),
+    );
+    add_and_phase(g, &lits, Phase::one());
+    g.scalar_mut().mul_sqrt2_pow(-1);
+    c
+}
+
+/// Multiplies the diagram by 1 if the spider `c` is 0 and by `w` if it is 1
+///
+/// As in [GraphLike::plug_state], phases are snapped to multiples of 1/4 when they are
+/// within floating point error of one, and are arbitrary otherwise.
+fn add_weight(g: &mut impl GraphLike, c: V, w: Complex<f64>) {
+    // a leaf with phase x gives (1 + e^(i pi x)) / sqrt(2) if c = 0 and
+    // (1 - e^(i pi x)) / sqrt(2) if c = 1, whose ratio is -i tan(pi x / 2)
+    let x = approx_phase(2.0 * w.norm().atan() / PI);
+    let leaf = g.add_vertex_with_phase(VType::Z, x);
+    g.add_edge_with_type(c, leaf, EType::H);
+    if !w.is_zero() {
+        g.add_to_phase(c, approx_phase(w.arg() / PI + 0.5));
+    }
+    let s = 2f64.sqrt() / (1.0 + Complex::from_polar(1.0, x.to_f64() * PI));
+    *g.scalar_mut() *= FScalar::complex(s.re, s.im);
+}
+
+/// Adds a plain Hadamard edge between `s` and `t` if `c` is 1
+///
+/// The factor `1/sqrt(2)` of the edge is in the scalar, so it is there either way.
+fn add_controlled_h_edge(g: &mut impl GraphLike, s: V, t: V, c: V) {
+    add_and_phase(g, &[(s, true), (t, true), (c, true)], Phase::one());
+    g.scalar_mut().mul_sqrt2_pow(-1);
+}
+
+/// Adds a copy of `h` controlled by the spider `c`, with its inputs and then its
+/// outputs connected to `legs`
+///
+/// If `c` is 1, the copy is `h` without its scalar. Otherwise, the values of its spiders
+/// that are not connected to `legs` are summed over independently, with a factor
+/// `1/sqrt(2)` for each Hadamard edge, so the copy is a constant tensor. Returns the
+/// entries of that tensor, as a power of `sqrt(2)`.
+fn add_controlled(g: &mut impl GraphLike, h: &impl GraphLike, c: V, legs: &[V]) -> i32 {
+    let mut h = h.clone();
+    h.x_to_z();
+    if h.scalar_factors().next().is_some() {
+        panic!("Parametrised scalar factors are not supported");
+    }
+
+    // the spiders of h are copied without their phases
+    let mut vmap: FxHashMap<V, V> = FxHashMap::default();
+    let mut summed = 0;
+    let mut h_edges = 0;
+    for v in h.vertices() {
+        match h.vertex_type(v) {
+            VType::B => {
+                if !h.inputs().contains(&v) && !h.outputs().contains(&v) {
+                    panic!("All boundary vertices must be an input or an output");
+                }
+            }
+            VType::Z => {
+                let (phase, vars) = h.phase_and_vars(v);
+                if !vars.is_empty() {
+                    panic!("Boolean variables are not supported");
+                }
+                let w = g.add_vertex(VType::Z);
+                vmap.insert(v, w);
+                summed += 1;
+                if !phase.is_zero() {
+                    add_and_phase(g, &[(w, true), (c, true)], phase);
+                }
+            }
+            t => panic!("Vertex type currently unsupported: {t:?}"),
+        }
+    }
+
+    // a boundary is replaced by its neighbour if that is a spider with no other
+    // boundaries and a plain edge to it, and by a fresh spider otherwise, so that all
+    // legs are independent when c is 0
+    let mut direct = vec![];
+    for (&b, &leg) in h.inputs().iter().chain(h.outputs()).zip(legs) {
+        match h.incident_edges(b).collect::<Vec<_>>()[..] {
+            [(w, EType::N)]
+                if h.vertex_type(w) == VType::Z
+                    && h.neighbors(w)
+                        .filter(|&n| h.vertex_type(n) == VType::B)
+                        .count()
+                        == 1 =>
+            {
+                g.add_edge(leg, vmap[&w]);
+                summed -= 1;
+                direct.push(b);
+            }
+            _ => {
+                let u = g.add_vertex(VType::Z);
+                g.add_edge(leg, u);
+                vmap.insert(b, u);
+            }
+        }
+    }
+
+    for (s, t, et) in h.edges() {
+        if direct.contains(&s) || direct.contains(&t) {
+            continue;
+        }
+        let (s, t) = (vmap[&s], vmap[&t]);
+        match et {
+            EType::H => {
+                add_controlled_h_edge(g, s, t, c);
+                h_edges += 1;
+            }
+            EType::N => {
+                // a phase-free spider between two Hadamard edges is a plain edge
+                let a = g.add_vertex(VType::Z);
+                summed += 1;
+                add_controlled_h_edge(g, s, a, c);
+                add_controlled_h_edge(g, a, t, c);
+                h_edges += 2;
+            }
+            et => panic!("Edge type currently unsupported: {et:?}"),
+        }
+    }
+    2 * summed - h_edges
+}
+
+/// Builds a diagram for the linear combination `sum_i a_i D_i` of the given terms
+/// `(a_i, D_i)`
+///
+/// The result is exact up to the phases chosen for the weights, see
+/// [GraphLike::plug_state], but it is much larger than the terms: each phase becomes a
+/// phase gadget and each edge becomes a few gadgets, as described in the
+/// [module documentation](self).
+///
+/// Panics if there are no terms, if the terms have different numbers of inputs or
+/// outputs, or if they contain anything besides boundaries, Z- and X-spiders with
+/// plain or Hadamard edges, such as boolean variables.
+pub fn linear_combination<G: GraphLike>(terms: &[(Complex<f64>, G)]) -> G {
+    let Some((_, d0)) = terms.first() else {
+        panic!("A linear combination needs at least one term");
+    };
+    let (nin, nout) = (d0.inputs().len(), d0.outputs().len());
+    if terms
+        .iter()
+        .any(|(_, d)| d.inputs().len() != nin || d.outputs().len() != nout)
+    {
+        panic!(
+            "All terms of a linear combination must have the same numbers of inputs and outputs"
+        );
+    }
+
+    let mut g = G::new();
+    let boundary = |g: &mut G, q: usize, row: f64| {
+        let b = g.add_vertex(VType::B);
+        g.set_qubit(b, q as f64);
+        g.set_row(b, row);
+        let leg = g.add_vertex(VType::Z);
+        g.add_edge(b, leg);
+        (b, leg)
+    };
+    let (inputs, mut legs): (Vec<V>, Vec<V>) = (0..nin).map(|q| boundary(&mut g, q, 0.0)).unzip();
+    let (outputs, out_legs): (Vec<V>, Vec<V>) = (0..nout).map(|q| boundary(&mut g, q, 2.0)).unzip();
+    legs.extend(out_legs);
+    g.set_inputs(inputs);
+    g.set_outputs(outputs);
+
+    let nbits = (0..).find(|&b| 1 << b >= terms.len()).unwrap();
+    let reg: Vec<V> = (0..nbits).map(|_| g.add_vertex(VType::Z)).collect();
+    let mut off = 0;
+    for r in 0..(1 << nbits) {
+        let c = add_selector(&mut g, &reg, r);
+        if let Some((a, d)) = terms.get(r) {
+            // each term that is not selected contributes a constant factor sqrt(2)^p
+            let p = add_controlled(&mut g, d, c, &legs);
+            off += p;
+            let w = a * d.scalar().complex_value() * 2f64.sqrt().powi(p);
+            add_weight(&mut g, c, w);
+        } else {
+            add_weight(&mut g, c, Complex::zero());
+        }
+    }
+    g.scalar_mut().mul_sqrt2_pow(-off);
+    g
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::circuit::Circuit;
+    use crate::tensor::*;
+    use crate::vec_graph::Graph;
+
+    /// Compares the tensor of `g` with `t`, after simplifying `g` to make the tensor
+    /// contraction feasible
+    fn assert_close(g: &Graph, t: &Tensor64) {
+        let mut g = g.clone();
+        crate::simplify::full_simp(&mut g);
+        let t0 = g.to_tensor64();
+        let t1 = t;
+        assert_eq!(t0.shape(), t1.shape());
+        for (a, b) in t0.iter().zip(t1.iter()) {
+            assert!((a - b).norm() < 1e-8, "{t0}\n!=\n{t1}");
+        }
+    }
+
+    fn sum(terms: &[(Complex<f64>, Graph)]) -> Tensor64 {
+        let mut t = terms[0].1.to_tensor64() * terms[0].0;
+        for (a, d) in &terms[1..] {
+            t = t + d.to_tensor64() * *a;
+        }
+        t
+    }
+
+    #[test]
+    fn sum_of_unitaries() {
+        let mut c0 = Circuit::new(1);
+        c0.add_gate("h", vec![0]);
+        c0.add_gate("t", vec![0]);
+        let mut c1 = Circuit::new(1);
+        c1.add_gate_with_phase("rx", vec![0], (1, 3));
+        let terms = vec![
+            (Complex::new(0.5, 0.25), c0.to_graph()),
+            (Complex::new(-1.0, 0.0), c1.to_graph()),
+        ];
+        let g: Graph = linear_combination(&terms);
+        assert_eq!(g.inputs().len(), 1);
+        assert_eq!(g.outputs().len(), 1);
+        assert_close(&g, &sum(&terms));
+
+        // X + Z is not unitary, and in particular not a multiple of X or Z
+        let mut x = Circuit::new(1);
+        x.add_gate("x", vec![0]);
+        let mut z = Circuit::new(1);
+        z.add_gate("z", vec![0]);
+        let one = Complex::one();
+        let terms = vec![(one, x.to_graph()), (one, z.to_graph())];
+        let g: Graph = linear_combination(&terms);
+        assert_close(&g, &sum(&terms));
+    }
+
+    #[test]
+    fn sum_of_states() {
+        let mut terms = vec![];
+        for (gate, a) in [
+            ("h", Complex::new(0.0, 2.0)),
+            ("s", Complex::new(0.3, -0.7)),
+        ] {
+            let mut c = Circuit::new(1);
+            c.add_gate(gate, vec![0]);
+            let mut g: Graph = c.to_graph();
+            g.plug_inputs(&[BasisElem::Z1]);
+            terms.push((a, g));
+        }
+        terms[1].1.scalar_mut().mul_sqrt2_pow(3);
+        let g: Graph = linear_combination(&terms);
+        assert_close(&g, &sum(&terms));
+
+        let g: Graph = linear_combination(&terms[1..]);
+        assert_close(&g, &sum(&terms[1..]));
+    }
+
+    #[test]
+    fn sum_of_scalars() {
+        // three terms, so one value of the selector register is unused
+        let mut g0 = Graph::new();
+        g0.add_vertex_with_phase(VType::Z, (1, 3));
+        let mut g1 = Graph::new();
+        let x = g1.add_vertex_with_phase(VType::X, (1, 2));
+        let z = g1.add_vertex_with_phase(VType::Z, (1, 4));
+        g1.add_edge(x, z);
+        let mut g2 = Graph::new();
+        *g2.scalar_mut() = FScalar::real(3.0);
+        let terms = vec![
+            (Complex::new(1.0, 0.0), g0),
+            (Complex::new(0.5, 0.5), g1),
+            (Complex::new(-0.25, 2.0), g2),
+        ];
+        let g: Graph = linear_combination(&terms);
+        assert_close(&g, &sum(&terms));
+    }
+
+    #[test]
+    #[should_panic(expected = "same numbers of inputs and outputs")]
+    fn mismatched_terms() {
+        let one = Complex::one();
+        linear_combination::<Graph>(&[
+            (one, Circuit::new(1).to_graph()),
+            (one, Circuit::new(2).to_graph()),
+        ]);
+    }
+}
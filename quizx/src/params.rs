@@ -64,12 +64,17 @@ impl Parity {
         Parity([].into(), true)
     }
 
+    /// Returns true if the constant term of the parity is set
+    pub fn is_flipped(&self) -> bool {
+        self.1
+    }
+
     /// Returns of a copy of the parity negated
     pub fn negated(&self) -> Self {
         Parity(self.0.clone(), !self.1)
     }
 
-    pub fn iter(&self) -> ParityIter {
+    pub fn iter(&self) -> ParityIter<'_> {
         let it = self.0.iter().copied();
         it
     }
@@ -187,7 +192,7 @@ impl Expr {
         self.len() == 1
     }
 
-    pub fn iter(&self) -> ExprIter {
+    pub fn iter(&self) -> ExprIter<'_> {
         self.0.iter()
     }
 }
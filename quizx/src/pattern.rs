@@ -0,0 +1,467 @@
+// QuiZX - Rust library for quantum circuit rewriting and optimisation
+//         using the ZX-calculus
+// Copyright (C) 2021 - Aleks Kissinger
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Rewriting graphs by replacing occurrences of a pattern
+//!
+//! A rule is given by two [`Pattern`]s with the same number of boundary vertices,
+//! standing for the equation `pattern = replacement` of open diagrams. [`rewrite`]
+//! finds an occurrence of the pattern and replaces it, e.g. identity removal is the
+//! rule `B - Z - B` to `B - B`.
+
+use crate::graph::*;
+use crate::vec_graph::Graph;
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::collections::VecDeque;
+
+/// A small graph whose boundary vertices are labelled `0..n`, to be matched in or
+/// inserted into a larger graph
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    graph: Graph,
+    boundary: Vec<V>,
+    interior: Vec<V>,
+}
+
+impl Pattern {
+    /// Makes a pattern from a graph, where the boundary vertices are the inputs
+    /// followed by the outputs
+    ///
+    /// The scalar of the graph is the factor picked up when the pattern is used as a
+    /// replacement, see [`rewrite`].
+    ///
+    /// Panics if the inputs and outputs are not exactly the boundary vertices of the
+    /// graph, or if one of them does not have exactly one edge.
+    pub fn new(graph: Graph) -> Pattern {
+        let boundary: Vec<V> = graph
+            .inputs()
+            .iter()
+            .chain(graph.outputs())
+            .copied()
+            .collect();
+        let bset: FxHashSet<V> = boundary.iter().copied().collect();
+        if bset.len() != boundary.len() {
+            panic!("Pattern has a repeated boundary vertex");
+        }
+        for v in graph.vertices() {
+            let is_b = graph.vertex_type(v) == VType::B;
+            if is_b != bset.contains(&v) {
+                panic!("Boundary vertex {v} is not an input or output of the pattern");
+            }
+            if is_b && graph.degree(v) != 1 {
+                panic!("Boundary vertex {v} of the pattern must have exactly one edge");
+            }
+        }
+
+        // interior vertices in BFS order, so that most vertices can be matched among
+        // the neighbours of a vertex that was already matched
+        let mut interior = vec![];
+        let mut seen = FxHashSet::default();
+        for v in graph.vertices() {
+            if bset.contains(&v) || !seen.insert(v) {
+                continue;
+            }
+            let mut queue = VecDeque::from([v]);
+            while let Some(u) = queue.pop_front() {
+                interior.push(u);
+                for w in graph.neighbors(u) {
+                    if !bset.contains(&w) && seen.insert(w) {
+                        queue.push_back(w);
+                    }
+                }
+            }
+        }
+
+        Pattern {
+            graph,
+            boundary,
+            interior,
+        }
+    }
+
+    pub fn graph(&self) -> &Graph {
+        &self.graph
+    }
+
+    /// The number of boundary vertices
+    pub fn arity(&self) -> usize {
+        self.boundary.len()
+    }
+
+    /// The interior vertex or boundary connected to boundary `i`, and the type of the
+    /// edge
+    fn leg(&self, i: usize) -> (V, EType) {
+        self.graph
+            .incident_edges(self.boundary[i])
+            .next()
+            .expect("Pattern boundary must have an edge")
+    }
+}
+
+/// An occurrence of a [`Pattern`] in a graph, see [`find_match`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Match {
+    /// the vertex of the graph for each interior vertex of the pattern
+    pub vertices: FxHashMap<V, V>,
+    /// for each boundary vertex of the pattern, the vertex of the graph outside the
+    /// match that its leg connects to
+    pub boundary: Vec<V>,
+}
+
+/// Finds the leftmost occurrence of `pattern` in `g`
+///
+/// An occurrence maps the interior vertices of the pattern to distinct vertices of
+/// `g` with the same type, phase and variables, such that edges between them are the
+/// same, and any other edge of a matched vertex corresponds to a boundary leg of the
+/// pattern with the same edge type. Legs of one vertex with the same edge type are
+/// matched to its outside neighbours in order.
+///
+/// The vertices of `g` are tried in order of row, then qubit, so the occurrence found
+/// is the one whose first interior vertex is leftmost.
+///
+/// Panics if the pattern has no interior vertices or connects two of its boundary
+/// vertices directly, since these can match anywhere.
+pub fn find_match(g: &impl GraphLike, pattern: &Pattern) -> Option<Match> {
+    if (0..pattern.arity()).any(|i| pattern.graph.vertex_type(pattern.leg(i).0) == VType::B) {
+        panic!("Cannot match a pattern with a bare wire between boundaries");
+    }
+    if pattern.interior.is_empty() {
+        panic!("Cannot match a pattern with no interior vertices");
+    }
+
+    let mut cands: Vec<V> = g
+        .vertices()
+        .filter(|&v| g.vertex_type(v) != VType::B)
+        .collect();
+    cands.sort_by(|&v, &w| {
+        g.row(v)
+            .total_cmp(&g.row(w))
+            .then(g.qubit(v).total_cmp(&g.qubit(w)))
+            .then(v.cmp(&w))
+    });
+    let rank: FxHashMap<V, usize> = cands.iter().enumerate().map(|(i, &v)| (v, i)).collect();
+
+    let mut m = Matcher {
+        g,
+        p: pattern,
+        cands: &cands,
+        rank: &rank,
+        image: vec![],
+    };
+    m.search()
+}
+
+struct Matcher<'a, G: GraphLike> {
+    g: &'a G,
+    p: &'a Pattern,
+    cands: &'a [V],
+    rank: &'a FxHashMap<V, usize>,
+    /// the image of the first few interior vertices of the pattern
+    image: Vec<V>,
+}
+
+impl<G: GraphLike> Matcher<'_, G> {
+    fn search(&mut self) -> Option<Match> {
+        let i = self.image.len();
+        if i == self.p.interior.len() {
+            return self.legs();
+        }
+
+        let u = self.p.interior[i];
+        // if u is next to a vertex that was already matched, only its neighbours can
+        // be matched to u
+        let matched_nhd = self.p.interior[..i]
+            .iter()
+            .position(|&u1| self.p.graph.connected(u, u1));
+        let cands: Vec<V> = match matched_nhd {
+            Some(j) => {
+                let mut ns: Vec<V> = self
+                    .g
+                    .neighbors(self.image[j])
+                    .filter(|w| self.rank.contains_key(w))
+                    .collect();
+                ns.sort_by_key(|w| self.rank[w]);
+                ns
+            }
+            None => self.cands.to_vec(),
+        };
+
+        for v in cands {
+            if self.compatible(u, v) {
+                self.image.push(v);
+                if let Some(m) = self.search() {
+                    return Some(m);
+                }
+                self.image.pop();
+            }
+        }
+        None
+    }
+
+    /// Checks `v` can be the image of the interior vertex `u`, given the vertices
+    /// matched so far
+    fn compatible(&self, u: V, v: V) -> bool {
+        let (du, dv) = (self.p.graph.vertex_data(u), self.g.vertex_data(v));
+        du.ty == dv.ty
+            && du.phase == dv.phase
+            && du.vars == dv.vars
+            && self.p.graph.degree(u) == self.g.degree(v)
+            && !self.image.contains(&v)
+            && self
+                .p
+                .interior
+                .iter()
+                .zip(&self.image)
+                .all(|(&u1, &v1)| self.p.graph.edge_type_opt(u, u1) == self.g.edge_type_opt(v, v1))
+    }
+
+    /// Matches the boundary legs of the pattern to the edges leaving the image
+    fn legs(&self) -> Option<Match> {
+        let vertices: FxHashMap<V, V> = self
+            .p
+            .interior
+            .iter()
+            .copied()
+            .zip(self.image.iter().copied())
+            .collect();
+        let inside: FxHashSet<V> = self.image.iter().copied().collect();
+        let mut used: FxHashSet<(V, V)> = FxHashSet::default();
+        let mut boundary = Vec::with_capacity(self.p.arity());
+
+        for i in 0..self.p.arity() {
+            let (u, et) = self.p.leg(i);
+            let v = vertices[&u];
+            let mut outside: Vec<V> = self
+                .g
+                .incident_edges(v)
+                .filter(|&(w, et1)| et1 == et && !inside.contains(&w) && !used.contains(&(v, w)))
+                .map(|(w, _)| w)
+                .collect();
+            outside.sort_by_key(|w| (self.rank.get(w).copied().unwrap_or(usize::MAX), *w));
+            let &w = outside.first()?;
+            used.insert((v, w));
+            boundary.push(w);
+        }
+
+        Some(Match { vertices, boundary })
+    }
+}
+
+/// Replaces the leftmost occurrence of `pattern` in `g` with `replacement`, and
+/// returns whether there was one
+///
+/// Boundary `i` of the replacement is connected to the vertex that boundary `i` of
+/// the pattern was matched to, see [`find_match`], and the scalar of `g` is multiplied
+/// by the scalar of the replacement graph. So a rule `pattern = λ · replacement` is
+/// given by making λ the scalar of the replacement, and the scalar of the pattern is
+/// ignored. Parallel edges created by the replacement are removed with
+/// [`GraphLike::add_edge_smart`], and new vertices are placed relative to the first
+/// matched vertex.
+///
+/// Panics if the patterns have different numbers of boundary vertices, or if
+/// `pattern` cannot be matched, see [`find_match`].
+pub fn rewrite(g: &mut impl GraphLike, pattern: &Pattern, replacement: &Pattern) -> bool {
+    if pattern.arity() != replacement.arity() {
+        panic!(
+            "Pattern has {} boundary vertices, but the replacement has {}",
+            pattern.arity(),
+            replacement.arity()
+        );
+    }
+    let Some(m) = find_match(g, pattern) else {
+        return false;
+    };
+
+    let anchor = g.coord(m.vertices[&pattern.interior[0]]);
+    let r_anchor = replacement
+        .interior
+        .first()
+        .map_or(Coord::default(), |&v| replacement.graph.coord(v));
+    for &v in m.vertices.values() {
+        g.remove_vertex(v);
+    }
+
+    let rg = &replacement.graph;
+    let mut vmap: FxHashMap<V, V> = FxHashMap::default();
+    for &v in &replacement.interior {
+        let mut d = rg.vertex_data(v).clone();
+        d.row += anchor.row() - r_anchor.row();
+        d.qubit += anchor.qubit() - r_anchor.qubit();
+        vmap.insert(v, g.add_vertex_with_data(d));
+    }
+    for (s, t, et) in rg.edges() {
+        if let (Some(&s), Some(&t)) = (vmap.get(&s), vmap.get(&t)) {
+            g.add_edge_with_type(s, t, et);
+        }
+    }
+    for i in 0..replacement.arity() {
+        let (r, et) = replacement.leg(i);
+        if let Some(&v) = vmap.get(&r) {
+            g.add_edge_smart(v, m.boundary[i], et);
+        } else {
+            // a bare wire between boundaries i and j, added once
+            let j = replacement.boundary.iter().position(|&b| b == r).unwrap();
+            if i < j {
+                g.add_edge_smart(m.boundary[i], m.boundary[j], et);
+            }
+        }
+    }
+
+    *g.scalar_mut() *= rg.scalar();
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tensor::*;
+    use num::Rational64;
+
+    /// A spider of the given type and phase with `n` legs of the given edge type
+    fn spider(ty: VType, phase: Rational64, legs: &[EType]) -> Pattern {
+        let mut g = Graph::new();
+        let v = g.add_vertex_with_phase(ty, phase);
+        let bs: Vec<V> = legs
+            .iter()
+            .map(|&et| {
+                let b = g.add_vertex(VType::B);
+                g.add_edge_with_type(v, b, et);
+                b
+            })
+            .collect();
+        g.set_inputs(bs);
+        Pattern::new(g)
+    }
+
+    fn wire() -> Pattern {
+        let mut g = Graph::new();
+        let b0 = g.add_vertex(VType::B);
+        let b1 = g.add_vertex(VType::B);
+        g.add_edge(b0, b1);
+        g.set_inputs(vec![b0]);
+        g.set_outputs(vec![b1]);
+        Pattern::new(g)
+    }
+
+    #[test]
+    fn remove_identities() {
+        // B - Z(0) - Z(1/4) - Z(0) - B, with the spiders at rows 1, 2, 3
+        let mut g = Graph::new();
+        let b0 = g.add_vertex(VType::B);
+        let vs: Vec<V> = [0, 1, 0]
+            .iter()
+            .enumerate()
+            .map(|(i, &p)| {
+                let v = g.add_vertex_with_phase(VType::Z, Rational64::new(p, 4));
+                g.set_row(v, (3 - i) as f64);
+                v
+            })
+            .collect();
+        let b1 = g.add_vertex(VType::B);
+        g.add_edge(b0, vs[0]);
+        g.add_edge(vs[0], vs[1]);
+        g.add_edge(vs[1], vs[2]);
+        g.add_edge(vs[2], b1);
+        g.set_inputs(vec![b0]);
+        g.set_outputs(vec![b1]);
+        let h = g.clone();
+
+        let pattern = spider(VType::Z, Rational64::new(0, 1), &[EType::N, EType::N]);
+        let m = find_match(&g, &pattern).unwrap();
+        assert_eq!(m.vertices[&0], vs[2]);
+        assert_eq!(m.boundary, vec![vs[1], b1]);
+
+        assert!(rewrite(&mut g, &pattern, &wire()));
+        assert!(!g.contains_vertex(vs[2]));
+        assert!(g.connected(vs[1], b1));
+        assert!(rewrite(&mut g, &pattern, &wire()));
+        assert!(!rewrite(&mut g, &pattern, &wire()));
+        assert_eq!(g.num_vertices(), 3);
+        assert_eq!(g.to_tensorf(), h.to_tensorf());
+    }
+
+    #[test]
+    fn rewrite_with_scalar() {
+        // Z - H - Z is the scalar sqrt(2)
+        let mut pg = Graph::new();
+        let z0 = pg.add_vertex(VType::Z);
+        let z1 = pg.add_vertex(VType::Z);
+        pg.add_edge_with_type(z0, z1, EType::H);
+        let pattern = Pattern::new(pg);
+        let mut rg = Graph::new();
+        rg.scalar_mut().mul_sqrt2_pow(1);
+        let replacement = Pattern::new(rg);
+
+        let mut g = Graph::new();
+        let x = g.add_vertex_with_phase(VType::X, Rational64::new(1, 2));
+        let z = g.add_vertex(VType::Z);
+        g.add_edge(x, z);
+        let v0 = g.add_vertex(VType::Z);
+        let v1 = g.add_vertex(VType::Z);
+        g.add_edge_with_type(v0, v1, EType::H);
+        let h = g.clone();
+
+        assert!(rewrite(&mut g, &pattern, &replacement));
+        assert_eq!(g.num_vertices(), 2);
+        assert_eq!(g.to_tensorf(), h.to_tensorf());
+        assert!(!rewrite(&mut g, &pattern, &replacement));
+    }
+
+    #[test]
+    fn rewrite_color_change() {
+        // an X-spider with Hadamard legs is a Z-spider with normal legs, with the
+        // legs matched in order
+        let pattern = spider(VType::X, Rational64::new(1, 4), &[EType::H, EType::N]);
+        let replacement = spider(VType::Z, Rational64::new(1, 4), &[EType::N, EType::H]);
+
+        let mut g = Graph::new();
+        let b0 = g.add_vertex(VType::B);
+        let x = g.add_vertex_with_phase(VType::X, Rational64::new(1, 4));
+        let z = g.add_vertex_with_phase(VType::Z, Rational64::new(1, 2));
+        let b1 = g.add_vertex(VType::B);
+        g.add_edge(b0, x);
+        g.add_edge_with_type(x, z, EType::H);
+        g.add_edge(z, b1);
+        g.set_inputs(vec![b0]);
+        g.set_outputs(vec![b1]);
+        let h = g.clone();
+
+        assert_eq!(find_match(&g, &pattern).unwrap().boundary, vec![z, b0]);
+        assert!(rewrite(&mut g, &pattern, &replacement));
+        assert_eq!(g.num_vertices(), 4);
+        assert_eq!(g.to_tensorf(), h.to_tensorf());
+
+        // a phase or edge type that differs does not match
+        let pattern = spider(VType::Z, Rational64::new(1, 4), &[EType::H, EType::H]);
+        assert_eq!(find_match(&g, &pattern), None);
+        let pattern = spider(VType::Z, Rational64::new(1, 2), &[EType::H, EType::N]);
+        assert_eq!(find_match(&g, &pattern), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "bare wire")]
+    fn match_bare_wire() {
+        let g = Graph::new();
+        find_match(&g, &wire());
+    }
+
+    #[test]
+    #[should_panic(expected = "boundary vertices, but the replacement has")]
+    fn rewrite_mismatched_arity() {
+        let mut g = Graph::new();
+        let pattern = spider(VType::Z, Rational64::new(0, 1), &[EType::N]);
+        rewrite(&mut g, &pattern, &wire());
+    }
+}
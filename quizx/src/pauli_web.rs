@@ -0,0 +1,343 @@
+// QuiZX - Rust library for quantum circuit rewriting and optimisation
+//         using the ZX-calculus
+// Copyright (C) 2021 - Aleks Kissinger
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Pauli webs, i.e. labellings of the edges of a ZX-diagram by Paulis that are
+//! consistent at every spider
+//!
+//! A Pauli web, or correlation surface, describes a stabiliser of the diagram: pushing
+//! the Paulis on its boundary edges through the diagram gives the same map, up to sign.
+//! See Bombin et al., "Unifying flavors of fault tolerance with the ZX calculus"
+//! (arXiv:2303.08829).
+//!
+//! At a Z-spider, the X parts of the labels on its legs are either all set or all
+//! unset, and the Z parts have even parity. X-spiders are the same with X and Z
+//! swapped. A Hadamard edge swaps X and Z between its two ends. At a spider with phase
+//! ±π/2, the other colour flips the phase, so the parity of its own colour must match
+//! the other colour part instead. Any other phase only commutes with Paulis of its own
+//! colour, so the labels of its legs must be of its own colour. Boundaries impose no
+//! condition.
+//!
+//! The webs of a diagram form a vector space over GF(2), where adding webs multiplies
+//! their labels and forgets the phase.
+
+use crate::graph::*;
+use crate::linalg::Mat2;
+use rustc_hash::FxHashMap;
+use std::fmt;
+
+/// A Pauli on a single edge, ignoring its phase
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Hash)]
+pub enum EdgePauli {
+    I,
+    X,
+    Y,
+    Z,
+}
+
+impl EdgePauli {
+    pub fn from_bits(x: bool, z: bool) -> EdgePauli {
+        match (x, z) {
+            (false, false) => EdgePauli::I,
+            (true, false) => EdgePauli::X,
+            (true, true) => EdgePauli::Y,
+            (false, true) => EdgePauli::Z,
+        }
+    }
+
+    /// Returns true if the Pauli has an X part, i.e. is X or Y
+    pub fn x(&self) -> bool {
+        matches!(self, EdgePauli::X | EdgePauli::Y)
+    }
+
+    /// Returns true if the Pauli has a Z part, i.e. is Z or Y
+    pub fn z(&self) -> bool {
+        matches!(self, EdgePauli::Z | EdgePauli::Y)
+    }
+
+    /// Conjugates by a Hadamard, which swaps X and Z
+    pub fn hadamard(&self) -> EdgePauli {
+        EdgePauli::from_bits(self.z(), self.x())
+    }
+}
+
+impl fmt::Display for EdgePauli {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+/// An assignment of Paulis to the edges of a diagram
+///
+/// The Pauli of an edge `(s, t)` is stored as seen from its smaller endpoint. For a
+/// Hadamard edge, the larger endpoint sees its conjugate by a Hadamard, see
+/// [`PauliWeb::pauli_at`]. Edges that are not set have the Pauli I.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct PauliWeb {
+    edges: FxHashMap<(V, V), EdgePauli>,
+}
+
+impl PauliWeb {
+    pub fn new() -> PauliWeb {
+        Default::default()
+    }
+
+    /// Returns true if all edges have the Pauli I
+    pub fn is_empty(&self) -> bool {
+        self.edges.is_empty()
+    }
+
+    /// Sets the Pauli of the edge between `s` and `t`, as seen from the smaller of the two
+    pub fn set_edge(&mut self, s: V, t: V, p: EdgePauli) {
+        let e = (s.min(t), s.max(t));
+        if p == EdgePauli::I {
+            self.edges.remove(&e);
+        } else {
+            self.edges.insert(e, p);
+        }
+    }
+
+    /// The Pauli of the edge between `s` and `t`, as seen from the smaller of the two
+    pub fn edge(&self, s: V, t: V) -> EdgePauli {
+        self.edges
+            .get(&(s.min(t), s.max(t)))
+            .copied()
+            .unwrap_or(EdgePauli::I)
+    }
+
+    /// The edges with a Pauli other than I, as `(s, t)` with `s < t`, and their Paulis
+    /// as seen from `s`
+    pub fn edges(&self) -> impl Iterator<Item = ((V, V), EdgePauli)> + '_ {
+        self.edges.iter().map(|(&e, &p)| (e, p))
+    }
+
+    /// The Pauli of the edge between `v` and `w`, as seen from `v`
+    pub fn pauli_at(&self, g: &impl GraphLike, v: V, w: V) -> EdgePauli {
+        let p = self.edge(v, w);
+        if v > w && g.edge_type_opt(v, w) == Some(EType::H) {
+            p.hadamard()
+        } else {
+            p
+        }
+    }
+
+    /// Returns true if the web is consistent at every spider of `g`, and only labels
+    /// edges of `g`
+    pub fn verify(&self, g: &impl GraphLike) -> bool {
+        if self
+            .edges
+            .keys()
+            .any(|&(s, t)| g.edge_type_opt(s, t).is_none())
+        {
+            return false;
+        }
+        g.vertices().all(|v| {
+            let Some((z_like, kind)) = spider_kind(g, v) else {
+                return true;
+            };
+            // (own colour, other colour) parts of the labels on each leg
+            let legs: Vec<(bool, bool)> = g
+                .neighbors(v)
+                .map(|w| {
+                    let p = self.pauli_at(g, v, w);
+                    if z_like {
+                        (p.z(), p.x())
+                    } else {
+                        (p.x(), p.z())
+                    }
+                })
+                .collect();
+            let own_odd = legs.iter().filter(|l| l.0).count() % 2 == 1;
+            let other = legs.first().is_some_and(|l| l.1);
+            let other_equal = legs.windows(2).all(|l| l[0].1 == l[1].1);
+            let parity = own_odd == (kind == PhaseKind::Clifford && other);
+            other_equal && parity && (kind != PhaseKind::Other || !other)
+        })
+    }
+}
+
+/// How the phase of a spider constrains the webs through it
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum PhaseKind {
+    /// A multiple of π
+    Pauli,
+    /// ±π/2
+    Clifford,
+    /// Anything else, including symbolic phases
+    Other,
+}
+
+/// For a spider, returns whether it is Z-like and the kind of its phase, or None for a
+/// boundary
+///
+/// Panics on other vertex types.
+fn spider_kind(g: &impl GraphLike, v: V) -> Option<(bool, PhaseKind)> {
+    let p = g.phase(v);
+    let kind = if g.is_symbolic(v) {
+        PhaseKind::Other
+    } else if p.is_pauli() {
+        PhaseKind::Pauli
+    } else if p.is_clifford() {
+        PhaseKind::Clifford
+    } else {
+        PhaseKind::Other
+    };
+    match g.vertex_type(v) {
+        VType::B => None,
+        VType::Z => Some((true, kind)),
+        VType::X => Some((false, kind)),
+        t => panic!("Vertex type currently unsupported: {t:?}"),
+    }
+}
+
+/// Returns a basis of the Pauli webs of `g`
+///
+/// Panics if `g` has vertices other than boundaries, Z-spiders and X-spiders.
+pub fn find_webs(g: &impl GraphLike) -> Vec<PauliWeb> {
+    let mut edges: Vec<(V, V)> = g.edges().map(|(s, t, _)| (s.min(t), s.max(t))).collect();
+    edges.sort();
+    let index: FxHashMap<(V, V), usize> = edges.iter().enumerate().map(|(i, &e)| (e, i)).collect();
+
+    // each edge has an x and a z bit, as seen from its smaller endpoint
+    let bit = |v: V, w: V, x: bool| {
+        let i = index[&(v.min(w), v.max(w))];
+        let swapped = v > w && g.edge_type(v, w) == EType::H;
+        2 * i + (x == swapped) as usize
+    };
+    let mut rows: Vec<Vec<u8>> = vec![];
+    for v in g.vertices() {
+        let Some((z_like, kind)) = spider_kind(g, v) else {
+            continue;
+        };
+        let ws: Vec<V> = g.neighbors(v).collect();
+        let own = |w: V| bit(v, w, !z_like);
+        let other = |w: V| bit(v, w, z_like);
+
+        let mut row = vec![0; 2 * edges.len()];
+        for &w in &ws {
+            row[own(w)] ^= 1;
+        }
+        if kind == PhaseKind::Clifford {
+            if let Some(&w) = ws.first() {
+                row[other(w)] ^= 1;
+            }
+        }
+        rows.push(row);
+        for pair in ws.windows(2) {
+            let mut row = vec![0; 2 * edges.len()];
+            row[other(pair[0])] ^= 1;
+            row[other(pair[1])] ^= 1;
+            rows.push(row);
+        }
+        if kind == PhaseKind::Other {
+            if let Some(&w) = ws.first() {
+                let mut row = vec![0; 2 * edges.len()];
+                row[other(w)] = 1;
+                rows.push(row);
+            }
+        }
+    }
+
+    let basis = if rows.is_empty() {
+        Mat2::zeros(1, 2 * edges.len()).nullspace()
+    } else {
+        Mat2::new(rows).nullspace()
+    };
+    basis
+        .into_iter()
+        .map(|b| {
+            let mut web = PauliWeb::new();
+            for (i, &(s, t)) in edges.iter().enumerate() {
+                web.set_edge(s, t, EdgePauli::from_bits(b[2 * i] == 1, b[2 * i + 1] == 1));
+            }
+            web
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::circuit::Circuit;
+    use crate::vec_graph::Graph;
+    use num::Rational64;
+
+    #[test]
+    fn web_spider() {
+        // a Z-spider with three legs has the webs XXX, ZZI and IZZ
+        let mut g = Graph::new();
+        let v = g.add_vertex(VType::Z);
+        let bs: Vec<V> = (0..3).map(|_| g.add_vertex(VType::B)).collect();
+        for &b in &bs {
+            g.add_edge(v, b);
+        }
+        let webs = find_webs(&g);
+        assert_eq!(webs.len(), 3);
+        assert!(webs.iter().all(|w| w.verify(&g) && !w.is_empty()));
+
+        let mut web = PauliWeb::new();
+        for &b in &bs {
+            web.set_edge(v, b, EdgePauli::X);
+        }
+        assert!(web.verify(&g));
+        web.set_edge(v, bs[0], EdgePauli::Y);
+        assert!(!web.verify(&g));
+        web.set_edge(bs[1], v, EdgePauli::Y);
+        assert!(web.verify(&g));
+        assert_eq!(web.edge(v, bs[1]), EdgePauli::Y);
+
+        // with an S phase, X becomes Y on one leg, and with a T phase only Z-webs
+        // are left
+        g.set_phase(v, 1);
+        assert!(web.verify(&g));
+        g.set_phase(v, Rational64::new(1, 2));
+        assert!(!web.verify(&g));
+        web.set_edge(v, bs[2], EdgePauli::Y);
+        assert!(web.verify(&g));
+        assert_eq!(find_webs(&g).len(), 3);
+        g.set_phase(v, Rational64::new(1, 4));
+        assert!(!web.verify(&g));
+        assert_eq!(find_webs(&g).len(), 2);
+    }
+
+    #[test]
+    fn web_circuits() {
+        // a Clifford circuit on n qubits has 2n independent webs, one for each
+        // stabiliser of its Choi state
+        let c = Circuit::random()
+            .seed(1)
+            .qubits(3)
+            .depth(30)
+            .clifford_t(0.0)
+            .build();
+        let g: Graph = c.to_graph();
+        let webs = find_webs(&g);
+        assert!(webs.iter().all(|w| w.verify(&g)));
+        assert_eq!(webs.len(), 6);
+
+        // across a Hadamard edge, X turns into Z
+        let c = Circuit::from_qasm("qreg q[1]; h q[0]; t q[0];").unwrap();
+        let g: Graph = c.to_graph();
+        let webs = find_webs(&g);
+        assert_eq!(webs.len(), 1);
+        let w = &webs[0];
+        let i = g.inputs()[0];
+        let o = g.outputs()[0];
+        let vi = g.neighbors(i).next().unwrap();
+        let vo = g.neighbors(o).next().unwrap();
+        assert_eq!(w.pauli_at(&g, i, vi), EdgePauli::X);
+        assert_eq!(w.pauli_at(&g, o, vo), EdgePauli::Z);
+    }
+}
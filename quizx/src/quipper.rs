@@ -0,0 +1,384 @@
+// QuiZX - Rust library for quantum circuit rewriting and optimisation
+//         using the ZX-calculus
+// Copyright (C) 2021 - Aleks Kissinger
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Decoding of circuits in Quipper's ASCII format.
+//!
+//! Only flat circuits on qubits are read, i.e. the `Inputs:` and `Outputs:` lines,
+//! `QGate` and `QRot` lines with optional controls, and `QInit`/`QTerm` for ancillas.
+//! Each wire is a qubit of the circuit, in the order of the inputs and then the order
+//! the ancillas are initialised. Ancillas are initialised with [`GType::InitAncilla`]
+//! and terminated with [`GType::PostSelect`], so a wire id that is reused after
+//! `QTerm` gets a fresh qubit.
+//!
+//! Global phases of uncontrolled gates are ignored. For controlled gates they are
+//! kept, as a phase on the controls, so e.g. a controlled `exp(-i%Z)` rotation is
+//! exact.
+
+use crate::circuit::Circuit;
+use crate::gate::{GType::*, Gate};
+use crate::graph::approx_phase;
+use regex::Regex;
+use rustc_hash::FxHashMap;
+use std::f64::consts::PI;
+
+/// The gates for a Quipper gate acting on qubits `0..ntargets`, along with its global
+/// phase in half turns.
+fn gate_body(name: &str, param: Option<f64>, ntargets: usize) -> Result<(Vec<Gate>, f64), String> {
+    let arity = |n: usize| {
+        if ntargets == n {
+            Ok(())
+        } else {
+            Err(format!(
+                "Expected {n} targets for Quipper gate {name}, got {ntargets}"
+            ))
+        }
+    };
+    let g = |t| vec![Gate::new(t, vec![0])];
+    match (name, param) {
+        ("not" | "x" | "X", None) => arity(1).map(|_| (g(NOT), 0.0)),
+        ("Y", None) => arity(1).map(|_| {
+            let gs = vec![Gate::new(Z, vec![0]), Gate::new(NOT, vec![0])];
+            (gs, 0.5)
+        }),
+        ("iX", None) => arity(1).map(|_| (g(NOT), 0.5)),
+        ("Z", None) => arity(1).map(|_| (g(Z), 0.0)),
+        ("H", None) => arity(1).map(|_| (g(HAD), 0.0)),
+        ("S", None) => arity(1).map(|_| (g(S), 0.0)),
+        ("T", None) => arity(1).map(|_| (g(T), 0.0)),
+        ("V", None) => arity(1).map(|_| {
+            let gs = vec![Gate::new_with_phase(XPhase, vec![0], approx_phase(0.5))];
+            (gs, 0.0)
+        }),
+        ("omega", None) => arity(1).map(|_| (vec![], 0.25)),
+        ("swap", None) => arity(2).map(|_| (vec![Gate::new(SWAP, vec![0, 1])], 0.0)),
+        ("multinot", None) => Ok((
+            (0..ntargets).map(|q| Gate::new(NOT, vec![q])).collect(),
+            0.0,
+        )),
+        // exp(-itZ) = e^{-it} diag(1, e^{2it})
+        ("exp(-i%Z)", Some(t)) => arity(1).map(|_| {
+            let gs = vec![Gate::new_with_phase(
+                ZPhase,
+                vec![0],
+                approx_phase(2.0 * t / PI),
+            )];
+            (gs, -t / PI)
+        }),
+        ("R(2pi/%)", Some(n)) if n != 0.0 => arity(1).map(|_| {
+            let gs = vec![Gate::new_with_phase(ZPhase, vec![0], approx_phase(2.0 / n))];
+            (gs, 0.0)
+        }),
+        _ => Err(format!("Unsupported Quipper gate: {name}")),
+    }
+}
+
+/// The circuit being read, with the current qubit of each live wire.
+struct QuipperReader {
+    gates: Vec<Gate>,
+    nqubits: usize,
+    wires: FxHashMap<usize, usize>,
+}
+
+impl QuipperReader {
+    fn qubit(&self, w: usize) -> Result<usize, String> {
+        self.wires
+            .get(&w)
+            .copied()
+            .ok_or_else(|| format!("Quipper wire {w} is not live"))
+    }
+
+    fn new_qubit(&mut self, w: usize) -> Result<usize, String> {
+        if self.wires.contains_key(&w) {
+            return Err(format!("Quipper wire {w} is already live"));
+        }
+        let q = self.nqubits;
+        self.nqubits += 1;
+        self.wires.insert(w, q);
+        Ok(q)
+    }
+
+    /// Pushes `body`, acting on the targets, controlled on the given (wire, positive)
+    /// pairs.
+    fn push_controlled(
+        &mut self,
+        body: Vec<Gate>,
+        phase: f64,
+        targets: &[usize],
+        controls: &[(usize, bool)],
+    ) -> Result<(), String> {
+        let mut qs = vec![];
+        for &(w, _) in controls {
+            qs.push(self.qubit(w)?);
+        }
+        for &w in targets {
+            qs.push(self.qubit(w)?);
+        }
+        for (i, q) in qs.iter().enumerate() {
+            if qs[..i].contains(q) {
+                return Err(format!("Quipper gate uses a wire twice: {targets:?}"));
+            }
+        }
+
+        let negative: Vec<usize> = controls
+            .iter()
+            .zip(&qs)
+            .filter(|((_, positive), _)| !positive)
+            .map(|(_, &q)| q)
+            .collect();
+        let k = controls.len();
+        let mut local = Circuit::new(targets.len());
+        for g in body {
+            local.push(g);
+        }
+        let local = if k == 0 {
+            local
+        } else {
+            let mut c = local.controlled(k);
+            // the global phase becomes a phase on the controls
+            let mut ph = Circuit::new(1);
+            ph.push(Gate::new_with_phase(ZPhase, vec![0], approx_phase(phase)));
+            c += &ph.controlled(k - 1);
+            c
+        };
+
+        let not = |q: usize| Gate::new(NOT, vec![q]);
+        self.gates.extend(negative.iter().map(|&q| not(q)));
+        for mut g in local.gates {
+            g.qs = g.qs.iter().map(|&q| qs[q]).collect();
+            self.gates.push(g);
+        }
+        self.gates.extend(negative.iter().map(|&q| not(q)));
+        Ok(())
+    }
+}
+
+/// Parses a comma-separated list of wires with types, as in the `Inputs:` line.
+fn parse_wires(s: &str) -> Result<Vec<usize>, String> {
+    let s = s.trim();
+    if s.is_empty() || s == "none" {
+        return Ok(vec![]);
+    }
+    s.split(',')
+        .map(|w| {
+            let (w, t) = w
+                .trim()
+                .split_once(':')
+                .ok_or_else(|| format!("Bad Quipper wire: {w}"))?;
+            if t != "Qbit" {
+                return Err(format!("Unsupported Quipper wire type: {t}"));
+            }
+            w.parse().map_err(|_| format!("Bad Quipper wire: {w}"))
+        })
+        .collect()
+}
+
+/// Reads a circuit from Quipper's ASCII format.
+pub(crate) fn circuit_from_quipper(s: &str) -> Result<Circuit, String> {
+    let gate_re =
+        Regex::new(r#"^(QGate|QRot)\["([^"]*)"(?:,([^\]]*))?\](\*?)\(([^)]*)\)(.*)$"#).unwrap();
+    let controls_re = Regex::new(r"^\s*with controls=\[([^\]]*)\]").unwrap();
+    let anc_re = Regex::new(r"^(QInit|QTerm)([01])\((\d+)\)$").unwrap();
+
+    let mut r = QuipperReader {
+        gates: vec![],
+        nqubits: 0,
+        wires: FxHashMap::default(),
+    };
+    let mut has_inputs = false;
+    let mut outputs = None;
+
+    for line in s.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with("Comment[") {
+            continue;
+        }
+
+        if let Some(ws) = line.strip_prefix("Inputs:") {
+            if has_inputs {
+                return Err("Quipper circuit has more than one Inputs line".to_string());
+            }
+            for w in parse_wires(ws)? {
+                r.new_qubit(w)?;
+            }
+            has_inputs = true;
+        } else if !has_inputs {
+            return Err(format!("Expected Quipper Inputs line, got: {line}"));
+        } else if outputs.is_some() {
+            return Err(format!("Unexpected line after Quipper Outputs: {line}"));
+        } else if let Some(ws) = line.strip_prefix("Outputs:") {
+            outputs = Some(parse_wires(ws)?);
+        } else if let Some(cap) = anc_re.captures(line) {
+            let one = &cap[2] == "1";
+            let w: usize = cap[3]
+                .parse()
+                .map_err(|_| format!("Bad Quipper wire: {line}"))?;
+            if &cap[1] == "QInit" {
+                let q = r.new_qubit(w)?;
+                r.gates.push(Gate::new(InitAncilla, vec![q]));
+                if one {
+                    r.gates.push(Gate::new(NOT, vec![q]));
+                }
+            } else {
+                let q = r.qubit(w)?;
+                if one {
+                    r.gates.push(Gate::new(NOT, vec![q]));
+                }
+                r.gates.push(Gate::new(PostSelect, vec![q]));
+                r.wires.remove(&w);
+            }
+        } else if let Some(cap) = gate_re.captures(line) {
+            let name = &cap[2];
+            let param = match (&cap[1], cap.get(3)) {
+                ("QRot", Some(p)) => Some(
+                    p.as_str()
+                        .trim()
+                        .parse::<f64>()
+                        .map_err(|_| format!("Bad Quipper rotation parameter: {line}"))?,
+                ),
+                ("QGate", None) => None,
+                _ => return Err(format!("Bad Quipper gate: {line}")),
+            };
+            let targets = cap[5]
+                .split(',')
+                .map(|w| w.trim().parse::<usize>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|_| format!("Bad Quipper targets: {line}"))?;
+
+            let mut rest = cap[6].trim();
+            let mut controls = vec![];
+            if let Some(c) = controls_re.captures(rest) {
+                for w in c[1].split(',') {
+                    let w = w.trim();
+                    let positive = w.starts_with('+');
+                    let id = w
+                        .strip_prefix(['+', '-'])
+                        .and_then(|w| w.parse::<usize>().ok())
+                        .ok_or_else(|| format!("Bad Quipper control: {w}"))?;
+                    controls.push((id, positive));
+                }
+                rest = rest[c[0].len()..].trim();
+            }
+            if !(rest.is_empty() || rest == "with nocontrol") {
+                return Err(format!("Unsupported Quipper gate option: {rest}"));
+            }
+
+            let (mut body, mut phase) = gate_body(name, param, targets.len())?;
+            if &cap[4] == "*" {
+                body.reverse();
+                for g in body.iter_mut() {
+                    g.adjoint();
+                }
+                phase = -phase;
+            }
+            r.push_controlled(body, phase, &targets, &controls)?;
+        } else {
+            return Err(format!("Unsupported Quipper construct: {line}"));
+        }
+    }
+
+    let outputs = outputs.ok_or_else(|| "Quipper circuit has no Outputs line".to_string())?;
+    for &w in &outputs {
+        r.qubit(w)?;
+    }
+    if outputs.len() != r.wires.len() {
+        return Err("Quipper outputs don't match the live wires".to_string());
+    }
+
+    let mut c = Circuit::new(r.nqubits);
+    for g in r.gates {
+        c.push(g);
+    }
+    Ok(c)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::graph::GraphLike;
+    use crate::tensor::*;
+    use crate::vec_graph::Graph;
+
+    const TEST_QUIPPER: &str = include_str!("../../test_files/quipper-circuit.quipper");
+
+    #[test]
+    fn quipper_decode() {
+        let c = circuit_from_quipper(TEST_QUIPPER).unwrap();
+        let c1 = Circuit::from_qasm(
+            r#"
+            qreg q[3];
+            h q[2];
+            cx q[1], q[2];
+            tdg q[2];
+            cx q[0], q[2];
+            t q[2];
+            x q[0];
+            ccx q[1], q[0], q[2];
+            x q[0];
+            rz(0.5*pi) q[1];
+            crz(0.5*pi) q[0], q[2];
+            cp(-0.5*pi) q[1], q[0];
+            swap q[0], q[1];
+            h q[2];
+        "#,
+        )
+        .unwrap();
+        assert_eq!(c.num_qubits(), 3);
+        assert!(TensorF::scalar_compare(&c, &c1));
+    }
+
+    #[test]
+    fn quipper_ancillas() {
+        // a Toffoli onto an ancilla, a Z, then uncomputing gives CCZ
+        let c = circuit_from_quipper(
+            r#"
+            Inputs: 0:Qbit, 1:Qbit
+            QInit0(2)
+            QGate["not"](2) with controls=[+0,+1]
+            QGate["Z"](2)
+            QGate["not"](2) with controls=[+0,+1]
+            QTerm0(2)
+            QInit1(2)
+            QTerm1(2)
+            Outputs: 0:Qbit, 1:Qbit
+        "#,
+        )
+        .unwrap();
+        assert_eq!(c.num_qubits(), 4);
+        let g: Graph = c.to_graph();
+        assert_eq!(g.inputs().len(), 2);
+        assert_eq!(g.outputs().len(), 2);
+        let c1 = Circuit::from_qasm("qreg q[2]; cz q[0], q[1];").unwrap();
+        assert!(TensorF::scalar_compare(&g, &c1));
+    }
+
+    #[test]
+    fn quipper_unsupported() {
+        let wrap = |s: &str| format!("Inputs: 0:Qbit, 1:Qbit\n{s}\nOutputs: 0:Qbit, 1:Qbit");
+        for s in [
+            "QMeas(0)",
+            "QGate[\"W\"](0,1)",
+            "QGate[\"H\"](2)",
+            "QGate[\"not\"](0) with controls=[+0]",
+            "QGate[\"swap\"](0)",
+            "Subroutine(x2)[\"foo\", shape \"([Q],())\"] (0,1)",
+        ] {
+            assert!(circuit_from_quipper(&wrap(s)).is_err(), "{s}");
+        }
+        assert!(circuit_from_quipper("QGate[\"H\"](0)").is_err());
+        assert!(circuit_from_quipper("Inputs: 0:Cbit\nOutputs: 0:Cbit").is_err());
+        assert!(circuit_from_quipper("Inputs: 0:Qbit\nQTerm0(0)\nOutputs: 0:Qbit").is_err());
+    }
+}
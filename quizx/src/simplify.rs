@@ -15,10 +15,39 @@
 // limitations under the License.
 
 use crate::basic_rules::*;
+use crate::circuit::Circuit;
+use crate::dense::{local_comp_unchecked as dense_local_comp, pivot_unchecked as dense_pivot};
+use crate::extract::ToCircuit;
+use crate::fscalar::FScalar;
+use crate::gate::{GType::*, Gate};
+use crate::gflow::has_gflow;
 use crate::graph::*;
 use crate::phase::Phase;
+use crate::symbolic::SymPhase;
+use crate::vec_graph::Graph;
 use num::{One, Zero};
 use rustc_hash::FxHashMap;
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Applies a rewrite, and records it if [`full_simp_traced`] is running on this thread
+macro_rules! traced {
+    ($g: ident, $rule: expr, $vs: expr, $apply: expr) => {{
+        if tracing() {
+            let scalar_before = *$g.scalar();
+            $apply;
+            record(RuleApplication {
+                rule: $rule,
+                vertices: $vs,
+                scalar_before,
+                scalar_after: *$g.scalar(),
+            });
+        } else {
+            $apply;
+        }
+    }};
+}
 
 /// Repeatedly apply the given rule at any vertex
 /// that matches the check function
@@ -27,7 +56,7 @@ use rustc_hash::FxHashMap;
 /// vertex, and leave other vertices in place (although
 /// edges might change).
 macro_rules! vertex_simp {
-    ($g: ident, $check: ident, $rule: ident, $force_reduce: ident) => {{
+    ($g: ident, $check: ident, $rule: ident, $name: expr, $force_reduce: ident) => {{
         let mut got_match = false;
         let mut new_matches = true;
         let mut numv;
@@ -35,8 +64,9 @@ macro_rules! vertex_simp {
             numv = $g.num_vertices();
             new_matches = false;
             for v in $g.vertex_vec() {
-                if $check($g, v) {
-                    $rule($g, v);
+                // boundary vertices are never rewritten, see BoundarySnapshot
+                if $g.vertex_type_opt(v) != Some(VType::B) && $check($g, v) {
+                    traced!($g, $name, vec![v], $rule($g, v));
                     new_matches = true;
                     got_match = true;
                 }
@@ -45,7 +75,7 @@ macro_rules! vertex_simp {
                 break;
             }
 
-            $g.pack(false);
+            pack_traced($g);
         }
 
         got_match
@@ -53,7 +83,7 @@ macro_rules! vertex_simp {
 }
 
 macro_rules! edge_simp {
-    ($g: ident, $check: ident, $rule: ident, $force_reduce: ident) => {{
+    ($g: ident, $check: ident, $rule: ident, $name: expr, $force_reduce: ident) => {{
         let mut got_match = false;
         let mut new_matches = true;
         let mut numv;
@@ -61,10 +91,13 @@ macro_rules! edge_simp {
             numv = $g.num_vertices();
             new_matches = false;
             for (s, t, _) in $g.edge_vec() {
-                if !$check($g, s, t) {
+                if $g.vertex_type_opt(s) == Some(VType::B)
+                    || $g.vertex_type_opt(t) == Some(VType::B)
+                    || !$check($g, s, t)
+                {
                     continue;
                 }
-                $rule($g, s, t);
+                traced!($g, $name, vec![s, t], $rule($g, s, t));
                 new_matches = true;
                 got_match = true;
             }
@@ -72,7 +105,7 @@ macro_rules! edge_simp {
                 break;
             }
 
-            $g.pack(false);
+            pack_traced($g);
         }
 
         got_match
@@ -116,35 +149,132 @@ macro_rules! edge_simp2 {
 */
 
 pub fn id_simp(g: &mut impl GraphLike) -> bool {
-    vertex_simp!(g, check_remove_id, remove_id_unchecked, false)
+    vertex_simp!(
+        g,
+        check_remove_id,
+        remove_id_unchecked,
+        Rule::RemoveId,
+        false
+    )
+}
+
+pub fn self_loop_simp(g: &mut impl GraphLike) -> bool {
+    vertex_simp!(
+        g,
+        check_remove_self_loop,
+        remove_self_loop_unchecked,
+        Rule::RemoveSelfLoop,
+        false
+    )
 }
 
 pub fn local_comp_simp(g: &mut impl GraphLike) -> bool {
-    vertex_simp!(g, check_local_comp, local_comp_unchecked, false)
+    if dense_rules() {
+        vertex_simp!(
+            g,
+            check_local_comp,
+            dense_local_comp,
+            Rule::LocalComp,
+            false
+        )
+    } else {
+        vertex_simp!(
+            g,
+            check_local_comp,
+            local_comp_unchecked,
+            Rule::LocalComp,
+            false
+        )
+    }
+}
+
+/// Removes spiders of degree 1, see [`check_remove_pendant`]
+///
+/// Copying a pendant also removes its neighbour, which the check skips once it is gone.
+pub fn pendant_simp(g: &mut impl GraphLike) -> bool {
+    vertex_simp!(
+        g,
+        check_remove_pendant,
+        remove_pendant_unchecked,
+        Rule::RemovePendant,
+        false
+    )
 }
 
 pub fn spider_simp(g: &mut impl GraphLike) -> bool {
-    edge_simp!(g, check_spider_fusion, spider_fusion_unchecked, false)
+    edge_simp!(
+        g,
+        check_spider_fusion,
+        spider_fusion_unchecked,
+        Rule::SpiderFusion,
+        false
+    )
+}
+
+/// Changes the color of spiders surrounded by Hadamard edges, where this lets them
+/// fuse with a neighbour, see [`check_h_color_change`]
+pub fn color_change_simp(g: &mut impl GraphLike) -> bool {
+    let mut got_match = false;
+    for v in g.vertex_vec() {
+        if check_h_color_change(g, v) {
+            let vt = g.vertex_type(v);
+            if g.neighbors(v).any(|w| {
+                let wt = g.vertex_type(w);
+                wt != vt && (wt == VType::Z || wt == VType::X)
+            }) {
+                traced!(
+                    g,
+                    Rule::HColorChange,
+                    vec![v],
+                    h_color_change_unchecked(g, v)
+                );
+                got_match = true;
+            }
+        }
+    }
+    got_match
 }
 
 pub fn pivot_simp(g: &mut impl GraphLike) -> bool {
     // edge_simp2!(g, check_pivot1, check_pivot2, pivot_unchecked, false)
-    edge_simp!(g, check_pivot, pivot_unchecked, false)
+    if dense_rules() {
+        edge_simp!(g, check_pivot, dense_pivot, Rule::Pivot, false)
+    } else {
+        edge_simp!(g, check_pivot, pivot_unchecked, Rule::Pivot, false)
+    }
 }
 
 pub fn gen_pivot_simp(g: &mut impl GraphLike) -> bool {
-    edge_simp!(g, check_gen_pivot_reduce, gen_pivot_unchecked, false)
+    edge_simp!(
+        g,
+        check_gen_pivot_reduce,
+        gen_pivot_unchecked,
+        Rule::GenPivot,
+        false
+    )
 }
 
 pub fn scalar_simp(g: &mut impl GraphLike) -> bool {
-    let mut m = vertex_simp!(g, check_remove_single, remove_single_unchecked, false);
-    m = edge_simp!(g, check_remove_pair, remove_pair_unchecked, false) || m;
+    let mut m = vertex_simp!(
+        g,
+        check_remove_single,
+        remove_single_unchecked,
+        Rule::RemoveSingle,
+        false
+    );
+    m = edge_simp!(
+        g,
+        check_remove_pair,
+        remove_pair_unchecked,
+        Rule::RemovePair,
+        false
+    ) || m;
     m
 }
 
 pub fn flow_simp(g: &mut impl GraphLike) -> bool {
     spider_simp(g);
-    g.x_to_z();
+    traced!(g, Rule::XToZ, vec![], g.x_to_z());
     let mut got_match = false;
     let mut m = true;
     while m {
@@ -233,7 +363,7 @@ pub fn local_ap_simp(g: &mut impl GraphLike, vs: impl IntoIterator<Item = V>) {
 
 pub fn interior_clifford_simp(g: &mut impl GraphLike) -> bool {
     spider_simp(g);
-    g.x_to_z();
+    traced!(g, Rule::XToZ, vec![], g.x_to_z());
     let mut got_match = false;
     let mut m = true;
     while m {
@@ -273,7 +403,11 @@ pub fn fuse_gadgets(g: &mut impl GraphLike) -> bool {
     for v in g.vertices() {
         if g.degree(v) == 1 && g.vertex_type(v) == VType::Z {
             let w = g.neighbors(v).next().unwrap();
-            if g.vertex_type(w) != VType::Z || !g.phase(w).is_zero() {
+            if g.vertex_type(w) != VType::Z || !g.phase(w).is_zero() || g.is_symbolic(w) {
+                continue;
+            }
+            // removing a hub would disconnect its boundary vertices
+            if g.neighbors(w).any(|n| g.vertex_type(n) == VType::B) {
                 continue;
             }
             let mut nhd = Vec::new();
@@ -300,24 +434,36 @@ pub fn fuse_gadgets(g: &mut impl GraphLike) -> bool {
     let mut fused = false;
     for (vs, gs) in gadgets.iter() {
         if gs.len() > 1 {
-            let num = gs.len() as i32;
-            let degree = vs.len() as i32;
             fused = true;
-            let mut ph = Phase::zero();
-            for (u, v) in gs.iter().skip(1).copied() {
-                ph += g.phase(v);
-                g.remove_vertex(u);
-                g.remove_vertex(v);
-            }
-
-            g.add_to_phase(gs[0].1, ph);
-            g.scalar_mut().mul_sqrt2_pow(-(num - 1) * (degree - 1));
+            let pairs = gs.iter().flat_map(|&(u, v)| [u, v]).collect();
+            let rule = Rule::FuseGadgets { degree: vs.len() };
+            traced!(g, rule, pairs, fuse_gadget_group(g, gs, vs.len()));
         }
     }
 
     fused
 }
 
+/// Fuses the phase gadgets with hubs and leaves `gs` into the first one
+///
+/// The gadgets must all have the same `degree`, i.e. number of targets.
+fn fuse_gadget_group(g: &mut impl GraphLike, gs: &[(V, V)], degree: usize) {
+    let num = gs.len() as i32;
+    let mut ph = Phase::zero();
+    let mut sym = SymPhase::default();
+    for (u, v) in gs.iter().skip(1).copied() {
+        ph += g.phase(v);
+        sym += &g.sym_phase(v);
+        g.remove_vertex(u);
+        g.remove_vertex(v);
+    }
+
+    g.add_to_phase(gs[0].1, ph);
+    g.add_to_sym_phase(gs[0].1, &sym);
+    g.scalar_mut()
+        .mul_sqrt2_pow(-(num - 1) * (degree as i32 - 1));
+}
+
 /// Perform a pi-copies to remove all pi phases from the
 /// centers of phase gadgets.
 fn remove_gadget_pi(g: &mut impl GraphLike) -> bool {
@@ -326,9 +472,13 @@ fn remove_gadget_pi(g: &mut impl GraphLike) -> bool {
         // Look for the outsides of phase gadgets
         .filter(|&v| g.degree(v) == 1 && g.vertex_type(v) == VType::Z)
         .map(|v| (g.neighbors(v).next().unwrap(), v))
-        // Check that the middle is a pi-phase
+        // Check that the middle is a pi-phase, and that neither phase is symbolic
         .filter(|&(n, v)| {
-            g.edge_type(v, n) == EType::H && g.vertex_type(n) == VType::Z && g.phase(n).is_one()
+            g.edge_type(v, n) == EType::H
+                && g.vertex_type(n) == VType::Z
+                && g.phase(n).is_one()
+                && !g.is_symbolic(n)
+                && !g.is_symbolic(v)
         })
         // Collect them in a hash-map keyed by the central vertex
         // so that multiple phases hanging off a single gadget
@@ -343,33 +493,487 @@ fn remove_gadget_pi(g: &mut impl GraphLike) -> bool {
         // We can use unchecked because we verified that
         // this vertex has the phase-gadget structure:
         // Z-spider connected to a single Z-spider with a H edge
-        pi_copy_unchecked(g, v);
+        traced!(g, Rule::PiCopy, vec![v], pi_copy_unchecked(g, v));
     }
 
     matched
 }
 
+/// The inputs and outputs of a graph, in order
+///
+/// Simplification never adds, removes, merges or reorders boundary vertices, which
+/// extraction relies on. Vertices may be renamed by [`GraphLike::pack`] though, so a
+/// snapshot records the position of each boundary vertex rather than its name.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BoundarySnapshot {
+    inputs: Vec<(f64, f64)>,
+    outputs: Vec<(f64, f64)>,
+}
+
+impl BoundarySnapshot {
+    pub fn new(g: &impl GraphLike) -> Self {
+        let pos = |vs: &[V]| vs.iter().map(|&v| (g.qubit(v), g.row(v))).collect();
+        BoundarySnapshot {
+            inputs: pos(g.inputs()),
+            outputs: pos(g.outputs()),
+        }
+    }
+
+    /// Checks that the boundary of `g` is the same as in the snapshot
+    ///
+    /// Returns an error describing the first difference otherwise.
+    pub fn check(&self, g: &impl GraphLike) -> Result<(), String> {
+        let mut seen = FxHashMap::default();
+        for (name, vs) in [("input", g.inputs()), ("output", g.outputs())] {
+            for (i, &v) in vs.iter().enumerate() {
+                if g.vertex_type_opt(v) != Some(VType::B) {
+                    return Err(format!("{name} {i} is not a boundary vertex"));
+                }
+                if let Some((name1, j)) = seen.insert(v, (name, i)) {
+                    return Err(format!("{name} {i} is the same vertex as {name1} {j}"));
+                }
+            }
+        }
+
+        let after = BoundarySnapshot::new(g);
+        for (name, vs0, vs1) in [
+            ("inputs", &self.inputs, &after.inputs),
+            ("outputs", &self.outputs, &after.outputs),
+        ] {
+            if vs0.len() != vs1.len() {
+                return Err(format!(
+                    "number of {name} changed from {} to {}",
+                    vs0.len(),
+                    vs1.len()
+                ));
+            }
+            if let Some(i) = (0..vs0.len()).find(|&i| vs0[i] != vs1[i]) {
+                return Err(format!("{name} were reordered at position {i}"));
+            }
+        }
+        Ok(())
+    }
+}
+
 pub fn full_simp(g: &mut impl GraphLike) -> bool {
-    let mut got_match = false;
+    #[cfg(debug_assertions)]
+    let boundary = BoundarySnapshot::new(g);
+
+    // rules are not applied to self-loops, so they are removed up front
+    let mut got_match = self_loop_simp(g);
+    got_match = color_change_simp(g) || got_match;
     let mut m = true;
     while m {
         m = clifford_simp(g);
         m = fuse_gadgets(g) || m;
         m = remove_gadget_pi(g) || m;
+        m = pendant_simp(g) || m;
         if m {
             got_match = true;
         }
     }
 
+    #[cfg(debug_assertions)]
+    if let Err(e) = boundary.check(g) {
+        panic!("full_simp changed the boundary: {e}");
+    }
+
+    got_match
+}
+
+/// Simplifies `g` with only the rules of [`full_simp`] which are known to preserve
+/// gflow, and checks that the result can still be extracted
+///
+/// These are self-loop removal, colour changes and the rules of
+/// [`interior_clifford_simp`]: spider fusion, identity removal, pivoting and local
+/// complementation of interior spiders, and the scalar rules. Pivots that turn phases
+/// into gadgets ([`gen_pivot_simp`]), gadget fusion, the removal of pi phases around
+/// gadgets and the removal of degree-1 spiders are left out, so the T-count may end
+/// up higher than with [`full_simp`]. The `simplifier` benchmark compares the two.
+///
+/// Returns whether any rule matched, or an error if the simplified graph has no
+/// gflow, see [`has_gflow`]. This can only happen if `g` did not have one to begin
+/// with.
+pub fn full_simp_extractable(g: &mut impl GraphLike) -> Result<bool, String> {
+    let mut got_match = self_loop_simp(g);
+    got_match = color_change_simp(g) || got_match;
+    got_match = interior_clifford_simp(g) || got_match;
+    if !has_gflow(g) {
+        return Err("The simplified graph has no gflow, so it can't be extracted".to_string());
+    }
+    Ok(got_match)
+}
+
+/// Simplifies `g` until its T-count stops decreasing and returns the final T-count
+///
+/// Each round applies the same passes as one iteration of [`full_simp`]: Clifford
+/// simplification, gadget fusion, the removal of pi phases around gadgets and of
+/// degree-1 spiders. Instead of running until no rule matches, this stops after the
+/// first round that doesn't lower the T-count, so it is never slower than
+/// [`full_simp`] but may leave some Clifford structure in place.
+pub fn reduce_tcount(g: &mut impl GraphLike) -> usize {
+    self_loop_simp(g);
+    color_change_simp(g);
+    let mut tcount = g.tcount();
+    loop {
+        clifford_simp(g);
+        fuse_gadgets(g);
+        remove_gadget_pi(g);
+        pendant_simp(g);
+        let t = g.tcount();
+        if t >= tcount {
+            return t;
+        }
+        tcount = t;
+    }
+}
+
+/// A rewrite rule applied by [`full_simp`], see [`RuleApplication`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Rule {
+    RemoveId,
+    RemoveSelfLoop,
+    LocalComp,
+    RemovePendant,
+    SpiderFusion,
+    HColorChange,
+    Pivot,
+    GenPivot,
+    RemoveSingle,
+    RemovePair,
+    /// [`GraphLike::x_to_z`], on the whole graph
+    XToZ,
+    /// Fusing phase gadgets on `degree` targets, given as pairs of hubs and leaves
+    FuseGadgets {
+        degree: usize,
+    },
+    PiCopy,
+    /// [`GraphLike::pack`], when it renamed vertices
+    Pack,
+}
+
+/// One step of a trace recorded by [`full_simp_traced`]
+///
+/// These are the vertices the rule was applied at, e.g. the two ends of the edge for
+/// spider fusion, and the scalar of the graph before and after the step.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuleApplication {
+    pub rule: Rule,
+    pub vertices: Vec<V>,
+    pub scalar_before: FScalar,
+    pub scalar_after: FScalar,
+}
+
+thread_local! {
+    static TRACE: RefCell<Option<Vec<RuleApplication>>> = const { RefCell::new(None) };
+    static DENSE: Cell<bool> = const { Cell::new(false) };
+}
+
+fn dense_rules() -> bool {
+    DENSE.with(Cell::get)
+}
+
+/// Runs `f` with [`local_comp_simp`] and [`pivot_simp`] applying the bitset versions
+/// of their rules from [`crate::dense`]
+///
+/// This gives the same graphs, but is faster when the spiders being removed have many
+/// neighbours. Only simplifications on the calling thread are affected.
+pub fn with_dense_rules<T>(f: impl FnOnce() -> T) -> T {
+    // restore the previous setting even if f panics
+    struct Guard(bool);
+    impl Drop for Guard {
+        fn drop(&mut self) {
+            DENSE.with(|d| d.set(self.0));
+        }
+    }
+
+    let _guard = Guard(DENSE.with(|d| d.replace(true)));
+    f()
+}
+
+fn tracing() -> bool {
+    TRACE.with(|t| t.borrow().is_some())
+}
+
+fn record(step: RuleApplication) {
+    TRACE.with(|t| {
+        if let Some(trace) = t.borrow_mut().as_mut() {
+            trace.push(step);
+        }
+    });
+}
+
+fn pack_traced(g: &mut impl GraphLike) {
+    let n = g.vindex();
+    let scalar = *g.scalar();
+    g.pack(false);
+    if g.vindex() != n {
+        record(RuleApplication {
+            rule: Rule::Pack,
+            vertices: vec![],
+            scalar_before: scalar,
+            scalar_after: scalar,
+        });
+    }
+}
+
+/// Same as [`full_simp`], but also returns every rule it applied, in order
+///
+/// The trace can be replayed on a copy of the original graph with [`replay_trace`],
+/// e.g. a prefix of it to find the first step that gives a wrong scalar. Only rules
+/// applied on the calling thread are recorded.
+pub fn full_simp_traced(g: &mut impl GraphLike) -> (bool, Vec<RuleApplication>) {
+    // stop tracing even if full_simp panics
+    struct Guard;
+    impl Drop for Guard {
+        fn drop(&mut self) {
+            TRACE.with(|t| t.borrow_mut().take());
+        }
+    }
+
+    TRACE.with(|t| *t.borrow_mut() = Some(vec![]));
+    let guard = Guard;
+    let got_match = full_simp(g);
+    let trace = TRACE.with(|t| t.borrow_mut().take()).unwrap_or_default();
+    drop(guard);
+    (got_match, trace)
+}
+
+/// Applies the steps of a trace recorded by [`full_simp_traced`] to `g`
+///
+/// Starting from the graph the trace was recorded on, this gives the same graph as
+/// [`full_simp`] after each step, with the same vertex names. Returns an error if a
+/// step doesn't match the graph, i.e. its vertices are missing or its rule doesn't
+/// apply there, in which case `g` is left as it was after the previous step.
+pub fn replay_trace(g: &mut impl GraphLike, trace: &[RuleApplication]) -> Result<(), String> {
+    for (i, step) in trace.iter().enumerate() {
+        let vs = &step.vertices;
+        let err = |reason: &str| format!("step {i} ({:?} at {vs:?}) {reason}", step.rule);
+        if let Some(v) = vs.iter().find(|&&v| !g.contains_vertex(v)) {
+            return Err(err(&format!("uses missing vertex {v}")));
+        }
+        let arity_ok = match step.rule {
+            Rule::XToZ | Rule::Pack => vs.is_empty(),
+            Rule::SpiderFusion | Rule::Pivot | Rule::GenPivot | Rule::RemovePair => vs.len() == 2,
+            // at least two pairs of a hub and a leaf
+            Rule::FuseGadgets { .. } => vs.len() >= 4 && vs.len() % 2 == 0,
+            _ => vs.len() == 1,
+        };
+        if !arity_ok {
+            return Err(err(&format!("has {} vertices", vs.len())));
+        }
+        let matches = match step.rule {
+            Rule::RemoveId => check_remove_id(g, vs[0]),
+            Rule::RemoveSelfLoop => check_remove_self_loop(g, vs[0]),
+            Rule::LocalComp => check_local_comp(g, vs[0]),
+            Rule::RemovePendant => check_remove_pendant(g, vs[0]),
+            Rule::SpiderFusion => check_spider_fusion(g, vs[0], vs[1]),
+            Rule::HColorChange => check_h_color_change(g, vs[0]),
+            Rule::Pivot => check_pivot(g, vs[0], vs[1]),
+            Rule::GenPivot => check_gen_pivot_reduce(g, vs[0], vs[1]),
+            Rule::RemoveSingle => check_remove_single(g, vs[0]),
+            Rule::RemovePair => check_remove_pair(g, vs[0], vs[1]),
+            Rule::FuseGadgets { .. } => vs
+                .chunks(2)
+                .all(|c| g.vertex_type(c[0]) == VType::Z && g.degree(c[1]) == 1),
+            Rule::PiCopy => g.vertex_type(vs[0]) == VType::Z && g.degree(vs[0]) == 1,
+            Rule::XToZ | Rule::Pack => true,
+        };
+        if !matches {
+            return Err(err("doesn't match the graph"));
+        }
+        match step.rule {
+            Rule::RemoveId => remove_id_unchecked(g, vs[0]),
+            Rule::RemoveSelfLoop => remove_self_loop_unchecked(g, vs[0]),
+            Rule::LocalComp => local_comp_unchecked(g, vs[0]),
+            Rule::RemovePendant => remove_pendant_unchecked(g, vs[0]),
+            Rule::SpiderFusion => spider_fusion_unchecked(g, vs[0], vs[1]),
+            Rule::HColorChange => h_color_change_unchecked(g, vs[0]),
+            Rule::Pivot => pivot_unchecked(g, vs[0], vs[1]),
+            Rule::GenPivot => gen_pivot_unchecked(g, vs[0], vs[1]),
+            Rule::RemoveSingle => remove_single_unchecked(g, vs[0]),
+            Rule::RemovePair => remove_pair_unchecked(g, vs[0], vs[1]),
+            Rule::XToZ => g.x_to_z(),
+            Rule::FuseGadgets { degree } => {
+                let gs: Vec<(V, V)> = vs.chunks(2).map(|c| (c[0], c[1])).collect();
+                fuse_gadget_group(g, &gs, degree)
+            }
+            Rule::PiCopy => pi_copy_unchecked(g, vs[0]),
+            Rule::Pack => g.pack(true),
+        }
+    }
+    Ok(())
+}
+
+/// Removes Hadamard edges between spiders and outputs using boundary pivots
+///
+/// Each Pauli spider connected to an output by a Hadamard edge is pivoted with an
+/// interior Pauli neighbour, see [`check_frontier_pivot`]. This pushes an identity
+/// spider to the frontier, which extraction turns into a plain wire rather than a
+/// Hadamard gate. The boundary is left in place and running it twice does nothing
+/// the second time.
+///
+/// It is meant to be run after [`full_simp`], right before extraction.
+pub fn clean_frontier(g: &mut impl GraphLike) -> bool {
+    let mut got_match = false;
+    let mut new_matches = true;
+    while new_matches {
+        new_matches = false;
+        for (s, t, _) in g.edge_vec() {
+            for (v0, v1) in [(s, t), (t, s)] {
+                if frontier_pivot(g, v0, v1) {
+                    new_matches = true;
+                    got_match = true;
+                    break;
+                }
+            }
+        }
+        g.pack(false);
+    }
+
     got_match
 }
 
+/// Removes single-qubit Cliffords next to the outputs, and returns them as a circuit
+///
+/// Starting from each output, Hadamard edges and Clifford phases of Z spiders are
+/// moved into the returned circuit, and spiders left with no phase and one other
+/// neighbour are removed, until a non-Clifford or higher-degree spider is reached. The
+/// graph is changed in place, and appending the returned circuit to a circuit
+/// extracted from it gives back the original linear map.
+///
+/// It is meant to be run after [`full_simp`], right before extraction, to keep the
+/// boundary Cliffords out of the graph.
+pub fn extract_boundary_cliffords(g: &mut impl GraphLike) -> Circuit {
+    let mut c = Circuit::new(g.outputs().len());
+    for q in 0..g.outputs().len() {
+        let o = g.outputs()[q];
+        // gates are found from the output inwards, so they are pushed to the front
+        let mut gates = vec![];
+        loop {
+            let Some((v, et)) = g.incident_edges(o).next() else {
+                break;
+            };
+            if et == EType::H {
+                gates.push(Gate::new(HAD, vec![q]));
+                g.set_edge_type(v, o, EType::N);
+                continue;
+            }
+            let p = g.phase(v);
+            if g.vertex_type(v) != VType::Z || g.is_symbolic(v) || !p.is_clifford() {
+                break;
+            }
+            let r = p.to_rational();
+            if *r.denom() == 2 {
+                gates.push(Gate::new(if *r.numer() == 1 { S } else { Sdg }, vec![q]));
+            } else if !p.is_zero() {
+                gates.push(Gate::new(Z, vec![q]));
+            }
+            g.set_phase(v, 0);
+
+            let Some((w, et1)) = g.incident_edges(v).find(|&(w, _)| w != o) else {
+                break;
+            };
+            if g.degree(v) != 2 || g.outputs().contains(&w) {
+                break;
+            }
+            g.remove_vertex(v);
+            g.add_edge_with_type(w, o, et1);
+        }
+        for gate in gates {
+            c.push_front(gate);
+        }
+    }
+    c
+}
+
+/// The effect of running [`full_simp`] on a circuit, as returned by [`reduce_report`]
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct ReductionReport {
+    pub tcount: usize,
+    pub reduced_tcount: usize,
+    pub twoq: usize,
+    /// two-qubit gate count of the extracted circuit, or None if extraction was
+    /// not requested or failed
+    pub reduced_twoq: Option<usize>,
+    /// time spent simplifying and (if requested) extracting
+    pub elapsed: Duration,
+}
+
+impl fmt::Display for ReductionReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "T-count: {} -> {}\n2-qubit: {} -> ",
+            self.tcount, self.reduced_tcount, self.twoq
+        )?;
+        match self.reduced_twoq {
+            Some(n) => write!(f, "{n}")?,
+            None => write!(f, "?")?,
+        }
+        write!(f, "\ntime: {:.3}s", self.elapsed.as_secs_f64())
+    }
+}
+
+/// Simplifies a circuit with [`full_simp`] and reports the reduction in T-count
+///
+/// If `extract` is set, a circuit is also extracted from the simplified graph to count
+/// its two-qubit gates. Two-qubit counts are of the circuits decomposed into basic gates.
+pub fn reduce_report(circuit: &Circuit, extract: bool) -> ReductionReport {
+    let mut g: Graph = circuit.to_graph();
+    let tcount = g.tcount();
+    let twoq = circuit.to_basic_gates().stats().twoq;
+
+    let start = Instant::now();
+    full_simp(&mut g);
+    let reduced_tcount = g.tcount();
+    let reduced_twoq = if extract {
+        g.to_circuit().ok().map(|c| c.to_basic_gates().stats().twoq)
+    } else {
+        None
+    };
+
+    ReductionReport {
+        tcount,
+        reduced_tcount,
+        twoq,
+        reduced_twoq,
+        elapsed: start.elapsed(),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::circuit::*;
-    use crate::tensor::ToTensor;
+    use crate::tensor::{CompareTensors, TensorF, ToTensor};
     use crate::vec_graph::Graph;
+    use num::Rational64;
+
+    #[test]
+    fn reduce_report_tcount() {
+        let c = Circuit::from_qasm(
+            r#"
+            qreg q[2];
+            t q[0];
+            cx q[0], q[1];
+            t q[0];
+            cx q[1], q[0];
+            tdg q[1];
+        "#,
+        )
+        .unwrap();
+
+        let r = reduce_report(&c, false);
+        assert_eq!(r.tcount, 3);
+        assert_eq!(r.reduced_tcount, 1);
+        assert_eq!(r.twoq, 2);
+        assert_eq!(r.reduced_twoq, None);
+
+        let r = reduce_report(&c, true);
+        assert!(r.reduced_twoq.is_some());
+    }
 
     #[test]
     fn simp_cnot() {
@@ -487,6 +1091,144 @@ mod tests {
         // assert_eq!(g.to_tensorf(), h.to_tensorf());
     }
 
+    #[test]
+    fn full_simp_keeps_boundary() {
+        for seed in 0..10 {
+            let c = Circuit::random()
+                .seed(seed)
+                .qubits(6)
+                .depth(150)
+                .p_t(0.2)
+                .with_cliffords()
+                .build();
+            let mut g: crate::hash_graph::Graph = c.to_graph();
+            let (inputs, outputs) = (g.inputs().clone(), g.outputs().clone());
+            full_simp(&mut g);
+            assert_eq!(g.inputs(), &inputs);
+            assert_eq!(g.outputs(), &outputs);
+
+            // vertices of a vec_graph may be renamed when packing
+            let mut g: Graph = c.to_graph();
+            let boundary = BoundarySnapshot::new(&g);
+            full_simp(&mut g);
+            assert_eq!(boundary.check(&g), Ok(()));
+        }
+    }
+
+    #[test]
+    fn clean_frontier_output_h_edges() {
+        let output_h_edges = |g: &Graph| {
+            g.outputs()
+                .iter()
+                .filter(|&&b| g.incident_edges(b).any(|(_, et)| et == EType::H))
+                .count()
+        };
+
+        let mut fired = 0;
+        for seed in [15, 17, 27, 45, 60] {
+            let c = Circuit::random()
+                .seed(seed)
+                .qubits(3)
+                .depth(30)
+                .clifford_t(0.2)
+                .build();
+            let mut g: Graph = c.to_graph();
+            full_simp(&mut g);
+            let mut h = g.clone();
+            let boundary = BoundarySnapshot::new(&h);
+            if clean_frontier(&mut h) {
+                fired += 1;
+            }
+            assert_eq!(boundary.check(&h), Ok(()));
+            assert!(output_h_edges(&h) < output_h_edges(&g) || g == h);
+            assert_eq!(g.to_tensorf(), h.to_tensorf());
+
+            let h1 = h.clone();
+            assert!(!clean_frontier(&mut h));
+            assert_eq!(h, h1);
+
+            let c1 = h.to_circuit().unwrap();
+            assert!(TensorF::scalar_compare(&c, &c1));
+        }
+        assert!(fired > 0);
+    }
+
+    #[test]
+    fn boundary_cliffords() {
+        let mut c = Circuit::new(2);
+        c.add_gate("t", vec![0]);
+        c.add_gate("cz", vec![0, 1]);
+        c.add_gate("h", vec![0]);
+        c.add_gate("s", vec![0]);
+        c.add_gate("h", vec![0]);
+        c.add_gate("z", vec![1]);
+        let mut g: Graph = c.to_graph();
+        let n = g.num_vertices();
+        let b = extract_boundary_cliffords(&mut g);
+        assert_eq!(b.num_gates(), 4);
+        assert_eq!(g.num_vertices(), n - 4);
+        assert!(g.outputs().iter().all(|&o| g
+            .incident_edges(o)
+            .all(|(v, et)| et == EType::N && g.phase(v).is_zero())));
+        let c1 = g.to_circuit().unwrap() + &b;
+        assert!(TensorF::scalar_compare(&c, &c1));
+
+        let mut fired = 0;
+        for seed in 0..20 {
+            let c = Circuit::random()
+                .seed(seed)
+                .qubits(4)
+                .depth(30)
+                .p_t(0.2)
+                .with_cliffords()
+                .build();
+            let mut g: Graph = c.to_graph();
+            full_simp(&mut g);
+            let b = extract_boundary_cliffords(&mut g);
+            if b.num_gates() > 0 {
+                fired += 1;
+            }
+            assert_eq!(b.num_qubits(), 4);
+            assert!(extract_boundary_cliffords(&mut g).num_gates() == 0);
+            let c1 = g.to_circuit().unwrap() + &b;
+            assert!(TensorF::scalar_compare(&c, &c1));
+        }
+        assert!(fired > 10);
+    }
+
+    #[test]
+    fn fuse_gadgets_boundary() {
+        // two gadgets acting on the same spiders, one of them with a boundary
+        // attached to its hub
+        let mut g = Graph::new();
+        let vs: Vec<_> = (0..2).map(|_| g.add_vertex(VType::Z)).collect();
+        let b = g.add_vertex(VType::B);
+        for i in 0..2 {
+            let hub = g.add_vertex(VType::Z);
+            let leaf = g.add_vertex_with_phase(VType::Z, Rational64::new(1, 4));
+            g.add_edge_with_type(hub, leaf, EType::H);
+            for &v in &vs {
+                g.add_edge_with_type(hub, v, EType::H);
+            }
+            if i == 1 {
+                g.add_edge(hub, b);
+            }
+        }
+        g.set_outputs(vec![b]);
+
+        let h = g.clone();
+        assert!(!fuse_gadgets(&mut g));
+        assert_eq!(g, h);
+        assert_eq!(BoundarySnapshot::new(&h).check(&g), Ok(()));
+
+        let mut g1 = g.clone();
+        g1.remove_vertex(b);
+        assert_eq!(
+            BoundarySnapshot::new(&g).check(&g1),
+            Err("output 0 is not a boundary vertex".to_string())
+        );
+    }
+
     #[test]
     fn simp_gadget_fusion() {
         let c = Circuit::from_qasm(
@@ -512,4 +1254,146 @@ mod tests {
         println!("{}", g.to_dot());
         assert_eq!(g.to_tensorf(), h.to_tensorf());
     }
+
+    #[test]
+    fn reduce_tcount_gadgets() {
+        let c = Circuit::from_qasm(
+            r#"
+            qreg q[2];
+            t q[0];
+            cx q[1], q[0];
+            t q[0];
+            cx q[1], q[0];
+            t q[0];
+            t q[1];
+        "#,
+        )
+        .unwrap();
+        let mut g: Graph = c.to_graph();
+        let h = g.clone();
+        let mut g1 = g.clone();
+        full_simp(&mut g1);
+
+        let t = reduce_tcount(&mut g);
+        assert_eq!(t, g.tcount());
+        assert_eq!(t, g1.tcount());
+        assert!(t < h.tcount());
+        assert_eq!(g.to_tensorf(), h.to_tensorf());
+    }
+
+    #[test]
+    fn traced_simp_replays() {
+        let snapshot = |g: &Graph| {
+            let mut vs: Vec<_> = g
+                .vertices()
+                .map(|v| (v, g.vertex_data(v).clone()))
+                .collect();
+            vs.sort_by_key(|(v, _)| *v);
+            let mut es = g.edge_vec();
+            es.sort();
+            (vs, es, *g.scalar(), g.inputs().clone(), g.outputs().clone())
+        };
+
+        for c in [
+            Circuit::random()
+                .seed(5)
+                .qubits(5)
+                .depth(60)
+                .clifford_t(0.3)
+                .build(),
+            Circuit::random_pauli_gadget()
+                .seed(5)
+                .qubits(4)
+                .depth(8)
+                .build(),
+        ] {
+            let g0: Graph = c.to_graph();
+            let mut g = g0.clone();
+            let (got_match, trace) = full_simp_traced(&mut g);
+            assert!(got_match);
+            assert!(trace.len() > 10);
+            assert_eq!(trace.last().unwrap().scalar_after, *g.scalar());
+
+            let mut h = g0.clone();
+            replay_trace(&mut h, &trace).unwrap();
+            assert_eq!(snapshot(&h), snapshot(&g));
+
+            // a prefix stops at the scalar after its last step
+            let k = trace.len() / 2;
+            let mut h = g0.clone();
+            replay_trace(&mut h, &trace[..k]).unwrap();
+            assert_eq!(*h.scalar(), trace[k - 1].scalar_after);
+            assert!(replay_trace(&mut h, &trace[..k]).is_err());
+        }
+
+        // two T gadgets on the same two targets, which full_simp fuses
+        let mut g0 = Graph::new();
+        let mut targets = vec![];
+        for _ in 0..2 {
+            let i = g0.add_vertex(VType::B);
+            let t = g0.add_vertex(VType::Z);
+            let o = g0.add_vertex(VType::B);
+            g0.add_edge(i, t);
+            g0.add_edge(t, o);
+            g0.inputs_mut().push(i);
+            g0.outputs_mut().push(o);
+            targets.push(t);
+        }
+        for _ in 0..2 {
+            let hub = g0.add_vertex(VType::Z);
+            let leaf = g0.add_vertex_with_phase(VType::Z, Rational64::new(1, 4));
+            g0.add_edge_with_type(hub, leaf, EType::H);
+            for &t in &targets {
+                g0.add_edge_with_type(hub, t, EType::H);
+            }
+        }
+        let mut g = g0.clone();
+        let (_, trace) = full_simp_traced(&mut g);
+        assert!(trace
+            .iter()
+            .any(|s| matches!(s.rule, Rule::FuseGadgets { .. })));
+        let mut h = g0.clone();
+        replay_trace(&mut h, &trace).unwrap();
+        assert_eq!(snapshot(&h), snapshot(&g));
+
+        // full_simp on its own doesn't record anything
+        full_simp(&mut g);
+        assert!(!tracing());
+    }
+
+    #[test]
+    fn simp_extractable() {
+        for seed in 0..10 {
+            let c = Circuit::random()
+                .seed(seed)
+                .qubits(5)
+                .depth(60)
+                .clifford_t(0.3)
+                .build();
+            let mut g: Graph = c.to_graph();
+            let mut h = g.clone();
+            full_simp(&mut g);
+            full_simp_extractable(&mut h).unwrap();
+            assert!(h.tcount() >= g.tcount());
+
+            let c1 = h.to_circuit().unwrap();
+            assert!(TensorF::scalar_compare(&c, &c1));
+        }
+    }
+
+    #[test]
+    fn simp_extractable_no_gflow() {
+        // a spider merging two inputs into one output can never be extracted
+        let mut g = Graph::new();
+        let is: Vec<_> = (0..2).map(|_| g.add_vertex(VType::B)).collect();
+        let o = g.add_vertex(VType::B);
+        let v = g.add_vertex_with_phase(VType::Z, Rational64::new(1, 4));
+        g.add_edge(is[0], v);
+        g.add_edge(is[1], v);
+        g.add_edge(v, o);
+        g.set_inputs(is);
+        g.set_outputs(vec![o]);
+        let err = full_simp_extractable(&mut g).unwrap_err();
+        assert!(err.contains("no gflow"));
+    }
 }
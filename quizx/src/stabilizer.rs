@@ -0,0 +1,1042 @@
+// QuiZX - Rust library for quantum circuit rewriting and optimisation
+//         using the ZX-calculus
+// Copyright (C) 2021 - Aleks Kissinger
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Stabiliser tableaux and sums of stabiliser states
+//!
+//! A [Tableau] is kept in a canonical (reduced row echelon) form, so two tableaux
+//! are equal precisely when they describe the same stabiliser state.
+
+use crate::fscalar::*;
+use crate::graph::*;
+use crate::tensor::Tensor64;
+use num::{Complex, Rational64, Zero};
+use rustc_hash::FxHashMap;
+use serde::Serialize;
+use std::io::Write;
+
+/// A Pauli operator `i^r X^x Z^z` on some number of qubits
+#[derive(PartialEq, Eq, Hash, Clone, Debug)]
+pub struct Pauli {
+    pub r: u8,
+    pub x: Vec<bool>,
+    pub z: Vec<bool>,
+}
+
+impl Pauli {
+    /// The identity on `n` qubits
+    pub fn id(n: usize) -> Pauli {
+        Pauli {
+            r: 0,
+            x: vec![false; n],
+            z: vec![false; n],
+        }
+    }
+
+    pub fn num_qubits(&self) -> usize {
+        self.x.len()
+    }
+
+    /// Bit `c` of the concatenated vector `[x | z]`
+    fn bit(&self, c: usize) -> bool {
+        let n = self.num_qubits();
+        if c < n {
+            self.x[c]
+        } else {
+            self.z[c - n]
+        }
+    }
+
+    /// Replaces `self` with the product `self * other`
+    pub fn mul_assign(&mut self, other: &Pauli) {
+        // moving X^x' past Z^z picks up a sign (-1)^(z.x')
+        let sign = self
+            .z
+            .iter()
+            .zip(other.x.iter())
+            .filter(|&(&a, &b)| a && b)
+            .count();
+        self.r = (self.r + other.r + 2 * (sign % 2) as u8) % 4;
+        for q in 0..self.num_qubits() {
+            self.x[q] ^= other.x[q];
+            self.z[q] ^= other.z[q];
+        }
+    }
+
+    /// Returns true if `self` and `other` anticommute
+    pub fn anticommutes(&self, other: &Pauli) -> bool {
+        let n = (0..self.num_qubits())
+            .filter(|&q| (self.x[q] && other.z[q]) != (self.z[q] && other.x[q]))
+            .count();
+        n % 2 == 1
+    }
+
+    /// Applies the Pauli to the basis vector `y`, returning the new basis vector and
+    /// the phase it picked up, as a power of `i`
+    fn apply(&self, y: &[bool]) -> (Vec<bool>, u8) {
+        let sign = self
+            .z
+            .iter()
+            .zip(y.iter())
+            .filter(|&(&a, &b)| a && b)
+            .count();
+        let y1 = y.iter().zip(self.x.iter()).map(|(&a, &b)| a ^ b).collect();
+        (y1, (self.r + 2 * (sign % 2) as u8) % 4)
+    }
+
+    fn remove_last_qubit(&mut self) {
+        self.x.pop();
+        self.z.pop();
+    }
+}
+
+/// The stabiliser tableau of a (non-zero) stabiliser state, in canonical form
+#[derive(PartialEq, Eq, Hash, Clone, Debug)]
+pub struct Tableau {
+    nqubits: usize,
+    rows: Vec<Pauli>,
+}
+
+impl Tableau {
+    /// Builds a tableau from a list of stabiliser generators
+    ///
+    /// Panics unless there is one generator on `n` qubits for each of the `n` qubits,
+    /// and the generators are Hermitian, commuting and independent.
+    pub fn new(stabilizers: Vec<Pauli>) -> Tableau {
+        let n = stabilizers.len();
+        for (i, p) in stabilizers.iter().enumerate() {
+            if p.num_qubits() != n || p.z.len() != n {
+                panic!("Expected {n} stabilisers on {n} qubits");
+            }
+            // (X^x Z^z)^dag = (-1)^(x.z) X^x Z^z
+            let xz = (0..n).filter(|&q| p.x[q] && p.z[q]).count();
+            if (p.r as usize + xz) % 2 != 0 {
+                panic!("Stabiliser {i} is not Hermitian");
+            }
+            if stabilizers[..i].iter().any(|p1| p1.anticommutes(p)) {
+                panic!("Stabiliser {i} does not commute with the others");
+            }
+        }
+
+        let mut tab = Tableau {
+            nqubits: n,
+            rows: stabilizers,
+        };
+        if rref(&mut tab.rows).len() != n {
+            panic!("Stabilisers are not independent");
+        }
+        tab
+    }
+
+    /// Builds the tableau of the state given by a Clifford ZX-diagram with no inputs
+    ///
+    /// Returns `None` if the diagram is zero. The scalar of the diagram is ignored.
+    /// Panics if the diagram has inputs, non-Clifford phases, or vertices other than
+    /// boundaries, Z-spiders and X-spiders.
+    pub fn from_graph(g: &impl GraphLike) -> Option<Tableau> {
+        if !g.inputs().is_empty() {
+            panic!("Tableau::from_graph expects a state, but the graph has inputs");
+        }
+
+        // The diagram is written as a graph state, followed by local phases and
+        // post-selection onto <+| of every qubit that doesn't correspond to an
+        // output. The first qubits are the outputs, followed by one qubit for
+        // each spider and one extra qubit for each non-Hadamard edge.
+        let mut qubit: FxHashMap<V, usize> = FxHashMap::default();
+        for (i, &o) in g.outputs().iter().enumerate() {
+            qubit.insert(o, i);
+        }
+        let mut phases = vec![0; g.outputs().len()];
+        for v in g.vertices() {
+            match g.vertex_type(v) {
+                VType::B => {
+                    if !qubit.contains_key(&v) {
+                        panic!("All boundary vertices must be outputs");
+                    }
+                }
+                VType::Z | VType::X => {
+                    let p = g.phase(v).to_rational() * 2;
+                    if !p.is_integer() {
+                        panic!("Tableau::from_graph expects a Clifford diagram");
+                    }
+                    qubit.insert(v, phases.len());
+                    phases.push(p.to_integer().rem_euclid(4) as u8);
+                }
+                t => panic!("Vertex type currently unsupported: {t:?}"),
+            }
+        }
+
+        let mut cz = vec![];
+        for (s, t, et) in g.edges() {
+            // an X-spider is a Z-spider with a Hadamard on each of its legs
+            let mut had = et == EType::H;
+            had ^= g.vertex_type(s) == VType::X;
+            had ^= g.vertex_type(t) == VType::X;
+            if had {
+                cz.push((qubit[&s], qubit[&t]));
+            } else {
+                // a phase-free spider between two Hadamards is the identity
+                let u = phases.len();
+                phases.push(0);
+                cz.push((qubit[&s], u));
+                cz.push((u, qubit[&t]));
+            }
+        }
+
+        let n = phases.len();
+        let mut rows: Vec<Pauli> = (0..n)
+            .map(|q| {
+                let mut p = Pauli::id(n);
+                p.r = phases[q];
+                p.x[q] = true;
+                p.z[q] = phases[q] % 2 == 1;
+                p
+            })
+            .collect();
+        for &(a, b) in &cz {
+            rows[a].z[b] ^= true;
+            rows[b].z[a] ^= true;
+        }
+
+        let mut tab = Tableau { nqubits: n, rows };
+        while tab.nqubits > g.outputs().len() {
+            if !tab.postselect_last_plus() {
+                return None;
+            }
+        }
+        tab.canonicalize();
+        Some(tab)
+    }
+
+    pub fn num_qubits(&self) -> usize {
+        self.nqubits
+    }
+
+    /// The stabiliser generators, in reduced row echelon form
+    pub fn stabilizers(&self) -> &[Pauli] {
+        &self.rows
+    }
+
+    /// Post-selects the last qubit onto <+| and removes it
+    ///
+    /// Returns `false` if the resulting state is zero.
+    fn postselect_last_plus(&mut self) -> bool {
+        let q = self.nqubits - 1;
+        let mut xq = Pauli::id(self.nqubits);
+        xq.x[q] = true;
+
+        let p = if let Some(p) = self.rows.iter().position(|row| row.z[q]) {
+            // X_q anticommutes with some stabiliser, so it can be projected onto freely
+            for j in 0..self.rows.len() {
+                if j != p && self.rows[j].z[q] {
+                    let row = self.rows[p].clone();
+                    self.rows[j].mul_assign(&row);
+                }
+            }
+            p
+        } else {
+            // otherwise +X_q or -X_q is already a stabiliser
+            let p = self
+                .rows
+                .iter()
+                .position(|row| row.x[q])
+                .expect("Stabilisers should be independent");
+            let mut row = self.rows.remove(p);
+            let pivots = rref(&mut self.rows);
+            for (j, &c) in pivots.iter().enumerate() {
+                if row.bit(c) {
+                    row.mul_assign(&self.rows[j]);
+                }
+            }
+            if row.r != 0 {
+                return false;
+            }
+            self.rows.insert(p, row);
+            p
+        };
+
+        self.rows[p] = xq;
+        for j in 0..self.rows.len() {
+            if j != p && self.rows[j].x[q] {
+                let row = self.rows[p].clone();
+                self.rows[j].mul_assign(&row);
+            }
+        }
+        self.rows.remove(p);
+        for row in &mut self.rows {
+            row.remove_last_qubit();
+        }
+        self.nqubits -= 1;
+        true
+    }
+
+    /// Builds a diagram of the canonical state of the tableau, as a graph state with
+    /// local Clifford phases
+    ///
+    /// Each output is connected to a Z-spider, by a Hadamard edge for the qubits that
+    /// need a Hadamard gate after the graph state. The scalar is set so that the diagram
+    /// is equal to [Tableau::to_tensor64].
+    pub fn to_graph<G: GraphLike>(&self) -> G {
+        let n = self.nqubits;
+
+        // Applying a Hadamard to each qubit that is not the pivot of a row with an X
+        // part makes the X part invertible, so the stabilisers can be brought into
+        // the form i^r X_q Z^z, where z is a row of the adjacency matrix plus the
+        // phase of q on the diagonal.
+        let mut had = vec![true; n];
+        for row in &self.rows {
+            if let Some(q) = row.x.iter().position(|&b| b) {
+                had[q] = false;
+            }
+        }
+        let mut rows = self.rows.clone();
+        for row in &mut rows {
+            for q in (0..n).filter(|&q| had[q]) {
+                // H X Z H = Z X = -X Z
+                if row.x[q] && row.z[q] {
+                    row.r = (row.r + 2) % 4;
+                }
+                std::mem::swap(&mut row.x[q], &mut row.z[q]);
+            }
+        }
+        rref(&mut rows);
+
+        let mut g = G::new();
+        let spiders: Vec<V> = rows
+            .iter()
+            .enumerate()
+            .map(|(q, row)| {
+                debug_assert!(row.x.iter().enumerate().all(|(q1, &b)| b == (q == q1)));
+                let v = g.add_vertex_with_phase(VType::Z, Rational64::new(row.r as i64, 2));
+                g.set_qubit(v, q as f64);
+                g.set_row(v, 1.0);
+                v
+            })
+            .collect();
+        for q0 in 0..n {
+            for q1 in (q0 + 1)..n {
+                if rows[q0].z[q1] {
+                    g.add_edge_with_type(spiders[q0], spiders[q1], EType::H);
+                }
+            }
+        }
+        let outputs = (0..n)
+            .map(|q| {
+                let b = g.add_vertex(VType::B);
+                g.set_qubit(b, q as f64);
+                g.set_row(b, 2.0);
+                let et = if had[q] { EType::H } else { EType::N };
+                g.add_edge_with_type(spiders[q], b, et);
+                b
+            })
+            .collect();
+        g.set_outputs(outputs);
+
+        let (phase, pow) = coefficient(self, &g)
+            .exact_phase_and_sqrt2_pow()
+            .expect("Clifford diagrams should have exact scalars");
+        g.scalar_mut().mul_phase(-phase);
+        g.scalar_mut().mul_sqrt2_pow(-pow as i32);
+        g
+    }
+
+    fn canonicalize(&mut self) {
+        rref(&mut self.rows);
+    }
+
+    /// The number of stabilisers with an X part, i.e. log2 of the size of the support
+    fn support_dim(&self) -> usize {
+        self.rows
+            .iter()
+            .filter(|row| row.x.iter().any(|&b| b))
+            .count()
+    }
+
+    /// A basis vector in the support of the state, determined by the tableau
+    ///
+    /// This sets the pivot of each Z-only stabiliser according to its sign and all
+    /// other bits to 0.
+    pub fn support_basis_vector(&self) -> Vec<bool> {
+        let mut b = vec![false; self.nqubits];
+        for row in &self.rows {
+            if !row.x.iter().any(|&x| x) {
+                if let Some(q) = row.z.iter().position(|&z| z) {
+                    b[q] = row.r == 2;
+                }
+            }
+        }
+        b
+    }
+
+    /// Dense vector of the canonical state for this tableau
+    ///
+    /// The canonical state is normalised and has a positive real amplitude on
+    /// [Tableau::support_basis_vector]. This takes exponential time and memory in the
+    /// number of qubits.
+    pub fn to_tensor64(&self) -> Tensor64 {
+        let n = self.nqubits;
+        let mut v = vec![Complex::zero(); 1 << n];
+        v[index(&self.support_basis_vector())] =
+            Complex::new(2f64.powf(self.support_dim() as f64 / 2.0), 0.0);
+
+        let iphase = [
+            Complex::new(1.0, 0.0),
+            Complex::new(0.0, 1.0),
+            Complex::new(-1.0, 0.0),
+            Complex::new(0.0, -1.0),
+        ];
+        for row in &self.rows {
+            let mut w = v.clone();
+            for (i, &a) in v.iter().enumerate() {
+                if !a.is_zero() {
+                    let y: Vec<bool> = (0..n).map(|q| (i >> (n - 1 - q)) & 1 == 1).collect();
+                    let (y1, r) = row.apply(&y);
+                    w[index(&y1)] += a * iphase[r as usize];
+                }
+            }
+            v = w.into_iter().map(|a| a * 0.5).collect();
+        }
+
+        Tensor64::from_shape_vec(vec![2; n], v).unwrap()
+    }
+
+    /// The amplitude of the canonical state on the basis vector `y`
+    ///
+    /// This is `2^(-d/2) i^r` for `2^d` the size of the support, or zero if `y` is not
+    /// in the support.
+    pub fn amplitude(&self, y: &[bool]) -> FScalar {
+        let b = self.support_basis_vector();
+        // the canonical state is 2^(d/2) times the projection of |b> onto the state, so
+        // <y|state> = 2^(-d/2) <y|P|b> for the stabiliser P with X part y + b
+        let mut p = Pauli::id(self.nqubits);
+        for row in &self.rows {
+            if let Some(q) = row.x.iter().position(|&x| x) {
+                if p.x[q] != (y[q] ^ b[q]) {
+                    p.mul_assign(row);
+                }
+            }
+        }
+        let (y1, r) = p.apply(&b);
+        if y1 != y {
+            return FScalar::zero();
+        }
+        let mut amp = FScalar::sqrt2_pow(-(self.support_dim() as i32));
+        amp.mul_phase(Rational64::new(r as i64, 2));
+        amp
+    }
+
+    /// The inner product `<self|other>` of the canonical states of two tableaux
+    ///
+    /// The stabilisers of `self` are projected onto `other` one at a time. A
+    /// stabiliser that commutes with the current state either fixes it or kills it,
+    /// and one that anticommutes replaces a stabiliser and scales the state by
+    /// 1/sqrt(2), with the phase worked out from a single amplitude. Once all of them
+    /// are projected this is `<self|other>` times the canonical state of `self`.
+    ///
+    /// Panics if the tableaux have different numbers of qubits.
+    pub fn inner_product(&self, other: &Tableau) -> FScalar {
+        if self.nqubits != other.nqubits {
+            panic!(
+                "Inner product of states on {} and {} qubits",
+                self.nqubits, other.nqubits
+            );
+        }
+        let mut coeff = FScalar::one();
+        let mut tab = other.clone();
+        for h in &self.rows {
+            if let Some(p) = tab.rows.iter().position(|row| row.anticommutes(h)) {
+                let mut rows = tab.rows.clone();
+                for j in 0..rows.len() {
+                    if j != p && rows[j].anticommutes(h) {
+                        let row = rows[p].clone();
+                        rows[j].mul_assign(&row);
+                    }
+                }
+                rows[p] = h.clone();
+                let mut tab1 = Tableau {
+                    nqubits: self.nqubits,
+                    rows,
+                };
+                tab1.canonicalize();
+
+                // compare (1 + h)/2 |tab> with |tab1> on the support vector of tab1,
+                // where <y|h = i^(-r) <y1| for h|y> = i^r |y1>
+                let y = tab1.support_basis_vector();
+                let (y1, r) = h.apply(&y);
+                let mut amp = tab.amplitude(&y1);
+                amp.mul_phase(Rational64::new(-(r as i64), 2));
+                amp += tab.amplitude(&y);
+                amp.mul_sqrt2_pow(tab1.support_dim() as i32 - 2);
+                coeff *= amp;
+                tab = tab1;
+            } else {
+                // h or -h is already a stabiliser
+                let mut q = h.clone();
+                for row in &tab.rows {
+                    let c = (0..2 * self.nqubits).find(|&c| row.bit(c)).unwrap();
+                    if q.bit(c) {
+                        q.mul_assign(row);
+                    }
+                }
+                if q.r != 0 {
+                    return FScalar::zero();
+                }
+            }
+        }
+        coeff
+    }
+}
+
+/// The coefficient of the state given by `g` with respect to the canonical state of
+/// `tab`, assuming that `g` is a multiple of that state
+fn coefficient(tab: &Tableau, g: &impl GraphLike) -> FScalar {
+    // compare an amplitude of g with the canonical state to get the coefficient
+    let b = tab.support_basis_vector();
+    let mut h = g.clone();
+    let plug: Vec<_> = b
+        .iter()
+        .map(|&bit| if bit { BasisElem::Z1 } else { BasisElem::Z0 })
+        .collect();
+    h.plug_outputs(&plug);
+    crate::simplify::full_simp(&mut h);
+    let mut coeff = *h.scalar();
+    if h.num_vertices() != 0 {
+        coeff = crate::tensor::ToTensor::to_tensorf(&h)[[]];
+    }
+    coeff.mul_sqrt2_pow(tab.support_dim() as i32);
+    coeff
+}
+
+/// Index of a basis vector, with qubit 0 as the most significant bit
+fn index(y: &[bool]) -> usize {
+    y.iter().fold(0, |i, &b| (i << 1) | (b as usize))
+}
+
+/// Puts a list of commuting Paulis in reduced row echelon form, as a binary
+/// matrix `[x | z]`, and returns the pivot column of each row
+fn rref(rows: &mut [Pauli]) -> Vec<usize> {
+    let mut pivots = vec![];
+    if rows.is_empty() {
+        return pivots;
+    }
+    let cols = 2 * rows[0].num_qubits();
+    let mut r = 0;
+    for c in 0..cols {
+        if r == rows.len() {
+            break;
+        }
+        if let Some(p) = (r..rows.len()).find(|&j| rows[j].bit(c)) {
+            rows.swap(r, p);
+            for j in 0..rows.len() {
+                if j != r && rows[j].bit(c) {
+                    let row = rows[r].clone();
+                    rows[j].mul_assign(&row);
+                }
+            }
+            pivots.push(c);
+            r += 1;
+        }
+    }
+    pivots
+}
+
+/// The non-zero term given by a Clifford ZX-diagram with no inputs, if there is one
+fn graph_term(g: &impl GraphLike) -> Option<(FScalar, Tableau)> {
+    let tab = Tableau::from_graph(g)?;
+    let coeff = coefficient(&tab, g);
+    (!coeff.is_zero()).then_some((coeff, tab))
+}
+
+/// A linear combination of stabiliser states
+///
+/// Each term is a coefficient times the canonical state of a tableau, as given by
+/// [Tableau::to_tensor64]. Terms added with [StabSum::add_term] and [StabSum::add_graph]
+/// are merged with any term that has an equal tableau.
+#[derive(Clone, Debug, Default)]
+pub struct StabSum {
+    pub terms: Vec<(FScalar, Tableau)>,
+}
+
+impl StabSum {
+    pub fn new() -> StabSum {
+        Default::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.terms.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    /// Adds `coeff` times the canonical state of `tab`, merging with an existing term if possible
+    pub fn add_term(&mut self, coeff: FScalar, tab: Tableau) {
+        if let Some(term) = self.terms.iter_mut().find(|(_, t)| *t == tab) {
+            term.0 += coeff;
+        } else {
+            self.terms.push((coeff, tab));
+        }
+    }
+
+    /// Adds the state given by a Clifford ZX-diagram with no inputs, including its scalar
+    pub fn add_graph(&mut self, g: &impl GraphLike) {
+        if let Some((coeff, tab)) = graph_term(g) {
+            self.add_term(coeff, tab);
+        }
+    }
+
+    /// Like [StabSum::add_graph], but always adds a new term, even if a term with an
+    /// equal tableau is already in the sum
+    pub fn push_graph(&mut self, g: &impl GraphLike) {
+        if let Some(term) = graph_term(g) {
+            self.terms.push(term);
+        }
+    }
+
+    /// Builds a sum from a list of Clifford ZX-diagrams with no inputs
+    pub fn from_graphs<'a, G: GraphLike + 'a>(gs: impl IntoIterator<Item = &'a G>) -> StabSum {
+        let mut sum = StabSum::new();
+        for g in gs {
+            sum.add_graph(g);
+        }
+        sum
+    }
+
+    /// The inner product `<self|other>` of two sums, as `sum_ij c_i^* d_j <i|j>`
+    ///
+    /// This takes time quadratic in the number of terms, and polynomial in the number
+    /// of qubits.
+    pub fn inner_product(&self, other: &StabSum) -> FScalar {
+        let mut s = FScalar::zero();
+        for (c, t) in &self.terms {
+            for (d, u) in &other.terms {
+                s += c.conj() * *d * t.inner_product(u);
+            }
+        }
+        s
+    }
+
+    /// Dense vector of the sum. This takes exponential time and memory in the number of qubits.
+    ///
+    /// Panics if the sum is empty.
+    pub fn to_tensor64(&self) -> Tensor64 {
+        let mut it = self.terms.iter();
+        let (c, tab) = it.next().expect("Empty sum");
+        let mut t = tab.to_tensor64() * c.complex_value();
+        for (c, tab) in it {
+            t = t + tab.to_tensor64() * c.complex_value();
+        }
+        t
+    }
+
+    /// Writes the sum as json, for processing by other tools
+    ///
+    /// The output is an object with the number of qubits `n` as `"num_qubits"`, and
+    /// the terms as `"terms"`. Each term is an object with
+    /// - `"coeff"`: the coefficient as `[re, im]`, in floating point
+    /// - `"tableau"`: the `n` stabiliser generators of the canonical tableau, in
+    ///   order. Generator `i^r X^x Z^z` is the string of `2n` characters `0` or `1`
+    ///   giving the bits `x[0] .. x[n-1]` followed by `z[0] .. z[n-1]`, so the list is
+    ///   the `n x 2n` binary matrix `[X | Z]` one row at a time.
+    /// - `"phases"`: the power `r` of `i` of each generator, from 0 to 3. As the
+    ///   generators are Hermitian, `r` is odd exactly when `x` and `z` overlap in an
+    ///   odd number of qubits, e.g. `Y = i X Z` has `r = 1`.
+    ///
+    /// Qubit `q` is axis `q` of [StabSum::to_tensor64]. An empty sum has 0 qubits.
+    pub fn write_json<W: Write>(&self, w: W) -> serde_json::Result<()> {
+        let n = self.terms.first().map_or(0, |(_, tab)| tab.num_qubits());
+        let terms = self
+            .terms
+            .iter()
+            .map(|(c, tab)| {
+                let c = c.complex_value();
+                JsonTerm {
+                    coeff: [c.re, c.im],
+                    tableau: tab
+                        .stabilizers()
+                        .iter()
+                        .map(|p| {
+                            p.x.iter()
+                                .chain(p.z.iter())
+                                .map(|&b| if b { '1' } else { '0' })
+                                .collect()
+                        })
+                        .collect(),
+                    phases: tab.stabilizers().iter().map(|p| p.r).collect(),
+                }
+            })
+            .collect();
+        serde_json::to_writer(
+            w,
+            &JsonStabSum {
+                num_qubits: n,
+                terms,
+            },
+        )
+    }
+}
+
+/// The json representation of a [StabSum], see [StabSum::write_json]
+#[derive(Serialize)]
+struct JsonStabSum {
+    num_qubits: usize,
+    terms: Vec<JsonTerm>,
+}
+
+#[derive(Serialize)]
+struct JsonTerm {
+    coeff: [f64; 2],
+    tableau: Vec<String>,
+    phases: Vec<u8>,
+}
+
+/// A linear combination of stabiliser operators
+///
+/// An operator is stored as the state given by bending its inputs round to be
+/// outputs, on the inputs followed by the outputs. This is the Choi state of the
+/// operator, without normalisation, and its dense vector is the same as the tensor
+/// of the operator's ZX-diagram, see [crate::tensor::ToTensor]. Each term is the Choi
+/// state of a stabiliser operator, e.g. a Clifford unitary or a Pauli projection.
+#[derive(Clone, Debug, Default)]
+pub struct OperatorStabSum {
+    pub num_inputs: usize,
+    pub num_outputs: usize,
+    pub choi: StabSum,
+}
+
+impl OperatorStabSum {
+    pub fn len(&self) -> usize {
+        self.choi.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.choi.is_empty()
+    }
+
+    /// Dense tensor of the operator, with an axis for each input followed by one for
+    /// each output. This takes exponential time and memory in the number of qubits.
+    ///
+    /// Panics if the sum is empty.
+    pub fn to_tensor64(&self) -> Tensor64 {
+        self.choi.to_tensor64()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::circuit::Circuit;
+    use crate::tensor::ToTensor;
+    use crate::vec_graph::Graph;
+
+    fn assert_close(t0: &Tensor64, t1: &Tensor64) {
+        assert_eq!(t0.shape(), t1.shape());
+        for (a, b) in t0.iter().zip(t1.iter()) {
+            assert!((a - b).norm() < 1e-8, "{t0}\n!=\n{t1}");
+        }
+    }
+
+    fn state_graph(c: &Circuit) -> Graph {
+        let mut g: Graph = c.to_graph();
+        g.plug_inputs(&vec![BasisElem::Z0; c.num_qubits()]);
+        g
+    }
+
+    #[test]
+    fn pauli_mul() {
+        // X * Z = -i Y, and Y = i X Z
+        let mut x = Pauli::id(1);
+        x.x[0] = true;
+        let mut z = Pauli::id(1);
+        z.z[0] = true;
+        assert!(x.anticommutes(&z));
+
+        let mut zx = z.clone();
+        zx.mul_assign(&x);
+        let mut xz = x.clone();
+        xz.mul_assign(&z);
+        assert_eq!(xz.r, 0);
+        assert_eq!(zx.r, 2);
+        assert_eq!(xz.x, zx.x);
+        assert_eq!(xz.z, zx.z);
+    }
+
+    #[test]
+    fn tableau_canonical() {
+        let mut c0 = Circuit::new(2);
+        c0.add_gate("h", vec![0]);
+        c0.add_gate("cx", vec![0, 1]);
+        let mut c1 = Circuit::new(2);
+        c1.add_gate("h", vec![1]);
+        c1.add_gate("cx", vec![1, 0]);
+        c1.add_gate("z", vec![0]);
+        c1.add_gate("z", vec![1]);
+
+        let t0 = Tableau::from_graph(&state_graph(&c0)).unwrap();
+        let t1 = Tableau::from_graph(&state_graph(&c1)).unwrap();
+        assert_eq!(t0, t1);
+
+        c1.add_gate("z", vec![1]);
+        let t2 = Tableau::from_graph(&state_graph(&c1)).unwrap();
+        assert_ne!(t0, t2);
+    }
+
+    #[test]
+    fn tableau_random_cliffords() {
+        for seed in 0..20 {
+            let c = Circuit::random()
+                .seed(seed)
+                .qubits(4)
+                .depth(30)
+                .clifford_t(0.0)
+                .build();
+            let mut g = state_graph(&c);
+            let t = g.to_tensor64();
+            let sum = StabSum::from_graphs([&g]);
+            assert_eq!(sum.len(), 1);
+            assert_close(&sum.to_tensor64(), &t);
+
+            crate::simplify::full_simp(&mut g);
+            let sum = StabSum::from_graphs([&g]);
+            assert_close(&sum.to_tensor64(), &t);
+        }
+    }
+
+    #[test]
+    fn tableau_to_graph() {
+        for seed in 0..20 {
+            let c = Circuit::random()
+                .seed(seed)
+                .qubits(5)
+                .depth(40)
+                .clifford_t(0.0)
+                .build();
+            let t = Tableau::from_graph(&state_graph(&c)).unwrap();
+            let g: Graph = t.to_graph();
+            assert!(g.edges().all(|(s, t, et)| et == EType::H
+                || g.vertex_type(s) == VType::B
+                || g.vertex_type(t) == VType::B));
+            assert_eq!(Tableau::from_graph(&g), Some(t.clone()));
+            assert_close(&g.to_tensor64(), &t.to_tensor64());
+        }
+    }
+
+    fn random_tableau(seed: u64, qubits: usize) -> Tableau {
+        let c = Circuit::random()
+            .seed(seed)
+            .qubits(qubits)
+            .depth(qubits * 5)
+            .clifford_t(0.0)
+            .build();
+        Tableau::from_graph(&state_graph(&c)).unwrap()
+    }
+
+    fn overlap(t0: &Tensor64, t1: &Tensor64) -> Complex<f64> {
+        t0.iter().zip(t1.iter()).map(|(a, b)| a.conj() * b).sum()
+    }
+
+    #[test]
+    fn amplitudes() {
+        for seed in 0..10 {
+            let t = random_tableau(seed, 4);
+            let v = t.to_tensor64();
+            for (i, a) in v.iter().enumerate() {
+                let y: Vec<bool> = (0..4).map(|q| (i >> (3 - q)) & 1 == 1).collect();
+                assert!((t.amplitude(&y).complex_value() - a).norm() < 1e-8);
+            }
+        }
+    }
+
+    #[test]
+    fn inner_products() {
+        let mut nonzero = 0;
+        for seed in 0..30 {
+            // use few qubits, so that the overlaps are often non-zero
+            let qubits = 2 + seed as usize % 3;
+            let t0 = random_tableau(seed, qubits);
+            let t1 = random_tableau(seed + 100, qubits);
+            let v0 = t0.to_tensor64();
+            let v1 = t1.to_tensor64();
+            let ip = t0.inner_product(&t1);
+            assert!((ip.complex_value() - overlap(&v0, &v1)).norm() < 1e-8);
+            assert!((t0.inner_product(&t0) - FScalar::one()).is_zero());
+            if !ip.is_zero() {
+                nonzero += 1;
+            }
+
+            let mut sum = StabSum::new();
+            sum.add_term(FScalar::sqrt2(), t0);
+            sum.add_term(FScalar::from_phase(Rational64::new(1, 4)), t1.clone());
+            let mut sum1 = StabSum::new();
+            sum1.add_term(FScalar::one(), t1);
+            let v = sum.to_tensor64();
+            assert!((sum.inner_product(&sum).complex_value() - overlap(&v, &v)).norm() < 1e-8);
+            assert!((sum.inner_product(&sum1).complex_value() - overlap(&v, &v1)).norm() < 1e-8);
+        }
+        assert!(nonzero > 10);
+    }
+
+    #[test]
+    fn orthogonal() {
+        let pauli = |r: u8, z: bool| Pauli {
+            r,
+            x: vec![!z],
+            z: vec![z],
+        };
+        let zero = Tableau::new(vec![pauli(0, true)]);
+        let one = Tableau::new(vec![pauli(2, true)]);
+        let minus = Tableau::new(vec![pauli(2, false)]);
+        assert!(zero.inner_product(&one).is_zero());
+        assert_eq!(
+            minus.inner_product(&one),
+            FScalar::sqrt2_pow(-1) * FScalar::from_phase(1)
+        );
+    }
+
+    #[test]
+    fn tableau_from_stabilizers() {
+        let pauli = |r: u8, x: [bool; 2], z: [bool; 2]| Pauli {
+            r,
+            x: x.to_vec(),
+            z: z.to_vec(),
+        };
+        // the Bell state (|00> + |11>)/sqrt(2)
+        let t = Tableau::new(vec![
+            pauli(0, [true, true], [false, false]),
+            pauli(0, [false, false], [true, true]),
+        ]);
+        let mut c = Circuit::new(2);
+        c.add_gate("h", vec![0]);
+        c.add_gate("cx", vec![0, 1]);
+        assert_eq!(Tableau::from_graph(&state_graph(&c)), Some(t.clone()));
+
+        // -YY = XX * ZZ
+        let t1 = Tableau::new(vec![
+            pauli(0, [true, true], [false, false]),
+            pauli(0, [true, true], [true, true]),
+        ]);
+        assert_eq!(t, t1);
+        // -ZZ and -XY stabilise |01> + i|10>, and the canonical state has a positive
+        // amplitude on |10>
+        let t2 = Tableau::new(vec![
+            pauli(2, [false, false], [true, true]),
+            pauli(3, [true, true], [false, true]),
+        ]);
+        assert_eq!(t2.support_basis_vector(), vec![true, false]);
+        let g: Graph = t2.to_graph();
+        assert_eq!(Tableau::from_graph(&g), Some(t2.clone()));
+        let s = 0.5f64.sqrt();
+        let psi = Tensor64::from_shape_vec(
+            vec![2, 2],
+            vec![0.0.into(), Complex::new(0.0, -s), s.into(), 0.0.into()],
+        )
+        .unwrap();
+        assert_close(&g.to_tensor64(), &psi);
+    }
+
+    #[test]
+    #[should_panic(expected = "does not commute")]
+    fn tableau_anticommuting() {
+        let mut x = Pauli::id(2);
+        x.x[0] = true;
+        let mut z = Pauli::id(2);
+        z.z[0] = true;
+        Tableau::new(vec![x, z]);
+    }
+
+    #[test]
+    fn zero_state() {
+        let mut g = Graph::new();
+        let x = g.add_vertex_with_phase(VType::X, 1);
+        let z = g.add_vertex(VType::Z);
+        let o = g.add_vertex(VType::B);
+        g.add_edge(x, z);
+        g.add_edge(z, o);
+        g.set_outputs(vec![o]);
+        assert!(Tableau::from_graph(&g).is_some());
+
+        // <+| applied to |->
+        let mut g = Graph::new();
+        let x = g.add_vertex_with_phase(VType::X, 1);
+        let z = g.add_vertex(VType::Z);
+        g.add_edge_with_type(x, z, EType::H);
+        assert!(Tableau::from_graph(&g).is_none());
+    }
+
+    #[test]
+    fn merge_terms() {
+        let mut c = Circuit::new(2);
+        c.add_gate("h", vec![0]);
+        c.add_gate("cx", vec![0, 1]);
+        let g = state_graph(&c);
+
+        let sum = StabSum::from_graphs([&g, &g]);
+        assert_eq!(sum.len(), 1);
+        assert_close(
+            &sum.to_tensor64(),
+            &(g.to_tensor64() * Complex::new(2.0, 0.0)),
+        );
+    }
+
+    #[test]
+    fn json_output() {
+        // |+i> is stabilised by Y = i X Z
+        let y = Pauli {
+            r: 1,
+            x: vec![true],
+            z: vec![true],
+        };
+        let mut sum = StabSum::new();
+        sum.add_term(FScalar::one(), Tableau::new(vec![y]));
+        let mut out = vec![];
+        sum.write_json(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            r#"{"num_qubits":1,"terms":[{"coeff":[1.0,0.0],"tableau":["11"],"phases":[1]}]}"#
+        );
+
+        // the tableaux can be read back as stabiliser generators
+        let mut c = Circuit::new(3);
+        c.add_gate("h", vec![0]);
+        c.add_gate("cx", vec![0, 1]);
+        c.add_gate("s", vec![1]);
+        c.add_gate("cx", vec![1, 2]);
+        let sum = StabSum::from_graphs([&state_graph(&c)]);
+        let mut out = vec![];
+        sum.write_json(&mut out).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(json["num_qubits"], 3);
+        let term = &json["terms"][0];
+        let rows = term["tableau"].as_array().unwrap();
+        let phases = term["phases"].as_array().unwrap();
+        let paulis = rows
+            .iter()
+            .zip(phases)
+            .map(|(row, r)| {
+                let bits: Vec<bool> = row.as_str().unwrap().chars().map(|b| b == '1').collect();
+                Pauli {
+                    r: r.as_u64().unwrap() as u8,
+                    x: bits[..3].to_vec(),
+                    z: bits[3..].to_vec(),
+                }
+            })
+            .collect();
+        assert_eq!(Tableau::new(paulis), sum.terms[0].1);
+        let coeff = sum.terms[0].0.complex_value();
+        assert_eq!(term["coeff"][0].as_f64().unwrap(), coeff.re);
+        assert_eq!(term["coeff"][1].as_f64().unwrap(), coeff.im);
+    }
+}
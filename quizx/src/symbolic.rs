@@ -0,0 +1,271 @@
+// QuiZX - Rust library for quantum circuit rewriting and optimisation
+//         using the ZX-calculus
+// Copyright (C) 2021 - Aleks Kissinger
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Symbolic phases, for circuits and graphs whose angles are only known later
+//!
+//! A [`SymPhase`] is a linear combination of named real parameters, such as the angles
+//! of a variational circuit. It is kept next to the numeric phase of a gate or spider,
+//! so the full phase is the numeric phase plus the symbolic one. Parameters are
+//! measured in half turns, like [`Phase`].
+//!
+//! Spider fusion and gadget fusion add symbolic phases, and generalised pivots move
+//! them into phase gadgets. Rules that depend on the value of a phase, e.g. removing
+//! Pauli or Clifford spiders, never apply to symbolic spiders. Symbols are instantiated
+//! with [`crate::graph::GraphLike::substitute`].
+
+use crate::phase::Phase;
+use num::{Rational64, Zero};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::ops::{Add, AddAssign, Neg};
+
+/// A linear combination of named parameters, in half turns
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct SymPhase(BTreeMap<String, Rational64>);
+
+impl SymPhase {
+    /// The phase given by a single parameter
+    pub fn param(name: impl Into<String>) -> Self {
+        SymPhase::term(name, 1)
+    }
+
+    /// The phase given by a multiple of a single parameter
+    pub fn term(name: impl Into<String>, coeff: impl Into<Rational64>) -> Self {
+        let mut s = SymPhase::default();
+        s.add_term(name.into(), coeff.into());
+        s
+    }
+
+    /// Returns true if the phase has no parameters, i.e. it is zero
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The parameters and their coefficients, sorted by name
+    pub fn terms(&self) -> impl Iterator<Item = (&str, Rational64)> {
+        self.0.iter().map(|(k, &c)| (k.as_str(), c))
+    }
+
+    /// The coefficient of a parameter, which is zero if it does not occur
+    pub fn coeff(&self, name: &str) -> Rational64 {
+        self.0.get(name).copied().unwrap_or_default()
+    }
+
+    fn add_term(&mut self, name: String, coeff: Rational64) {
+        let c = self.0.entry(name).or_default();
+        *c += coeff;
+        if c.is_zero() {
+            self.0.retain(|_, c| !c.is_zero());
+        }
+    }
+
+    /// Substitutes values for some of the parameters
+    ///
+    /// Returns the sum of the substituted terms, which should be added to the numeric
+    /// phase, and the remaining symbolic phase.
+    pub fn substitute(&self, params: &HashMap<String, f64>) -> (f64, SymPhase) {
+        let mut value = 0.0;
+        let mut rest = SymPhase::default();
+        for (name, &c) in &self.0 {
+            if let Some(x) = params.get(name) {
+                value += (*c.numer() as f64 / *c.denom() as f64) * x;
+            } else {
+                rest.0.insert(name.clone(), c);
+            }
+        }
+        (value, rest)
+    }
+}
+
+impl Add<&SymPhase> for &SymPhase {
+    type Output = SymPhase;
+    fn add(self, rhs: &SymPhase) -> SymPhase {
+        let mut s = self.clone();
+        s += rhs;
+        s
+    }
+}
+
+impl AddAssign<&SymPhase> for SymPhase {
+    fn add_assign(&mut self, rhs: &SymPhase) {
+        for (name, &c) in &rhs.0 {
+            self.add_term(name.clone(), c);
+        }
+    }
+}
+
+impl Neg for SymPhase {
+    type Output = SymPhase;
+    fn neg(mut self) -> SymPhase {
+        for c in self.0.values_mut() {
+            *c = -*c;
+        }
+        self
+    }
+}
+
+impl From<&str> for SymPhase {
+    fn from(name: &str) -> Self {
+        SymPhase::param(name)
+    }
+}
+
+/// Symbolic phases are written as e.g. `θ - 1/2*φ`, and as `0` if there are no
+/// parameters
+impl fmt::Display for SymPhase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "0");
+        }
+        for (i, (name, c)) in self.terms().enumerate() {
+            let (sign, c) = if c < Rational64::zero() {
+                ("-", -c)
+            } else {
+                ("+", c)
+            };
+            if i > 0 {
+                write!(f, " {sign} ")?;
+            } else if sign == "-" {
+                write!(f, "-")?;
+            }
+            if c == Rational64::from(1) {
+                write!(f, "{name}")?;
+            } else {
+                write!(f, "{c}*{name}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Formats a numeric phase plus a symbolic one, as used by [`crate::gate::Gate::to_qasm`]
+pub(crate) fn fmt_phase(phase: Phase, sym: &SymPhase) -> String {
+    if sym.is_empty() {
+        format!("{}*pi", phase.to_f64())
+    } else if phase.is_zero() {
+        format!("({sym})*pi")
+    } else {
+        format!("({} + {sym})*pi", phase.to_f64())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::circuit::Circuit;
+    use crate::decompose::Decomposer;
+    use crate::extract::ToCircuit;
+    use crate::gate::{GType::*, Gate};
+    use crate::graph::*;
+    use crate::simplify::{full_simp, spider_simp};
+    use crate::tensor::{CompareTensors, TensorF};
+    use crate::vec_graph::Graph;
+
+    fn ansatz() -> Circuit {
+        let mut c = Circuit::new(3);
+        for q in 0..3 {
+            c.push(Gate::new(HAD, vec![q]));
+        }
+        c.push(Gate::new(CNOT, vec![0, 1]));
+        c.push(Gate::new_symbolic(ZPhase, vec![1], 0, "a"));
+        c.push(Gate::new(CNOT, vec![0, 1]));
+        c.push(Gate::new(CNOT, vec![1, 2]));
+        c.push(Gate::new_symbolic(ZPhase, vec![2], 0, "b"));
+        c.push(Gate::new_symbolic(
+            ZPhase,
+            vec![2],
+            Rational64::new(1, 4),
+            "b",
+        ));
+        c.push(Gate::new(CNOT, vec![1, 2]));
+        c.push(Gate::new_symbolic(
+            XPhase,
+            vec![0],
+            0,
+            SymPhase::term("a", Rational64::new(-1, 2)),
+        ));
+        c.push(Gate::new(T, vec![1]));
+        c
+    }
+
+    /// The ansatz with the parameters replaced by their values
+    fn numeric(c: &Circuit, params: &HashMap<String, f64>) -> Circuit {
+        let mut c = c.clone();
+        for g in c.gates.iter_mut() {
+            let (value, _) = g.sym.substitute(params);
+            g.phase += crate::graph::approx_phase(value);
+            g.sym = SymPhase::default();
+        }
+        c
+    }
+
+    #[test]
+    fn sym_to_graph() {
+        let c = ansatz();
+        let mut g: Graph = c.to_graph();
+        spider_simp(&mut g);
+        assert!(g
+            .vertices()
+            .any(|v| g.sym_phase(v) == SymPhase::term("b", 2)));
+
+        let params = HashMap::from([("a".to_string(), 0.5), ("b".to_string(), -0.25)]);
+        full_simp(&mut g);
+        assert!(g.has_symbols());
+        assert_eq!(g.tcount(), 4);
+
+        let mut h = g.clone();
+        h.substitute(&params);
+        assert!(!h.has_symbols());
+        assert!(TensorF::scalar_compare(&h, &numeric(&c, &params)));
+    }
+
+    #[test]
+    fn sym_extract() {
+        let c = ansatz();
+        let mut g: Graph = c.to_graph();
+        full_simp(&mut g);
+        let c1 = g.to_circuit().unwrap();
+        assert!(c1.gates.iter().any(|g| !g.sym.is_empty()));
+
+        let params = HashMap::from([("a".to_string(), -0.5), ("b".to_string(), 0.75)]);
+        let mut h: Graph = c1.to_graph();
+        h.substitute(&params);
+        assert!(TensorF::scalar_compare(&h, &numeric(&c, &params)));
+    }
+
+    #[test]
+    #[should_panic(expected = "Symbolic phases must be substituted")]
+    fn sym_decompose() {
+        let g: Graph = ansatz().to_graph();
+        Decomposer::new(&g);
+    }
+
+    #[test]
+    fn sym_arithmetic() {
+        let a = SymPhase::param("a");
+        let b = SymPhase::term("b", Rational64::new(-1, 2));
+        let s = &a + &b;
+        assert_eq!(s.to_string(), "a - 1/2*b");
+        assert_eq!((-s.clone()).to_string(), "-a + 1/2*b");
+        assert_eq!(s.coeff("b"), Rational64::new(-1, 2));
+        assert!((&s + &(-s.clone())).is_empty());
+
+        let params = HashMap::from([("b".to_string(), 0.5)]);
+        let (value, rest) = s.substitute(&params);
+        assert_eq!(value, -0.25);
+        assert_eq!(rest, a);
+    }
+}
@@ -0,0 +1,237 @@
+// QuiZX - Rust library for quantum circuit rewriting and optimisation
+//         using the ZX-calculus
+// Copyright (C) 2021 - Aleks Kissinger
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Qubit permutation symmetries of the state prepared by a circuit
+//!
+//! A permutation of the qubits is a symmetry if permuting the outputs of the circuit,
+//! applied to |0...0>, gives the same state, so amplitudes of bit strings related by
+//! the permutation are equal. Symmetries are found by simplifying the state once and
+//! checking which permutations of its outputs are automorphisms of the simplified
+//! graph, see [`crate::canonical`]. This is sound, but may miss symmetries that only
+//! hold after further rewriting.
+
+use crate::canonical::canonical_form;
+use crate::circuit::Circuit;
+use crate::decompose::Decomposer;
+use crate::fscalar::FScalar;
+use crate::graph::*;
+use crate::simplify::full_simp;
+use crate::vec_graph::Graph;
+use rustc_hash::FxHashMap;
+
+/// A permutation of qubits, sending qubit `i` to `p[i]`
+pub type Permutation = Vec<usize>;
+
+/// Applies a permutation to a bit string, moving the bit of qubit `i` to `p[i]`
+pub fn permute_bits(p: &Permutation, bits: &[bool]) -> Vec<bool> {
+    let mut out = vec![false; bits.len()];
+    for (i, &b) in bits.iter().enumerate() {
+        out[p[i]] = b;
+    }
+    out
+}
+
+/// The smallest bit string in the orbit of `bits`
+///
+/// `symmetries` should be closed under composition, as returned by
+/// [`detect_qubit_symmetry`].
+pub fn orbit_representative(bits: &[bool], symmetries: &[Permutation]) -> Vec<bool> {
+    symmetries
+        .iter()
+        .map(|p| permute_bits(p, bits))
+        .fold(bits.to_vec(), |m, b| m.min(b))
+}
+
+/// The state prepared by the circuit from |0...0>, simplified
+fn state_graph(c: &Circuit) -> Graph {
+    let mut g: Graph = c.to_graph();
+    g.plug_inputs(&vec![BasisElem::Z0; g.inputs().len()]);
+    full_simp(&mut g);
+    g
+}
+
+/// Returns the qubit permutations, other than the identity, that leave the state
+/// prepared by the circuit invariant
+///
+/// The permutations act on the outputs of the circuit, and together with the identity
+/// they form a group. Candidates are pruned by the data of the vertex next to each
+/// output, but this is exponential in the worst case, e.g. n! for a fully symmetric
+/// state on n qubits.
+pub fn detect_qubit_symmetry(c: &Circuit) -> Vec<Permutation> {
+    symmetries_of(&state_graph(c))
+}
+
+fn symmetries_of(g: &Graph) -> Vec<Permutation> {
+    let outs = g.outputs().to_vec();
+    let key = |o: V| {
+        g.incident_edges(o).next().map(|(w, et)| {
+            let d = g.vertex_data(w);
+            (et, d.ty, d.phase.to_rational(), g.degree(w))
+        })
+    };
+    let keys: Vec<_> = outs.iter().map(|&o| key(o)).collect();
+    let cf = canonical_form(g);
+
+    let mut found = vec![];
+    let mut perm: Permutation = vec![];
+    let mut used = vec![false; outs.len()];
+    let mut h = g.clone();
+    search(&keys, &mut perm, &mut used, &mut |p| {
+        if p.iter().enumerate().any(|(i, &j)| i != j) {
+            for (i, &j) in p.iter().enumerate() {
+                h.outputs_mut()[j] = outs[i];
+            }
+            if canonical_form(&h) == cf {
+                found.push(p.clone());
+            }
+        }
+    });
+    found
+}
+
+/// Calls `f` on every permutation sending each position to one with the same key
+fn search<K: PartialEq>(
+    keys: &[K],
+    perm: &mut Permutation,
+    used: &mut [bool],
+    f: &mut impl FnMut(&Permutation),
+) {
+    let i = perm.len();
+    if i == keys.len() {
+        f(perm);
+        return;
+    }
+    for j in 0..keys.len() {
+        if !used[j] && keys[i] == keys[j] {
+            used[j] = true;
+            perm.push(j);
+            search(keys, perm, used, f);
+            perm.pop();
+            used[j] = false;
+        }
+    }
+}
+
+/// Computes amplitudes of the state prepared by a circuit, computing only one
+/// amplitude for each orbit of the qubit symmetries
+pub struct SymmetricAmplitudes {
+    g: Graph,
+    symmetries: Vec<Permutation>,
+    cache: FxHashMap<Vec<bool>, FScalar>,
+}
+
+impl SymmetricAmplitudes {
+    pub fn new(c: &Circuit) -> Self {
+        let g = state_graph(c);
+        let symmetries = symmetries_of(&g);
+        SymmetricAmplitudes {
+            g,
+            symmetries,
+            cache: FxHashMap::default(),
+        }
+    }
+
+    /// The symmetries found by [`detect_qubit_symmetry`]
+    pub fn symmetries(&self) -> &[Permutation] {
+        &self.symmetries
+    }
+
+    /// The number of amplitudes that were actually computed
+    pub fn num_computed(&self) -> usize {
+        self.cache.len()
+    }
+
+    /// Computes the amplitude `<bits|C|0...0>`, or looks it up if a symmetric
+    /// amplitude was already computed
+    ///
+    /// Panics unless there is one bit per output.
+    pub fn amplitude(&mut self, bits: &[bool]) -> FScalar {
+        if bits.len() != self.g.outputs().len() {
+            panic!(
+                "Expected {} bits, got {}",
+                self.g.outputs().len(),
+                bits.len()
+            );
+        }
+        let rep = orbit_representative(bits, &self.symmetries);
+        let g = &self.g;
+        *self.cache.entry(rep).or_insert_with_key(|rep| {
+            let mut h = g.clone();
+            let plug: Vec<BasisElem> = rep
+                .iter()
+                .map(|&b| if b { BasisElem::Z1 } else { BasisElem::Z0 })
+                .collect();
+            h.plug_outputs(&plug);
+            let mut d = Decomposer::new(&h);
+            d.with_full_simp().decompose();
+            d.scalar()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scalar_traits::Sqrt2;
+    use num::Zero;
+
+    fn ghz(n: usize) -> Circuit {
+        let mut c = Circuit::new(n);
+        c.add_gate("h", vec![0]);
+        for q in 1..n {
+            c.add_gate("cx", vec![q - 1, q]);
+        }
+        c
+    }
+
+    #[test]
+    fn ghz_symmetry() {
+        let syms = detect_qubit_symmetry(&ghz(4));
+        assert_eq!(syms.len(), 23);
+        assert!(syms.contains(&vec![1, 2, 3, 0]));
+
+        let mut a = SymmetricAmplitudes::new(&ghz(4));
+        let s = FScalar::sqrt2_pow(-1);
+        for (bits, amp) in [
+            ([false, false, true, true], FScalar::zero()),
+            ([true, false, true, false], FScalar::zero()),
+            ([false, false, false, false], s),
+            ([true, true, true, true], s),
+            ([true, true, false, false], FScalar::zero()),
+        ] {
+            assert_eq!(a.amplitude(&bits), amp, "{bits:?}");
+        }
+        assert_eq!(a.num_computed(), 3);
+    }
+
+    #[test]
+    fn partial_symmetry() {
+        // only the first two qubits are interchangeable
+        let mut c = Circuit::new(4);
+        c.add_gate("h", vec![0]);
+        c.add_gate("cx", vec![0, 1]);
+        c.add_gate("t", vec![1]);
+        c.add_gate("cz", vec![0, 2]);
+        c.add_gate("x", vec![2]);
+        c.add_gate("h", vec![3]);
+        assert_eq!(detect_qubit_symmetry(&c), vec![vec![1, 0, 2, 3]]);
+
+        let mut c = ghz(3);
+        c.add_gate("s", vec![0]);
+        assert_eq!(detect_qubit_symmetry(&c).len(), 5);
+        assert!(detect_qubit_symmetry(&Circuit::new(1)).is_empty());
+    }
+}
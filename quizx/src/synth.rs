@@ -0,0 +1,546 @@
+// QuiZX - Rust library for quantum circuit rewriting and optimisation
+//         using the ZX-calculus
+// Copyright (C) 2021 - Aleks Kissinger
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Approximating single-qubit rotations with Clifford+T circuits
+//!
+//! This uses the Solovay-Kitaev algorithm, in the version of Dawson and Nielsen
+//! (arXiv:quant-ph/0505030), starting from a net of all Clifford+T operators up to a
+//! fixed T-count. This gives T-counts that grow polylogarithmically in the
+//! precision, but are a good deal larger than the optimal ones, e.g. found by the
+//! number-theoretic algorithm of Ross and Selinger.
+//!
+//! Errors are measured in the operator norm, up to global phase, so a circuit
+//! approximating `rz(θ)` to precision `ε` changes any amplitude by at most `ε` in
+//! absolute value, after fixing the global phase.
+
+use crate::circuit::Circuit;
+use crate::gate::{GType::*, Gate};
+use crate::phase::Phase;
+use num::Zero;
+use rayon::prelude::*;
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::f64::consts::{FRAC_1_SQRT_2, PI};
+use std::sync::OnceLock;
+
+/// T-count of the largest operators in the net, which gives about 9000 operators.
+/// Products of two of them are used as the initial approximations.
+const NET_TCOUNT: usize = 7;
+
+/// The maximum recursion depth of Solovay-Kitaev
+const MAX_DEPTH: usize = 8;
+
+/// A unit quaternion `w + xi + yj + zk`, standing for the operator
+/// `w I - i(x X + y Y + z Z)`, which is a unitary up to global phase
+#[derive(Clone, Copy, Debug)]
+struct Quat([f64; 4]);
+
+impl Quat {
+    const ID: Quat = Quat([1.0, 0.0, 0.0, 0.0]);
+
+    /// Rotation by `angle` about the given unit vector
+    fn rotation(angle: f64, axis: [f64; 3]) -> Quat {
+        let (s, c) = (angle / 2.0).sin_cos();
+        Quat([c, s * axis[0], s * axis[1], s * axis[2]])
+    }
+
+    fn mul(&self, other: &Quat) -> Quat {
+        let [a1, b1, c1, d1] = self.0;
+        let [a2, b2, c2, d2] = other.0;
+        Quat([
+            a1 * a2 - b1 * b2 - c1 * c2 - d1 * d2,
+            a1 * b2 + b1 * a2 + c1 * d2 - d1 * c2,
+            a1 * c2 - b1 * d2 + c1 * a2 + d1 * b2,
+            a1 * d2 + b1 * c2 - c1 * b2 + d1 * a2,
+        ])
+    }
+
+    fn inv(&self) -> Quat {
+        let [a, b, c, d] = self.0;
+        Quat([a, -b, -c, -d])
+    }
+
+    /// |tr(U^dag V)| / 2 for the corresponding operators
+    fn overlap(&self, other: &Quat) -> f64 {
+        self.0
+            .iter()
+            .zip(other.0.iter())
+            .map(|(a, b)| a * b)
+            .sum::<f64>()
+            .abs()
+    }
+
+    /// Operator norm distance, minimised over global phases
+    fn dist(&self, other: &Quat) -> f64 {
+        (2.0 - 2.0 * self.overlap(other).min(1.0)).sqrt()
+    }
+
+    /// Rotation angle in [0, pi] and axis, with the sign chosen so that w >= 0
+    fn angle_axis(&self) -> (f64, [f64; 3]) {
+        let q = if self.0[0] < 0.0 {
+            Quat(self.0.map(|a| -a))
+        } else {
+            *self
+        };
+        let s = (q.0[1] * q.0[1] + q.0[2] * q.0[2] + q.0[3] * q.0[3]).sqrt();
+        let angle = 2.0 * s.atan2(q.0[0]);
+        if s < 1e-15 {
+            (angle, [0.0, 0.0, 1.0])
+        } else {
+            (angle, [q.0[1] / s, q.0[2] / s, q.0[3] / s])
+        }
+    }
+
+    /// Key for deduplicating operators, equal for q and -q
+    fn key(&self) -> [i64; 4] {
+        let sign = self
+            .0
+            .iter()
+            .find(|a| a.abs() > 1e-9)
+            .map_or(1.0, |a| a.signum());
+        self.0.map(|a| (sign * a * 1e8).round() as i64)
+    }
+}
+
+/// A gate in a Clifford+T word, either a Hadamard or a Z rotation by k π/4
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Letter {
+    H,
+    Z(u8),
+}
+
+impl Letter {
+    fn quat(&self) -> Quat {
+        match self {
+            Letter::H => Quat([0.0, FRAC_1_SQRT_2, 0.0, FRAC_1_SQRT_2]),
+            Letter::Z(k) => Quat::rotation(*k as f64 * PI / 4.0, [0.0, 0.0, 1.0]),
+        }
+    }
+
+    fn inv(&self) -> Letter {
+        match self {
+            Letter::H => Letter::H,
+            Letter::Z(k) => Letter::Z((8 - k) % 8),
+        }
+    }
+}
+
+/// An operator given by a word, with the letters in circuit order
+#[derive(Clone, Debug)]
+struct Word {
+    letters: Vec<Letter>,
+    q: Quat,
+}
+
+impl Word {
+    fn id() -> Word {
+        Word {
+            letters: vec![],
+            q: Quat::ID,
+        }
+    }
+
+    /// `other` followed by `self`
+    fn after(&self, other: &Word) -> Word {
+        Word {
+            letters: other.letters.iter().chain(&self.letters).copied().collect(),
+            q: self.q.mul(&other.q),
+        }
+    }
+
+    fn inv(&self) -> Word {
+        Word {
+            letters: self.letters.iter().rev().map(|l| l.inv()).collect(),
+            q: self.q.inv(),
+        }
+    }
+
+    fn push(&mut self, l: Letter) {
+        self.letters.push(l);
+        self.q = l.quat().mul(&self.q);
+    }
+}
+
+/// All Clifford+T operators up to global phase with T-count at most [NET_TCOUNT],
+/// together with a grid for finding the nearest one to a given operator
+struct Net {
+    words: Vec<Word>,
+    grid: FxHashMap<[i32; 4], Vec<usize>>,
+}
+
+/// Side length of the grid cells. This is more than twice the distance (as vectors)
+/// from a unit quaternion to the nearest one in the net, so the nearest one is in
+/// one of the 16 cells around it.
+const CELL: f64 = 0.25;
+
+fn cell(q: &Quat) -> [i32; 4] {
+    q.0.map(|a| (a / CELL).floor() as i32)
+}
+
+impl Net {
+    fn new() -> Net {
+        let mut seen = FxHashSet::default();
+        // the 24 single-qubit Cliffords, by breadth-first search over H and S
+        let mut cliffords = vec![Word::id()];
+        seen.insert(Quat::ID.key());
+        let mut i = 0;
+        while i < cliffords.len() {
+            for l in [Letter::H, Letter::Z(2)] {
+                let mut w = cliffords[i].clone();
+                w.push(l);
+                if seen.insert(w.q.key()) {
+                    cliffords.push(w);
+                }
+            }
+            i += 1;
+        }
+
+        // every operator is a Clifford, or one with a smaller T-count followed by T
+        // and a Clifford
+        let mut words = cliffords.clone();
+        let mut layer = cliffords.clone();
+        for _ in 0..NET_TCOUNT {
+            let mut next = vec![];
+            for w in &layer {
+                let mut wt = w.clone();
+                wt.push(Letter::Z(1));
+                for c in &cliffords {
+                    let w1 = c.after(&wt);
+                    if seen.insert(w1.q.key()) {
+                        next.push(w1);
+                    }
+                }
+            }
+            words.extend(next.iter().cloned());
+            layer = next;
+        }
+
+        // q and -q give the same operator, so both go in the grid
+        let mut grid: FxHashMap<[i32; 4], Vec<usize>> = FxHashMap::default();
+        for (i, w) in words.iter().enumerate() {
+            grid.entry(cell(&w.q)).or_default().push(i);
+            grid.entry(cell(&Quat(w.q.0.map(|a| -a))))
+                .or_default()
+                .push(i);
+        }
+        Net { words, grid }
+    }
+
+    fn get() -> &'static Net {
+        static NET: OnceLock<Net> = OnceLock::new();
+        NET.get_or_init(Net::new)
+    }
+
+    /// The index of the operator in the net nearest to `q`
+    fn nearest(&self, q: &Quat) -> usize {
+        let lo = cell(&Quat(q.0.map(|a| a - CELL / 2.0)));
+        let hi = cell(&Quat(q.0.map(|a| a + CELL / 2.0)));
+        let candidates = (0..16).flat_map(|d| {
+            let key = [0, 1, 2, 3].map(|i| if d & (1 << i) == 0 { lo[i] } else { hi[i] });
+            // skip repeated cells, where lo and hi agree
+            let repeated = (0..4).any(|i| d & (1 << i) != 0 && lo[i] == hi[i]);
+            self.grid
+                .get(&key)
+                .filter(|_| !repeated)
+                .into_iter()
+                .flatten()
+        });
+        let best = candidates.max_by(|&&i, &&j| {
+            let oi = self.words[i].q.overlap(q);
+            let oj = self.words[j].q.overlap(q);
+            oi.total_cmp(&oj)
+        });
+        *best.expect("The net should have a point near every operator")
+    }
+
+    /// The best approximation of `q` by a product of two operators in the net
+    fn approximate(&self, q: &Quat) -> Word {
+        let (a, b, _) = (0..self.words.len())
+            .into_par_iter()
+            .map(|a| {
+                let b = self.nearest(&self.words[a].q.inv().mul(q));
+                (a, b, self.words[a].q.mul(&self.words[b].q).overlap(q))
+            })
+            .max_by(|x, y| x.2.total_cmp(&y.2))
+            .unwrap();
+        // a applied after b
+        self.words[a].after(&self.words[b])
+    }
+}
+
+/// Writes `q` as a group commutator `v w v^-1 w^-1` of two rotations by the same,
+/// smaller angle, see Dawson and Nielsen, section 4.1
+fn group_commutator(q: &Quat) -> (Quat, Quat) {
+    let (theta, n) = q.angle_axis();
+    let c = (theta / 2.0).cos();
+    let phi = 2.0 * ((1.0 - c) / 2.0).powf(0.25).asin();
+    let v = Quat::rotation(phi, [1.0, 0.0, 0.0]);
+    let w = Quat::rotation(phi, [0.0, 1.0, 0.0]);
+    let comm = v.mul(&w).mul(&v.inv()).mul(&w.inv());
+
+    // conjugate by a rotation taking the axis of the commutator to the axis of q
+    let (_, m) = comm.angle_axis();
+    let cross = [
+        m[1] * n[2] - m[2] * n[1],
+        m[2] * n[0] - m[0] * n[2],
+        m[0] * n[1] - m[1] * n[0],
+    ];
+    let dot: f64 = (0..3).map(|i| m[i] * n[i]).sum();
+    let norm = cross.iter().map(|a| a * a).sum::<f64>().sqrt();
+    let s = if norm < 1e-12 {
+        if dot > 0.0 {
+            Quat::ID
+        } else {
+            // any rotation by pi about an axis orthogonal to m
+            let a = if m[0].abs() < 0.9 {
+                [0.0, -m[2], m[1]]
+            } else {
+                [m[2], 0.0, -m[0]]
+            };
+            let an = a.iter().map(|x| x * x).sum::<f64>().sqrt();
+            Quat::rotation(PI, a.map(|x| x / an))
+        }
+    } else {
+        Quat::rotation(norm.atan2(dot), cross.map(|a| a / norm))
+    };
+    (s.mul(&v).mul(&s.inv()), s.mul(&w).mul(&s.inv()))
+}
+
+fn solovay_kitaev(q: &Quat, depth: usize) -> Word {
+    let u = Net::get().approximate(q);
+    (1..=depth).fold(u, |u, d| refine(q, &u, d))
+}
+
+/// Improves an approximation `u` of `q`, found by [solovay_kitaev] with depth
+/// `depth - 1`, to one with depth `depth`
+fn refine(q: &Quat, u: &Word, depth: usize) -> Word {
+    let (v, w) = group_commutator(&q.mul(&u.q.inv()));
+    let v = solovay_kitaev(&v, depth - 1);
+    let w = solovay_kitaev(&w, depth - 1);
+    // v w v^-1 w^-1 u, in circuit order
+    let uw = w.inv().after(u);
+    v.after(&w.after(&v.inv().after(&uw)))
+}
+
+/// Cancels adjacent Hadamards and merges adjacent Z rotations
+fn simplify(letters: &[Letter]) -> Vec<Letter> {
+    let mut out: Vec<Letter> = vec![];
+    for &l in letters {
+        match (out.last(), l) {
+            (_, Letter::Z(0)) => {}
+            (Some(Letter::H), Letter::H) => {
+                out.pop();
+            }
+            (Some(&Letter::Z(k0)), Letter::Z(k1)) => {
+                out.pop();
+                if (k0 + k1) % 8 != 0 {
+                    out.push(Letter::Z((k0 + k1) % 8));
+                }
+            }
+            _ => out.push(l),
+        }
+    }
+    out
+}
+
+fn push_letters(c: &mut Circuit, qubit: usize, letters: &[Letter]) {
+    for &l in letters {
+        let gates: &[_] = match l {
+            Letter::H => &[HAD],
+            Letter::Z(1) => &[T],
+            Letter::Z(2) => &[S],
+            Letter::Z(3) => &[S, T],
+            Letter::Z(4) => &[Z],
+            Letter::Z(5) => &[Z, T],
+            Letter::Z(6) => &[Sdg],
+            Letter::Z(7) => &[Tdg],
+            Letter::Z(_) => &[],
+        };
+        for &t in gates {
+            c.push(Gate::new(t, vec![qubit]));
+        }
+    }
+}
+
+/// Returns a Clifford+T circuit on one qubit that is within `epsilon` of `rz(phase)`,
+/// up to global phase
+///
+/// Phases that are multiples of π/4 are synthesised exactly. Panics if `epsilon` is
+/// not positive, or too small to be reached, which can happen below about 1e-10
+/// because of rounding errors.
+pub fn rz_clifford_t(phase: impl Into<Phase>, epsilon: f64) -> Circuit {
+    let mut c = Circuit::new(1);
+    push_rz(&mut c, 0, phase.into(), epsilon);
+    c
+}
+
+fn push_rz(c: &mut Circuit, qubit: usize, phase: Phase, epsilon: f64) {
+    if epsilon <= 0.0 {
+        panic!("Precision must be positive, got {epsilon}");
+    }
+    let r = phase.to_rational() * 4;
+    if r.is_integer() {
+        let k = r.to_integer().rem_euclid(8) as u8;
+        push_letters(c, qubit, &[Letter::Z(k)]);
+        return;
+    }
+
+    let q = Quat::rotation(phase.to_f64() * PI, [0.0, 0.0, 1.0]);
+    let mut w = Net::get().approximate(&q);
+    let mut depth = 0;
+    while w.q.dist(&q) > epsilon {
+        if depth == MAX_DEPTH {
+            panic!("Could not approximate rz({phase}) to precision {epsilon}");
+        }
+        depth += 1;
+        w = refine(&q, &w, depth);
+    }
+    push_letters(c, qubit, &simplify(&w.letters));
+}
+
+impl Circuit {
+    /// Replaces every Z and X rotation that is not a multiple of π/4 with a Clifford+T
+    /// approximation, and returns the new circuit together with the number of T gates
+    /// added, see [`rz_clifford_t`]
+    ///
+    /// The circuit is first decomposed into basic gates, see
+    /// [`Circuit::to_basic_gates`]. Each rotation is approximated to within `epsilon`,
+    /// so the whole circuit is within `epsilon` times the number of rotations of the
+    /// original, up to global phase. Gates with variables or symbolic phases are left
+    /// as they are.
+    pub fn approximate_to_clifford_t(&self, epsilon: f64) -> (Circuit, usize) {
+        let mut c = Circuit::new(self.num_qubits());
+        let mut tcount = 0;
+        for g in self.to_basic_gates().gates {
+            let exact = g.phase.to_rational() * 4;
+            if !matches!(g.t, ZPhase | XPhase)
+                || exact.is_integer()
+                || !g.vars.is_zero()
+                || !g.sym.is_empty()
+            {
+                c.push(g);
+                continue;
+            }
+            let start = c.gates.len();
+            let q = g.qs[0];
+            if g.t == XPhase {
+                c.push(Gate::new(HAD, vec![q]));
+            }
+            push_rz(&mut c, q, g.phase, epsilon);
+            if g.t == XPhase {
+                c.push(Gate::new(HAD, vec![q]));
+            }
+            tcount += c
+                .gates
+                .iter()
+                .skip(start)
+                .filter(|g| matches!(g.t, T | Tdg))
+                .count();
+        }
+        (c, tcount)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tensor::ToTensor;
+    use num::Rational64;
+
+    /// The operator norm distance up to global phase between two single-qubit circuits
+    fn circuit_dist(c0: &Circuit, c1: &Circuit) -> f64 {
+        let t0 = c0.to_tensor64();
+        let t1 = c1.to_tensor64();
+        let tr: num::Complex<f64> = [[0, 0], [0, 1], [1, 0], [1, 1]]
+            .iter()
+            .map(|&[i, j]| t0[[i, j]].conj() * t1[[i, j]])
+            .sum();
+        // |tr| for unitaries scaled to determinant 1
+        let det =
+            |t: &crate::tensor::Tensor64| (t[[0, 0]] * t[[1, 1]] - t[[0, 1]] * t[[1, 0]]).norm();
+        let tr = tr.norm() / (det(&t0) * det(&t1)).sqrt();
+        (2.0 - tr.min(2.0)).sqrt()
+    }
+
+    fn rz(phase: Rational64) -> Circuit {
+        let mut c = Circuit::new(1);
+        c.add_gate_with_phase("rz", vec![0], phase);
+        c
+    }
+
+    #[test]
+    fn net_size() {
+        // 24 Cliffords, and 24 * 3 * 2^(t-1) operators with T-count t > 0
+        assert_eq!(Net::get().words.len(), 24 * (3 * (1 << NET_TCOUNT) - 2));
+    }
+
+    #[test]
+    fn exact_phases() {
+        for k in 0..8 {
+            let c = rz_clifford_t(Rational64::new(k, 4), 1e-3);
+            assert!(c.num_gates() <= 2);
+            assert!(circuit_dist(&c, &rz(Rational64::new(k, 4))) < 1e-10);
+        }
+    }
+
+    #[test]
+    fn approximations() {
+        for (phase, eps) in [
+            (Rational64::new(1, 3), 1e-2),
+            (Rational64::new(-2, 7), 1e-3),
+            (Rational64::new(1, 100), 1e-3),
+        ] {
+            let c = rz_clifford_t(phase, eps);
+            assert!(c
+                .gates
+                .iter()
+                .all(|g| matches!(g.t, HAD | T | Tdg | S | Sdg | Z)));
+            let d = circuit_dist(&c, &rz(phase));
+            assert!(d <= eps, "{phase}: {d} > {eps}");
+        }
+    }
+
+    #[test]
+    fn approximate_circuit() {
+        let mut c = Circuit::new(2);
+        c.add_gate_with_phase("rz", vec![0], Rational64::new(1, 3));
+        c.add_gate("cx", vec![0, 1]);
+        c.add_gate_with_phase("rx", vec![1], Rational64::new(1, 5));
+        c.add_gate("t", vec![1]);
+        c.add_gate("cz", vec![1, 0]);
+        c.add_gate_with_phase("rz", vec![1], Rational64::new(1, 2));
+
+        let (c1, tcount) = c.approximate_to_clifford_t(1e-2);
+        assert_eq!(
+            c1.num_gates_of_type(T) + c1.num_gates_of_type(Tdg),
+            tcount + 1
+        );
+        // only the exact rotation by pi/2 is left
+        assert_eq!(c1.num_gates_of_type(ZPhase), 1);
+        assert_eq!(c1.num_gates_of_type(XPhase), 0);
+
+        // two approximated rotations, so the error is at most 2e-2 up to global phase
+        let t = c.to_tensor64();
+        let t1 = c1.to_tensor64();
+        let phase: num::Complex<f64> = t.iter().zip(t1.iter()).map(|(a, b)| a.conj() * b).sum();
+        let phase = phase / phase.norm();
+        let err = t
+            .iter()
+            .zip(t1.iter())
+            .map(|(a, b)| (a * phase - b).norm())
+            .fold(0.0, f64::max);
+        assert!(err <= 2e-2, "{err}");
+    }
+}
@@ -16,6 +16,7 @@
 
 // use crate::scalar::*;
 use crate::circuit::*;
+use crate::decompose::Decomposer;
 use crate::fscalar::*;
 use crate::graph::*;
 use crate::phase::Phase;
@@ -99,6 +100,17 @@ impl<T> TensorElem for T where
 /// and [ToTensor::to_tensorf] for [FScalar] and floating-point [Complex] numbers,
 /// respectively.
 pub trait ToTensor {
+    /// Computes the tensor of a graph or circuit
+    ///
+    /// Graphs may have open inputs and outputs. The tensor has one index of dimension 2
+    /// for each input, followed by one for each output, in the order of
+    /// [`GraphLike::inputs`] and [`GraphLike::outputs`]. A closed graph gives a tensor with
+    /// no indices, whose only entry is at `[]`.
+    ///
+    /// Since ndarray is row-major, reshaping the tensor of a graph with n inputs and m
+    /// outputs into a 2^n × 2^m matrix indexes rows by inputs and columns by outputs,
+    /// with the first qubit being most significant. This is the transpose of the usual
+    /// matrix of the linear map.
     fn to_tensor<A: TensorElem>(&self) -> Tensor<A>;
 
     /// Shorthand for `to_tensor::<FScalar>()`
@@ -122,7 +134,10 @@ pub trait QubitOps<A: TensorElem> {
     fn hadamard_at(&mut self, i: usize);
 
     /// split into two non-overlapping pieces, where index q=0 and q=1
-    fn slice_qubit_mut(&mut self, q: usize) -> (ArrayViewMut<A, IxDyn>, ArrayViewMut<A, IxDyn>);
+    fn slice_qubit_mut(
+        &mut self,
+        q: usize,
+    ) -> (ArrayViewMut<'_, A, IxDyn>, ArrayViewMut<'_, A, IxDyn>);
 
     /// contract the last n qubit indices with the first n qubits of other
     ///
@@ -177,7 +192,10 @@ impl<A: TensorElem> CompareTensors for Tensor<A> {
 }
 
 impl<A: TensorElem> QubitOps<A> for Tensor<A> {
-    fn slice_qubit_mut(&mut self, q: usize) -> (ArrayViewMut<A, IxDyn>, ArrayViewMut<A, IxDyn>) {
+    fn slice_qubit_mut(
+        &mut self,
+        q: usize,
+    ) -> (ArrayViewMut<'_, A, IxDyn>, ArrayViewMut<'_, A, IxDyn>) {
         let slice0: SliceInfo<_, IxDyn, IxDyn> =
             SliceInfo::try_from(Vec::from_iter((0..self.ndim()).map(|i| {
                 if i == q {
@@ -319,6 +337,12 @@ impl<G: GraphLike + Clone> ToTensor for G {
             if t != VType::B && t != VType::Z {
                 panic!("Vertex type currently unsupported: {t:?}");
             }
+            if g.is_symbolic(v) {
+                panic!(
+                    "Symbolic phases must be substituted first: {}",
+                    g.sym_phase(v)
+                );
+            }
         }
 
         // initialise the trivial tensor
@@ -433,6 +457,14 @@ impl ToTensor for Circuit {
                     a.cphase_at(Rational64::one(), &g.qs);
                     a.hadamard_at(g.qs[1]);
                 }
+                // the CNOTs of a fan-out commute, so their order doesn't matter
+                Fanout => {
+                    for &t in &g.qs[1..] {
+                        a.hadamard_at(t);
+                        a.cphase_at(Rational64::one(), &[g.qs[0], t]);
+                        a.hadamard_at(t);
+                    }
+                }
                 TOFF => {
                     a.hadamard_at(g.qs[2]);
                     a.cphase_at(Rational64::one(), &g.qs);
@@ -447,6 +479,12 @@ impl ToTensor for Circuit {
                     a.hadamard_at(g.qs[0]);
                     a.hadamard_at(g.qs[1]);
                 }
+                MeasureZ => {
+                    // the projector onto the recorded outcome is diagonal, so
+                    // we just zero out the other outcome
+                    let (a0, a1) = a.slice_qubit_mut(g.qs[0]);
+                    if g.phase.is_zero() { a1 } else { a0 }.fill(A::zero());
+                }
                 // TODO: these "gates" are not implemented yet
                 ParityPhase => {
                     panic!("Unsupported gate: ParityPhase")
@@ -463,6 +501,7 @@ impl ToTensor for Circuit {
                 MeasureReset => {
                     panic!("Unsupported gate: MeasureReset")
                 }
+                Barrier => {}
                 UnknownGate => {} // unknown gates are quietly ignored
             }
         }
@@ -470,6 +509,82 @@ impl ToTensor for Circuit {
     }
 }
 
+/// Amplitudes of a graph with open inputs and outputs, served from its tensor
+///
+/// If the graph has at most `max_legs` inputs and outputs in total, the tensor is
+/// contracted once when the `CachedTensor` is built, and each amplitude is a single
+/// lookup. Otherwise, the tensor would have too many entries to store, so each query
+/// plugs basis states into a copy of the graph and computes the resulting scalar with
+/// a [`Decomposer`] instead.
+pub struct CachedTensor<G: GraphLike> {
+    graph: G,
+    tensor: Option<Vec<Complex<f64>>>,
+}
+
+impl<G: GraphLike> CachedTensor<G> {
+    /// The default limit on legs, for a tensor of 2^24 complex numbers, i.e. 256 MiB
+    pub const DEFAULT_MAX_LEGS: usize = 24;
+
+    /// Builds the cache, with the default limit on the number of legs
+    pub fn new(g: &G) -> Self {
+        CachedTensor::with_max_legs(g, CachedTensor::<G>::DEFAULT_MAX_LEGS)
+    }
+
+    /// Builds the cache, contracting the tensor if `g` has at most `max_legs` inputs
+    /// and outputs
+    pub fn with_max_legs(g: &G, max_legs: usize) -> Self {
+        let legs = g.inputs().len() + g.outputs().len();
+        let tensor = (legs <= max_legs).then(|| {
+            let t = g.to_tensor64();
+            t.as_standard_layout().iter().copied().collect()
+        });
+        CachedTensor {
+            graph: g.clone(),
+            tensor,
+        }
+    }
+
+    /// Returns true if the tensor was contracted, so amplitudes are lookups
+    pub fn is_cached(&self) -> bool {
+        self.tensor.is_some()
+    }
+
+    /// Returns the amplitude `<output|g|input>` for computational basis states
+    ///
+    /// The states are given as one bit per input and output. Panics if there are too
+    /// few or too many bits.
+    pub fn amplitude(&self, input: &[bool], output: &[bool]) -> Complex<f64> {
+        let (ni, no) = (self.graph.inputs().len(), self.graph.outputs().len());
+        if input.len() != ni || output.len() != no {
+            panic!(
+                "Expected {ni} input and {no} output bits, got {} and {}",
+                input.len(),
+                output.len()
+            );
+        }
+
+        if let Some(t) = &self.tensor {
+            // inputs come first, with the first leg most significant
+            let i = input
+                .iter()
+                .chain(output)
+                .fold(0, |i, &b| 2 * i + b as usize);
+            t[i]
+        } else {
+            let basis = |bs: &[bool]| -> Vec<BasisElem> {
+                bs.iter()
+                    .map(|&b| if b { BasisElem::Z1 } else { BasisElem::Z0 })
+                    .collect()
+            };
+            let mut g = self.graph.clone();
+            g.plug_inputs(&basis(input));
+            g.plug_outputs(&basis(output));
+            let mut d = Decomposer::new(&g);
+            d.with_full_simp().decompose();
+            d.scalar().complex_value()
+        }
+    }
+}
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -509,6 +624,55 @@ mod tests {
         assert_eq!(t, Tensor::ident(1));
     }
 
+    #[test]
+    fn tensor_open_boundary_order() {
+        // a wire from the input to the first output, and |1> on the second output
+        let mut g = Graph::new();
+        let i = g.add_vertex(VType::B);
+        let o0 = g.add_vertex(VType::B);
+        let o1 = g.add_vertex(VType::B);
+        let x = g.add_vertex_with_phase(VType::X, 1);
+        g.add_edge(i, o0);
+        g.add_edge(x, o1);
+        g.set_inputs(vec![i]);
+        g.set_outputs(vec![o0, o1]);
+
+        let t = g.to_tensor64();
+        assert_eq!(t.shape(), &[2, 2, 2]);
+        let m = t.into_shape_with_order((2, 4)).unwrap();
+        for r in 0..2 {
+            for c in 0..4 {
+                let expected = if c == 2 * r + 1 { 2f64.sqrt() } else { 0.0 };
+                assert!((m[[r, c]] - expected).norm() < 1e-10, "{m}");
+            }
+        }
+
+        // a bare wire is the 2x2 identity
+        let mut g = Graph::new();
+        let i = g.add_vertex(VType::B);
+        let o = g.add_vertex(VType::B);
+        g.add_edge(i, o);
+        g.set_inputs(vec![i]);
+        g.set_outputs(vec![o]);
+        let m = g.to_tensorf().into_shape_with_order((2, 2)).unwrap();
+        assert_eq!(m, ndarray::Array2::from_diag_elem(2, FScalar::one()));
+
+        // the matrix is the transpose of the matrix of H S
+        let c = Circuit::from_qasm("qreg q[1]; s q[0]; h q[0];").unwrap();
+        let g: Graph = c.to_graph();
+        let m = g.to_tensor64().into_shape_with_order((2, 2)).unwrap();
+        let r = 0.5f64.sqrt();
+        let expected = [
+            [Complex::new(r, 0.0), Complex::new(r, 0.0)],
+            [Complex::new(0.0, r), Complex::new(0.0, -r)],
+        ];
+        for (row, erow) in m.outer_iter().zip(expected) {
+            for (x, e) in row.iter().zip(erow) {
+                assert!((x - e).norm() < 1e-10, "{m}");
+            }
+        }
+    }
+
     #[test]
     fn tensor_delta() {
         let mut g = Graph::new();
@@ -619,4 +783,30 @@ mod tests {
 
         assert_eq!(t3, c3.to_tensorf());
     }
+
+    #[test]
+    fn cached_tensor() {
+        let c = Circuit::random()
+            .seed(3)
+            .qubits(3)
+            .depth(30)
+            .clifford_t(0.3)
+            .build();
+        let g: Graph = c.to_graph();
+        let cached = CachedTensor::new(&g);
+        let uncached = CachedTensor::with_max_legs(&g, 4);
+        assert!(cached.is_cached());
+        assert!(!uncached.is_cached());
+
+        let u = c.to_tensor64().into_shape_with_order((8, 8)).unwrap();
+        let bits = |x: usize| -> Vec<bool> { (0..3).map(|q| x >> (2 - q) & 1 == 1).collect() };
+        for i in 0..8 {
+            for o in 0..8 {
+                let a = cached.amplitude(&bits(i), &bits(o));
+                assert!((a - u[[i, o]]).norm() < 1e-10);
+                let a1 = uncached.amplitude(&bits(i), &bits(o));
+                assert!((a - a1).norm() < 1e-10, "{a} != {a1}");
+            }
+        }
+    }
 }
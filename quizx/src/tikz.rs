@@ -0,0 +1,339 @@
+// QuiZX - Rust library for quantum circuit rewriting and optimisation
+//         using the ZX-calculus
+// Copyright (C) 2021 - Aleks Kissinger
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Decoding of ZX diagrams drawn in TikZ.
+//!
+//! This reads the `\node` and `\draw` commands written by TikZiT and by PyZX's
+//! `to_tikz`, e.g. `\node [style=Z phase dot] (1) at (1, -2) {$\frac{\pi}{2}$};` and
+//! `\draw [style=hadamard edge] (0) to (1);`. Everything else, like the
+//! `tikzpicture` and `pgfonlayer` environments, is skipped.
+//!
+//! Node styles are the TikZiT ZX styles (`Z dot`, `X phase dot`, `hadamard`, `none`,
+//! ...) and the short forms `zx_z`, `zx_x` and `zx_h`. Nodes without a style are
+//! boundaries. The x coordinate of a node is its row and the y coordinate its negated
+//! qubit, as in PyZX. Phases are read from the node labels, in units of `\pi`.
+
+use crate::graph::{EType, GraphLike, VType, V};
+use crate::phase::Phase;
+use crate::vec_graph::Graph;
+use num::Rational64;
+use regex::Regex;
+use rustc_hash::FxHashMap;
+
+fn vertex_type(style: &str) -> Option<VType> {
+    match style.to_lowercase().as_str() {
+        "" | "none" | "zx_boundary" => Some(VType::B),
+        "z dot" | "z phase dot" | "white dot" | "white phase dot" | "zx_z" => Some(VType::Z),
+        "x dot" | "x phase dot" | "grey dot" | "grey phase dot" | "gray dot" | "gray phase dot"
+        | "zx_x" => Some(VType::X),
+        "hadamard" | "h box" | "zx_h" => Some(VType::H),
+        _ => None,
+    }
+}
+
+fn edge_type(style: &str) -> Option<EType> {
+    match style.to_lowercase().as_str() {
+        "" | "none" | "simple" => Some(EType::N),
+        "hadamard edge" | "hadamard" | "h edge" => Some(EType::H),
+        _ => None,
+    }
+}
+
+/// The style in a TikZ option list, either as `style=...` or as a bare key
+fn style(opts: Option<&str>) -> &str {
+    let Some(opts) = opts else { return "" };
+    let mut bare = None;
+    for opt in opts.split(',').map(str::trim) {
+        if let Some(s) = opt.strip_prefix("style") {
+            if let Some(s) = s.trim_start().strip_prefix('=') {
+                return s.trim().trim_matches(|c| c == '{' || c == '}');
+            }
+        }
+        if bare.is_none() && !opt.contains('=') {
+            bare = Some(opt);
+        }
+    }
+    bare.unwrap_or("")
+}
+
+/// Reads a phase like `$-\frac{3\pi}{4}$` from a node label.
+fn parse_phase(label: &str) -> Option<Phase> {
+    let s: String = label
+        .chars()
+        .filter(|&c| !c.is_whitespace() && c != '$')
+        .collect();
+    if s.is_empty() || s == "0" {
+        return Some(Phase::new(0));
+    }
+    let (neg, s) = match s.strip_prefix('-') {
+        Some(s) => (true, s),
+        None => (false, s.as_str()),
+    };
+
+    // either \frac{n\pi}{d} or n\pi/d, where n defaults to 1
+    let (numer, denom) = if let Some(s) = s.strip_prefix(r"\frac{") {
+        let (numer, rest) = s.split_once('}')?;
+        let (denom, rest) = rest.strip_prefix('{')?.split_once('}')?;
+        match rest {
+            "" => (numer.to_string(), denom),
+            r"\pi" if !numer.contains(r"\pi") => (format!(r"{numer}\pi"), denom),
+            _ => return None,
+        }
+    } else {
+        match s.split_once('/') {
+            Some((numer, denom)) => (numer.to_string(), denom),
+            None => (s.to_string(), "1"),
+        }
+    };
+    let n = numer.strip_suffix(r"\pi")?.trim_end_matches('*');
+    let n: i64 = if n.is_empty() { 1 } else { n.parse().ok()? };
+    let d: i64 = denom.parse().ok()?;
+    if d <= 0 {
+        return None;
+    }
+    Some(Phase::new(Rational64::new(if neg { -n } else { n }, d)))
+}
+
+/// Reads a ZX diagram from TikZ, see [`crate::graph::from_tikz`].
+pub(crate) fn graph_from_tikz(s: &str) -> Result<Graph, String> {
+    let node_re = Regex::new(
+        r"(?s)^\\node\s*(?:\[([^\]]*)\])?\s*\(([^)]*)\)\s*at\s*\(([^,]*),([^)]*)\)\s*\{(.*)\}$",
+    )
+    .unwrap();
+    let draw_re = Regex::new(r"(?s)^\\draw\s*(?:\[([^\]]*)\])?(.*)$").unwrap();
+    let end_re = Regex::new(r"\(([^)]*)\)").unwrap();
+
+    // drop comments, TikZ statements are then separated by semicolons
+    let s: String = s
+        .lines()
+        .map(|l| l.split_once('%').map_or(l, |(l, _)| l))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let mut g = Graph::new();
+    let mut names: FxHashMap<String, V> = FxHashMap::default();
+    let mut edges = vec![];
+
+    for stmt in s.split(';').map(str::trim) {
+        // skip \begin{..} and \end{..} lines in front of the statement
+        let stmt = stmt
+            .lines()
+            .map(str::trim)
+            .skip_while(|l| l.starts_with(r"\begin") || l.starts_with(r"\end"))
+            .collect::<Vec<_>>()
+            .join(" ");
+        if stmt.starts_with(r"\node") {
+            let cap = node_re
+                .captures(&stmt)
+                .ok_or_else(|| format!("Bad TikZ node: {stmt}"))?;
+            let name = cap[2].trim();
+            let st = style(cap.get(1).map(|m| m.as_str()));
+            let ty = vertex_type(st)
+                .ok_or_else(|| format!("Unsupported TikZ node style {st} for node {name}"))?;
+            let coord = |c: &str| {
+                c.trim()
+                    .parse::<f64>()
+                    .map_err(|_| format!("Bad TikZ coordinate for node {name}: {c}"))
+            };
+            let (x, y) = (coord(&cap[3])?, coord(&cap[4])?);
+
+            let v = g.add_vertex(ty);
+            g.set_row(v, x);
+            g.set_qubit(v, if y == 0.0 { 0.0 } else { -y });
+            if matches!(ty, VType::Z | VType::X) {
+                let label = cap[5].trim();
+                let p = parse_phase(label)
+                    .ok_or_else(|| format!("Bad TikZ phase for node {name}: {label}"))?;
+                g.set_phase(v, p);
+            }
+            if names.insert(name.to_string(), v).is_some() {
+                return Err(format!("TikZ node {name} is defined twice"));
+            }
+        } else if stmt.starts_with(r"\draw") {
+            let cap = draw_re.captures(&stmt).unwrap();
+            let st = style(cap.get(1).map(|m| m.as_str()));
+            let ety = edge_type(st).ok_or_else(|| format!("Unsupported TikZ edge style {st}"))?;
+            let ends: Vec<String> = end_re
+                .captures_iter(&cap[2])
+                .map(|c| c[1].trim().to_string())
+                .collect();
+            if ends.len() < 2 {
+                return Err(format!("Bad TikZ edge: {stmt}"));
+            }
+            for e in ends.windows(2) {
+                edges.push((e[0].clone(), e[1].clone(), ety));
+            }
+        }
+    }
+
+    // edges can refer to nodes defined later, and to anchors like (0.center)
+    let vertex = |name: &str| {
+        names
+            .get(name)
+            .or_else(|| names.get(name.split_once('.')?.0))
+            .copied()
+            .ok_or_else(|| format!("TikZ edge to undefined node {name}"))
+    };
+    for (s, t, ety) in edges {
+        let (v, w) = (vertex(&s)?, vertex(&t)?);
+        // only self-loops and parallel edges on spiders can be simplified away
+        let spiders = [v, w]
+            .iter()
+            .all(|&u| matches!(g.vertex_type(u), VType::Z | VType::X));
+        if v == w && !spiders {
+            return Err(format!("Unsupported TikZ self-loop on node {s}"));
+        }
+        if g.connected(v, w) && !spiders {
+            return Err(format!(
+                "Unsupported TikZ parallel edges between {s} and {t}"
+            ));
+        }
+        g.add_edge_smart(v, w, ety);
+    }
+
+    // boundaries to the left of their neighbour are inputs, the others outputs
+    let mut inputs = vec![];
+    let mut outputs = vec![];
+    for v in g.vertices() {
+        if g.vertex_type(v) != VType::B {
+            continue;
+        }
+        if g.neighbors(v).any(|w| g.row(w) > g.row(v)) {
+            inputs.push(v);
+        } else {
+            outputs.push(v);
+        }
+    }
+    let by_position = |&v: &V, &w: &V| {
+        (g.qubit(v), g.row(v))
+            .partial_cmp(&(g.qubit(w), g.row(w)))
+            .unwrap()
+    };
+    inputs.sort_by(by_position);
+    outputs.sort_by(by_position);
+    g.set_inputs(inputs);
+    g.set_outputs(outputs);
+
+    Ok(g)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::circuit::Circuit;
+    use crate::tensor::{CompareTensors, TensorF};
+    use num::One;
+
+    #[test]
+    fn phases() {
+        let r = |n, d| Some(Phase::new(Rational64::new(n, d)));
+        assert_eq!(parse_phase(""), r(0, 1));
+        assert_eq!(parse_phase("$\\pi$"), r(1, 1));
+        assert_eq!(parse_phase("$-\\pi$"), r(1, 1));
+        assert_eq!(parse_phase("$\\frac{\\pi}{2}$"), r(1, 2));
+        assert_eq!(parse_phase("$-\\frac{3\\pi}{4}$"), r(-3, 4));
+        assert_eq!(parse_phase("$\\frac{3}{4}\\pi$"), r(3, 4));
+        assert_eq!(parse_phase("$3\\pi/4$"), r(3, 4));
+        assert_eq!(parse_phase("$\\pi/8$"), r(1, 8));
+        assert_eq!(parse_phase("$\\alpha$"), None);
+        assert_eq!(parse_phase("$\\frac{1}{2}$"), None);
+    }
+
+    #[test]
+    fn cnot() {
+        let tikz = r"
+            \begin{tikzpicture}
+                \begin{pgfonlayer}{nodelayer}
+                    \node [style=none] (0) at (0, 0) {};
+                    \node [style=none] (1) at (0, -1) {};
+                    \node [style=Z dot] (2) at (1, 0) {};
+                    \node [style=X dot] (3) at (1, -1) {};
+                    \node [style=none] (4) at (2, 0) {};
+                    \node [style=none] (5) at (2, -1) {};
+                \end{pgfonlayer}
+                \begin{pgfonlayer}{edgelayer}
+                    \draw (0) to (2);
+                    \draw (1.center) to (3);
+                    \draw (2) to (3);
+                    \draw (2) -- (4);
+                    \draw (3) to (5); % this is the target
+                \end{pgfonlayer}
+            \end{tikzpicture}
+        ";
+        let g = graph_from_tikz(tikz).unwrap();
+        assert_eq!(g.num_vertices(), 6);
+        assert_eq!(g.num_edges(), 5);
+        assert_eq!(g.inputs(), &[0, 1]);
+        assert_eq!(g.outputs(), &[4, 5]);
+        assert_eq!(g.qubit(3), 1.0);
+        assert_eq!(g.row(3), 1.0);
+
+        let mut c = Circuit::new(2);
+        c.add_gate("cx", vec![0, 1]);
+        let h: Graph = c.to_graph();
+        assert!(TensorF::scalar_compare(&g, &h));
+    }
+
+    #[test]
+    fn hadamard_edges_and_phases() {
+        let tikz = r"
+            \node [zx_z] (a) at (1, 0) {$\frac{\pi}{4}$};
+            \node (in) at (0, 0) {};
+            \node [style=X phase dot] (b) at (2, 0) {$\pi$};
+            \node [zx_h] (h) at (3, 0) {};
+            \node [style=none] (out) at (4, 0) {};
+            \draw (in) to (a);
+            \draw [style=hadamard edge] (a) to (b);
+            \draw (b) to (h) to (out);
+        ";
+        let g = graph_from_tikz(tikz).unwrap();
+        assert_eq!(g.phase(0), Rational64::new(1, 4).into());
+        assert_eq!(g.phase(2), Phase::one());
+        assert_eq!(g.vertex_type(3), VType::H);
+        assert_eq!(g.edge_type(0, 2), EType::H);
+        assert_eq!(g.inputs(), &[1]);
+        assert_eq!(g.outputs(), &[4]);
+        assert_eq!(g.num_edges(), 4);
+    }
+
+    #[test]
+    fn errors() {
+        let err = graph_from_tikz(r"\node [style=W dot] (0) at (0, 0) {};").unwrap_err();
+        assert!(err.contains("W dot"), "{err}");
+        assert!(graph_from_tikz(r"\node [style=Z dot] (0) at (0, 0) {$\alpha$};").is_err());
+        assert!(graph_from_tikz(r"\node (0) at (0, 0) {}; \draw (0) to (1);").is_err());
+        assert!(graph_from_tikz(r"\node (0) at (0, 0) {}; \node (0) at (1, 0) {};").is_err());
+        assert!(graph_from_tikz(r"\node (0) at (0, 0) {}; \draw [blue] (0) to (0);").is_err());
+
+        // self-loops and parallel edges are only allowed on spiders
+        let nodes = r"\node (b) at (0, 0) {}; \node [zx_h] (h) at (1, 0) {};
+            \node [zx_z] (z) at (2, 0) {}; \node [zx_x] (x) at (3, 0) {};";
+        for edges in [
+            r"\draw (b) to (b);",
+            r"\draw [hadamard edge] (h) to (h);",
+            r"\draw (b) to (z); \draw (b) to (z);",
+            r"\draw (h) to (z); \draw [hadamard edge] (z) to (h);",
+        ] {
+            let err = graph_from_tikz(&format!("{nodes} {edges}")).unwrap_err();
+            assert!(err.contains("Unsupported TikZ"), "{err}");
+        }
+        let g = graph_from_tikz(&format!(
+            "{nodes} \\draw (z) to (z); \\draw (z) to (x); \\draw (x) to (z);"
+        ))
+        .unwrap();
+        assert_eq!(g.num_edges(), 0);
+    }
+}
@@ -0,0 +1,390 @@
+// QuiZX - Rust library for quantum circuit rewriting and optimisation
+//         using the ZX-calculus
+// Copyright (C) 2021 - Aleks Kissinger
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Circuits and graphs for small unitary matrices
+//!
+//! Single-qubit unitaries are decomposed into Euler angles, as in [`Gate::u3`]. Two-qubit
+//! unitaries use the KAK decomposition U = (A1 ⊗ B1) exp(i(a XX + b YY + c ZZ)) (A2 ⊗ B2),
+//! computed by diagonalising U^T U in the magic basis, after Vatan and Williams
+//! (arXiv:quant-ph/0308006). Each term of the interaction becomes a parity phase.
+//!
+//! Matrices are indexed as usual, i.e. row by output, with the first qubit being the
+//! most significant.
+
+use crate::circuit::Circuit;
+use crate::fscalar::FScalar;
+use crate::gate::{GType::*, Gate};
+use crate::graph::*;
+use crate::tensor::ToTensor;
+use crate::vec_graph::Graph;
+use ndarray::{array, Array1, Array2};
+use num::{Complex, Zero};
+use std::f64::consts::PI;
+
+type C = Complex<f64>;
+
+/// Entries of the input may differ from a unitary by this much
+const TOLERANCE: f64 = 1e-8;
+
+fn dagger(m: &Array2<C>) -> Array2<C> {
+    m.t().mapv(|z| z.conj())
+}
+
+fn max_dist(m0: &Array2<C>, m1: &Array2<C>) -> f64 {
+    m0.iter()
+        .zip(m1.iter())
+        .map(|(a, b)| (a - b).norm())
+        .fold(0.0, f64::max)
+}
+
+fn det(m: &Array2<C>) -> C {
+    let mut m = m.clone();
+    let n = m.nrows();
+    let mut d = C::new(1.0, 0.0);
+    for c in 0..n {
+        let p = (c..n)
+            .max_by(|&i, &j| m[(i, c)].norm().total_cmp(&m[(j, c)].norm()))
+            .unwrap();
+        if m[(p, c)].norm() == 0.0 {
+            return C::new(0.0, 0.0);
+        }
+        if p != c {
+            for k in 0..n {
+                m.swap((p, k), (c, k));
+            }
+            d = -d;
+        }
+        d *= m[(c, c)];
+        for r in c + 1..n {
+            let f = m[(r, c)] / m[(c, c)];
+            for k in c..n {
+                let x = m[(c, k)];
+                m[(r, k)] -= f * x;
+            }
+        }
+    }
+    d
+}
+
+/// Eigenvectors of a real symmetric matrix by Jacobi rotations, as the columns of an
+/// orthogonal matrix
+fn jacobi_eigenvectors(mut a: Array2<f64>) -> Array2<f64> {
+    let n = a.nrows();
+    let mut v = Array2::eye(n);
+    for _ in 0..100 {
+        let off: f64 = (0..n)
+            .flat_map(|i| (0..n).filter(move |&j| j != i).map(move |j| (i, j)))
+            .map(|ij| a[ij] * a[ij])
+            .sum();
+        if off < 1e-30 {
+            break;
+        }
+        for p in 0..n {
+            for q in p + 1..n {
+                if a[(p, q)].abs() < 1e-300 {
+                    continue;
+                }
+                let theta = (a[(q, q)] - a[(p, p)]) / (2.0 * a[(p, q)]);
+                let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+                let t = if theta == 0.0 { 1.0 } else { t };
+                let c = 1.0 / (t * t + 1.0).sqrt();
+                let s = t * c;
+                for k in 0..n {
+                    let (akp, akq) = (a[(k, p)], a[(k, q)]);
+                    a[(k, p)] = c * akp - s * akq;
+                    a[(k, q)] = s * akp + c * akq;
+                }
+                for k in 0..n {
+                    let (apk, aqk) = (a[(p, k)], a[(q, k)]);
+                    a[(p, k)] = c * apk - s * aqk;
+                    a[(q, k)] = s * apk + c * aqk;
+                }
+                for k in 0..n {
+                    let (vkp, vkq) = (v[(k, p)], v[(k, q)]);
+                    v[(k, p)] = c * vkp - s * vkq;
+                    v[(k, q)] = s * vkp + c * vkq;
+                }
+            }
+        }
+    }
+    v
+}
+
+/// Pushes gates implementing a 2×2 matrix proportional to a unitary, up to a global
+/// phase
+fn push_single_qubit(c: &mut Circuit, q: usize, u: &Array2<C>) {
+    // u ~ U3(θ, φ, λ) = [[cos, -e^{iλ} sin], [e^{iφ} sin, e^{i(φ+λ)} cos]]
+    let theta = 2.0 * u[(1, 0)].norm().atan2(u[(0, 0)].norm());
+    let (phi, lambda) = if u[(1, 0)].norm() < TOLERANCE {
+        ((u[(1, 1)] / u[(0, 0)]).arg(), 0.0)
+    } else if u[(0, 0)].norm() < TOLERANCE {
+        ((-u[(1, 0)] / u[(0, 1)]).arg(), 0.0)
+    } else {
+        (
+            (u[(1, 0)] / u[(0, 0)]).arg(),
+            (-u[(0, 1)] / u[(0, 0)]).arg(),
+        )
+    };
+    for g in Gate::u3(
+        q,
+        approx_phase(theta / PI),
+        approx_phase(phi / PI),
+        approx_phase(lambda / PI),
+    ) {
+        if !g.phase.is_zero() {
+            c.push(g);
+        }
+    }
+}
+
+/// Splits a 4×4 matrix of the form A ⊗ B into A and B, up to scalars
+fn tensor_factors(k: &Array2<C>) -> (Array2<C>, Array2<C>) {
+    let (i, j) = (0..16)
+        .map(|x| (x / 4, x % 4))
+        .max_by(|&x, &y| k[x].norm().total_cmp(&k[y].norm()))
+        .unwrap();
+    let a = Array2::from_shape_fn((2, 2), |(m, n)| k[(2 * m + i % 2, 2 * n + j % 2)]);
+    let b = Array2::from_shape_fn((2, 2), |(m, n)| {
+        k[(2 * (i / 2) + m, 2 * (j / 2) + n)] / a[(i / 2, j / 2)]
+    });
+    (a, b)
+}
+
+/// Pushes gates for exp(i(a XX + b YY + c ZZ)), up to a global phase
+fn push_interaction(circ: &mut Circuit, a: f64, b: f64, c: f64) {
+    // exp(i t ZZ) is a parity phase of -2t/π, up to a global phase
+    let zz = |circ: &mut Circuit, t: f64| {
+        circ.push(Gate::new_with_phase(
+            ParityPhase,
+            vec![0, 1],
+            approx_phase(-2.0 * t / PI),
+        ))
+    };
+    // XX = (H ⊗ H) ZZ (H ⊗ H) and YY = (SH ⊗ SH) ZZ (HS† ⊗ HS†)
+    for (t, basis) in [(a, vec![HAD]), (b, vec![Sdg, HAD]), (c, vec![])] {
+        if t.abs() < TOLERANCE {
+            continue;
+        }
+        for &g in &basis {
+            circ.push(Gate::new(g, vec![0]));
+            circ.push(Gate::new(g, vec![1]));
+        }
+        zz(circ, t);
+        for &g in basis.iter().rev() {
+            let mut g = Gate::new(g, vec![0]);
+            g.adjoint();
+            circ.push(g.clone());
+            g.qs = vec![1];
+            circ.push(g);
+        }
+    }
+}
+
+/// Pushes gates implementing a two-qubit unitary up to a global phase, by the KAK
+/// decomposition
+fn push_two_qubit(circ: &mut Circuit, u: &Array2<C>) {
+    let h = 0.5f64.sqrt();
+    let (o, i) = (C::new(h, 0.0), C::new(0.0, h));
+    let z = C::new(0.0, 0.0);
+    let magic: Array2<C> = array![[o, z, z, i], [z, i, o, z], [z, i, -o, z], [o, z, z, -i]];
+
+    // the magic basis turns SU(2) ⊗ SU(2) into SO(4), and diagonalises XX, YY and ZZ
+    let u = u / det(u).powf(0.25);
+    let ub = dagger(&magic).dot(&u).dot(&magic);
+    let m2 = ub.t().dot(&ub);
+
+    // the real and imaginary parts of m2 commute, so a generic combination of them has
+    // the same real eigenvectors as m2
+    let mut p = jacobi_eigenvectors(m2.mapv(|z| z.re + 0.5731 * z.im));
+    if det(&p.mapv(|x| C::new(x, 0.0))).re < 0.0 {
+        p.column_mut(0).mapv_inplace(|x| -x);
+    }
+    let pc = p.mapv(|x| C::new(x, 0.0));
+    let d = pc.t().dot(&m2).dot(&pc);
+    let mut thetas: Vec<f64> = (0..4).map(|j| d[(j, j)].arg() / 2.0).collect();
+    if thetas.iter().sum::<f64>().cos() < 0.0 {
+        thetas[0] += PI;
+    }
+
+    // ub = k1 diag(e^{iθ}) p^T, where k1 is also in SO(4)
+    let inv_diag = Array2::from_diag(&Array1::from_iter(
+        thetas.iter().map(|&t| C::from_polar(1.0, -t)),
+    ));
+    let k1 = ub.dot(&pc).dot(&inv_diag);
+    let k1 = magic.dot(&k1).dot(&dagger(&magic));
+    let k2 = magic.dot(&pc.t()).dot(&dagger(&magic));
+
+    // write the θ's in terms of the eigenvalues of XX, YY and ZZ on the magic basis,
+    // which are ±1 patterns forming a Hadamard matrix
+    let e = C::new(1.0, 0.0);
+    let x = array![[z, z, z, e], [z, z, e, z], [z, e, z, z], [e, z, z, z]];
+    let y = array![[z, z, z, -e], [z, z, e, z], [z, e, z, z], [-e, z, z, z]];
+    let zz = Array2::from_diag(&array![e, -e, -e, e]);
+    let eig = |pp: &Array2<C>| -> Vec<f64> {
+        (0..4)
+            .map(|j| {
+                let m = magic.column(j);
+                m.mapv(|z| z.conj()).dot(&pp.dot(&m)).re
+            })
+            .collect()
+    };
+    let (ex, ey, ez) = (eig(&x), eig(&y), eig(&zz));
+    let coeff = |e: &[f64]| (0..4).map(|j| e[j] * thetas[j]).sum::<f64>() / 4.0;
+
+    let (a2, b2) = tensor_factors(&k2);
+    push_single_qubit(circ, 0, &a2);
+    push_single_qubit(circ, 1, &b2);
+    push_interaction(circ, coeff(&ex), coeff(&ey), coeff(&ez));
+    let (a1, b1) = tensor_factors(&k1);
+    push_single_qubit(circ, 0, &a1);
+    push_single_qubit(circ, 1, &b1);
+}
+
+/// Builds a circuit for a 2×2 or 4×4 unitary, up to a global phase
+///
+/// Phases are rounded as for angles read from QASM. Returns an error if the matrix has
+/// another size, or is not unitary.
+pub fn unitary_to_circuit(u: &Array2<C>) -> Result<Circuit, String> {
+    let (rows, cols) = u.dim();
+    if rows != cols || (rows != 2 && rows != 4) {
+        return Err(format!(
+            "only 1- and 2-qubit unitaries are supported, got a {rows}x{cols} matrix"
+        ));
+    }
+    if max_dist(&dagger(u).dot(u), &Array2::eye(rows)) > TOLERANCE {
+        return Err("matrix is not unitary".to_string());
+    }
+
+    if rows == 2 {
+        let mut c = Circuit::new(1);
+        push_single_qubit(&mut c, 0, u);
+        Ok(c)
+    } else {
+        let mut c = Circuit::new(2);
+        push_two_qubit(&mut c, u);
+        Ok(c)
+    }
+}
+
+/// Builds a graph for a 2×2 or 4×4 unitary, including its global phase
+///
+/// See [`unitary_to_circuit`]. The scalar of the graph is chosen so its matrix agrees
+/// with `u` up to rounding.
+pub fn from_unitary(u: &Array2<C>) -> Result<Graph, String> {
+    let c = unitary_to_circuit(u)?;
+    let mut g: Graph = c.to_graph();
+    let n = u.nrows();
+    let t = g.to_tensor64();
+    let t = t.as_slice().unwrap();
+
+    // the tensor is indexed by inputs, then outputs
+    let m = Array2::from_shape_fn((n, n), |(r, c)| t[c * n + r]);
+    let (r, c) = (0..n * n)
+        .map(|x| (x / n, x % n))
+        .max_by(|&x, &y| u[x].norm().total_cmp(&u[y].norm()))
+        .unwrap();
+    let ratio = u[(r, c)] / m[(r, c)];
+    *g.scalar_mut() *= FScalar::complex(ratio.re, ratio.im);
+
+    if max_dist(&m.mapv(|z| z * ratio), u) > 1e-6 {
+        return Err("decomposition did not reproduce the unitary".to_string());
+    }
+    Ok(g)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn matrix(g: &Graph) -> Array2<C> {
+        let n = 1 << g.inputs().len();
+        let t = g.to_tensor64();
+        let t = t.as_slice().unwrap().to_vec();
+        Array2::from_shape_fn((n, n), |(r, c)| t[c * n + r])
+    }
+
+    /// A random unitary, from Gram-Schmidt on a random complex matrix
+    fn random_unitary(n: usize, rng: &mut StdRng) -> Array2<C> {
+        let mut m = Array2::from_shape_fn((n, n), |_| {
+            C::new(rng.gen::<f64>() - 0.5, rng.gen::<f64>() - 0.5)
+        });
+        for j in 0..n {
+            for k in 0..j {
+                let proj: C = (0..n).map(|i| m[(i, k)].conj() * m[(i, j)]).sum();
+                for i in 0..n {
+                    let x = m[(i, k)];
+                    m[(i, j)] -= proj * x;
+                }
+            }
+            let norm = (0..n).map(|i| m[(i, j)].norm_sqr()).sum::<f64>().sqrt();
+            m.column_mut(j).mapv_inplace(|z| z / norm);
+        }
+        m
+    }
+
+    #[test]
+    fn unitary_single_qubit() {
+        let mut rng = StdRng::seed_from_u64(1337);
+        for _ in 0..20 {
+            let u = random_unitary(2, &mut rng);
+            let g = from_unitary(&u).unwrap();
+            assert!(max_dist(&matrix(&g), &u) < 1e-6);
+        }
+
+        let h = 0.5f64.sqrt();
+        let had = array![
+            [C::new(h, 0.0), C::new(h, 0.0)],
+            [C::new(h, 0.0), C::new(-h, 0.0)]
+        ];
+        let c = unitary_to_circuit(&had).unwrap();
+        assert!(c.stats().non_cliff == 0);
+    }
+
+    #[test]
+    fn unitary_two_qubit() {
+        let mut rng = StdRng::seed_from_u64(42);
+        for _ in 0..20 {
+            let u = random_unitary(4, &mut rng);
+            let g = from_unitary(&u).unwrap();
+            assert!(max_dist(&matrix(&g), &u) < 1e-6);
+        }
+
+        // CNOT, SWAP and the identity have degenerate spectra in the magic basis
+        let (o, z) = (C::new(1.0, 0.0), C::new(0.0, 0.0));
+        for u in [
+            array![[o, z, z, z], [z, o, z, z], [z, z, z, o], [z, z, o, z]],
+            array![[o, z, z, z], [z, z, o, z], [z, o, z, z], [z, z, z, o]],
+            Array2::eye(4),
+        ] {
+            let g = from_unitary(&u).unwrap();
+            assert!(max_dist(&matrix(&g), &u) < 1e-6);
+        }
+    }
+
+    #[test]
+    fn unitary_errors() {
+        assert!(from_unitary(&Array2::eye(8)).is_err());
+        assert!(from_unitary(&Array2::zeros((2, 3))).is_err());
+        let mut u: Array2<C> = Array2::eye(2);
+        u[(0, 1)] = C::new(0.5, 0.0);
+        assert_eq!(
+            from_unitary(&u).unwrap_err(),
+            "matrix is not unitary".to_string()
+        );
+    }
+}
@@ -27,7 +27,7 @@ pub type VTab<T> = Vec<Option<T>>;
 /// is greater than the number of allocated vertex indices.
 const PACK_RATIO: usize = 10;
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone)]
 pub struct Graph {
     vdata: VTab<VData>,
     edata: VTab<Vec<(V, EType)>>,
@@ -38,9 +38,79 @@ pub struct Graph {
     nume: usize,
     scalar: FScalar,
     scalar_factors: FxHashMap<Expr, FScalar>,
+    // optional map from each neighbor of a vertex to its position in edata
+    nhd_index: Option<Vec<FxHashMap<V, usize>>>,
+}
+
+impl PartialEq for Graph {
+    /// Compares graphs up to renaming of vertices
+    ///
+    /// This computes the canonical forms of both graphs with
+    /// [`canonical_form`](crate::canonical::canonical_form), which is usually fast but
+    /// exponential in the worst case, see there. Vertex positions and the neighbor
+    /// index are ignored.
+    fn eq(&self, other: &Self) -> bool {
+        crate::canonical::graphs_equal(self, other)
+    }
 }
 
 impl Graph {
+    /// Enable or disable an index from the neighbors of each vertex to their position
+    /// in its adjacency list
+    ///
+    /// Degrees and iteration over neighbors are always O(1) per step, but looking up
+    /// a single edge, e.g. with `connected` or `edge_type_opt`, is linear in the degree
+    /// of the vertex. With the index enabled, these lookups are O(1), at the cost of
+    /// extra memory and slower edge insertion and removal. This pays off for large
+    /// graphs with high-degree vertices. The index is kept up to date as the graph
+    /// changes and never affects the result of any graph operation.
+    pub fn set_neighbor_index(&mut self, enabled: bool) {
+        if enabled {
+            self.rebuild_neighbor_index();
+        } else {
+            self.nhd_index = None;
+        }
+    }
+
+    /// Returns true if the graph maintains a neighbor index
+    pub fn has_neighbor_index(&self) -> bool {
+        self.nhd_index.is_some()
+    }
+
+    fn rebuild_neighbor_index(&mut self) {
+        self.nhd_index = Some(
+            self.edata
+                .iter()
+                .map(|nhd| {
+                    nhd.iter()
+                        .flatten()
+                        .enumerate()
+                        .map(|(i, &(v, _))| (v, i))
+                        .collect()
+                })
+                .collect(),
+        );
+    }
+
+    /// Returns the neighbor index of the given vertex, if the index is enabled
+    fn neighbor_index_mut(&mut self, v: V) -> Option<&mut FxHashMap<V, usize>> {
+        let len = self.edata.len();
+        let idx = self.nhd_index.as_mut()?;
+        if idx.len() < len {
+            idx.resize_with(len, Default::default);
+        }
+        idx.get_mut(v)
+    }
+
+    /// Position of 't' in the adjacency list of 's'
+    fn position(&self, s: V, t: V) -> Option<usize> {
+        if let Some(idx) = &self.nhd_index {
+            idx.get(s)?.get(&t).copied()
+        } else {
+            Graph::index(self.edata.get(s)?.as_ref()?, t)
+        }
+    }
+
     /// Explicitly index neighbors of a vertex. Used for iteration.
     pub fn neighbor_at(&self, v: V, n: usize) -> V {
         if let Some(d) = &self.edata[v] {
@@ -67,8 +137,22 @@ impl Graph {
     /// is used by remove_edge and remove_vertex to make the latter slightly
     /// more efficient.
     fn remove_half_edge(&mut self, s: V, t: V) {
-        if let Some(Some(nhd)) = self.edata.get_mut(s) {
-            Graph::index(nhd, t).map(|i| nhd.swap_remove(i));
+        if let Some(i) = self.position(s, t) {
+            if let Some(Some(nhd)) = self.edata.get_mut(s) {
+                nhd.swap_remove(i);
+                let moved = nhd.get(i).map(|&(v, _)| v);
+                // a self-loop appears twice in the adjacency list
+                let remaining = if s == t { Graph::index(nhd, t) } else { None };
+                if let Some(idx) = self.neighbor_index_mut(s) {
+                    idx.remove(&t);
+                    if let Some(v) = moved {
+                        idx.insert(v, i);
+                    }
+                    if let Some(j) = remaining {
+                        idx.insert(t, j);
+                    }
+                }
+            }
         }
     }
 
@@ -104,6 +188,7 @@ impl GraphLike for Graph {
             nume: 0,
             scalar: 1.into(),
             scalar_factors: FxHashMap::default(),
+            nhd_index: None,
         }
     }
 
@@ -163,7 +248,7 @@ impl GraphLike for Graph {
 
     fn add_vertex_with_data(&mut self, d: VData) -> V {
         self.numv += 1;
-        if let Some(v) = self.holes.pop() {
+        let v = if let Some(v) = self.holes.pop() {
             self.vdata[v] = Some(d);
             self.edata[v] = Some(Vec::new());
             v
@@ -171,7 +256,11 @@ impl GraphLike for Graph {
             self.vdata.push(Some(d));
             self.edata.push(Some(Vec::new()));
             self.vdata.len() - 1
+        };
+        if let Some(idx) = self.neighbor_index_mut(v) {
+            idx.clear();
         }
+        v
     }
 
     fn add_named_vertex_with_data(&mut self, v: V, d: VData) -> Result<(), &str> {
@@ -192,6 +281,9 @@ impl GraphLike for Graph {
         self.numv += 1;
         self.vdata[v] = Some(d);
         self.edata[v] = Some(Vec::new());
+        if let Some(idx) = self.neighbor_index_mut(v) {
+            idx.clear();
+        }
         Ok(())
     }
 
@@ -206,22 +298,33 @@ impl GraphLike for Graph {
             self.nume -= 1;
             self.remove_half_edge(v1, v);
         }
+        if let Some(idx) = self.neighbor_index_mut(v) {
+            idx.clear();
+        }
     }
 
     fn add_edge_with_type(&mut self, s: V, t: V, ety: EType) {
         self.nume += 1;
         // if self.connected(s,t) { panic!("introducing parallel edge!"); }
 
-        if let Some(Some(nhd)) = self.edata.get_mut(s) {
+        let i = if let Some(Some(nhd)) = self.edata.get_mut(s) {
             nhd.push((t, ety));
+            nhd.len() - 1
         } else {
             panic!("Source vertex not found");
+        };
+        if let Some(idx) = self.neighbor_index_mut(s) {
+            idx.insert(t, i);
         }
 
-        if let Some(Some(nhd)) = self.edata.get_mut(t) {
+        let i = if let Some(Some(nhd)) = self.edata.get_mut(t) {
             nhd.push((s, ety));
+            nhd.len() - 1
         } else {
             panic!("Target vertex not found");
+        };
+        if let Some(idx) = self.neighbor_index_mut(t) {
+            idx.insert(s, i);
         }
     }
 
@@ -245,16 +348,16 @@ impl GraphLike for Graph {
     }
 
     fn set_edge_type(&mut self, s: V, t: V, ety: EType) {
+        let i = self.position(s, t);
         if let Some(Some(nhd)) = self.edata.get_mut(s) {
-            let i = Graph::index(nhd, t).expect("Edge not found");
-            nhd[i] = (t, ety);
+            nhd[i.expect("Edge not found")] = (t, ety);
         } else {
             panic!("Source vertex not found");
         }
 
+        let i = self.position(t, s);
         if let Some(Some(nhd)) = self.edata.get_mut(t) {
-            let i = Graph::index(nhd, s).expect("Edge not found");
-            nhd[i] = (s, ety);
+            nhd[i.expect("Edge not found")] = (s, ety);
         } else {
             panic!("Target vertex not found");
         }
@@ -262,7 +365,11 @@ impl GraphLike for Graph {
 
     fn edge_type_opt(&self, s: V, t: V) -> Option<EType> {
         if let Some(Some(nhd)) = self.edata.get(s) {
-            Graph::value(nhd, t)
+            if self.nhd_index.is_some() {
+                self.position(s, t).map(|i| nhd[i].1)
+            } else {
+                Graph::value(nhd, t)
+            }
         } else {
             None
         }
@@ -416,6 +523,10 @@ impl GraphLike for Graph {
 
             self.inputs = self.inputs.iter().map(|v| vtab[*v]).collect();
             self.outputs = self.outputs.iter().map(|v| vtab[*v]).collect();
+
+            if self.nhd_index.is_some() {
+                self.rebuild_neighbor_index();
+            }
         }
     }
 }
@@ -568,4 +679,58 @@ mod tests {
         assert!(g.vertex_type(1) == VType::Z);
         assert!(g.vertex_type(2) == VType::B);
     }
+
+    fn check_neighbor_index(g: &Graph) {
+        let idx = g.nhd_index.as_ref().expect("Index should be enabled");
+        for v in g.vertices() {
+            let nhd = g.edata[v].as_ref().unwrap();
+            let distinct: std::collections::HashSet<_> = nhd.iter().map(|&(w, _)| w).collect();
+            assert_eq!(idx[v].len(), distinct.len());
+            for &(w, _) in nhd.iter() {
+                assert_eq!(nhd[idx[v][&w]].0, w);
+            }
+        }
+    }
+
+    #[test]
+    fn neighbor_index() {
+        let c = crate::circuit::Circuit::random()
+            .seed(1337)
+            .qubits(10)
+            .depth(200)
+            .clifford_t(0.2)
+            .build();
+        let g: Graph = c.to_graph();
+        let mut h = g.clone();
+        h.set_neighbor_index(true);
+        assert!(h.has_neighbor_index());
+        assert_eq!(g, h);
+        check_neighbor_index(&h);
+
+        let mut g1 = g.clone();
+        crate::simplify::full_simp(&mut g1);
+        crate::simplify::full_simp(&mut h);
+        check_neighbor_index(&h);
+        assert_eq!(g1, h);
+
+        h.pack(true);
+        g1.pack(true);
+        check_neighbor_index(&h);
+        assert_eq!(g1, h);
+        for v in h.vertices() {
+            for w in h.vertices() {
+                assert_eq!(g1.edge_type_opt(v, w), h.edge_type_opt(v, w));
+            }
+        }
+
+        let v = h.add_vertex(VType::Z);
+        h.add_edge_with_type(v, v, EType::H);
+        check_neighbor_index(&h);
+        h.remove_edge(v, v);
+        check_neighbor_index(&h);
+        assert_eq!(h.degree(v), 0);
+
+        h.set_neighbor_index(false);
+        assert!(!h.has_neighbor_index());
+    }
 }
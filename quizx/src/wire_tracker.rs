@@ -0,0 +1,259 @@
+// QuiZX - Rust library for quantum circuit rewriting and optimisation
+//         using the ZX-calculus
+// Copyright (C) 2021 - Aleks Kissinger
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//    http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Building graphs gate by gate
+//!
+//! A [`WireTracker`] keeps track of the last vertex on each qubit wire, so spiders can
+//! be appended to a wire without keeping track of vertices by hand. For example, a Bell
+//! state preparation followed by a T gate is:
+//!
+//! ```
+//! use quizx::graph::GraphLike;
+//! use quizx::vec_graph::Graph;
+//! use quizx::wire_tracker::WireTracker;
+//! use num::Rational64;
+//!
+//! let mut t = WireTracker::<Graph>::new(2);
+//! t.apply_h(0).apply_cx(0, 1).apply_z(1, Rational64::new(1, 4));
+//! let g = t.finish();
+//! assert_eq!(g.num_vertices(), 7);
+//! ```
+
+use crate::fscalar::FScalar;
+use crate::graph::*;
+use crate::phase::Phase;
+use crate::scalar_traits::Sqrt2;
+
+/// Appends spiders to the qubit wires of a graph, and connects them to inputs and
+/// outputs
+///
+/// Each wire has an open vertex, which is the input to begin with, and the last added
+/// spider afterwards. Hadamard gates don't add vertices, but make the next edge added
+/// to the wire a Hadamard edge.
+pub struct WireTracker<G: GraphLike> {
+    graph: G,
+    frontier: Vec<V>,
+    had: Vec<bool>,
+}
+
+impl<G: GraphLike> WireTracker<G> {
+    /// Starts a graph with `qubits` inputs
+    pub fn new(qubits: usize) -> Self {
+        let mut graph = G::new();
+        let inputs: Vec<V> = (0..qubits)
+            .map(|q| {
+                graph.add_vertex_with_data(VData {
+                    ty: VType::B,
+                    qubit: q as f64,
+                    row: 1.0,
+                    ..Default::default()
+                })
+            })
+            .collect();
+        graph.set_inputs(inputs.clone());
+        WireTracker {
+            graph,
+            frontier: inputs,
+            had: vec![false; qubits],
+        }
+    }
+
+    /// The number of qubit wires
+    pub fn num_qubits(&self) -> usize {
+        self.frontier.len()
+    }
+
+    /// The open vertex on the given qubit
+    pub fn frontier(&self, qubit: usize) -> V {
+        self.frontier[qubit]
+    }
+
+    /// The graph built so far, without outputs
+    pub fn graph(&self) -> &G {
+        &self.graph
+    }
+
+    /// Mutable access to the graph built so far, e.g. to add edges between spiders
+    pub fn graph_mut(&mut self) -> &mut G {
+        &mut self.graph
+    }
+
+    /// Appends a spider to the given qubit, in the row after its open vertex, and
+    /// returns it
+    pub fn add_spider(&mut self, qubit: usize, ty: VType, phase: impl Into<Phase>) -> V {
+        self.add_spider_at(qubit, ty, phase, self.next_row(qubit))
+    }
+
+    fn add_spider_at(&mut self, qubit: usize, ty: VType, phase: impl Into<Phase>, row: f64) -> V {
+        if qubit >= self.num_qubits() {
+            panic!(
+                "Qubit {} out of range for {} qubits",
+                qubit,
+                self.num_qubits()
+            );
+        }
+        let v = self.graph.add_vertex_with_data(VData {
+            ty,
+            phase: phase.into(),
+            qubit: qubit as f64,
+            row,
+            ..Default::default()
+        });
+        self.connect(qubit, v);
+        v
+    }
+
+    fn connect(&mut self, qubit: usize, v: V) {
+        let et = if self.had[qubit] { EType::H } else { EType::N };
+        self.graph.add_edge_with_type(self.frontier[qubit], v, et);
+        self.frontier[qubit] = v;
+        self.had[qubit] = false;
+    }
+
+    fn next_row(&self, qubit: usize) -> f64 {
+        self.graph.row(self.frontier[qubit]) + 1.0
+    }
+
+    /// Appends a Z spider with the given phase
+    pub fn apply_z(&mut self, qubit: usize, phase: impl Into<Phase>) -> &mut Self {
+        self.add_spider(qubit, VType::Z, phase);
+        self
+    }
+
+    /// Appends an X spider with the given phase
+    pub fn apply_x(&mut self, qubit: usize, phase: impl Into<Phase>) -> &mut Self {
+        self.add_spider(qubit, VType::X, phase);
+        self
+    }
+
+    /// Applies a Hadamard gate, by toggling the type of the next edge on the wire
+    pub fn apply_h(&mut self, qubit: usize) -> &mut Self {
+        self.had[qubit] = !self.had[qubit];
+        self
+    }
+
+    /// Appends a CNOT, as a Z spider on the control and an X spider on the target
+    /// in the same row
+    pub fn apply_cx(&mut self, control: usize, target: usize) -> &mut Self {
+        self.two_qubit(control, target, VType::X, EType::N);
+        *self.graph.scalar_mut() *= FScalar::sqrt2();
+        self
+    }
+
+    /// Appends a CZ, as two Z spiders in the same row connected by a Hadamard edge
+    pub fn apply_cz(&mut self, control: usize, target: usize) -> &mut Self {
+        self.two_qubit(control, target, VType::Z, EType::H);
+        *self.graph.scalar_mut() *= FScalar::sqrt2();
+        self
+    }
+
+    fn two_qubit(&mut self, control: usize, target: usize, ty: VType, et: EType) {
+        if control == target {
+            panic!("Two-qubit gate on qubit {control} twice");
+        }
+        let row = self.next_row(control).max(self.next_row(target));
+        let c = self.add_spider_at(control, VType::Z, 0, row);
+        let t = self.add_spider_at(target, ty, 0, row);
+        self.graph.add_edge_with_type(c, t, et);
+    }
+
+    /// Caps every wire with an output and returns the graph
+    pub fn finish(mut self) -> G {
+        let row = (0..self.num_qubits())
+            .map(|q| self.next_row(q))
+            .fold(2.0, f64::max);
+        let outputs: Vec<V> = (0..self.num_qubits())
+            .map(|q| {
+                let o = self.graph.add_vertex_with_data(VData {
+                    ty: VType::B,
+                    qubit: q as f64,
+                    row,
+                    ..Default::default()
+                });
+                self.connect(q, o);
+                o
+            })
+            .collect();
+        self.graph.set_outputs(outputs);
+        self.graph
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::circuit::Circuit;
+    use crate::tensor::ToTensor;
+    use crate::vec_graph::Graph;
+    use num::Rational64;
+
+    #[test]
+    fn matches_circuit() {
+        let mut c = Circuit::new(3);
+        c.add_gate("h", vec![0]);
+        c.add_gate("cx", vec![0, 1]);
+        c.add_gate_with_phase("rz", vec![1], Rational64::new(1, 4));
+        c.add_gate("h", vec![2]);
+        c.add_gate("h", vec![2]);
+        c.add_gate("cz", vec![1, 2]);
+        c.add_gate_with_phase("rx", vec![2], Rational64::new(1, 2));
+        c.add_gate("cx", vec![2, 0]);
+        c.add_gate("h", vec![1]);
+
+        let mut t = WireTracker::<Graph>::new(3);
+        t.apply_h(0)
+            .apply_cx(0, 1)
+            .apply_z(1, Rational64::new(1, 4))
+            .apply_h(2)
+            .apply_h(2)
+            .apply_cz(1, 2)
+            .apply_x(2, Rational64::new(1, 2))
+            .apply_cx(2, 0)
+            .apply_h(1);
+        let g = t.finish();
+
+        assert_eq!(g.inputs().len(), 3);
+        assert_eq!(g.outputs().len(), 3);
+        assert_eq!(c.to_tensorf(), g.to_tensorf());
+    }
+
+    #[test]
+    fn frontier() {
+        let mut t = WireTracker::<Graph>::new(2);
+        let i = t.frontier(1);
+        assert_eq!(t.graph().inputs()[1], i);
+        let v = t.add_spider(1, VType::Z, 0);
+        assert_eq!(t.frontier(1), v);
+        assert!(t.graph().connected(i, v));
+
+        t.apply_cx(0, 1);
+        let (c, x) = (t.frontier(0), t.frontier(1));
+        assert_eq!(t.graph().row(c), t.graph().row(x));
+        assert_eq!(t.graph().vertex_type(x), VType::X);
+
+        t.apply_h(0);
+        let g = t.finish();
+        let o = g.outputs()[0];
+        assert_eq!(g.edge_type(c, o), EType::H);
+        assert_eq!(g.row(o), g.row(g.outputs()[1]));
+    }
+
+    #[test]
+    #[should_panic]
+    fn out_of_range() {
+        WireTracker::<Graph>::new(2).apply_z(2, 0);
+    }
+}